@@ -64,6 +64,55 @@ pub fn packages_metadata_dir(project_root: &Path) -> PathBuf {
     lpm_metadata_dir(project_root).join("packages")
 }
 
+/// Lua version a lua_modules tree is laid out for when no Lua is detected
+pub const DEFAULT_LUA_VERSION: &str = "5.4";
+
+/// Where a lua_modules tree keeps Lua modules, like LuaRocks:
+/// `<lua_modules>/share/lua/<version>`
+pub fn tree_lua_dir(lua_modules: &Path, version: &str) -> PathBuf {
+    lua_modules.join("share").join("lua").join(version)
+}
+
+/// Where a lua_modules tree keeps native modules, like LuaRocks:
+/// `<lua_modules>/lib/lua/<version>`
+pub fn tree_lib_dir(lua_modules: &Path, version: &str) -> PathBuf {
+    lua_modules.join("lib").join("lua").join(version)
+}
+
+/// File extension Lua expects for native modules on this platform
+///
+/// Matches the `package.cpath` entries written by the lpm loader.
+pub fn native_extension() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "dll"
+    } else if cfg!(target_os = "macos") {
+        "dylib"
+    } else {
+        "so"
+    }
+}
+
+/// Whether an entry of lua_modules is part of the tree rather than a
+/// package's directory: the `.lpm` metadata, `share/` and `lib/`
+pub fn is_tree_entry(name: &str) -> bool {
+    matches!(name, ".lpm" | "share" | "lib")
+}
+
+/// The Lua versions a lua_modules tree has modules installed for, sorted
+pub fn tree_lua_versions(lua_modules: &Path) -> Vec<String> {
+    let mut versions: Vec<String> = ["share", "lib"]
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(lua_modules.join(dir).join("lua")).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    versions.sort();
+    versions.dedup();
+    versions
+}
+
 /// Get the global installation directory
 ///
 /// Platform-specific locations:
@@ -176,4 +225,16 @@ mod tests {
         assert!(dir.exists());
         assert!(dir.is_dir());
     }
+
+    #[test]
+    fn test_tree_lua_versions() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        assert!(tree_lua_versions(&lua_modules).is_empty());
+
+        fs::create_dir_all(tree_lua_dir(&lua_modules, "5.4")).unwrap();
+        fs::create_dir_all(tree_lib_dir(&lua_modules, "5.4")).unwrap();
+        fs::create_dir_all(tree_lib_dir(&lua_modules, "5.1")).unwrap();
+        assert_eq!(tree_lua_versions(&lua_modules), vec!["5.1", "5.4"]);
+    }
 }
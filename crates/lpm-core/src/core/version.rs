@@ -1,6 +1,8 @@
 use crate::core::error::{LpmError, LpmResult};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 /// Version constraint types
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,13 +19,50 @@ pub enum VersionConstraint {
     LessThan(Version),
     /// Any patch version: "1.2.x"
     AnyPatch(Version),
+    /// Greater than: ">1.2.3"
+    GreaterThan(Version),
+    /// Less than or equal: "<=2.0.0"
+    LessOrEqual(Version),
+    /// Not equal: "~=1.2.3" (LuaRocks) or "!=1.2.3"
+    NotEqual(Version),
+    /// LuaRocks pessimistic match: "~>1.2" (>=1.2 <1.3), "~>1" (>=1 <2)
+    ///
+    /// The last component written is the one allowed to grow.
+    Pessimistic(Version),
+    /// Any release: "*" or an empty constraint
+    Any,
+    /// All constraints must hold: ">=1.2, <2.0" or ">=1.2 <2.0"
+    All(Vec<VersionConstraint>),
+    /// At least one constraint must hold: "^1.0 || ^2.0"
+    AnyOf(Vec<VersionConstraint>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+/// A package version
+///
+/// Models both SemVer versions ("1.2.3", "1.0.0-rc1") and LuaRocks versions, where the
+/// upstream version is followed by a rockspec revision ("3.0-1", "1.2.3.4-2", "scm-1").
+/// The upstream version and the revision are kept separate and the original spelling
+/// is preserved, so `Version::parse(s)?.to_string() == s` for every valid `s`.
+///
+/// Ordering follows LuaRocks: numeric components first (missing components count as
+/// zero), then pre-release labels (a release sorts after its pre-releases), then the
+/// revision. Non-numeric upstream versions ("scm", "dev") sort above every release.
+#[derive(Debug, Clone)]
 pub struct Version {
     pub major: u64,
     pub minor: u64,
     pub patch: u64,
+    /// Numeric components beyond patch, e.g. `[4]` for "1.2.3.4"
+    pub extra: Vec<u64>,
+    /// Pre-release label, e.g. "rc1" for "1.0.0-rc1", or the whole upstream
+    /// version for development versions such as "scm" and "dev"
+    pub pre: Option<String>,
+    /// LuaRocks rockspec revision, e.g. `1` for "3.0-1"
+    pub revision: Option<u64>,
+    /// Number of numeric components as written ("3.0" has 2, "scm" has 0)
+    precision: u8,
+    /// Separator written before the pre-release label ('-', '.' or none as in "2.0beta")
+    pre_separator: Option<char>,
 }
 
 impl Version {
@@ -32,83 +71,594 @@ impl Version {
             major,
             minor,
             patch,
+            extra: Vec::new(),
+            pre: None,
+            revision: None,
+            precision: 3,
+            pre_separator: None,
         }
     }
 
-    /// Parse a version string (e.g., "1.2.3" or "3.0-1" from LuaRocks)
+    /// Parse a version string
+    ///
+    /// Accepts SemVer-style versions ("1.2.3", "1.0.0-rc1", "1.0.0-beta.2") and LuaRocks
+    /// versions with a rockspec revision ("3.0-1", "1.0rc1-1", "1.2.3.4-2", "scm-1").
+    /// A trailing `-N` with a purely numeric `N` is always read as the revision.
     pub fn parse(s: &str) -> LpmResult<Self> {
-        // Handle LuaRocks format: "3.0-1" -> "3.0.1"
-        let normalized = s.replace('-', ".");
-        let parts: Vec<&str> = normalized.split('.').collect();
+        let s = s.trim();
+        let invalid = || LpmError::Version(format!("Invalid version format: {}", s));
 
-        if parts.len() < 2 {
-            return Err(LpmError::Version(format!("Invalid version format: {}", s)));
+        // Split off the rockspec revision ("3.0-1" -> "3.0", 1)
+        let (upstream, revision) = match s.rsplit_once('-') {
+            Some((upstream, rev))
+                if !upstream.is_empty()
+                    && !rev.is_empty()
+                    && rev.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                let rev = rev.parse::<u64>().map_err(|_| invalid())?;
+                (upstream, Some(rev))
+            }
+            _ => (s, None),
+        };
+
+        if upstream.is_empty() {
+            return Err(invalid());
         }
 
-        let major = parts[0]
-            .parse()
-            .map_err(|_| LpmError::Version(format!("Invalid major version: {}", s)))?;
-        let minor = parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
-        let patch = parts.get(2).and_then(|s| s.parse().ok()).unwrap_or(0);
+        // Development versions: "scm", "dev", "cvs"
+        if dev_rank(upstream) > 0 {
+            return Ok(Self {
+                major: 0,
+                minor: 0,
+                patch: 0,
+                extra: Vec::new(),
+                pre: Some(upstream.to_string()),
+                revision,
+                precision: 0,
+                pre_separator: None,
+            });
+        }
 
+        // Leading numeric components ("1.2.3" in "1.2.3-rc1")
+        let numeric_len = upstream
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(upstream.len());
+        let numeric = upstream[..numeric_len].trim_end_matches('.');
+        if numeric.is_empty() {
+            return Err(invalid());
+        }
+        let mut components = Vec::new();
+        for part in numeric.split('.') {
+            components.push(part.parse::<u64>().map_err(|_| invalid())?);
+        }
+
+        // Whatever follows is the pre-release label ("-rc1", ".beta", "rc1")
+        let rest = &upstream[numeric.len()..];
+        let (pre_separator, pre) = match rest.chars().next() {
+            None => (None, None),
+            Some(sep @ ('-' | '.')) => (Some(sep), Some(&rest[1..])),
+            Some(_) => (None, Some(rest)),
+        };
+        if let Some(label) = pre {
+            if label.is_empty()
+                || !label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+            {
+                return Err(invalid());
+            }
+        }
+
+        let precision = components.len().min(u8::MAX as usize) as u8;
+        let mut components = components.into_iter();
         Ok(Self {
-            major,
-            minor,
-            patch,
+            major: components.next().unwrap_or(0),
+            minor: components.next().unwrap_or(0),
+            patch: components.next().unwrap_or(0),
+            extra: components.collect(),
+            pre: pre.map(|p| p.to_string()),
+            revision,
+            precision,
+            pre_separator,
         })
     }
 
+    /// Whether this is a development version ("scm", "dev") rather than a release
+    pub fn is_dev(&self) -> bool {
+        self.precision == 0
+    }
+
+    /// Whether this is a pre-release ("1.0.0-rc1") or development version
+    pub fn is_prerelease(&self) -> bool {
+        self.pre.is_some()
+    }
+
+    /// The upstream version without the rockspec revision ("3.0-1" -> "3.0")
+    pub fn upstream(&self) -> String {
+        if self.is_dev() {
+            return self.pre.clone().unwrap_or_default();
+        }
+
+        let mut components = vec![self.major, self.minor, self.patch];
+        components.extend(&self.extra);
+        // Never drop non-zero components, even if the version was built by hand
+        let significant = components
+            .iter()
+            .rposition(|&c| c != 0)
+            .map_or(1, |i| i + 1);
+        let shown = (self.precision as usize)
+            .max(significant)
+            .min(components.len());
+
+        let mut out = components[..shown]
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(".");
+        if let Some(ref pre) = self.pre {
+            if let Some(sep) = self.pre_separator {
+                out.push(sep);
+            }
+            out.push_str(pre);
+        }
+        out
+    }
+
+    /// The same version without a rockspec revision
+    pub fn without_revision(&self) -> Self {
+        Self {
+            revision: None,
+            ..self.clone()
+        }
+    }
+
+    /// Numeric components with trailing zeros removed, for comparison and hashing
+    fn numeric_key(&self) -> Vec<u64> {
+        let mut components = vec![self.major, self.minor, self.patch];
+        components.extend(&self.extra);
+        while components.last() == Some(&0) {
+            components.pop();
+        }
+        components
+    }
+
     /// Check if this version satisfies a constraint
+    ///
+    /// If a comparator does not mention a revision, the revision of this version is
+    /// ignored ("1.2.3" matches "1.2.3-1"). Pre-releases and development versions only
+    /// satisfy a range if one of its comparators names a pre-release of the same
+    /// version, so `^1.0.0` never selects "2.0.0-rc1" or "scm-1", while
+    /// ">=2.0.0-beta, <2.0.0" does select "2.0.0-rc1".
     pub fn satisfies(&self, constraint: &VersionConstraint) -> bool {
+        if let VersionConstraint::AnyOf(alternatives) = constraint {
+            return alternatives.iter().any(|c| self.satisfies(c));
+        }
+
+        let mut comparators = Vec::new();
+        constraint.comparators(&mut comparators);
+
+        if self.is_prerelease() {
+            let opted_in = comparators.iter().any(|c| {
+                c.version().is_some_and(|bound| {
+                    bound.is_prerelease()
+                        && bound.is_dev() == self.is_dev()
+                        && bound.numeric_key() == self.numeric_key()
+                })
+            });
+            if !opted_in {
+                return false;
+            }
+        }
+
+        comparators.iter().all(|c| self.matches_comparator(c))
+    }
+
+    /// Check a single (non-compound) comparator, without pre-release gating
+    fn matches_comparator(&self, constraint: &VersionConstraint) -> bool {
+        let Some(bound) = constraint.version() else {
+            // `Any`, or a disjunction nested inside a conjunction
+            return match constraint {
+                VersionConstraint::AnyOf(_) => self.satisfies(constraint),
+                _ => true,
+            };
+        };
+        let this = if bound.revision.is_none() {
+            self.without_revision()
+        } else {
+            self.clone()
+        };
+
+        let numeric = this.numeric_key();
+        let numeric_at = |i: usize| numeric.get(i).copied().unwrap_or(0);
         match constraint {
-            VersionConstraint::Exact(v) => self == v,
+            VersionConstraint::Exact(v) => this == *v,
+            VersionConstraint::NotEqual(v) => this != *v,
             VersionConstraint::Compatible(v) => {
-                self >= v
-                    && self.major == v.major
-                    && (self.major, self.minor, self.patch) < (v.major + 1, 0, 0)
+                this >= *v && numeric_at(0) == v.major && !this.is_dev()
             }
             VersionConstraint::Patch(v) => {
-                self >= v
-                    && (self.major, self.minor) == (v.major, v.minor)
-                    && (self.major, self.minor, self.patch) < (v.major, v.minor + 1, 0)
+                this >= *v && (numeric_at(0), numeric_at(1)) == (v.major, v.minor) && !this.is_dev()
+            }
+            VersionConstraint::GreaterOrEqual(v) => this >= *v,
+            VersionConstraint::GreaterThan(v) => this > *v,
+            VersionConstraint::LessThan(v) => this < *v,
+            VersionConstraint::LessOrEqual(v) => this <= *v,
+            VersionConstraint::AnyPatch(v) => {
+                (numeric_at(0), numeric_at(1)) == (v.major, v.minor) && !this.is_dev()
             }
-            VersionConstraint::GreaterOrEqual(v) => self >= v,
-            VersionConstraint::LessThan(v) => self < v,
-            VersionConstraint::AnyPatch(v) => self.major == v.major && self.minor == v.minor,
+            VersionConstraint::Pessimistic(v) => {
+                if v.is_dev() {
+                    return this == *v;
+                }
+                this >= *v && this.without_pre() < v.pessimistic_upper_bound()
+            }
+            VersionConstraint::Any | VersionConstraint::All(_) | VersionConstraint::AnyOf(_) => {
+                unreachable!("compound constraints have no single bound")
+            }
+        }
+    }
+
+    /// The same version without its pre-release label
+    fn without_pre(&self) -> Self {
+        if self.is_dev() {
+            return self.clone();
+        }
+        Self {
+            pre: None,
+            pre_separator: None,
+            ..self.clone()
+        }
+    }
+
+    /// Exclusive upper bound of `~> self`: the last written component is bumped
+    /// and everything after it dropped ("1.2" -> "1.3", "1.2.3" -> "1.2.4", "1" -> "2")
+    fn pessimistic_upper_bound(&self) -> Self {
+        let mut components = vec![self.major, self.minor, self.patch];
+        components.extend(&self.extra);
+        let len = (self.precision as usize).clamp(1, components.len());
+        components.truncate(len);
+        if let Some(last) = components.last_mut() {
+            *last += 1;
+        }
+        let mut components = components.into_iter();
+        Self {
+            major: components.next().unwrap_or(0),
+            minor: components.next().unwrap_or(0),
+            patch: components.next().unwrap_or(0),
+            extra: components.collect(),
+            pre: None,
+            revision: None,
+            precision: len as u8,
+            pre_separator: None,
         }
     }
 }
 
+/// Rank of well-known development labels (LuaRocks: dev > scm > cvs)
+fn dev_rank(label: &str) -> u8 {
+    match label {
+        "dev" => 3,
+        "scm" => 2,
+        "cvs" => 1,
+        _ => 0,
+    }
+}
+
+/// Rank of well-known pre-release words (alpha < beta < pre < rc)
+fn pre_word_rank(word: &str) -> u8 {
+    match word.to_ascii_lowercase().as_str() {
+        "alpha" | "a" => 1,
+        "beta" | "b" => 2,
+        "pre" => 3,
+        "rc" => 4,
+        _ => 0,
+    }
+}
+
+/// Split a pre-release label into numeric and word chunks at `.`, `-` and
+/// digit boundaries: "rc.10" and "rc10" both give `[Err("rc"), Ok(10)]`
+fn pre_chunks(label: &str) -> Vec<Result<u64, String>> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut digits = false;
+    let flush = |out: &mut Vec<Result<u64, String>>, current: &mut String, digits: bool| {
+        if !current.is_empty() {
+            out.push(if digits {
+                Ok(current.parse().unwrap_or(u64::MAX))
+            } else {
+                Err(std::mem::take(current))
+            });
+            current.clear();
+        }
+    };
+    for c in label.chars() {
+        if c == '.' || c == '-' {
+            flush(&mut out, &mut current, digits);
+            continue;
+        }
+        if !current.is_empty() && c.is_ascii_digit() != digits {
+            flush(&mut out, &mut current, digits);
+        }
+        digits = c.is_ascii_digit();
+        current.push(c);
+    }
+    flush(&mut out, &mut current, digits);
+    out
+}
+
+/// Compare pre-release labels chunk by chunk ("rc1" < "rc2" < "rc10", "alpha" < "beta")
+fn compare_pre(a: &str, b: &str) -> Ordering {
+    let (a, b) = (pre_chunks(a), pre_chunks(b));
+    for (x, y) in a.iter().zip(b.iter()) {
+        let ord = match (x, y) {
+            (Ok(x), Ok(y)) => x.cmp(y),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(x), Err(y)) => pre_word_rank(x)
+                .cmp(&pre_word_rank(y))
+                .then_with(|| x.to_ascii_lowercase().cmp(&y.to_ascii_lowercase())),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let upstream = match (self.is_dev(), other.is_dev()) {
+            (true, true) => {
+                let (a, b) = (
+                    self.pre.as_deref().unwrap_or(""),
+                    other.pre.as_deref().unwrap_or(""),
+                );
+                dev_rank(a).cmp(&dev_rank(b)).then_with(|| a.cmp(b))
+            }
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => {
+                let (a, b) = (self.numeric_key(), other.numeric_key());
+                let len = a.len().max(b.len());
+                let numeric = (0..len)
+                    .map(|i| {
+                        let x = a.get(i).copied().unwrap_or(0);
+                        let y = b.get(i).copied().unwrap_or(0);
+                        x.cmp(&y)
+                    })
+                    .find(|o| *o != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal);
+                numeric.then_with(|| match (&self.pre, &other.pre) {
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) => Ordering::Greater,
+                    (Some(_), None) => Ordering::Less,
+                    (Some(a), Some(b)) => compare_pre(a, b),
+                })
+            }
+        };
+        upstream.then_with(|| self.revision.unwrap_or(0).cmp(&other.revision.unwrap_or(0)))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.is_dev().hash(state);
+        self.numeric_key().hash(state);
+        // The chunks compare_pre sees, so that "rc.1", "rc1" and "RC01" hash alike
+        self.pre
+            .as_deref()
+            .map(|pre| {
+                pre_chunks(pre)
+                    .into_iter()
+                    .map(|chunk| chunk.map_err(|word| word.to_ascii_lowercase()))
+                    .collect::<Vec<_>>()
+            })
+            .hash(state);
+        self.revision.unwrap_or(0).hash(state);
+    }
+}
+
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+        write!(f, "{}", self.upstream())?;
+        if let Some(revision) = self.revision {
+            write!(f, "-{}", revision)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Version::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl VersionConstraint {
+    /// The version a single comparator is anchored on (`None` for compound constraints)
+    pub fn version(&self) -> Option<&Version> {
+        match self {
+            VersionConstraint::Exact(v)
+            | VersionConstraint::Compatible(v)
+            | VersionConstraint::Patch(v)
+            | VersionConstraint::GreaterOrEqual(v)
+            | VersionConstraint::LessThan(v)
+            | VersionConstraint::AnyPatch(v)
+            | VersionConstraint::GreaterThan(v)
+            | VersionConstraint::LessOrEqual(v)
+            | VersionConstraint::NotEqual(v)
+            | VersionConstraint::Pessimistic(v) => Some(v),
+            VersionConstraint::Any | VersionConstraint::All(_) | VersionConstraint::AnyOf(_) => {
+                None
+            }
+        }
+    }
+
+    /// Flatten nested conjunctions into their comparators
+    fn comparators<'a>(&'a self, out: &mut Vec<&'a VersionConstraint>) {
+        match self {
+            VersionConstraint::All(parts) => {
+                for part in parts {
+                    part.comparators(out);
+                }
+            }
+            other => out.push(other),
+        }
+    }
+}
+
+impl fmt::Display for VersionConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionConstraint::Exact(v) => write!(f, "{}", v),
+            VersionConstraint::Compatible(v) => write!(f, "^{}", v),
+            VersionConstraint::Patch(v) => write!(f, "~{}", v),
+            VersionConstraint::GreaterOrEqual(v) => write!(f, ">={}", v),
+            VersionConstraint::LessThan(v) => write!(f, "<{}", v),
+            VersionConstraint::AnyPatch(v) => write!(f, "{}.{}.x", v.major, v.minor),
+            VersionConstraint::GreaterThan(v) => write!(f, ">{}", v),
+            VersionConstraint::LessOrEqual(v) => write!(f, "<={}", v),
+            VersionConstraint::NotEqual(v) => write!(f, "~={}", v),
+            VersionConstraint::Pessimistic(v) => write!(f, "~>{}", v),
+            VersionConstraint::Any => write!(f, "*"),
+            VersionConstraint::All(parts) => {
+                let parts: Vec<String> = parts
+                    .iter()
+                    .map(|p| match p {
+                        VersionConstraint::AnyOf(_) => format!("({})", p),
+                        _ => p.to_string(),
+                    })
+                    .collect();
+                write!(f, "{}", parts.join(", "))
+            }
+            VersionConstraint::AnyOf(alternatives) => {
+                let alternatives: Vec<String> =
+                    alternatives.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}", alternatives.join(" || "))
+            }
+        }
     }
 }
 
 /// Parse a version constraint string
+///
+/// Besides the single-operator forms ("^1.2.3", "~1.2.3", ">=1.2.3", "<2.0.0",
+/// "1.2.x", "1.2.3"), this accepts every LuaRocks operator (`==`, `~=`, `>=`, `>`,
+/// `<=`, `<`, `~>`), "*", conjunctions separated by commas or whitespace
+/// (">= 1.6, < 2" or ">=1.6 <2") and disjunctions separated by `||`.
 pub fn parse_constraint(s: &str) -> LpmResult<VersionConstraint> {
     let s = s.trim();
 
-    if let Some(rest) = s.strip_prefix('^') {
-        let version = Version::parse(rest)?;
-        Ok(VersionConstraint::Compatible(version))
-    } else if let Some(rest) = s.strip_prefix('~') {
-        let version = Version::parse(rest)?;
-        Ok(VersionConstraint::Patch(version))
-    } else if let Some(rest) = s.strip_prefix(">=") {
-        let version = Version::parse(rest)?;
-        Ok(VersionConstraint::GreaterOrEqual(version))
-    } else if let Some(rest) = s.strip_prefix('<') {
-        let version = Version::parse(rest)?;
-        Ok(VersionConstraint::LessThan(version))
-    } else if let Some(base) = s.strip_suffix(".x") {
+    if s.contains("||") {
+        let alternatives = s
+            .split("||")
+            .map(parse_conjunction)
+            .collect::<LpmResult<Vec<_>>>()?;
+        return Ok(VersionConstraint::AnyOf(alternatives));
+    }
+
+    parse_conjunction(s)
+}
+
+/// Parse comma- or whitespace-separated comparators that must all hold
+fn parse_conjunction(s: &str) -> LpmResult<VersionConstraint> {
+    let mut comparators = Vec::new();
+    for part in s.split(',') {
+        // Glue operators written apart from their version (">= 1.6") back together
+        let mut pending_operator: Option<&str> = None;
+        for token in part.split_whitespace() {
+            if token.chars().all(|c| "<>=~!^".contains(c)) {
+                if pending_operator.is_some() {
+                    return Err(LpmError::Version(format!(
+                        "Invalid version constraint: {}",
+                        s
+                    )));
+                }
+                pending_operator = Some(token);
+                continue;
+            }
+            let comparator = match pending_operator.take() {
+                Some(op) => parse_comparator(&format!("{}{}", op, token))?,
+                None => parse_comparator(token)?,
+            };
+            comparators.push(comparator);
+        }
+        if let Some(op) = pending_operator {
+            return Err(LpmError::Version(format!(
+                "Invalid version constraint: missing version after '{}' in {}",
+                op, s
+            )));
+        }
+    }
+
+    // "*" alongside other comparators adds nothing
+    if comparators.len() > 1 {
+        comparators.retain(|c| *c != VersionConstraint::Any);
+    }
+
+    match comparators.len() {
+        0 => Ok(VersionConstraint::Any),
+        1 => Ok(comparators.remove(0)),
+        _ => Ok(VersionConstraint::All(comparators)),
+    }
+}
+
+/// Parse a single operator and version, e.g. ">=1.2.3" or "~>1.2"
+fn parse_comparator(s: &str) -> LpmResult<VersionConstraint> {
+    if s == "*" || s == "x" {
+        return Ok(VersionConstraint::Any);
+    }
+
+    // Longer operators first so ">=" is not read as ">" followed by "=1.2"
+    type Constructor = fn(Version) -> VersionConstraint;
+    let operators: [(&str, Constructor); 11] = [
+        ("~>", VersionConstraint::Pessimistic),
+        ("~=", VersionConstraint::NotEqual),
+        ("!=", VersionConstraint::NotEqual),
+        ("==", VersionConstraint::Exact),
+        (">=", VersionConstraint::GreaterOrEqual),
+        ("<=", VersionConstraint::LessOrEqual),
+        ("^", VersionConstraint::Compatible),
+        ("~", VersionConstraint::Patch),
+        (">", VersionConstraint::GreaterThan),
+        ("<", VersionConstraint::LessThan),
+        ("=", VersionConstraint::Exact),
+    ];
+    for (operator, constructor) in operators {
+        if let Some(rest) = s.strip_prefix(operator) {
+            return Ok(constructor(Version::parse(rest)?));
+        }
+    }
+
+    if let Some(base) = s.strip_suffix(".x").or_else(|| s.strip_suffix(".*")) {
         let version = Version::parse(base)?;
-        Ok(VersionConstraint::AnyPatch(version))
-    } else {
-        // Exact version
-        let version = Version::parse(s)?;
-        Ok(VersionConstraint::Exact(version))
+        return Ok(VersionConstraint::AnyPatch(version));
     }
+
+    // Exact version
+    let version = Version::parse(s)?;
+    Ok(VersionConstraint::Exact(version))
 }
 
 #[cfg(test)]
@@ -128,7 +678,139 @@ mod tests {
         let v = Version::parse("3.0-1").unwrap();
         assert_eq!(v.major, 3);
         assert_eq!(v.minor, 0);
-        assert_eq!(v.patch, 1);
+        assert_eq!(v.patch, 0);
+        assert_eq!(v.revision, Some(1));
+        assert_eq!(v.upstream(), "3.0");
+    }
+
+    #[test]
+    fn test_version_display_round_trips() {
+        for input in [
+            "1.2.3",
+            "3.0-1",
+            "1.0.0-rc1",
+            "1.0.0-beta.2",
+            "1.0rc1-1",
+            "2.0beta-1",
+            "1.2.3.4-2",
+            "scm-1",
+            "dev-1",
+            "5",
+        ] {
+            assert_eq!(Version::parse(input).unwrap().to_string(), input);
+        }
+        assert_eq!(Version::new(1, 2, 3).to_string(), "1.2.3");
+    }
+
+    #[test]
+    fn test_version_parse_rejects_garbage() {
+        for input in ["", "-1", "1..2", "v1.2.3", "1.2.3-rc!", "latest"] {
+            assert!(Version::parse(input).is_err(), "{input} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        let ordered = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.2",
+            "1.0.0-beta",
+            "1.0.0-rc1",
+            "1.0.0-rc2",
+            "1.0.0-rc10",
+            "1.0.0",
+            "1.0.0-1",
+            "1.0.0-2",
+            "1.0.1",
+            "1.0.1.1",
+            "1.10",
+            "cvs-1",
+            "scm-1",
+            "dev-1",
+        ];
+        for pair in ordered.windows(2) {
+            let (a, b) = (
+                Version::parse(pair[0]).unwrap(),
+                Version::parse(pair[1]).unwrap(),
+            );
+            assert!(a < b, "{} should sort before {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn test_version_equality_ignores_spelling() {
+        assert_eq!(
+            Version::parse("3.0").unwrap(),
+            Version::parse("3.0.0").unwrap()
+        );
+        assert_eq!(
+            Version::parse("3.0-0").unwrap(),
+            Version::parse("3.0").unwrap()
+        );
+        assert_ne!(
+            Version::parse("3.0-1").unwrap(),
+            Version::parse("3.0.1").unwrap()
+        );
+
+        let mut set = std::collections::HashSet::new();
+        set.insert(Version::parse("3.0").unwrap());
+        assert!(set.contains(&Version::parse("3.0.0").unwrap()));
+    }
+
+    #[test]
+    fn test_equal_versions_hash_equally() {
+        fn hash(v: &Version) -> u64 {
+            use std::collections::hash_map::DefaultHasher;
+            let mut hasher = DefaultHasher::new();
+            v.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        for (a, b) in [
+            ("1.0-rc.1", "1.0-rc1"),
+            ("1.0rc01", "1.0rc1"),
+            ("1.0RC1", "1.0rc1"),
+            ("2.0.0-beta.2", "2.0-beta2"),
+        ] {
+            let (a, b) = (Version::parse(a).unwrap(), Version::parse(b).unwrap());
+            assert_eq!(a, b);
+            assert_eq!(hash(&a), hash(&b), "{} and {}", a, b);
+        }
+
+        let mut set = std::collections::HashSet::new();
+        set.insert(Version::parse("1.0-rc.1").unwrap());
+        assert!(set.contains(&Version::parse("1.0-rc1").unwrap()));
+    }
+
+    #[test]
+    fn test_version_satisfies_ignores_revision_when_unspecified() {
+        let v = Version::parse("1.2.3-2").unwrap();
+        assert!(v.satisfies(&parse_constraint("1.2.3").unwrap()));
+        assert!(v.satisfies(&parse_constraint("^1.2.0").unwrap()));
+        assert!(!v.satisfies(&parse_constraint("1.2.3-1").unwrap()));
+        assert!(v.satisfies(&parse_constraint(">=1.2.3-2").unwrap()));
+    }
+
+    #[test]
+    fn test_prerelease_only_matches_explicit_constraints() {
+        let rc = Version::parse("2.0.0-rc1").unwrap();
+        assert!(!rc.satisfies(&parse_constraint(">=1.0.0").unwrap()));
+        assert!(!rc.satisfies(&parse_constraint("<2.0.0").unwrap()));
+        assert!(rc.satisfies(&parse_constraint(">=2.0.0-beta").unwrap()));
+        assert!(rc.satisfies(&parse_constraint("2.0.0-rc1").unwrap()));
+
+        let scm = Version::parse("scm-1").unwrap();
+        assert!(!scm.satisfies(&parse_constraint(">=1.0.0").unwrap()));
+        assert!(scm.satisfies(&parse_constraint("scm").unwrap()));
+    }
+
+    #[test]
+    fn test_version_serde_as_string() {
+        let v = Version::parse("1.0rc1-1").unwrap();
+        let yaml = serde_yaml::to_string(&v).unwrap();
+        assert_eq!(yaml.trim(), "1.0rc1-1");
+        let back: Version = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(back.to_string(), "1.0rc1-1");
     }
 
     #[test]
@@ -169,4 +851,117 @@ mod tests {
             VersionConstraint::Exact(_)
         ));
     }
+
+    #[test]
+    fn test_constraint_display_round_trips() {
+        for input in [
+            "^1.2.3",
+            "~1.2.0",
+            ">=1.0.0",
+            "<2.0.0",
+            "1.2.x",
+            "1.2.3",
+            ">1.0",
+            "<=2.0",
+            "~=1.4",
+            "~>1.2",
+            "*",
+            ">=1.2, <2.0",
+            "^1.0.0 || ^2.0.0",
+            ">=1.0, <3.0 || 5.0-1",
+        ] {
+            let constraint = parse_constraint(input).unwrap();
+            assert_eq!(constraint.to_string(), input);
+            assert_eq!(
+                parse_constraint(&constraint.to_string()).unwrap(),
+                constraint
+            );
+        }
+    }
+
+    fn satisfies(version: &str, constraint: &str) -> bool {
+        Version::parse(version)
+            .unwrap()
+            .satisfies(&parse_constraint(constraint).unwrap())
+    }
+
+    #[test]
+    fn test_parse_luarocks_operators() {
+        assert_eq!(
+            parse_constraint("== 1.2").unwrap(),
+            VersionConstraint::Exact(Version::parse("1.2").unwrap())
+        );
+        assert!(matches!(
+            parse_constraint("~= 1.4").unwrap(),
+            VersionConstraint::NotEqual(_)
+        ));
+        assert!(matches!(
+            parse_constraint("> 1.4").unwrap(),
+            VersionConstraint::GreaterThan(_)
+        ));
+        assert!(matches!(
+            parse_constraint("<= 1.4").unwrap(),
+            VersionConstraint::LessOrEqual(_)
+        ));
+        assert!(matches!(
+            parse_constraint("~> 1.4").unwrap(),
+            VersionConstraint::Pessimistic(_)
+        ));
+        assert_eq!(parse_constraint("*").unwrap(), VersionConstraint::Any);
+        assert_eq!(parse_constraint("").unwrap(), VersionConstraint::Any);
+    }
+
+    #[test]
+    fn test_parse_constraint_rejects_dangling_operator() {
+        assert!(parse_constraint(">=").is_err());
+        assert!(parse_constraint(">= 1.0, <").is_err());
+        assert!(parse_constraint(">= < 1.0").is_err());
+    }
+
+    #[test]
+    fn test_conjunction() {
+        for constraint in [">= 1.6, < 2", ">=1.6 <2", ">= 1.6 < 2"] {
+            assert!(satisfies("1.6.0", constraint));
+            assert!(satisfies("1.8.1-1", constraint));
+            assert!(!satisfies("2.0.0", constraint));
+            assert!(!satisfies("1.5.9", constraint));
+        }
+    }
+
+    #[test]
+    fn test_disjunction() {
+        assert!(satisfies("1.4.0", "^1.0.0 || ^3.0.0"));
+        assert!(satisfies("3.1.0", "^1.0.0 || ^3.0.0"));
+        assert!(!satisfies("2.0.0", "^1.0.0 || ^3.0.0"));
+    }
+
+    #[test]
+    fn test_pessimistic_follows_luarocks() {
+        assert!(satisfies("1.2", "~> 1.2"));
+        assert!(satisfies("1.2.9", "~> 1.2"));
+        assert!(!satisfies("1.3", "~> 1.2"));
+        assert!(satisfies("1.9", "~> 1"));
+        assert!(!satisfies("2.0", "~> 1"));
+        assert!(satisfies("1.2.3-2", "~> 1.2.3"));
+        assert!(!satisfies("1.2.4", "~> 1.2.3"));
+        assert!(!satisfies("1.1", "~> 1.2"));
+    }
+
+    #[test]
+    fn test_not_equal_and_strict_bounds() {
+        assert!(!satisfies("1.4-1", "~= 1.4"));
+        assert!(satisfies("1.4.1", "~= 1.4"));
+        assert!(satisfies("1.4.1", "> 1.4"));
+        assert!(!satisfies("1.4", "> 1.4"));
+        assert!(satisfies("1.4-3", "<= 1.4"));
+        assert!(!satisfies("1.4.1", "<= 1.4"));
+    }
+
+    #[test]
+    fn test_prerelease_opt_in_applies_to_whole_range() {
+        assert!(satisfies("2.0.0-rc1", ">=2.0.0-beta, <2.0.0"));
+        assert!(!satisfies("2.0.0-rc1", ">=1.0.0, <3.0.0"));
+        assert!(!satisfies("scm-1", "*"));
+        assert!(satisfies("1.0.0", "*"));
+    }
 }
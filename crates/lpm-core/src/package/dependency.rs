@@ -0,0 +1,310 @@
+use serde::de::{self, Deserializer};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Where a dependency in package.yaml comes from
+///
+/// Dependencies are stored as strings in `PackageManifest`: a version
+/// constraint (`"^1.2.0"`), a local path (`"path:../mylib"`), or a git
+/// repository (`"git+https://example.com/mylib.git#tag=v1.2"`). In package.yaml,
+/// git dependencies are written as tables:
+///
+/// ```yaml
+/// dependencies:
+///   mylib: { git: "https://example.com/mylib.git", tag: "v1.2" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencySpec {
+    /// A version constraint resolved against the registry
+    Registry(String),
+    /// A package in a local directory
+    Path(String),
+    /// A package in a git repository
+    Git(GitSpec),
+}
+
+/// A git repository and the reference to check out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSpec {
+    pub url: String,
+    pub reference: GitReference,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitReference {
+    Tag(String),
+    Branch(String),
+    Rev(String),
+    /// The repository's default branch
+    DefaultBranch,
+}
+
+impl DependencySpec {
+    /// Interpret a dependency string from `PackageManifest`
+    pub fn parse(spec: &str) -> Self {
+        if let Some(path) = spec.strip_prefix("path:") {
+            return DependencySpec::Path(path.to_string());
+        }
+        if let Some(rest) = spec.strip_prefix("git+") {
+            let (url, fragment) = match rest.split_once('#') {
+                Some((url, fragment)) => (url, Some(fragment)),
+                None => (rest, None),
+            };
+            let reference = match fragment.and_then(|f| f.split_once('=')) {
+                Some(("tag", tag)) => GitReference::Tag(tag.to_string()),
+                Some(("branch", branch)) => GitReference::Branch(branch.to_string()),
+                Some(("rev", rev)) => GitReference::Rev(rev.to_string()),
+                _ => GitReference::DefaultBranch,
+            };
+            return DependencySpec::Git(GitSpec {
+                url: url.to_string(),
+                reference,
+            });
+        }
+        DependencySpec::Registry(spec.to_string())
+    }
+
+    /// The version constraint, for registry dependencies
+    pub fn as_registry(&self) -> Option<&str> {
+        match self {
+            DependencySpec::Registry(constraint) => Some(constraint),
+            _ => None,
+        }
+    }
+
+    pub fn is_registry(&self) -> bool {
+        matches!(self, DependencySpec::Registry(_))
+    }
+}
+
+impl fmt::Display for DependencySpec {
+    /// The string form stored in `PackageManifest`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DependencySpec::Registry(constraint) => write!(f, "{}", constraint),
+            DependencySpec::Path(path) => write!(f, "path:{}", path),
+            DependencySpec::Git(git) => {
+                write!(f, "git+{}", git.url)?;
+                match &git.reference {
+                    GitReference::DefaultBranch => Ok(()),
+                    reference => write!(f, "#{}", reference),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for GitReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitReference::Tag(tag) => write!(f, "tag={}", tag),
+            GitReference::Branch(branch) => write!(f, "branch={}", branch),
+            GitReference::Rev(rev) => write!(f, "rev={}", rev),
+            GitReference::DefaultBranch => write!(f, "HEAD"),
+        }
+    }
+}
+
+/// A dependency as written in package.yaml: a string or a table
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum RawDependency {
+    Spec(String),
+    Table(DependencyTable),
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct DependencyTable {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rev: Option<String>,
+}
+
+impl DependencyTable {
+    fn into_spec(self) -> Result<DependencySpec, String> {
+        if let Some(url) = self.git {
+            let reference = match (self.tag, self.branch, self.rev) {
+                (None, None, None) => GitReference::DefaultBranch,
+                (Some(tag), None, None) => GitReference::Tag(tag),
+                (None, Some(branch), None) => GitReference::Branch(branch),
+                (None, None, Some(rev)) => GitReference::Rev(rev),
+                _ => return Err("use only one of 'tag', 'branch' or 'rev'".to_string()),
+            };
+            let url = url.strip_prefix("git+").unwrap_or(&url).to_string();
+            check_git_url(&url)?;
+            return Ok(DependencySpec::Git(GitSpec { url, reference }));
+        }
+        if self.tag.is_some() || self.branch.is_some() || self.rev.is_some() {
+            return Err("'tag', 'branch' and 'rev' require 'git'".to_string());
+        }
+        match (self.path, self.version) {
+            (Some(path), None) => Ok(DependencySpec::Path(path)),
+            (None, Some(version)) => Ok(DependencySpec::Registry(version)),
+            _ => Err("expected exactly one of 'version', 'path' or 'git'".to_string()),
+        }
+    }
+
+    fn from_spec(spec: &DependencySpec) -> Self {
+        match spec {
+            DependencySpec::Registry(version) => Self {
+                version: Some(version.clone()),
+                ..Self::default()
+            },
+            DependencySpec::Path(path) => Self {
+                path: Some(path.clone()),
+                ..Self::default()
+            },
+            DependencySpec::Git(git) => {
+                let mut table = Self {
+                    git: Some(git.url.clone()),
+                    ..Self::default()
+                };
+                match &git.reference {
+                    GitReference::Tag(tag) => table.tag = Some(tag.clone()),
+                    GitReference::Branch(branch) => table.branch = Some(branch.clone()),
+                    GitReference::Rev(rev) => table.rev = Some(rev.clone()),
+                    GitReference::DefaultBranch => {}
+                }
+                table
+            }
+        }
+    }
+}
+
+/// Reject URLs git would read as an option, like `--upload-pack=...`
+fn check_git_url(url: &str) -> Result<(), String> {
+    if url.starts_with('-') {
+        return Err(format!("git URL '{}' must not start with '-'", url));
+    }
+    Ok(())
+}
+
+/// Deserialize a dependency map whose values are strings or tables
+pub(crate) fn deserialize_dependencies<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw: Option<HashMap<String, RawDependency>> = Option::deserialize(deserializer)?;
+    raw.unwrap_or_default()
+        .into_iter()
+        .map(|(name, dependency)| {
+            let spec = match dependency {
+                RawDependency::Spec(spec) => {
+                    if let DependencySpec::Git(git) = DependencySpec::parse(&spec) {
+                        check_git_url(&git.url).map_err(|e| {
+                            de::Error::custom(format!("dependency '{}': {}", name, e))
+                        })?;
+                    }
+                    spec
+                }
+                RawDependency::Table(table) => table
+                    .into_spec()
+                    .map_err(|e| de::Error::custom(format!("dependency '{}': {}", name, e)))?
+                    .to_string(),
+            };
+            Ok((name, spec))
+        })
+        .collect()
+}
+
+/// Serialize a dependency map, writing git dependencies back as tables
+pub(crate) fn serialize_dependencies<S>(
+    dependencies: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let sorted: BTreeMap<_, _> = dependencies.iter().collect();
+    let mut map = serializer.serialize_map(Some(sorted.len()))?;
+    for (name, spec) in sorted {
+        match DependencySpec::parse(spec) {
+            git @ DependencySpec::Git(_) => {
+                map.serialize_entry(name, &DependencyTable::from_spec(&git))?
+            }
+            _ => map.serialize_entry(name, spec)?,
+        }
+    }
+    map.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_specs() {
+        assert_eq!(
+            DependencySpec::parse("^1.2.0"),
+            DependencySpec::Registry("^1.2.0".to_string())
+        );
+        assert_eq!(
+            DependencySpec::parse("path:../mylib"),
+            DependencySpec::Path("../mylib".to_string())
+        );
+        assert_eq!(
+            DependencySpec::parse("git+https://example.com/mylib.git#tag=v1.2"),
+            DependencySpec::Git(GitSpec {
+                url: "https://example.com/mylib.git".to_string(),
+                reference: GitReference::Tag("v1.2".to_string()),
+            })
+        );
+        assert_eq!(
+            DependencySpec::parse("git+/srv/repos/mylib.git"),
+            DependencySpec::Git(GitSpec {
+                url: "/srv/repos/mylib.git".to_string(),
+                reference: GitReference::DefaultBranch,
+            })
+        );
+    }
+
+    #[test]
+    fn test_spec_round_trips_through_string() {
+        for spec in [
+            "~> 3.0",
+            "path:./local",
+            "git+https://example.com/a.git#branch=main",
+            "git+https://example.com/a.git#rev=0123abc",
+            "git+https://example.com/a.git",
+        ] {
+            assert_eq!(DependencySpec::parse(spec).to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn test_table_validation() {
+        let table = DependencyTable {
+            git: Some("https://example.com/a.git".to_string()),
+            tag: Some("v1".to_string()),
+            branch: Some("main".to_string()),
+            ..DependencyTable::default()
+        };
+        assert!(table.into_spec().is_err());
+
+        let table = DependencyTable {
+            tag: Some("v1".to_string()),
+            ..DependencyTable::default()
+        };
+        assert!(table.into_spec().is_err());
+
+        let table = DependencyTable {
+            git: Some("--upload-pack=touch /tmp/pwned".to_string()),
+            ..DependencyTable::default()
+        };
+        assert!(table.into_spec().is_err());
+    }
+}
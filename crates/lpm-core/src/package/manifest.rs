@@ -1,4 +1,5 @@
 use crate::core::{LpmError, LpmResult};
+use crate::package::dependency::{deserialize_dependencies, serialize_dependencies};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
@@ -15,9 +16,18 @@ pub struct PackageManifest {
     pub license: Option<String>,
     #[serde(default = "default_lua_version")]
     pub lua_version: String,
-    #[serde(default)]
+    /// Name → version constraint, `path:` or `git+` spec (see `DependencySpec`)
+    #[serde(
+        default,
+        deserialize_with = "deserialize_dependencies",
+        serialize_with = "serialize_dependencies"
+    )]
     pub dependencies: std::collections::HashMap<String, String>,
-    #[serde(default)]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_dependencies",
+        serialize_with = "serialize_dependencies"
+    )]
     pub dev_dependencies: std::collections::HashMap<String, String>,
     #[serde(default)]
     pub scripts: std::collections::HashMap<String, String>,
@@ -209,4 +219,74 @@ dependencies:
 
         assert!(temp.path().join("package.yaml").exists());
     }
+
+    #[test]
+    fn test_git_dependency_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let manifest_content = r#"
+name: app
+version: 1.0.0
+dependencies:
+  penlight: "^1.13.0"
+  mylib: { git: "https://example.com/mylib.git", tag: "v1.2" }
+dev_dependencies:
+  helpers:
+    git: "git+https://example.com/helpers.git"
+    branch: main
+"#;
+        fs::write(temp.path().join("package.yaml"), manifest_content).unwrap();
+
+        let manifest = PackageManifest::load(temp.path()).unwrap();
+        assert_eq!(
+            manifest.dependencies["mylib"],
+            "git+https://example.com/mylib.git#tag=v1.2"
+        );
+        assert_eq!(
+            manifest.dev_dependencies["helpers"],
+            "git+https://example.com/helpers.git#branch=main"
+        );
+
+        manifest.save(temp.path()).unwrap();
+        let saved = fs::read_to_string(temp.path().join("package.yaml")).unwrap();
+        assert!(
+            saved.contains("git: https://example.com/mylib.git"),
+            "{saved}"
+        );
+        assert!(saved.contains("tag: v1.2"), "{saved}");
+        let reloaded = PackageManifest::load(temp.path()).unwrap();
+        assert_eq!(reloaded.dependencies, manifest.dependencies);
+    }
+
+    #[test]
+    fn test_invalid_dependency_table() {
+        let temp = TempDir::new().unwrap();
+        let manifest_content = r#"
+name: app
+version: 1.0.0
+dependencies:
+  mylib: { git: "https://example.com/mylib.git", tag: "v1", rev: "abc" }
+"#;
+        fs::write(temp.path().join("package.yaml"), manifest_content).unwrap();
+
+        let err = PackageManifest::load(temp.path()).unwrap_err().to_string();
+        assert!(
+            err.contains("only one of 'tag', 'branch' or 'rev'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_git_url_must_not_be_an_option() {
+        let temp = TempDir::new().unwrap();
+        let manifest_content = r#"
+name: app
+version: 1.0.0
+dependencies:
+  mylib: "git+--upload-pack=touch /tmp/pwned"
+"#;
+        fs::write(temp.path().join("package.yaml"), manifest_content).unwrap();
+
+        let err = PackageManifest::load(temp.path()).unwrap_err().to_string();
+        assert!(err.contains("must not start with '-'"), "{err}");
+    }
 }
@@ -1,3 +1,5 @@
+pub mod dependency;
 pub mod manifest;
 
+pub use dependency::{DependencySpec, GitReference, GitSpec};
 pub use manifest::PackageManifest;
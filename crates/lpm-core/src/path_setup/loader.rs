@@ -1,7 +1,10 @@
-use crate::core::path::lua_modules_dir;
+use crate::core::path::{
+    lua_modules_dir, native_extension, tree_lib_dir, tree_lua_dir, DEFAULT_LUA_VERSION,
+};
 use crate::core::LpmResult;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
 /// Generates the lpm.loader Lua module that sets up package.path and package.cpath
 pub struct PathSetup;
@@ -10,23 +13,12 @@ impl PathSetup {
     /// Generate the lpm.loader module content
     ///
     /// This generates version-aware loader code that works with both
-    /// Lua 5.1 (package.loaders) and Lua 5.2+ (package.searchers)
+    /// Lua 5.1 (package.loaders) and Lua 5.2+ (package.searchers).
+    /// Installed modules are found in the LuaRocks-style tree for the running
+    /// Lua's version; `lua_modules/` itself holds linked local packages and
+    /// the loader.
     pub fn generate_loader(project_root: &Path) -> String {
         let lua_modules = lua_modules_dir(project_root);
-        let lua_modules_str = lua_modules.to_string_lossy();
-
-        // Generate platform-specific cpath extension
-        let cpath_extension = if cfg!(target_os = "windows") {
-            format!("{}/?.dll;{}/?/init.dll", lua_modules_str, lua_modules_str)
-        } else if cfg!(target_os = "macos") {
-            format!(
-                "{}/?.dylib;{}/?/init.dylib",
-                lua_modules_str, lua_modules_str
-            )
-        } else {
-            // Linux and other Unix-like systems
-            format!("{}/?.so;{}/?/init.so", lua_modules_str, lua_modules_str)
-        };
 
         format!(
             r#"-- LPM Loader Module
@@ -34,15 +26,21 @@ impl PathSetup {
 -- Compatible with Lua 5.1, 5.3, and 5.4
 
 local lua_modules = [[{}]]
-
--- Add lua_modules to package.path
--- Supports both ?/init.lua and ?.lua patterns
-local lpm_path = lua_modules .. [[/?/init.lua;]] ..
+local version = _VERSION:match("%d+%.%d+")
+local share = lua_modules .. [[/share/lua/]] .. version
+local lib = lua_modules .. [[/lib/lua/]] .. version
+
+-- Installed modules first: share/lua/<version>/socket/http.lua
+-- Then lua_modules itself: linked local packages and lpm.loader
+local lpm_path = share .. [[/?.lua;]] ..
+                 share .. [[/?/init.lua;]] ..
+                 lua_modules .. [[/?/init.lua;]] ..
                  lua_modules .. [[/?.lua;]] ..
                  lua_modules .. [[/?/?.lua;]]
 
--- Add lua_modules to package.cpath for native modules
-local lpm_cpath = [[{}]]
+-- Native modules: lib/lua/<version>/socket/core.{ext}
+local lpm_cpath = lib .. [[/?.{ext};]] ..
+                  lua_modules .. [[/?.{ext};]]
 
 -- Prepend LPM paths to existing paths
 package.path = lpm_path .. package.path
@@ -60,10 +58,55 @@ return {{
     cpath = lpm_cpath,
 }}
 "#,
-            lua_modules_str, cpath_extension
+            lua_modules.to_string_lossy(),
+            ext = native_extension()
+        )
+    }
+
+    /// `LUA_PATH` for running Lua outside the loader: the tree's module
+    /// directory for `lua_version` (the default version if unknown), then the
+    /// default path
+    ///
+    /// Only one version's directories are listed, like the loader does from
+    /// `_VERSION`, so a Lua never loads modules installed for another one.
+    pub fn lua_path(project_root: &Path, lua_version: Option<&str>) -> String {
+        let lua_modules = lua_modules_dir(project_root);
+        let share = tree_lua_dir(&lua_modules, lua_version.unwrap_or(DEFAULT_LUA_VERSION));
+        format!(
+            "{0}/?.lua;{0}/?/init.lua;{1}/?.lua;{1}/?/init.lua;{1}/?/?.lua;;",
+            share.to_string_lossy(),
+            lua_modules.to_string_lossy()
         )
     }
 
+    /// `LUA_CPATH` for running Lua outside the loader
+    pub fn lua_cpath(project_root: &Path, lua_version: Option<&str>) -> String {
+        let lua_modules = lua_modules_dir(project_root);
+        let lib = tree_lib_dir(&lua_modules, lua_version.unwrap_or(DEFAULT_LUA_VERSION));
+        format!(
+            "{}/?.{ext};{}/?.{ext};;",
+            lib.to_string_lossy(),
+            lua_modules.to_string_lossy(),
+            ext = native_extension()
+        )
+    }
+
+    /// The `major.minor` version of a Lua interpreter, from its `_VERSION`
+    ///
+    /// `None` if it can't be run; LuaJIT reports 5.1.
+    pub fn detect_lua_version(lua_binary: &Path) -> Option<String> {
+        let output = Command::new(lua_binary)
+            .arg("-e")
+            .arg("io.write(_VERSION)")
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let version = String::from_utf8_lossy(&output.stdout);
+        version.trim().strip_prefix("Lua ").map(|v| v.to_string())
+    }
+
     /// Install the lpm.loader module to lua_modules/lpm/loader.lua
     /// This allows it to be required as "lpm.loader"
     pub fn install_loader(project_root: &Path) -> LpmResult<()> {
@@ -102,6 +145,26 @@ mod tests {
         assert!(loader.contains("package.path"));
         assert!(loader.contains("package.cpath"));
         assert!(loader.contains("lua_modules"));
+        assert!(loader.contains("/share/lua/"));
+        assert!(loader.contains("/lib/lua/"));
+    }
+
+    #[test]
+    fn test_lua_path_is_for_one_version() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = lua_modules_dir(temp.path());
+        fs::create_dir_all(tree_lib_dir(&lua_modules, "5.1")).unwrap();
+        fs::create_dir_all(tree_lib_dir(&lua_modules, "5.4")).unwrap();
+
+        let share = tree_lua_dir(&lua_modules, DEFAULT_LUA_VERSION);
+        let path = PathSetup::lua_path(temp.path(), None);
+        assert!(path.starts_with(&format!("{}/?.lua;", share.display())));
+        assert!(path.ends_with(";;"));
+
+        let cpath = PathSetup::lua_cpath(temp.path(), Some("5.1"));
+        let lib = tree_lib_dir(&lua_modules, "5.1");
+        assert!(cpath.starts_with(&lib.display().to_string()));
+        assert!(!cpath.contains(&tree_lib_dir(&lua_modules, "5.4").display().to_string()));
     }
 
     #[test]
@@ -69,13 +69,16 @@ impl LuaRunner {
             cmd.env(key, value);
         }
 
-        // Add LUA_PATH to include lua_modules
-        let lua_path = format!(
-            "{}/?.lua;{}/?/init.lua;",
-            lua_modules.to_string_lossy(),
-            lua_modules.to_string_lossy()
+        // Add LUA_PATH and LUA_CPATH to include lua_modules, for the Lua being run
+        let lua_version = PathSetup::detect_lua_version(&lua_binary);
+        cmd.env(
+            "LUA_PATH",
+            PathSetup::lua_path(&project_root, lua_version.as_deref()),
+        );
+        cmd.env(
+            "LUA_CPATH",
+            PathSetup::lua_cpath(&project_root, lua_version.as_deref()),
         );
-        cmd.env("LUA_PATH", lua_path);
 
         // Run the command
         let status = cmd.status()?;
@@ -89,7 +92,6 @@ impl LuaRunner {
     pub fn exec_command(command_str: &str, options: RunOptions) -> LpmResult<i32> {
         let current_dir = std::env::current_dir()?;
         let project_root = find_project_root(&current_dir)?;
-        let lua_modules = lua_modules_dir(&project_root);
 
         // Ensure loader is installed
         PathSetup::install_loader(&project_root)?;
@@ -136,30 +138,15 @@ impl LuaRunner {
             || program.ends_with("lua")
             || program.ends_with("luajit")
         {
-            let lua_path = format!(
-                "{}/?.lua;{}/?/init.lua;{}/?/?.lua;",
-                lua_modules.to_string_lossy(),
-                lua_modules.to_string_lossy(),
-                lua_modules.to_string_lossy()
+            let lua_version = PathSetup::detect_lua_version(Path::new(&actual_program));
+            cmd.env(
+                "LUA_PATH",
+                PathSetup::lua_path(&project_root, lua_version.as_deref()),
             );
-            cmd.env("LUA_PATH", lua_path);
-
-            // Set up LUA_CPATH for native modules
-            let cpath_ext = if cfg!(target_os = "windows") {
-                "dll"
-            } else if cfg!(target_os = "macos") {
-                "dylib"
-            } else {
-                "so"
-            };
-            let lua_cpath = format!(
-                "{}/?.{};{}/?/init.{};",
-                lua_modules.to_string_lossy(),
-                cpath_ext,
-                lua_modules.to_string_lossy(),
-                cpath_ext
+            cmd.env(
+                "LUA_CPATH",
+                PathSetup::lua_cpath(&project_root, lua_version.as_deref()),
             );
-            cmd.env("LUA_CPATH", lua_cpath);
         }
 
         // Set additional environment variables
@@ -215,13 +202,16 @@ impl LuaRunner {
             cmd.env(key, value);
         }
 
-        // Add LUA_PATH
-        let lua_path = format!(
-            "{}/?.lua;{}/?/init.lua;",
-            lua_modules.to_string_lossy(),
-            lua_modules.to_string_lossy()
+        // Add LUA_PATH and LUA_CPATH, for the Lua being run
+        let lua_version = PathSetup::detect_lua_version(&lua_binary);
+        cmd.env(
+            "LUA_PATH",
+            PathSetup::lua_path(&project_root, lua_version.as_deref()),
+        );
+        cmd.env(
+            "LUA_CPATH",
+            PathSetup::lua_cpath(&project_root, lua_version.as_deref()),
         );
-        cmd.env("LUA_PATH", lua_path);
 
         // Run the command
         let status = cmd.status()?;
@@ -458,13 +458,10 @@ fn load_watch_config_from_manifest(project_root: &Path) -> LpmResult<ManifestWat
                                 .map(|c| c.split_whitespace().map(|s| s.to_string()).collect())
                                 .collect(),
                         )
-                    } else if let Some(cmd) = watch_config.command {
-                        Some(vec![cmd
-                            .split_whitespace()
-                            .map(|s| s.to_string())
-                            .collect()])
                     } else {
-                        None
+                        watch_config.command.map(|cmd| {
+                            vec![cmd.split_whitespace().map(|s| s.to_string()).collect()]
+                        })
                     };
 
                     return Ok(ManifestWatchConfig {
@@ -0,0 +1,460 @@
+use crate::core::path::lpm_home;
+pub use crate::core::path::native_extension;
+use crate::core::{LpmError, LpmResult};
+use crate::lua_manager::VersionSwitcher;
+use crate::lua_version::detector::LuaVersionDetector;
+use crate::luarocks::rockspec::BuildModule;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Compiles native modules for the LuaRocks `builtin` build type
+///
+/// Mirrors the LuaRocks builtin backend: every source is compiled with the
+/// system C compiler against the Lua headers, then linked into a shared
+/// library named after the module. Undefined Lua API symbols are resolved by
+/// the interpreter at load time, so liblua itself is never linked.
+pub struct NativeModuleBuilder {
+    compiler: String,
+    cflags: Vec<String>,
+    libflags: Vec<String>,
+    variables: HashMap<String, String>,
+}
+
+impl NativeModuleBuilder {
+    /// Create a builder that compiles against the Lua headers in `lua_incdir`
+    ///
+    /// The compiler is taken from `$CC` (default `cc`), extra flags from `$CFLAGS`.
+    pub fn new(lua_incdir: &Path) -> Self {
+        let compiler = std::env::var("CC")
+            .ok()
+            .filter(|cc| !cc.trim().is_empty())
+            .unwrap_or_else(|| "cc".to_string());
+
+        let mut cflags = vec!["-O2".to_string(), "-fPIC".to_string()];
+        if let Ok(extra) = std::env::var("CFLAGS") {
+            cflags.extend(extra.split_whitespace().map(|s| s.to_string()));
+        }
+
+        let libflags: Vec<String> = if cfg!(target_os = "macos") {
+            vec!["-bundle", "-undefined", "dynamic_lookup", "-all_load"]
+        } else {
+            vec!["-shared"]
+        }
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let lua_libdir = lua_incdir
+            .parent()
+            .map(|p| p.join("lib"))
+            .unwrap_or_else(|| lua_incdir.to_path_buf());
+
+        let mut variables = HashMap::new();
+        variables.insert(
+            "LUA_INCDIR".to_string(),
+            lua_incdir.to_string_lossy().to_string(),
+        );
+        variables.insert(
+            "LUA_LIBDIR".to_string(),
+            lua_libdir.to_string_lossy().to_string(),
+        );
+        variables.insert("CC".to_string(), compiler.clone());
+        variables.insert("LD".to_string(), compiler.clone());
+        variables.insert("CFLAGS".to_string(), cflags.join(" "));
+        variables.insert("LIBFLAG".to_string(), libflags.join(" "));
+        variables.insert("LIB_EXTENSION".to_string(), native_extension().to_string());
+        variables.insert("OBJ_EXTENSION".to_string(), "o".to_string());
+
+        Self {
+            compiler,
+            cflags,
+            libflags,
+            variables,
+        }
+    }
+
+    /// Add or override `$(NAME)` variables (e.g. `build.variables` from the rockspec)
+    pub fn with_variables(mut self, variables: &HashMap<String, String>) -> Self {
+        for (name, value) in variables {
+            let value = substitute_variables(value, &self.variables);
+            self.variables.insert(name.clone(), value);
+        }
+        self
+    }
+
+    /// Variables available for `$(NAME)` substitution
+    pub fn variables(&self) -> &HashMap<String, String> {
+        &self.variables
+    }
+
+    /// Compile and link one module, returning the path of the built library
+    ///
+    /// Relative source, include and library paths are resolved against `source_dir`;
+    /// objects and the library are written to `build_dir`.
+    pub fn compile(
+        &self,
+        source_dir: &Path,
+        build_dir: &Path,
+        module_name: &str,
+        module: &BuildModule,
+    ) -> LpmResult<PathBuf> {
+        if module.sources.is_empty() {
+            return Err(LpmError::Package(format!(
+                "Native module '{}' has no sources",
+                module_name
+            )));
+        }
+
+        let module_build_dir = build_dir.join(module_name);
+        fs::create_dir_all(&module_build_dir)?;
+
+        let expand = |values: &[String]| -> Vec<String> {
+            values
+                .iter()
+                .map(|v| substitute_variables(v, &self.variables))
+                .collect()
+        };
+        let incdirs = expand(&module.incdirs);
+        let libdirs = expand(&module.libdirs);
+        let defines = expand(&module.defines);
+        let libraries = expand(&module.libraries);
+
+        let mut objects = Vec::new();
+        for source in expand(&module.sources) {
+            let object_name = format!("{}.o", source.replace(['/', '\\'], "_"));
+            let object = module_build_dir.join(object_name);
+
+            let mut cmd = Command::new(&self.compiler);
+            cmd.current_dir(source_dir);
+            cmd.args(&self.cflags);
+            for define in &defines {
+                cmd.arg(format!("-D{}", define));
+            }
+            for incdir in &incdirs {
+                cmd.arg(format!("-I{}", incdir));
+            }
+            cmd.arg(format!("-I{}", self.variables["LUA_INCDIR"]));
+            cmd.arg("-c").arg(&source).arg("-o").arg(&object);
+            self.run(
+                cmd,
+                &format!("compile {} for module '{}'", source, module_name),
+            )?;
+
+            objects.push(object);
+        }
+
+        let library = module_build_dir.join(format!(
+            "{}.{}",
+            module_name.rsplit('.').next().unwrap_or(module_name),
+            native_extension()
+        ));
+        let mut cmd = Command::new(&self.compiler);
+        cmd.current_dir(source_dir);
+        cmd.args(&self.libflags);
+        cmd.arg("-o").arg(&library);
+        cmd.args(&objects);
+        for libdir in &libdirs {
+            cmd.arg(format!("-L{}", libdir));
+        }
+        for lib in &libraries {
+            cmd.arg(format!("-l{}", lib));
+        }
+        self.run(cmd, &format!("link module '{}'", module_name))?;
+
+        Ok(library)
+    }
+
+    fn run(&self, mut cmd: Command, what: &str) -> LpmResult<()> {
+        let output = cmd.output().map_err(|e| {
+            LpmError::Package(format!(
+                "Failed to run C compiler '{}' to {}: {}. Install a C compiler or set CC.",
+                self.compiler, what, e
+            ))
+        })?;
+        if !output.status.success() {
+            return Err(LpmError::Package(format!(
+                "Failed to {}:\n{}",
+                what,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Where a native module is installed: "socket.core" -> lua_modules/socket/core.so
+pub fn native_module_path(lua_modules: &Path, module_name: &str) -> PathBuf {
+    let mut path = lua_modules.to_path_buf();
+    for part in module_name.split('.') {
+        path.push(part);
+    }
+    path.set_extension(native_extension());
+    path
+}
+
+/// Replace `$(NAME)` references with their values; unknown names are left as is
+pub fn substitute_variables(value: &str, variables: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("$(") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find(')') {
+            Some(end) => {
+                let name = &after[..end];
+                match variables.get(name) {
+                    Some(v) => out.push_str(v),
+                    None => out.push_str(&rest[start..start + 3 + end]),
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Locate the Lua headers (the directory containing `lua.h`) for the selected Lua
+///
+/// The Lua version is taken from the project's `.lua-version`, the version
+/// selected with `lpm lua use`, or `lua -v`. `$LUA_INCDIR` overrides the search.
+pub fn find_lua_headers(project_root: &Path) -> LpmResult<PathBuf> {
+    if let Ok(dir) = std::env::var("LUA_INCDIR") {
+        let dir = PathBuf::from(dir);
+        if dir.join("lua.h").exists() {
+            return Ok(dir);
+        }
+        return Err(LpmError::Package(format!(
+            "LUA_INCDIR is set to {}, but it does not contain lua.h",
+            dir.display()
+        )));
+    }
+
+    let (version, managed_dir) = selected_lua(project_root);
+    let candidates = lua_incdir_candidates(version.as_deref(), managed_dir.as_deref());
+    for dir in &candidates {
+        let header = dir.join("lua.h");
+        if !header.exists() {
+            continue;
+        }
+        // Generic include directories may hold headers for a different Lua
+        match (&version, header_version(&header)) {
+            (Some(wanted), Some(found)) if *wanted != found => continue,
+            _ => return Ok(dir.clone()),
+        }
+    }
+
+    Err(LpmError::Package(format!(
+        "Lua {}headers (lua.h) not found. Install the Lua development package \
+         (e.g. liblua{}-dev) or set LUA_INCDIR.",
+        version
+            .as_ref()
+            .map(|v| format!("{} ", v))
+            .unwrap_or_default(),
+        version.as_deref().unwrap_or("5.4")
+    )))
+}
+
+/// The selected Lua as ("5.4", managed installation directory)
+fn selected_lua(project_root: &Path) -> (Option<String>, Option<PathBuf>) {
+    let major_minor = |v: &str| v.split('.').take(2).collect::<Vec<_>>().join(".");
+    let home = lpm_home().ok();
+
+    let local = fs::read_to_string(project_root.join(".lua-version"))
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+    let managed = local.or_else(|| {
+        home.as_ref()
+            .and_then(|h| VersionSwitcher::new(h).current().ok())
+    });
+    if let Some(version) = managed {
+        let dir = home.map(|h| h.join("versions").join(&version));
+        return (Some(major_minor(&version)), dir);
+    }
+
+    let detected = LuaVersionDetector::detect().ok().map(|v| v.major_minor());
+    (detected, None)
+}
+
+fn lua_incdir_candidates(version: Option<&str>, managed_dir: Option<&Path>) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(dir) = managed_dir {
+        candidates.push(dir.join("include"));
+    }
+
+    let prefixes = ["/usr/local", "/usr", "/opt/homebrew", "/opt/local"];
+    if let Some(v) = version {
+        let compact = v.replace('.', "");
+        for prefix in prefixes {
+            let include = Path::new(prefix).join("include");
+            candidates.push(include.join(format!("lua{}", v)));
+            candidates.push(include.join(format!("lua{}", compact)));
+            candidates.push(include.join(format!("lua-{}", v)));
+        }
+        for prefix in ["/opt/homebrew/opt", "/usr/local/opt"] {
+            candidates.push(
+                Path::new(prefix)
+                    .join(format!("lua@{}", v))
+                    .join("include")
+                    .join(format!("lua{}", v)),
+            );
+        }
+    }
+    for prefix in prefixes {
+        let include = Path::new(prefix).join("include");
+        candidates.push(include.join("lua"));
+        candidates.push(include);
+    }
+    candidates
+}
+
+/// Read "5.4" from `#define LUA_VERSION_NUM 504` (or the 5.1 `LUA_VERSION` string)
+fn header_version(lua_h: &Path) -> Option<String> {
+    let content = fs::read_to_string(lua_h).ok()?;
+    for line in content.lines() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("#define") {
+            continue;
+        }
+        match words.next() {
+            Some("LUA_VERSION_NUM") => {
+                let num: u32 = words
+                    .next()?
+                    .trim_matches(|c| c == '(' || c == ')')
+                    .parse()
+                    .ok()?;
+                return Some(format!("{}.{}", num / 100, num % 100));
+            }
+            Some("LUA_VERSION") => {
+                let rest: String = words.collect::<Vec<_>>().join(" ");
+                if let Some(v) = rest.trim_matches('"').strip_prefix("Lua ") {
+                    return Some(v.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_substitute_variables() {
+        let mut vars = HashMap::new();
+        vars.insert("LUA_INCDIR".to_string(), "/usr/include/lua5.4".to_string());
+        assert_eq!(
+            substitute_variables("-I$(LUA_INCDIR)/x", &vars),
+            "-I/usr/include/lua5.4/x"
+        );
+        assert_eq!(substitute_variables("$(UNKNOWN)", &vars), "$(UNKNOWN)");
+        assert_eq!(substitute_variables("$(broken", &vars), "$(broken");
+    }
+
+    #[test]
+    fn test_native_module_path() {
+        let path = native_module_path(Path::new("/p/lua_modules"), "socket.core");
+        assert_eq!(
+            path,
+            PathBuf::from(format!("/p/lua_modules/socket/core.{}", native_extension()))
+        );
+    }
+
+    #[test]
+    fn test_header_version() {
+        let temp = TempDir::new().unwrap();
+        let header = temp.path().join("lua.h");
+        fs::write(&header, "#define LUA_VERSION_NUM\t\t504\n").unwrap();
+        assert_eq!(header_version(&header), Some("5.4".to_string()));
+        fs::write(&header, "#define LUA_VERSION\t\"Lua 5.1\"\n").unwrap();
+        assert_eq!(header_version(&header), Some("5.1".to_string()));
+    }
+
+    #[test]
+    fn test_incdir_candidates_prefer_versioned_dirs() {
+        let candidates = lua_incdir_candidates(Some("5.4"), None);
+        let versioned = candidates
+            .iter()
+            .position(|p| p.ends_with("lua5.4"))
+            .unwrap();
+        let generic = candidates
+            .iter()
+            .position(|p| p == Path::new("/usr/include"))
+            .unwrap();
+        assert!(versioned < generic);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_compile_native_module() {
+        if which::which("cc").is_err() {
+            return;
+        }
+        let temp = TempDir::new().unwrap();
+        let incdir = temp.path().join("include");
+        fs::create_dir_all(&incdir).unwrap();
+        fs::write(
+            incdir.join("lua.h"),
+            "typedef struct lua_State lua_State;\nvoid lua_pushinteger(lua_State *L, long n);\n",
+        )
+        .unwrap();
+
+        let src = temp.path().join("src");
+        fs::create_dir_all(src.join("inc")).unwrap();
+        fs::write(src.join("inc/answer.h"), "#define ANSWER 42\n").unwrap();
+        fs::write(
+            src.join("core.c"),
+            "#include \"lua.h\"\n#include \"answer.h\"\n\
+             int luaopen_demo_core(lua_State *L) { lua_pushinteger(L, ANSWER + EXTRA); return 1; }\n",
+        )
+        .unwrap();
+
+        let mut variables = HashMap::new();
+        variables.insert("MY_INC".to_string(), "inc".to_string());
+        let builder = NativeModuleBuilder::new(&incdir).with_variables(&variables);
+        let module = BuildModule {
+            sources: vec!["core.c".to_string()],
+            defines: vec!["EXTRA=1".to_string()],
+            incdirs: vec!["$(MY_INC)".to_string()],
+            ..BuildModule::default()
+        };
+        let library = builder
+            .compile(&src, &temp.path().join("build"), "demo.core", &module)
+            .unwrap();
+        assert!(library.exists());
+        assert_eq!(
+            library.file_name().unwrap().to_string_lossy(),
+            format!("core.{}", native_extension())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_compile_reports_compiler_errors() {
+        if which::which("cc").is_err() {
+            return;
+        }
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("lua.h"), "").unwrap();
+        fs::write(temp.path().join("bad.c"), "this is not C").unwrap();
+        let module = BuildModule {
+            sources: vec!["bad.c".to_string()],
+            ..BuildModule::default()
+        };
+        let err = NativeModuleBuilder::new(temp.path())
+            .compile(temp.path(), &temp.path().join("build"), "bad", &module)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("compile bad.c"), "{err}");
+    }
+}
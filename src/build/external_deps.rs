@@ -0,0 +1,289 @@
+use crate::core::{LpmError, LpmResult};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Locates system libraries declared in a rockspec's `external_dependencies`
+///
+/// Each dependency lists probes (`header`, `library`, `program`) that must all
+/// be found under one prefix. A found dependency `OPENSSL` provides the
+/// `OPENSSL_DIR`, `OPENSSL_INCDIR`, `OPENSSL_LIBDIR` and `OPENSSL_BINDIR`
+/// variables used by make, cmake and builtin builds.
+pub struct ExternalDependencyResolver {
+    prefixes: Vec<PathBuf>,
+    overrides: HashMap<String, String>,
+}
+
+impl ExternalDependencyResolver {
+    /// Search only the given prefixes
+    pub fn new(prefixes: Vec<PathBuf>) -> Self {
+        Self {
+            prefixes,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Search the configured prefixes followed by the standard system prefixes
+    ///
+    /// `NAME_DIR`, `NAME_INCDIR` and `NAME_LIBDIR` environment variables take
+    /// precedence, as with `luarocks install rock NAME_DIR=...`.
+    pub fn system(configured: &[PathBuf]) -> Self {
+        let mut prefixes = configured.to_vec();
+        prefixes.extend(default_prefixes());
+        Self {
+            prefixes,
+            overrides: std::env::vars()
+                .filter(|(k, _)| {
+                    k.ends_with("_DIR") || k.ends_with("_INCDIR") || k.ends_with("_LIBDIR")
+                })
+                .collect(),
+        }
+    }
+
+    /// Explicitly set a `NAME_DIR`/`NAME_INCDIR`/`NAME_LIBDIR` override
+    pub fn with_override(mut self, name: &str, value: &str) -> Self {
+        self.overrides.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Probe every dependency and return the build variables
+    ///
+    /// Fails on the first dependency that cannot be found, naming the library.
+    pub fn resolve(
+        &self,
+        dependencies: &HashMap<String, HashMap<String, String>>,
+    ) -> LpmResult<HashMap<String, String>> {
+        let mut variables = HashMap::new();
+        let sorted: BTreeMap<_, _> = dependencies.iter().collect();
+
+        for (name, probes) in sorted {
+            let found = self
+                .find(name, probes)
+                .ok_or_else(|| missing_error(name, probes))?;
+            let dir = found.dir.to_string_lossy().to_string();
+            variables.insert(format!("{}_DIR", name), dir);
+            variables.insert(
+                format!("{}_INCDIR", name),
+                found.incdir.to_string_lossy().to_string(),
+            );
+            variables.insert(
+                format!("{}_LIBDIR", name),
+                found.libdir.to_string_lossy().to_string(),
+            );
+            variables.insert(
+                format!("{}_BINDIR", name),
+                found.dir.join("bin").to_string_lossy().to_string(),
+            );
+        }
+
+        Ok(variables)
+    }
+
+    fn find(&self, name: &str, probes: &HashMap<String, String>) -> Option<FoundDependency> {
+        let override_for = |suffix: &str| {
+            self.overrides
+                .get(&format!("{}_{}", name, suffix))
+                .map(PathBuf::from)
+        };
+
+        let mut candidates = match override_for("DIR") {
+            Some(dir) => vec![dir],
+            None => self.prefixes.clone(),
+        };
+        if candidates.is_empty() && override_for("INCDIR").is_some() {
+            // Only the explicit directories are probed
+            candidates.push(PathBuf::new());
+        }
+
+        for dir in candidates {
+            let incdir = override_for("INCDIR").unwrap_or_else(|| dir.join("include"));
+            if let Some(header) = probes.get("header") {
+                if !incdir.join(header).is_file() {
+                    continue;
+                }
+            }
+
+            let libdirs = match override_for("LIBDIR") {
+                Some(libdir) => vec![libdir],
+                None => library_dirs(&dir),
+            };
+            let libdir = match probes.get("library") {
+                Some(library) => match libdirs.into_iter().find(|d| has_library(d, library)) {
+                    Some(libdir) => libdir,
+                    None => continue,
+                },
+                None => libdirs
+                    .into_iter()
+                    .find(|d| d.is_dir())
+                    .unwrap_or_else(|| dir.join("lib")),
+            };
+
+            if let Some(program) = probes.get("program") {
+                if !has_program(&dir.join("bin"), program) {
+                    continue;
+                }
+            }
+
+            return Some(FoundDependency {
+                dir,
+                incdir,
+                libdir,
+            });
+        }
+
+        None
+    }
+}
+
+struct FoundDependency {
+    dir: PathBuf,
+    incdir: PathBuf,
+    libdir: PathBuf,
+}
+
+fn default_prefixes() -> Vec<PathBuf> {
+    let prefixes: &[&str] = if cfg!(target_os = "windows") {
+        &["C:\\Program Files", "C:\\Program Files (x86)"]
+    } else if cfg!(target_os = "macos") {
+        &["/opt/homebrew", "/usr/local", "/opt/local", "/usr"]
+    } else {
+        &["/usr/local", "/usr", "/opt/local"]
+    };
+    prefixes.iter().map(PathBuf::from).collect()
+}
+
+/// Library directories under a prefix, including Debian-style multiarch dirs
+fn library_dirs(prefix: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![prefix.join("lib"), prefix.join("lib64")];
+    let multiarch = format!("{}-linux-gnu", std::env::consts::ARCH);
+    dirs.push(prefix.join("lib").join(multiarch));
+    dirs
+}
+
+/// Whether `libdir` contains the library, given as `ssl`, `libssl` or a file name
+fn has_library(libdir: &Path, library: &str) -> bool {
+    if library.contains('.') {
+        return libdir.join(library).is_file();
+    }
+
+    let base = library.strip_prefix("lib").unwrap_or(library);
+    let names = [
+        format!("lib{}.so", base),
+        format!("lib{}.a", base),
+        format!("lib{}.dylib", base),
+        format!("{}.lib", library),
+        format!("{}.dll", library),
+    ];
+    if names.iter().any(|n| libdir.join(n).is_file()) {
+        return true;
+    }
+
+    // Versioned shared objects such as libssl.so.3
+    let versioned = format!("lib{}.so.", base);
+    std::fs::read_dir(libdir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .any(|e| e.file_name().to_string_lossy().starts_with(&versioned))
+        })
+        .unwrap_or(false)
+}
+
+fn has_program(bindir: &Path, program: &str) -> bool {
+    bindir.join(program).is_file() || bindir.join(format!("{}.exe", program)).is_file()
+}
+
+fn missing_error(name: &str, probes: &HashMap<String, String>) -> LpmError {
+    let mut wanted: Vec<String> = probes
+        .iter()
+        .map(|(kind, value)| format!("{} '{}'", kind, value))
+        .collect();
+    wanted.sort();
+    LpmError::Package(format!(
+        "Missing system library {} ({}). Install its development package, \
+         or set {}_DIR (or {}_INCDIR and {}_LIBDIR) to where it is installed.",
+        name,
+        wanted.join(", "),
+        name,
+        name,
+        name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn probes(pairs: &[(&str, &str)]) -> HashMap<String, HashMap<String, String>> {
+        let probes = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        HashMap::from([("FOO".to_string(), probes)])
+    }
+
+    fn fake_prefix(temp: &TempDir) -> PathBuf {
+        let prefix = temp.path().join("prefix");
+        fs::create_dir_all(prefix.join("include/foo")).unwrap();
+        fs::create_dir_all(prefix.join("lib")).unwrap();
+        fs::write(prefix.join("include/foo/foo.h"), "").unwrap();
+        fs::write(prefix.join("lib/libfoo.so.1"), "").unwrap();
+        prefix
+    }
+
+    #[test]
+    fn test_resolve_finds_header_and_library() {
+        let temp = TempDir::new().unwrap();
+        let prefix = fake_prefix(&temp);
+        let resolver =
+            ExternalDependencyResolver::new(vec![temp.path().join("empty"), prefix.clone()]);
+
+        let vars = resolver
+            .resolve(&probes(&[("header", "foo/foo.h"), ("library", "foo")]))
+            .unwrap();
+        assert_eq!(vars["FOO_DIR"], prefix.to_string_lossy());
+        assert_eq!(vars["FOO_INCDIR"], prefix.join("include").to_string_lossy());
+        assert_eq!(vars["FOO_LIBDIR"], prefix.join("lib").to_string_lossy());
+    }
+
+    #[test]
+    fn test_resolve_reports_missing_library() {
+        let temp = TempDir::new().unwrap();
+        let prefix = fake_prefix(&temp);
+        let resolver = ExternalDependencyResolver::new(vec![prefix]);
+
+        let err = resolver
+            .resolve(&probes(&[("header", "foo/foo.h"), ("library", "bar")]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Missing system library FOO"), "{err}");
+        assert!(err.contains("library 'bar'"), "{err}");
+        assert!(err.contains("FOO_DIR"), "{err}");
+    }
+
+    #[test]
+    fn test_resolve_with_overrides() {
+        let temp = TempDir::new().unwrap();
+        let prefix = fake_prefix(&temp);
+
+        let by_dir = ExternalDependencyResolver::new(Vec::new())
+            .with_override("FOO_DIR", &prefix.to_string_lossy());
+        let vars = by_dir.resolve(&probes(&[("header", "foo/foo.h")])).unwrap();
+        assert_eq!(vars["FOO_DIR"], prefix.to_string_lossy());
+
+        let by_parts = ExternalDependencyResolver::new(Vec::new())
+            .with_override("FOO_INCDIR", &prefix.join("include").to_string_lossy())
+            .with_override("FOO_LIBDIR", &prefix.join("lib").to_string_lossy());
+        let vars = by_parts
+            .resolve(&probes(&[("header", "foo/foo.h"), ("library", "libfoo")]))
+            .unwrap();
+        assert_eq!(vars["FOO_LIBDIR"], prefix.join("lib").to_string_lossy());
+    }
+
+    #[test]
+    fn test_resolve_nothing_to_probe() {
+        let resolver = ExternalDependencyResolver::new(Vec::new());
+        assert!(resolver.resolve(&HashMap::new()).unwrap().is_empty());
+    }
+}
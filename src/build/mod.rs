@@ -1,8 +1,12 @@
 pub mod builder;
+pub mod builtin;
+pub mod external_deps;
 pub mod prebuilt;
 pub mod sandbox;
 pub mod targets;
 
 pub use builder::RustBuilder;
+pub use builtin::NativeModuleBuilder;
+pub use external_deps::ExternalDependencyResolver;
 pub use sandbox::BuildSandbox;
 pub use targets::{Target, SUPPORTED_TARGETS};
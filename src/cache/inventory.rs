@@ -0,0 +1,428 @@
+//! What is in the cache, and which package each file belongs to
+//!
+//! Rockspecs are named after their package version and Rust builds are kept
+//! per package and version. Source archives are named by URL hash, so they
+//! are attributed by the metadata recorded when they were installed
+//! (`Cache::record_source`), else by the cached rockspec pointing at them.
+
+use crate::cache::{Cache, CacheCleanResult};
+use crate::core::{LpmError, LpmResult};
+use crate::luarocks::client::split_rockspec_name;
+use crate::luarocks::rockspec::Rockspec;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+/// What a cached file holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CacheKind {
+    /// Registry manifests and the package indexes built from them
+    Index,
+    Rockspec,
+    Source,
+    /// Bare mirrors of git repositories and archives of their commits
+    Git,
+    RustBuild,
+}
+
+impl CacheKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CacheKind::Index => "indexes",
+            CacheKind::Rockspec => "rockspecs",
+            CacheKind::Source => "sources",
+            CacheKind::Git => "git",
+            CacheKind::RustBuild => "rust-builds",
+        }
+    }
+}
+
+/// A cached file (or git mirror)
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub kind: CacheKind,
+    pub path: PathBuf,
+    /// Bytes, including the entry's metadata file
+    pub size: u64,
+    pub modified: SystemTime,
+    /// Package and version the entry belongs to, when known
+    pub package: Option<(String, String)>,
+}
+
+/// A cached source whose checksum differs from the one its lockfile pins
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorruptSource {
+    pub path: PathBuf,
+    pub package: Option<(String, String)>,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Cache {
+    /// Every entry in the cache, oldest first
+    pub fn entries(&self) -> LpmResult<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+
+        for path in files(&self.rockspecs_dir()) {
+            let name = file_name(&path);
+            let (kind, package) = match name.strip_suffix(".rockspec") {
+                Some(_) => match split_rockspec_name(&name) {
+                    (package, Some(version)) => {
+                        (CacheKind::Rockspec, Some((package.to_string(), version)))
+                    }
+                    _ => (CacheKind::Rockspec, None),
+                },
+                None => (CacheKind::Index, None),
+            };
+            entries.push(self.entry(kind, path, package)?);
+        }
+
+        let mut by_rockspec = None;
+        for path in files(&self.sources_dir()) {
+            let package = match self.read_meta(&path).and_then(|meta| meta.package()) {
+                Some(package) => Some(package),
+                None => by_rockspec
+                    .get_or_insert_with(|| self.sources_by_rockspec())
+                    .get(&path)
+                    .cloned(),
+            };
+            entries.push(self.entry(CacheKind::Source, path, package)?);
+        }
+
+        for path in files(&self.git_dir().join("archives")) {
+            entries.push(self.entry(CacheKind::Git, path, None)?);
+        }
+        let repos = self.git_dir().join("repos");
+        if repos.is_dir() {
+            for repo in fs::read_dir(&repos)? {
+                let path = repo?.path();
+                entries.push(CacheEntry {
+                    kind: CacheKind::Git,
+                    size: files(&path).iter().map(|f| file_size(f)).sum(),
+                    modified: modified(&path),
+                    path,
+                    package: None,
+                });
+            }
+        }
+
+        let rust_builds = self.rust_builds_dir();
+        for path in files(&rust_builds) {
+            // rust-builds/<package>/<version>/<lua version>/<target>/<library>
+            let parts: Vec<String> = path
+                .strip_prefix(&rust_builds)
+                .map(|relative| {
+                    relative
+                        .iter()
+                        .map(|p| p.to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let package = match parts.as_slice() {
+                [package, version, _, ..] => Some((package.clone(), version.clone())),
+                _ => None,
+            };
+            entries.push(self.entry(CacheKind::RustBuild, path, package)?);
+        }
+
+        entries.sort_by(|a, b| a.modified.cmp(&b.modified).then(a.path.cmp(&b.path)));
+        Ok(entries)
+    }
+
+    /// Delete a cached file (or git mirror) and its metadata
+    pub fn remove_entry(&self, path: &Path) -> LpmResult<()> {
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+        removed
+            .map_err(|e| LpmError::Cache(format!("Failed to remove {}: {}", path.display(), e)))?;
+        let _ = fs::remove_file(Self::meta_path(path));
+        Ok(())
+    }
+
+    /// Remove entries older than `max_age`, then the oldest entries until the
+    /// cache is no larger than `max_size` bytes
+    pub fn prune(
+        &self,
+        max_age: Option<Duration>,
+        max_size: Option<u64>,
+    ) -> LpmResult<CacheCleanResult> {
+        let now = SystemTime::now();
+        let entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|e| e.size).sum();
+        let mut result = CacheCleanResult::default();
+
+        for entry in &entries {
+            let expired = max_age.is_some_and(|max_age| {
+                now.duration_since(entry.modified)
+                    .is_ok_and(|age| age > max_age)
+            });
+            let oversized = max_size.is_some_and(|max_size| total > max_size);
+            if !expired && !oversized {
+                continue;
+            }
+            match self.remove_entry(&entry.path) {
+                Ok(()) => {
+                    result.files_removed += 1;
+                    result.bytes_freed += entry.size;
+                    total -= entry.size;
+                }
+                Err(e) => eprintln!("Warning: {}", e),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Remove everything from the cache
+    pub fn clear(&self) -> LpmResult<CacheCleanResult> {
+        let entries = self.entries()?;
+        let result = CacheCleanResult {
+            files_removed: entries.len(),
+            bytes_freed: entries.iter().map(|e| e.size).sum(),
+        };
+        for dir in [self.luarocks_dir(), self.git_dir(), self.rust_builds_dir()] {
+            if dir.exists() {
+                fs::remove_dir_all(&dir).map_err(|e| {
+                    LpmError::Cache(format!("Failed to remove {}: {}", dir.display(), e))
+                })?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Re-hash cached sources against the checksums lockfiles pin them to
+    ///
+    /// `locked` adds `(source URL, package, version, checksum)` from a lockfile
+    /// to the checksums recorded when sources were installed. Returns how many
+    /// sources were checked, and the ones that don't match.
+    pub fn verify_sources<'a>(
+        &self,
+        locked: impl IntoIterator<Item = (&'a str, &'a str, &'a str, &'a str)>,
+    ) -> LpmResult<(usize, Vec<CorruptSource>)> {
+        let mut expected: HashMap<PathBuf, (Option<(String, String)>, String)> = HashMap::new();
+        for path in files(&self.sources_dir()) {
+            if let Some(meta) = self.read_meta(&path) {
+                if let Some(checksum) = meta.checksum.clone() {
+                    expected.insert(path, (meta.package(), checksum));
+                }
+            }
+        }
+        for (url, package, version, checksum) in locked {
+            let path = self.source_path(url);
+            if path.exists() {
+                let package = Some((package.to_string(), version.to_string()));
+                expected.insert(path, (package, checksum.to_string()));
+            }
+        }
+
+        let mut expected: Vec<_> = expected.into_iter().collect();
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut corrupt = Vec::new();
+        for (path, (package, checksum)) in &expected {
+            let actual = Cache::checksum(path)?;
+            if &actual != checksum {
+                corrupt.push(CorruptSource {
+                    path: path.clone(),
+                    package: package.clone(),
+                    expected: checksum.clone(),
+                    actual,
+                });
+            }
+        }
+        Ok((expected.len(), corrupt))
+    }
+
+    fn entry(
+        &self,
+        kind: CacheKind,
+        path: PathBuf,
+        package: Option<(String, String)>,
+    ) -> LpmResult<CacheEntry> {
+        Ok(CacheEntry {
+            kind,
+            size: file_size(&path) + file_size(&Self::meta_path(&path)),
+            modified: modified(&path),
+            path,
+            package,
+        })
+    }
+
+    /// Cached source paths, by the package version whose cached rockspec points at them
+    fn sources_by_rockspec(&self) -> HashMap<PathBuf, (String, String)> {
+        let mut sources = HashMap::new();
+        for path in files(&self.rockspecs_dir()) {
+            if path.extension().is_none_or(|e| e != "rockspec") {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if let Ok(rockspec) = Rockspec::parse_lua(&content) {
+                sources.insert(
+                    self.source_path(&rockspec.source.url),
+                    (rockspec.package, rockspec.version),
+                );
+            }
+        }
+        sources
+    }
+}
+
+/// Files under `dir`, except metadata files
+fn files(dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && !e.file_name().to_string_lossy().ends_with(".meta"))
+        .map(|e| e.into_path())
+        .collect()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cache() -> (TempDir, Cache) {
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        cache.init().unwrap();
+        (temp, cache)
+    }
+
+    fn set_age(path: &Path, days: u64) {
+        let time = SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_entries_are_attributed() {
+        let (_temp, cache) = cache();
+        let url = "https://example.com/penlight-1.14.0.tar.gz";
+        cache
+            .write(
+                &cache.rockspec_path("penlight", "1.14.0-1"),
+                format!(
+                    "package = 'penlight'\nversion = '1.14.0-1'\nsource = {{ url = '{}' }}\n",
+                    url
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        cache.write(&cache.source_path(url), b"archive").unwrap();
+        let recorded = "https://example.com/lfs.tar.gz";
+        cache.write(&cache.source_path(recorded), b"lfs").unwrap();
+        cache
+            .record_source(recorded, "luafilesystem", "1.8.0-1", "sha256:00")
+            .unwrap();
+        cache
+            .write(&cache.rockspecs_dir().join("manifest.json"), b"{}")
+            .unwrap();
+        cache
+            .write(
+                &cache.rust_build_path("mylib", "0.1.0", "5.4", "x86_64-unknown-linux-gnu"),
+                b"so",
+            )
+            .unwrap();
+
+        let entries = cache.entries().unwrap();
+        let find = |path: &Path| entries.iter().find(|e| e.path == path).unwrap();
+        let package = |name: &str, version: &str| Some((name.to_string(), version.to_string()));
+
+        let rockspec = find(&cache.rockspec_path("penlight", "1.14.0-1"));
+        assert_eq!(rockspec.kind, CacheKind::Rockspec);
+        assert_eq!(rockspec.package, package("penlight", "1.14.0-1"));
+        assert_eq!(
+            find(&cache.source_path(url)).package,
+            package("penlight", "1.14.0-1")
+        );
+        let lfs = find(&cache.source_path(recorded));
+        assert_eq!(lfs.package, package("luafilesystem", "1.8.0-1"));
+        // The metadata file counts towards its entry
+        assert!(lfs.size > 3);
+        assert_eq!(
+            find(&cache.rockspecs_dir().join("manifest.json")).kind,
+            CacheKind::Index
+        );
+        assert!(entries
+            .iter()
+            .any(|e| e.kind == CacheKind::RustBuild && e.package == package("mylib", "0.1.0")));
+        assert_eq!(entries.len(), 5);
+    }
+
+    #[test]
+    fn test_prune_by_age_and_size() {
+        let (_temp, cache) = cache();
+        let old = cache.source_path("https://example.com/old.tar.gz");
+        let middle = cache.source_path("https://example.com/middle.tar.gz");
+        let new = cache.source_path("https://example.com/new.tar.gz");
+        for (path, days) in [(&old, 40), (&middle, 10), (&new, 0)] {
+            cache.write(path, &[0; 100]).unwrap();
+            set_age(path, days);
+        }
+
+        let result = cache
+            .prune(Some(Duration::from_secs(30 * 24 * 60 * 60)), None)
+            .unwrap();
+        assert_eq!(result.files_removed, 1);
+        assert!(!old.exists());
+
+        let result = cache.prune(None, Some(150)).unwrap();
+        assert_eq!(result.bytes_freed, 100);
+        assert!(!middle.exists());
+        assert!(new.exists());
+
+        cache.clear().unwrap();
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_verify_sources() {
+        let (_temp, cache) = cache();
+        let good = "https://example.com/good.tar.gz";
+        let bad = "https://example.com/bad.tar.gz";
+        cache.write(&cache.source_path(good), b"good").unwrap();
+        cache.write(&cache.source_path(bad), b"tampered").unwrap();
+        let good_checksum = Cache::checksum(&cache.source_path(good)).unwrap();
+        cache
+            .record_source(good, "good", "1.0-1", &good_checksum)
+            .unwrap();
+
+        let (checked, corrupt) = cache
+            .verify_sources([(bad, "bad", "1.0-1", "sha256:00")])
+            .unwrap();
+        assert_eq!(checked, 2);
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(corrupt[0].path, cache.source_path(bad));
+        assert_eq!(
+            corrupt[0].package,
+            Some(("bad".to_string(), "1.0-1".to_string()))
+        );
+    }
+}
@@ -1,10 +1,15 @@
+pub mod inventory;
+
 use crate::core::path::{cache_dir, ensure_dir};
 use crate::core::{LpmError, LpmResult};
+use crate::luarocks::registry::{Registry, DEFAULT_REGISTRY};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 
 /// Package cache manager
 #[derive(Clone)]
@@ -12,6 +17,51 @@ pub struct Cache {
     root: PathBuf,
 }
 
+/// When a cached download was fetched, and how to ask the server whether it changed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntryMeta {
+    /// URL the entry was downloaded from (empty if unknown)
+    #[serde(default)]
+    pub url: String,
+    pub fetched_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// Package a cached source archive was installed for, and its version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Checksum the lockfile pins a cached source archive to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+impl CacheEntryMeta {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            fetched_at: Utc::now(),
+            etag: None,
+            last_modified: None,
+            package: None,
+            version: None,
+            checksum: None,
+        }
+    }
+
+    /// The package version the entry belongs to, if recorded
+    pub fn package(&self) -> Option<(String, String)> {
+        Some((self.package.clone()?, self.version.clone()?))
+    }
+
+    /// Time since the entry was fetched or last revalidated
+    pub fn age(&self) -> Duration {
+        (Utc::now() - self.fetched_at).to_std().unwrap_or_default()
+    }
+}
+
 impl Cache {
     /// Create a new cache instance
     pub fn new(cache_root: PathBuf) -> LpmResult<Self> {
@@ -24,6 +74,11 @@ impl Cache {
         Self::new(cache_dir()?)
     }
 
+    /// The cache directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
     /// Get the LuaRocks cache directory
     pub fn luarocks_dir(&self) -> PathBuf {
         self.root.join("luarocks")
@@ -39,6 +94,25 @@ impl Cache {
         self.luarocks_dir().join("sources")
     }
 
+    /// Get the git repositories cache directory
+    pub fn git_dir(&self) -> PathBuf {
+        self.root.join("git")
+    }
+
+    /// Get the cached bare mirror of a git repository
+    pub fn git_mirror_path(&self, url: &str) -> PathBuf {
+        self.git_dir()
+            .join("repos")
+            .join(format!("{}.git", Self::url_hash(url)))
+    }
+
+    /// Get the cached archive of a git commit
+    pub fn git_archive_path(&self, url: &str, commit: &str) -> PathBuf {
+        self.git_dir()
+            .join("archives")
+            .join(format!("{}-{}.tar.gz", Self::url_hash(url), commit))
+    }
+
     /// Get the Rust builds cache directory
     pub fn rust_builds_dir(&self) -> PathBuf {
         self.root.join("rust-builds")
@@ -53,6 +127,24 @@ impl Cache {
         Ok(())
     }
 
+    /// Get cached manifest path for a registry
+    ///
+    /// The default registry keeps the `manifest.json` name used before
+    /// registries were configurable, so existing caches stay valid offline.
+    pub fn manifest_path(&self, registry: &Registry) -> PathBuf {
+        if registry.name == DEFAULT_REGISTRY {
+            self.rockspecs_dir().join("manifest.json")
+        } else {
+            let hash = Self::url_hash(&registry.base_url);
+            self.rockspecs_dir().join(format!("manifest-{}.json", hash))
+        }
+    }
+
+    /// Get the package index built from a registry's cached manifest
+    pub fn index_path(&self, registry: &Registry) -> PathBuf {
+        self.manifest_path(registry).with_extension("index")
+    }
+
     /// Get the cached path for a rockspec file
     pub fn rockspec_path(&self, package: &str, version: &str) -> PathBuf {
         let filename = format!("{}-{}.rockspec", package, version);
@@ -108,6 +200,54 @@ impl Cache {
         Ok(())
     }
 
+    /// Freshness metadata of a cached file, if the file is cached
+    ///
+    /// Files cached without metadata count as fetched when they were last modified.
+    pub fn read_meta(&self, path: &Path) -> Option<CacheEntryMeta> {
+        let modified = fs::metadata(path).ok()?.modified().ok()?;
+        fs::read_to_string(Self::meta_path(path))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .or_else(|| {
+                Some(CacheEntryMeta {
+                    fetched_at: modified.into(),
+                    ..CacheEntryMeta::new("")
+                })
+            })
+    }
+
+    /// Record freshness metadata next to a cached file
+    pub fn write_meta(&self, path: &Path, meta: &CacheEntryMeta) -> LpmResult<()> {
+        let content = serde_json::to_string_pretty(meta)
+            .map_err(|e| LpmError::Cache(format!("Failed to serialize cache metadata: {}", e)))?;
+        self.write(&Self::meta_path(path), content.as_bytes())
+    }
+
+    /// Record which package version a cached source archive belongs to, and
+    /// the checksum its lockfile pins it to (see `lpm cache verify`)
+    pub fn record_source(
+        &self,
+        url: &str,
+        package: &str,
+        version: &str,
+        checksum: &str,
+    ) -> LpmResult<()> {
+        let meta = CacheEntryMeta {
+            package: Some(package.to_string()),
+            version: Some(version.to_string()),
+            checksum: Some(checksum.to_string()),
+            ..CacheEntryMeta::new(url)
+        };
+        self.write_meta(&self.source_path(url), &meta)
+    }
+
+    /// `manifest.json` → `manifest.json.meta`
+    fn meta_path(path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".meta");
+        path.with_file_name(name)
+    }
+
     /// Calculate SHA-256 checksum of a file
     pub fn checksum(path: &Path) -> LpmResult<String> {
         let data = fs::read(path)?;
@@ -207,108 +347,12 @@ impl Cache {
     }
 
     /// Clean old cache entries based on age and size
+    ///
+    /// Removes entries older than `max_age_days`, then the oldest entries until
+    /// the whole cache fits in `max_size_mb` (see `prune`).
     pub fn clean(&self, max_age_days: u64, max_size_mb: u64) -> LpmResult<CacheCleanResult> {
-        use std::time::{Duration, SystemTime};
-
-        let max_age = Duration::from_secs(max_age_days * 24 * 60 * 60);
-        let max_size_bytes = max_size_mb * 1024 * 1024;
-        let now = SystemTime::now();
-
-        let mut result = CacheCleanResult {
-            files_removed: 0,
-            bytes_freed: 0,
-        };
-
-        // Clean rockspecs
-        result += self.clean_directory(&self.rockspecs_dir(), &now, max_age, max_size_bytes)?;
-
-        // Clean sources
-        result += self.clean_directory(&self.sources_dir(), &now, max_age, max_size_bytes)?;
-
-        // Clean Rust builds
-        result += self.clean_directory(&self.rust_builds_dir(), &now, max_age, max_size_bytes)?;
-
-        Ok(result)
-    }
-
-    /// Clean a directory based on age and total size
-    fn clean_directory(
-        &self,
-        dir: &Path,
-        now: &SystemTime,
-        max_age: Duration,
-        max_size_bytes: u64,
-    ) -> LpmResult<CacheCleanResult> {
-        use walkdir::WalkDir;
-
-        if !dir.exists() {
-            return Ok(CacheCleanResult::default());
-        }
-
-        let mut files: Vec<(PathBuf, SystemTime, u64)> = Vec::new();
-        let mut total_size = 0u64;
-
-        // Collect all files with metadata
-        for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
-            if entry.file_type().is_file() {
-                if let Ok(metadata) = entry.metadata() {
-                    if let Ok(modified) = metadata.modified() {
-                        let size = metadata.len();
-                        files.push((entry.path().to_path_buf(), modified, size));
-                        total_size += size;
-                    }
-                }
-            }
-        }
-
-        // Sort by modification time (oldest first)
-        files.sort_by_key(|(_, modified, _)| *modified);
-
-        let mut result = CacheCleanResult::default();
-
-        // Remove files older than max_age
-        for (path, modified, size) in &files {
-            if let Ok(age) = now.duration_since(*modified) {
-                if age > max_age {
-                    if let Err(e) = fs::remove_file(path) {
-                        eprintln!(
-                            "Warning: Failed to remove old cache file {}: {}",
-                            path.display(),
-                            e
-                        );
-                    } else {
-                        result.files_removed += 1;
-                        result.bytes_freed += size;
-                        total_size -= size;
-                    }
-                }
-            }
-        }
-
-        // If still over size limit, remove oldest files
-        if total_size > max_size_bytes {
-            let target_size = max_size_bytes;
-            for (path, _, size) in &files {
-                if total_size <= target_size {
-                    break;
-                }
-                if path.exists() {
-                    if let Err(e) = fs::remove_file(path) {
-                        eprintln!(
-                            "Warning: Failed to remove cache file {}: {}",
-                            path.display(),
-                            e
-                        );
-                    } else {
-                        result.files_removed += 1;
-                        result.bytes_freed += size;
-                        total_size -= size;
-                    }
-                }
-            }
-        }
-
-        Ok(result)
+        let max_age = Duration::from_secs(max_age_days.saturating_mul(24 * 60 * 60));
+        self.prune(Some(max_age), Some(max_size_mb.saturating_mul(1024 * 1024)))
     }
 }
 
@@ -377,6 +421,28 @@ mod tests {
         assert!(cache.rust_builds_dir().ends_with("rust-builds"));
     }
 
+    #[test]
+    fn test_cache_entry_meta() {
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        let path = cache.rockspec_path("test", "1.0-1");
+        assert!(cache.read_meta(&path).is_none());
+
+        // Without metadata, the file's modification time is used
+        cache.write(&path, b"package = 'test'").unwrap();
+        let meta = cache.read_meta(&path).unwrap();
+        assert!(meta.url.is_empty());
+        assert!(meta.age() < Duration::from_secs(60));
+
+        let meta = CacheEntryMeta {
+            etag: Some("\"abc\"".to_string()),
+            ..CacheEntryMeta::new("https://example.com/test-1.0-1.rockspec")
+        };
+        cache.write_meta(&path, &meta).unwrap();
+        assert_eq!(cache.read_meta(&path), Some(meta));
+        assert!(path.with_file_name("test-1.0-1.rockspec.meta").exists());
+    }
+
     #[test]
     fn test_cache_rockspec_path() {
         let temp = TempDir::new().unwrap();
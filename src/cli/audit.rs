@@ -93,6 +93,7 @@ mod tests {
             size: Some(1000),
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         lockfile.add_package("test-pkg".to_string(), locked_pkg);
         lockfile.save(temp.path()).unwrap();
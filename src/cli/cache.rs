@@ -0,0 +1,268 @@
+use clap::Subcommand;
+use lpm::cache::inventory::{CacheEntry, CacheKind};
+use lpm::cache::Cache;
+use lpm::config::Config;
+use lpm::core::path::find_project_root;
+use lpm::core::version::parse_constraint;
+use lpm::core::{LpmError, LpmResult};
+use lpm::luarocks::client::LuaRocksClient;
+use lpm::package::lockfile::Lockfile;
+use lpm::package::manifest::PackageManifest;
+use lpm::package::offline;
+use lpm::resolver::DependencyResolver;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::time::Duration;
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Show what the cache holds, by kind and by package
+    #[command(alias = "list")]
+    Ls,
+    /// Re-hash cached sources against the checksums their lockfiles pin
+    Verify {
+        /// Delete sources that don't match, so they are downloaded again
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Remove old entries, then the oldest ones until the cache fits a size
+    Prune {
+        /// Remove entries not used for more than this many days
+        #[arg(long, value_name = "DAYS", required_unless_present = "max_size")]
+        max_age: Option<u64>,
+        /// Shrink the cache to at most this many megabytes
+        #[arg(long, value_name = "MB")]
+        max_size: Option<u64>,
+    },
+    /// Remove everything from the cache
+    Clear,
+    /// Download packages and their dependencies into the cache without
+    /// installing them, e.g. to pre-warm a CI cache for `lpm ci --offline`
+    Add {
+        /// `name`, `name@version` or `name@constraint`
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+}
+
+pub async fn run(command: CacheCommands) -> LpmResult<()> {
+    let config = Config::load()?;
+    let cache = Cache::new(config.get_cache_dir()?)?;
+
+    match command {
+        CacheCommands::Ls => list(&cache),
+        CacheCommands::Verify { remove } => verify(&cache, remove),
+        CacheCommands::Prune { max_age, max_size } => {
+            let max_age =
+                max_age.map(|days| Duration::from_secs(days.saturating_mul(24 * 60 * 60)));
+            let max_size = max_size.map(|mb| mb.saturating_mul(1024 * 1024));
+            let result = cache.prune(max_age, max_size)?;
+            println!(
+                "✓ Removed {} cache entries ({})",
+                result.files_removed,
+                format_size(result.bytes_freed)
+            );
+            Ok(())
+        }
+        CacheCommands::Clear => {
+            let result = cache.clear()?;
+            println!(
+                "✓ Cleared {} cache entries ({}) from {}",
+                result.files_removed,
+                format_size(result.bytes_freed),
+                cache.root().display()
+            );
+            Ok(())
+        }
+        CacheCommands::Add { packages } => add(&config, cache, &packages).await,
+    }
+}
+
+fn list(cache: &Cache) -> LpmResult<()> {
+    let entries = cache.entries()?;
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    println!("Cache: {} ({})", cache.root().display(), format_size(total));
+    if entries.is_empty() {
+        println!("  (empty)");
+        return Ok(());
+    }
+
+    let mut by_kind: BTreeMap<CacheKind, (usize, u64)> = BTreeMap::new();
+    for entry in &entries {
+        let (count, size) = by_kind.entry(entry.kind).or_default();
+        *count += 1;
+        *size += entry.size;
+    }
+    println!();
+    for (kind, (count, size)) in &by_kind {
+        println!(
+            "  {:<12} {:>5} {:<7} {:>10}",
+            kind.name(),
+            count,
+            if *count == 1 { "entry" } else { "entries" },
+            format_size(*size)
+        );
+    }
+
+    let mut by_package: BTreeMap<(String, String), Vec<&CacheEntry>> = BTreeMap::new();
+    let mut unattributed = 0u64;
+    for entry in &entries {
+        match &entry.package {
+            Some(package) => by_package.entry(package.clone()).or_default().push(entry),
+            None if entry.kind == CacheKind::Source => unattributed += entry.size,
+            None => {}
+        }
+    }
+    if !by_package.is_empty() {
+        println!();
+        println!("Packages:");
+        for ((name, version), entries) in &by_package {
+            let mut sizes: BTreeMap<CacheKind, u64> = BTreeMap::new();
+            for entry in entries {
+                *sizes.entry(entry.kind).or_default() += entry.size;
+            }
+            let sizes: Vec<String> = sizes
+                .iter()
+                .map(|(kind, size)| format!("{} {}", kind.name(), format_size(*size)))
+                .collect();
+            println!("  {} {}: {}", name, version, sizes.join(", "));
+        }
+    }
+    if unattributed > 0 {
+        println!(
+            "  (sources of unknown packages: {})",
+            format_size(unattributed)
+        );
+    }
+    Ok(())
+}
+
+fn verify(cache: &Cache, remove: bool) -> LpmResult<()> {
+    // The current project's lockfile, if there is one, besides the checksums
+    // recorded when sources were installed
+    let lockfile = env::current_dir()
+        .ok()
+        .and_then(|dir| find_project_root(&dir).ok())
+        .map(|root| Lockfile::load(&root))
+        .transpose()?
+        .flatten();
+    let locked: Vec<(&str, &str, &str, &str)> = lockfile
+        .iter()
+        .flat_map(|lockfile| &lockfile.packages)
+        .filter(|(_, pkg)| pkg.is_registry())
+        .filter_map(|(name, pkg)| {
+            let url = pkg.source_url.as_deref()?;
+            Some((
+                url,
+                name.as_str(),
+                pkg.version.as_str(),
+                pkg.checksum.as_str(),
+            ))
+        })
+        .collect();
+
+    let (checked, corrupt) = cache.verify_sources(locked)?;
+    if corrupt.is_empty() {
+        println!("✓ {} cached source(s) match their checksums", checked);
+        return Ok(());
+    }
+
+    for source in &corrupt {
+        let package = source
+            .package
+            .as_ref()
+            .map(|(name, version)| format!("{}@{}", name, version))
+            .unwrap_or_else(|| "unknown package".to_string());
+        println!(
+            "  ❌ {} ({}): expected {}, got {}",
+            package,
+            source.path.display(),
+            source.expected,
+            source.actual
+        );
+        if remove {
+            cache.remove_entry(&source.path)?;
+        }
+    }
+    if remove {
+        println!(
+            "✓ Removed {} corrupted source(s); they will be downloaded again",
+            corrupt.len()
+        );
+        return Ok(());
+    }
+    Err(LpmError::Cache(format!(
+        "{} of {} cached source(s) don't match their checksums; run `lpm cache verify --remove` to delete them",
+        corrupt.len(),
+        checked
+    )))
+}
+
+async fn add(config: &Config, cache: Cache, specs: &[String]) -> LpmResult<()> {
+    let mut roots = HashMap::new();
+    for spec in specs {
+        let (name, constraint) = match spec.split_once('@') {
+            Some((name, constraint)) => {
+                parse_constraint(constraint).map_err(|e| {
+                    LpmError::Version(format!(
+                        "Invalid version constraint '{}': {}",
+                        constraint, e
+                    ))
+                })?;
+                (name, constraint)
+            }
+            None => (spec.as_str(), "*"),
+        };
+        roots.insert(name.to_string(), constraint.to_string());
+    }
+
+    let client = LuaRocksClient::new(config, cache);
+    let manifest = client.fetch_manifest().await?;
+    let mut resolver = DependencyResolver::new(manifest.clone()).with_root_name("lpm cache add");
+    // Inside a project, pick the versions its Lua can use
+    let project = env::current_dir()
+        .ok()
+        .and_then(|dir| find_project_root(&dir).ok())
+        .and_then(|root| PackageManifest::load(&root).ok());
+    if let Some(project) = &project {
+        resolver = resolver.with_lua_version(&project.lua_version);
+    }
+    let resolved = resolver.resolve(&roots).await?;
+
+    let mut resolved: Vec<_> = resolved.into_iter().collect();
+    resolved.sort_by(|a, b| a.0.cmp(&b.0));
+    for (name, version) in &resolved {
+        let version = version.to_string();
+        offline::prefetch(&client, &manifest, name, &version).await?;
+        println!("✓ Cached {} {}", name, version);
+    }
+    Ok(())
+}
+
+/// `1536` → `1.5 KB`
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MB");
+    }
+}
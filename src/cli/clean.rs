@@ -1,4 +1,4 @@
-use lpm::core::path::{find_project_root, lua_modules_dir};
+use lpm::core::path::{find_project_root, is_tree_entry, lua_modules_dir};
 use lpm::core::{LpmError, LpmResult};
 use std::env;
 use std::fs;
@@ -37,11 +37,11 @@ fn count_packages(lua_modules: &std::path::Path) -> LpmResult<usize> {
             let entry = entry?;
             let path = entry.path();
 
-            // Skip .lpm metadata directory
+            // Skip the .lpm metadata and the module tree
             if path
                 .file_name()
                 .and_then(|n| n.to_str())
-                .map(|n| n == ".lpm")
+                .map(is_tree_entry)
                 .unwrap_or(false)
             {
                 continue;
@@ -85,11 +85,13 @@ mod tests {
 
         // Create package directories
         fs::create_dir_all(lua_modules.join("package1")).unwrap();
-        // Create .lpm metadata directory (should be skipped)
+        // Create .lpm metadata and module tree directories (should be skipped)
         fs::create_dir_all(lua_modules.join(".lpm")).unwrap();
+        fs::create_dir_all(lua_modules.join("share/lua/5.4")).unwrap();
+        fs::create_dir_all(lua_modules.join("lib/lua/5.4")).unwrap();
 
         let count = count_packages(&lua_modules).unwrap();
-        assert_eq!(count, 1); // .lpm should be skipped
+        assert_eq!(count, 1); // .lpm and the tree should be skipped
     }
 
     #[test]
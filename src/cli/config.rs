@@ -0,0 +1,98 @@
+use clap::Subcommand;
+use lpm::config::layers::{
+    format_value, project_config_file, set_in_file, unset_in_file, ConfigLayers, Source,
+};
+use lpm::core::path::config_file;
+use lpm::core::{LpmError, LpmResult};
+use std::env;
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print the value of a setting
+    Get {
+        /// Setting, e.g. `index_ttl` or `lua_binary_sources.5.4.8`
+        key: String,
+    },
+    /// Set a setting in the user config, or the project's .lpmrc.yaml
+    Set {
+        key: String,
+        /// Value; lists and mappings are written as YAML, e.g. `[/opt/openssl]`
+        value: String,
+        /// Write to the project's .lpmrc.yaml
+        #[arg(long)]
+        project: bool,
+    },
+    /// Remove a setting from the user config, or the project's .lpmrc.yaml
+    Unset {
+        key: String,
+        /// Remove from the project's .lpmrc.yaml
+        #[arg(long)]
+        project: bool,
+    },
+    /// List every setting in effect and where it comes from
+    List,
+}
+
+pub fn run(command: ConfigCommands) -> LpmResult<()> {
+    let current_dir = env::current_dir()
+        .map_err(|e| LpmError::Path(format!("Failed to get current directory: {}", e)))?;
+    let mut layers = ConfigLayers::load(Some(&current_dir))?;
+
+    match command {
+        ConfigCommands::Get { key } => match layers.get(&key)? {
+            Some((value, _)) => {
+                println!("{}", format_value(&value));
+                Ok(())
+            }
+            None => Err(LpmError::Config(format!("'{}' is not set", key))),
+        },
+        ConfigCommands::Set {
+            key,
+            value,
+            project,
+        } => {
+            let source = target(project, &current_dir)?;
+            set_in_file(&mut layers, &source, &key, &value)?;
+            println!("✓ Set {} in {}", key, source);
+            warn_overridden(&layers, &key, &source)
+        }
+        ConfigCommands::Unset { key, project } => {
+            let source = target(project, &current_dir)?;
+            if unset_in_file(&mut layers, &source, &key)? {
+                println!("✓ Removed {} from {}", key, source);
+                warn_overridden(&layers, &key, &source)
+            } else {
+                println!("{} is not set in {}", key, source);
+                Ok(())
+            }
+        }
+        ConfigCommands::List => {
+            for (key, value, source) in layers.list() {
+                println!("{} = {}  ({})", key, format_value(&value), source);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The config file `set` and `unset` write to
+fn target(project: bool, current_dir: &std::path::Path) -> LpmResult<Source> {
+    if !project {
+        return Ok(Source::User(config_file()?));
+    }
+    project_config_file(current_dir)
+        .map(Source::Project)
+        .ok_or_else(|| {
+            LpmError::Config(
+                "Not in an LPM project (no package.yaml found); run without --project to change the user config"
+                    .to_string(),
+            )
+        })
+}
+
+fn warn_overridden(layers: &ConfigLayers, key: &str, source: &Source) -> LpmResult<()> {
+    for above in layers.overrides(key, source)? {
+        eprintln!("⚠️  {} is overridden by {}", key, above);
+    }
+    Ok(())
+}
@@ -6,17 +6,22 @@ use lpm::config::Config;
 use lpm::core::path::{
     ensure_dir, find_project_root, global_bin_dir, global_dir, global_lua_modules_dir,
 };
-use lpm::core::version::parse_constraint;
+use lpm::core::version::{parse_constraint, Version};
 use lpm::core::{LpmError, LpmResult};
 use lpm::lua_version::compatibility::PackageCompatibility;
 use lpm::lua_version::detector::LuaVersionDetector;
 use lpm::luarocks::client::LuaRocksClient;
 use lpm::package::conflict_checker::ConflictChecker;
+use lpm::package::dependency::DependencySpec;
 use lpm::package::installer::PackageInstaller;
+use lpm::package::local;
 use lpm::package::lockfile::Lockfile;
 use lpm::package::lockfile_builder::LockfileBuilder;
+use lpm::package::lockfile_drift::LockfileDrift;
 use lpm::package::manifest::PackageManifest;
-use lpm::package::rollback::with_rollback_async;
+use lpm::package::offline;
+use lpm::package::pipeline::InstallPipeline;
+use lpm::package::rollback::{self, with_rollback_async};
 use lpm::path_setup::loader::PathSetup;
 use lpm::resolver::DependencyResolver;
 use lpm::workspace::Workspace;
@@ -25,6 +30,7 @@ use std::env;
 use std::fs;
 use std::path::Path;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     package: Option<String>,
     dev: bool,
@@ -33,7 +39,27 @@ pub async fn run(
     dev_only: bool,
     global: bool,
     interactive: bool,
+    offline: bool,
+    frozen: bool,
 ) -> LpmResult<()> {
+    if offline && (global || interactive) {
+        return Err(LpmError::Package(
+            "--offline cannot be combined with --global or --interactive, which need the registry"
+                .to_string(),
+        ));
+    }
+    if frozen && (package.is_some() || path.is_some() || global || interactive) {
+        return Err(LpmError::Package(
+            "--frozen installs exactly what package.lock records and cannot add packages"
+                .to_string(),
+        ));
+    }
+    if no_dev && dev_only {
+        return Err(LpmError::Package(
+            "Cannot use both --no-dev and --dev-only flags".to_string(),
+        ));
+    }
+
     // Handle global installation (install to system-wide location).
     if global {
         if package.is_none() {
@@ -90,24 +116,48 @@ pub async fn run(
             // Install from local path
             (None, Some(local_path)) => {
                 install_from_path(&local_path, dev, &mut manifest)?;
+                // Lock and link it (and whatever it depends on) like any other dependency
+                let declared = declared_dependencies(&manifest, workspace.as_ref());
+                let lockfile = generate_lockfile(install_root, &declared, false).await?;
+                install_from_lockfile(install_root, &lockfile, &declared, false, false, false)
+                    .await?;
+                PathSetup::install_loader(&project_root)?;
             }
             // Install specific package
             (Some(pkg_spec), None) => {
-                install_package(&project_root, &pkg_spec, dev, &mut manifest).await?;
+                install_package(&project_root, &pkg_spec, dev, &mut manifest, offline).await?;
             }
             // Install all dependencies
+            (None, None) if frozen => {
+                // Never touches package.yaml or package.lock
+                frozen_install(
+                    install_root,
+                    &manifest,
+                    workspace.as_ref(),
+                    no_dev,
+                    dev_only,
+                    offline,
+                )
+                .await?;
+                PathSetup::install_loader(&project_root)?;
+                return Ok(());
+            }
+            (None, None) if offline => {
+                // Resolve purely from package.lock and the cache; the lockfile stays as is
+                let lockfile = offline::require_lockfile(Lockfile::load(install_root)?)?;
+                install_from_lockfile(install_root, &lockfile, &manifest, no_dev, dev_only, true)
+                    .await?;
+                PathSetup::install_loader(&project_root)?;
+            }
             (None, None) => {
-                if let Some(ref ws) = workspace {
-                    // Install workspace dependencies (shared + all packages)
-                    install_workspace_dependencies(install_root, ws, no_dev, dev_only).await?;
-                } else {
-                    // Install single package dependencies
-                    install_all_dependencies(install_root, &manifest, no_dev, dev_only).await?;
-                }
+                // Resolve the whole graph (workspace members share the root's
+                // lua_modules) into package.lock, then install exactly that
+                let declared = declared_dependencies(&manifest, workspace.as_ref());
+                let lockfile = generate_lockfile(install_root, &declared, no_dev).await?;
+                install_from_lockfile(install_root, &lockfile, &declared, no_dev, dev_only, false)
+                    .await?;
                 // Generate loader after installation
                 PathSetup::install_loader(&project_root)?;
-                // Generate lockfile
-                generate_lockfile(install_root, &manifest, no_dev).await?;
             }
             // Invalid combination
             (Some(_), Some(_)) => {
@@ -155,7 +205,7 @@ async fn install_global(package_spec: String) -> LpmResult<()> {
     let cache = Cache::new(config.get_cache_dir()?)?;
     let client = LuaRocksClient::new(&config, cache.clone());
     let luarocks_manifest = client.fetch_manifest().await?;
-    let resolver = DependencyResolver::new(luarocks_manifest);
+    let resolver = DependencyResolver::new(luarocks_manifest.clone());
 
     let constraint_str = version_constraint
         .clone()
@@ -181,19 +231,16 @@ async fn install_global(package_spec: String) -> LpmResult<()> {
         .await?;
 
     // Extract executables from rockspec and create wrappers
-    let rockspec_url = lpm::luarocks::search_api::SearchAPI::new().get_rockspec_url(
-        &package_name,
-        &version_str,
-        None,
-    );
+    let rockspec_url = client.rockspec_url(&luarocks_manifest, &package_name, &version_str);
     let rockspec_content = client.download_rockspec(&rockspec_url).await?;
     let rockspec = client.parse_rockspec(&rockspec_content)?;
 
     create_global_executables(
         &package_name,
         &package_path,
+        &installer.layout().lua_dir(),
         &global_bin,
-        &global_lua_modules,
+        &global_root,
         &rockspec,
     )
     .await?;
@@ -213,16 +260,47 @@ async fn install_global(package_spec: String) -> LpmResult<()> {
 async fn create_global_executables(
     package_name: &str,
     package_path: &std::path::Path,
+    lua_dir: &std::path::Path,
     global_bin: &std::path::Path,
-    global_lua_modules: &std::path::Path,
+    global_root: &std::path::Path,
     rockspec: &lpm::luarocks::rockspec::Rockspec,
 ) -> LpmResult<()> {
+    let executables = find_executables(package_name, package_path, lua_dir, rockspec);
+
+    // Track executable names for metadata.
+    let mut exe_names = Vec::new();
+
+    // Create wrapper scripts for each executable.
+    for (exe_name, script_path) in executables {
+        create_executable_wrapper(&exe_name, &script_path, global_bin, global_root)?;
+        exe_names.push(exe_name);
+    }
+
+    // Save metadata about this globally installed package.
+    save_global_package_metadata(package_name, &exe_names)?;
+
+    Ok(())
+}
+
+/// The scripts of a globally installed package to create wrappers for, by executable name
+///
+/// `package_path` is the package's directory, `lua_dir` the tree's `share/lua/<version>`.
+fn find_executables(
+    package_name: &str,
+    package_path: &std::path::Path,
+    lua_dir: &std::path::Path,
+    rockspec: &lpm::luarocks::rockspec::Rockspec,
+) -> Vec<(String, std::path::PathBuf)> {
     let mut executables = Vec::new();
 
     // First, check rockspec build.install.bin for explicitly defined executables.
+    // They are installed as bin/<name> in the package's directory.
     for (exe_name, source_path) in &rockspec.build.install.bin {
+        let installed = package_path.join("bin").join(exe_name);
         let full_path = package_path.join(source_path);
-        if full_path.exists() && full_path.is_file() {
+        if installed.is_file() {
+            executables.push((exe_name.clone(), installed));
+        } else if full_path.exists() && full_path.is_file() {
             executables.push((exe_name.clone(), full_path));
         } else {
             // Try relative to package root if absolute path doesn't exist.
@@ -233,7 +311,8 @@ async fn create_global_executables(
         }
     }
 
-    // Check for common executable locations.
+    // Check for common executable locations. Pure Lua packages have their
+    // modules in the tree's share/lua, not in the package's directory.
     let possible_paths = vec![
         package_path.join("bin").join(package_name),
         package_path
@@ -242,6 +321,9 @@ async fn create_global_executables(
         package_path.join(format!("{}.lua", package_name)),
         package_path.join("cli.lua"),
         package_path.join("main.lua"),
+        lua_dir.join(format!("{}.lua", package_name)),
+        lua_dir.join("cli.lua"),
+        lua_dir.join("main.lua"),
     ];
 
     for path in possible_paths {
@@ -276,25 +358,19 @@ async fn create_global_executables(
     // If no executables found, create one with the package name.
     if executables.is_empty() {
         // Try to find a main entry point (init.lua).
-        let main_script = package_path.join("init.lua");
-        if main_script.exists() {
+        let main_script = [
+            package_path.join("init.lua"),
+            lua_dir.join(package_name).join("init.lua"),
+            lua_dir.join("init.lua"),
+        ]
+        .into_iter()
+        .find(|path| path.is_file());
+        if let Some(main_script) = main_script {
             executables.push((package_name.to_string(), main_script));
         }
     }
 
-    // Track executable names for metadata.
-    let mut exe_names = Vec::new();
-
-    // Create wrapper scripts for each executable.
-    for (exe_name, script_path) in executables {
-        create_executable_wrapper(&exe_name, &script_path, global_bin, global_lua_modules)?;
-        exe_names.push(exe_name);
-    }
-
-    // Save metadata about this globally installed package.
-    save_global_package_metadata(package_name, &exe_names)?;
-
-    Ok(())
+    executables
 }
 
 /// Save metadata about a globally installed package
@@ -324,11 +400,13 @@ fn save_global_package_metadata(package_name: &str, executables: &[String]) -> L
 }
 
 /// Create a wrapper script for a global executable
+///
+/// The wrapper points LUA_PATH and LUA_CPATH at the global lua_modules tree.
 fn create_executable_wrapper(
     exe_name: &str,
     script_path: &std::path::Path,
     global_bin: &std::path::Path,
-    global_lua_modules: &std::path::Path,
+    global_root: &std::path::Path,
 ) -> LpmResult<()> {
     use lpm::core::path::lpm_home;
     use lpm::lua_manager::VersionSwitcher;
@@ -350,20 +428,22 @@ fn create_executable_wrapper(
         "lua".to_string()
     };
 
-    // Create wrapper script
+    // Create wrapper script, with paths for the version of that Lua
     let wrapper_path = global_bin.join(exe_name);
+    let tree_version = PathSetup::detect_lua_version(std::path::Path::new(&lua_binary));
 
     #[cfg(unix)]
     {
         let wrapper_content = format!(
             r#"#!/bin/sh
 # Wrapper for {} (installed globally by LPM)
-export LUA_PATH="{}/?.lua;{}/?/init.lua;$LUA_PATH"
+export LUA_PATH="{}$LUA_PATH"
+export LUA_CPATH="{}$LUA_CPATH"
 exec "{}" "{}" "$@"
 "#,
             exe_name,
-            global_lua_modules.to_string_lossy(),
-            global_lua_modules.to_string_lossy(),
+            PathSetup::lua_path(global_root, tree_version.as_deref()),
+            PathSetup::lua_cpath(global_root, tree_version.as_deref()),
             lua_binary,
             script_path.to_string_lossy()
         );
@@ -380,12 +460,13 @@ exec "{}" "{}" "$@"
         let wrapper_content = format!(
             r#"@echo off
 REM Wrapper for {} (installed globally by LPM)
-set LUA_PATH={}\?.lua;{}\?\init.lua;%LUA_PATH%
+set LUA_PATH={}%LUA_PATH%
+set LUA_CPATH={}%LUA_CPATH%
 "{}" "{}" %*
 "#,
             exe_name,
-            global_lua_modules.to_string_lossy().replace('\\', "\\\\"),
-            global_lua_modules.to_string_lossy().replace('\\', "\\\\"),
+            PathSetup::lua_path(global_root, tree_version.as_deref()).replace('/', "\\"),
+            PathSetup::lua_cpath(global_root, tree_version.as_deref()).replace('/', "\\"),
             lua_binary,
             script_path.to_string_lossy()
         );
@@ -433,6 +514,7 @@ async fn install_package(
     pkg_spec: &str,
     dev: bool,
     manifest: &mut PackageManifest,
+    offline: bool,
 ) -> LpmResult<()> {
     // Parse package spec (format: "package" or "package@version" or "package@^1.2.3").
     let (package_name, version_constraint) = if let Some(at_pos) = pkg_spec.find('@') {
@@ -457,30 +539,32 @@ async fn install_package(
 
     println!("Installing package: {}", package_name);
 
-    // Resolve version using dependency resolver (handles version constraints).
-    let config = Config::load()?;
-    let cache = Cache::new(config.get_cache_dir()?)?;
-    let client = LuaRocksClient::new(&config, cache.clone());
-    let luarocks_manifest = client.fetch_manifest().await?;
-    let resolver = DependencyResolver::new(luarocks_manifest);
-
-    // Build dependency map for resolver.
-    let constraint_str = version_constraint
-        .clone()
-        .unwrap_or_else(|| "*".to_string());
-    let mut deps = HashMap::new();
-    deps.insert(package_name.clone(), constraint_str);
-
-    // Resolve to exact version using dependency resolver.
-    let resolved_versions = resolver.resolve(&deps).await?;
-    let version = resolved_versions.get(&package_name).ok_or_else(|| {
-        LpmError::Package(format!("Could not resolve version for '{}'", package_name))
-    })?;
-
-    let version_str = version.to_string();
+    let version_str = if offline {
+        locked_version(project_root, &package_name, &version_str)?
+    } else {
+        // Resolve version using dependency resolver (handles version constraints).
+        let config = Config::load()?;
+        let cache = Cache::new(config.get_cache_dir()?)?;
+        let client = LuaRocksClient::new(&config, cache.clone());
+        let luarocks_manifest = client.fetch_manifest().await?;
+        let resolver = DependencyResolver::new(luarocks_manifest)
+            .with_root_name(&manifest.name)
+            .with_lua_version(&manifest.lua_version);
+
+        // Build dependency map for resolver.
+        let mut deps = HashMap::new();
+        deps.insert(package_name.clone(), version_str);
+
+        // Resolve to exact version using dependency resolver.
+        let resolved_versions = resolver.resolve(&deps).await?;
+        let version = resolved_versions.get(&package_name).ok_or_else(|| {
+            LpmError::Package(format!("Could not resolve version for '{}'", package_name))
+        })?;
+        version.to_string()
+    };
     println!("  Resolved version: {}", version_str);
 
-    let installer = PackageInstaller::new(project_root)?;
+    let installer = PackageInstaller::new(project_root)?.with_offline(offline);
     installer.init()?;
     installer
         .install_package(&package_name, &version_str)
@@ -504,147 +588,185 @@ async fn install_package(
     Ok(())
 }
 
-async fn install_all_dependencies(
+/// The locked version of a package, checked against the requested constraint
+fn locked_version(project_root: &Path, package_name: &str, constraint: &str) -> LpmResult<String> {
+    let lockfile = offline::require_lockfile(Lockfile::load(project_root)?)?;
+    let locked = lockfile.get_package(package_name).ok_or_else(|| {
+        LpmError::Package(format!(
+            "'{}' is not in package.lock, so it cannot be resolved offline",
+            package_name
+        ))
+    })?;
+
+    let version = Version::parse(&locked.version)?;
+    if !version.satisfies(&parse_constraint(constraint)?) {
+        return Err(LpmError::Version(format!(
+            "package.lock has {}@{}, which does not satisfy '{}'; cannot resolve offline",
+            package_name, locked.version, constraint
+        )));
+    }
+
+    let config = Config::load()?;
+    let cache = Cache::new(config.get_cache_dir()?)?;
+    let missing = offline::missing_artifacts(
+        &cache,
+        [(
+            package_name,
+            locked.version.as_str(),
+            locked.source_url.as_deref(),
+        )],
+    );
+    offline::ensure_nothing_missing(&missing)?;
+
+    Ok(locked.version.clone())
+}
+
+/// Install in CI mode: fail on any drift between package.yaml and package.lock,
+/// then clean-install exactly the locked versions
+async fn frozen_install(
     project_root: &Path,
     manifest: &PackageManifest,
+    workspace: Option<&Workspace>,
     no_dev: bool,
     dev_only: bool,
+    offline: bool,
 ) -> LpmResult<()> {
-    if no_dev && dev_only {
-        return Err(LpmError::Package(
-            "Cannot use both --no-dev and --dev-only flags".to_string(),
-        ));
-    }
-
-    println!("Installing dependencies...");
-
-    // Initialize package installer.
-    let installer = PackageInstaller::new(project_root)?;
-    installer.init()?;
-
-    let mut total_deps = 0;
-    let mut installed_count = 0;
+    let lockfile = Lockfile::load(project_root)?.ok_or_else(|| {
+        LpmError::Package(
+            "--frozen requires package.lock. Run `lpm install` and commit package.lock first."
+                .to_string(),
+        )
+    })?;
 
-    // Install regular dependencies (unless dev_only flag is set).
-    if !dev_only {
-        total_deps += manifest.dependencies.len();
-        for (name, version) in &manifest.dependencies {
-            println!("  Installing {}@{}", name, version);
-            installer.install_package(name, version).await?;
-            installed_count += 1;
-        }
+    let declared = declared_dependencies(manifest, workspace);
+    let drift = LockfileDrift::detect(&declared, &lockfile, !no_dev);
+    if !drift.is_empty() {
+        return Err(LpmError::Package(format!(
+            "package.lock is out of date with package.yaml:\n{}\
+             Run `lpm install` to update package.lock, then commit it.",
+            drift
+        )));
     }
 
-    // Install dev dependencies (unless no_dev flag is set).
-    if !no_dev {
-        total_deps += manifest.dev_dependencies.len();
-        for (name, version) in &manifest.dev_dependencies {
-            println!("  Installing {}@{} (dev)", name, version);
-            installer.install_package(name, version).await?;
-            installed_count += 1;
-        }
+    // Nothing is removed unless the offline install can go through
+    if offline {
+        check_cached(&lockfile)?;
     }
 
-    if total_deps == 0 {
-        println!("No dependencies to install");
-        return Ok(());
-    }
+    // Clean install: nothing left over from earlier installs
+    rollback::clear_lua_modules(project_root)?;
 
-    println!("✓ Installed {} package(s)", installed_count);
-    if no_dev {
-        println!("  (dev dependencies skipped)");
-    } else if dev_only {
-        println!("  (only dev dependencies)");
+    install_from_lockfile(
+        project_root,
+        &lockfile,
+        &declared,
+        no_dev,
+        dev_only,
+        offline,
+    )
+    .await
+}
+
+/// The project's dependencies, plus those of every workspace member
+///
+/// Workspace members' dependencies are locked and installed at the workspace
+/// root, so their `path:` dependencies are rebased onto it.
+fn declared_dependencies(
+    manifest: &PackageManifest,
+    workspace: Option<&Workspace>,
+) -> PackageManifest {
+    let mut declared = manifest.clone();
+    if let Some(ws) = workspace {
+        for member in ws.packages.values() {
+            let member_path = member.path.to_string_lossy();
+            let rebase = |spec: &String| match DependencySpec::parse(spec) {
+                DependencySpec::Path(path) => {
+                    format!("path:{}", local::join_path(Some(&member_path), &path))
+                }
+                _ => spec.clone(),
+            };
+            for (name, constraint) in &member.manifest.dependencies {
+                declared
+                    .dependencies
+                    .entry(name.clone())
+                    .or_insert_with(|| rebase(constraint));
+            }
+            for (name, constraint) in &member.manifest.dev_dependencies {
+                declared
+                    .dev_dependencies
+                    .entry(name.clone())
+                    .or_insert_with(|| rebase(constraint));
+            }
+        }
     }
+    declared
+}
 
-    Ok(())
+/// Fail unless every artifact `lockfile` needs is cached
+///
+/// Reports every missing artifact up front instead of failing one package at a time.
+fn check_cached(lockfile: &Lockfile) -> LpmResult<()> {
+    let config = Config::load()?;
+    let cache = Cache::new(config.get_cache_dir()?)?;
+    offline::check_lockfile_cached(&cache, lockfile)
 }
 
-async fn install_workspace_dependencies(
-    install_root: &Path,
-    workspace: &Workspace,
+/// Install exactly what package.lock records
+///
+/// With `offline`, only cached artifacts are used and anything missing is
+/// reported up front.
+async fn install_from_lockfile(
+    project_root: &Path,
+    lockfile: &Lockfile,
+    manifest: &PackageManifest,
     no_dev: bool,
     dev_only: bool,
+    offline: bool,
 ) -> LpmResult<()> {
-    println!("Installing workspace dependencies...");
+    if offline {
+        println!("Installing dependencies from package.lock (offline)...");
+        check_cached(lockfile)?;
+    } else {
+        println!("Installing dependencies from package.lock...");
+    }
 
-    let installer = PackageInstaller::new(install_root)?;
+    let installer = PackageInstaller::new(project_root)?.with_offline(offline);
     installer.init()?;
 
-    // Resolve all workspace dependencies.
-    let config = Config::load()?;
-    let cache = Cache::new(config.get_cache_dir()?)?;
-    let client = LuaRocksClient::new(&config, cache.clone());
-    let luarocks_manifest = client.fetch_manifest().await?;
-    let resolver = DependencyResolver::new(luarocks_manifest);
-
-    // Collect all dependencies from workspace packages.
-    let mut all_dependencies = HashMap::new();
-    let mut all_dev_dependencies = HashMap::new();
-
-    for workspace_pkg in workspace.packages.values() {
-        // Collect regular dependencies from workspace package.
-        if !dev_only {
-            for (dep_name, dep_version) in &workspace_pkg.manifest.dependencies {
-                // Use most restrictive constraint if multiple packages specify the same dependency
-                all_dependencies
-                    .entry(dep_name.clone())
-                    .or_insert_with(|| dep_version.clone());
-            }
-        }
-
-        // Collect dev dependencies from workspace package.
-        if !no_dev {
-            for (dep_name, dep_version) in &workspace_pkg.manifest.dev_dependencies {
-                all_dev_dependencies
-                    .entry(dep_name.clone())
-                    .or_insert_with(|| dep_version.clone());
-            }
-        }
+    // Only what the selected roots need
+    let mut roots: Vec<&str> = Vec::new();
+    if !dev_only {
+        roots.extend(manifest.dependencies.keys().map(String::as_str));
     }
-
-    // Resolve versions
-    let resolved_versions = resolver.resolve(&all_dependencies).await?;
-    let resolved_dev_versions = if !all_dev_dependencies.is_empty() {
-        resolver.resolve(&all_dev_dependencies).await?
-    } else {
-        HashMap::new()
-    };
-
-    let mut installed_count = 0;
-
-    // Install regular dependencies
-    for (name, version) in &resolved_versions {
-        println!("  Installing {}@{} (shared)", name, version);
-        installer
-            .install_package(name, &version.to_string())
-            .await?;
-        installed_count += 1;
+    if !no_dev {
+        roots.extend(manifest.dev_dependencies.keys().map(String::as_str));
     }
+    let needed = lockfile.dependency_closure(roots);
 
-    if !no_dev {
-        for (name, version) in &resolved_dev_versions {
-            println!("  Installing {}@{} (shared, dev)", name, version);
-            installer
-                .install_package(name, &version.to_string())
-                .await?;
-            installed_count += 1;
+    let installed_count = InstallPipeline::new(installer)
+        .install(lockfile, &needed)
+        .await?;
+
+    if installed_count == 0 {
+        println!("No dependencies to install");
+    } else {
+        println!("✓ Installed {} locked package(s)", installed_count);
+        if no_dev {
+            println!("  (dev dependencies skipped)");
+        } else if dev_only {
+            println!("  (only dev dependencies)");
         }
     }
 
-    println!(
-        "\n✓ Installed {} shared dependency(ies) at workspace root",
-        installed_count
-    );
-
     Ok(())
 }
 
+/// Resolve the manifest into package.lock, reusing unchanged entries
 async fn generate_lockfile(
     project_root: &Path,
     manifest: &PackageManifest,
     no_dev: bool,
-) -> LpmResult<()> {
+) -> LpmResult<Lockfile> {
     // Load config to get cache directory
     let config = Config::load()?;
     let cache = Cache::new(config.get_cache_dir()?)?;
@@ -673,7 +795,7 @@ async fn generate_lockfile(
         println!("  (dev dependencies excluded)");
     }
 
-    Ok(())
+    Ok(lockfile)
 }
 
 /// Interactive package installation
@@ -711,7 +833,7 @@ pub async fn run_interactive(
         .collect();
 
     // Sort by score (higher is better)
-    matches.sort_by(|a, b| b.1.cmp(&a.1));
+    matches.sort_by_key(|m| std::cmp::Reverse(m.1));
     matches.truncate(20); // Limit to top 20 results
 
     if matches.is_empty() {
@@ -1253,13 +1375,6 @@ mod tests {
         // These tests verify the function signatures and structure
     }
 
-    #[test]
-    fn test_install_workspace_dependencies_structure() {
-        // Test install_workspace_dependencies structure
-        // The actual async tests would require network access
-        // These tests verify the function signatures and structure
-    }
-
     #[test]
     fn test_create_global_executables_structure() {
         // Test create_global_executables structure
@@ -1294,6 +1409,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_find_executables_in_lua_tree() {
+        use lpm::luarocks::rockspec::Rockspec;
+        use tempfile::TempDir;
+        let temp = TempDir::new().unwrap();
+        let package_path = temp.path().join("lua_modules/tool");
+        let lua_dir = temp.path().join("lua_modules/share/lua/5.4");
+        fs::create_dir_all(&package_path).unwrap();
+        fs::create_dir_all(lua_dir.join("other")).unwrap();
+        fs::write(lua_dir.join("other/init.lua"), "return {}").unwrap();
+
+        // A pure Lua package leaves its own directory empty
+        let rockspec = Rockspec::default();
+        assert!(find_executables("tool", &package_path, &lua_dir, &rockspec).is_empty());
+
+        fs::create_dir_all(lua_dir.join("tool")).unwrap();
+        fs::write(lua_dir.join("tool/init.lua"), "return {}").unwrap();
+        assert_eq!(
+            find_executables("tool", &package_path, &lua_dir, &rockspec),
+            vec![("tool".to_string(), lua_dir.join("tool/init.lua"))]
+        );
+
+        fs::write(lua_dir.join("tool.lua"), "print('tool')").unwrap();
+        assert_eq!(
+            find_executables("tool", &package_path, &lua_dir, &rockspec),
+            vec![("tool".to_string(), lua_dir.join("tool.lua"))]
+        );
+    }
+
     #[test]
     fn test_save_global_package_metadata() {
         // Test save_global_package_metadata
@@ -34,7 +34,7 @@ pub fn run(tree: bool, global: bool) -> LpmResult<()> {
 }
 
 fn list_global() -> LpmResult<()> {
-    use lpm::core::path::{global_lua_modules_dir, global_packages_metadata_dir};
+    use lpm::core::path::{global_lua_modules_dir, global_packages_metadata_dir, is_tree_entry};
     use serde::Deserialize;
     use std::fs;
 
@@ -52,8 +52,8 @@ fn list_global() -> LpmResult<()> {
             let path = entry.path();
             if path.is_dir() {
                 if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                    // Skip .lpm metadata directory
-                    if name != ".lpm" {
+                    // Skip the .lpm metadata and the module tree
+                    if !is_tree_entry(name) {
                         packages.push(name.to_string());
                     }
                 }
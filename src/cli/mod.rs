@@ -1,6 +1,8 @@
 pub mod audit;
 pub mod build;
+pub mod cache;
 pub mod clean;
+pub mod config;
 pub mod exec;
 pub mod generate_rockspec;
 pub mod init;
@@ -9,9 +11,11 @@ pub mod list;
 pub mod login;
 pub mod lua;
 pub mod outdated;
+pub mod owns;
 pub mod package;
 pub mod plugin;
 pub mod publish;
+pub mod registry;
 pub mod remove;
 pub mod run;
 pub mod template;
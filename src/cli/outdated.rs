@@ -1,3 +1,4 @@
+use chrono::Utc;
 use lpm::cache::Cache;
 use lpm::config::Config;
 use lpm::core::path::find_project_root;
@@ -35,6 +36,7 @@ pub async fn run() -> LpmResult<()> {
     // Create LuaRocks client
     let client = LuaRocksClient::new(&config, cache);
     let luarocks_manifest = client.fetch_manifest().await.ok();
+    print_index_age(&client);
 
     let mut outdated_count = 0;
     let mut up_to_date_count = 0;
@@ -134,6 +136,31 @@ pub async fn run() -> LpmResult<()> {
     Ok(())
 }
 
+/// Tell the user how old the package index they are looking at is
+pub fn print_index_age(client: &LuaRocksClient) {
+    let Some(refreshed_at) = client.index_refreshed_at() else {
+        return;
+    };
+    let age = (Utc::now() - refreshed_at).num_seconds().max(0);
+    if age < 60 {
+        println!("Package index refreshed just now");
+    } else {
+        println!(
+            "Package index refreshed {} ago (run with --refresh to check for newer releases)",
+            describe_age(age)
+        );
+    }
+}
+
+fn describe_age(seconds: i64) -> String {
+    let (count, unit) = match seconds {
+        s if s < 60 * 60 => (s / 60, "minute"),
+        s if s < 24 * 60 * 60 => (s / (60 * 60), "hour"),
+        s => (s / (24 * 60 * 60), "day"),
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
 #[derive(Debug)]
 enum OutdatedStatus {
     UpToDate,
@@ -191,7 +218,14 @@ async fn check_outdated(
 
     // Check if current version is outdated
     if let Some(current) = current_version {
-        if current < &latest {
+        // A current version without a rockspec revision is up to date with
+        // every revision of the same upstream release
+        let newer = if current.revision.is_none() {
+            *current < latest.without_revision()
+        } else {
+            *current < latest
+        };
+        if newer {
             Ok(OutdatedStatus::Outdated {
                 current: Some(current.clone()),
                 latest,
@@ -215,6 +249,14 @@ mod tests {
     use lpm::luarocks::manifest::{Manifest, PackageVersion};
     use tempfile::TempDir;
 
+    #[test]
+    fn test_describe_age() {
+        assert_eq!(describe_age(60), "1 minute");
+        assert_eq!(describe_age(59 * 60), "59 minutes");
+        assert_eq!(describe_age(2 * 60 * 60 + 5), "2 hours");
+        assert_eq!(describe_age(3 * 24 * 60 * 60), "3 days");
+    }
+
     #[test]
     fn test_outdated_status_variants() {
         // Test that OutdatedStatus enum variants can be created
@@ -290,8 +332,7 @@ mod tests {
                 latest: l,
             } => {
                 assert_eq!(c, Some(Version::new(1, 0, 0)));
-                // "2.0.0-1" normalizes to "2.0.1" (revision added to patch)
-                assert_eq!(l, Version::new(2, 0, 1));
+                assert_eq!(l.to_string(), "2.0.0-1");
             }
             status => panic!("Expected Outdated status, got: {:?}", status),
         }
@@ -327,18 +368,9 @@ mod tests {
             panic!("check_outdated failed: {}", e);
         }
         match result.unwrap() {
-            // "1.0.0-1" normalizes to "1.0.1", which is > "1.0.0", so it will be Outdated
-            OutdatedStatus::Outdated {
-                current: c,
-                latest: l,
-            } => {
-                assert_eq!(c, Some(Version::new(1, 0, 0)));
-                assert_eq!(l, Version::new(1, 0, 1));
-            }
-            OutdatedStatus::UpToDate => {
-                // If somehow it's up to date, that's also acceptable
-            }
-            status => panic!("Expected Outdated or UpToDate status, got: {:?}", status),
+            // "1.0.0-1" is the first rockspec revision of the installed 1.0.0
+            OutdatedStatus::UpToDate => {}
+            status => panic!("Expected UpToDate status, got: {:?}", status),
         }
     }
 }
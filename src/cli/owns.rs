@@ -0,0 +1,125 @@
+use lpm::build::builtin::native_extension;
+use lpm::core::path::{
+    find_project_root, lua_modules_dir, packages_metadata_dir, tree_lib_dir, tree_lua_dir,
+    tree_lua_versions,
+};
+use lpm::core::{LpmError, LpmResult};
+use lpm::package::ownership::{self, owner_of};
+use std::env;
+use std::path::{Path, PathBuf};
+
+pub fn run(path: String) -> LpmResult<()> {
+    let current_dir = env::current_dir()
+        .map_err(|e| LpmError::Path(format!("Failed to get current directory: {}", e)))?;
+
+    let project_root = find_project_root(&current_dir)?;
+    let lua_modules = lua_modules_dir(&project_root);
+    let packages_dir = packages_metadata_dir(&project_root);
+
+    for relative in candidates(&lua_modules, &current_dir, &path) {
+        let Some((package, files)) = owner_of(&packages_dir, &relative)? else {
+            continue;
+        };
+
+        let display = Path::new("lua_modules").join(&relative);
+        let key = relative.to_string_lossy().replace('\\', "/");
+        let note = match files.files.get(&key) {
+            None => " (local package)",
+            Some(recorded) => match ownership::checksum(&lua_modules.join(&relative)) {
+                Ok(actual) if &actual == recorded => "",
+                Ok(_) => " (modified since it was installed)",
+                Err(_) => " (missing: reinstall the package)",
+            },
+        };
+        println!(
+            "{} is owned by {} {}{}",
+            display.display(),
+            package,
+            files.version,
+            note
+        );
+        return Ok(());
+    }
+
+    Err(LpmError::Package(format!(
+        "No installed package owns {}",
+        path
+    )))
+}
+
+/// Paths relative to lua_modules that `arg` may mean, most likely first
+///
+/// `arg` is a path (relative to the current directory or to lua_modules) or
+/// a module name such as `socket.http`.
+fn candidates(lua_modules: &Path, current_dir: &Path, arg: &str) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    let given = current_dir.join(arg);
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    if let Ok(relative) = canonical(&given).strip_prefix(canonical(lua_modules)) {
+        candidates.push(relative.to_path_buf());
+    } else if let Ok(relative) = given.strip_prefix(lua_modules) {
+        candidates.push(relative.to_path_buf());
+    }
+    candidates.push(PathBuf::from(arg));
+
+    // A module name, resolved the way package.path and package.cpath would:
+    // in the tree for each Lua version, then in lua_modules itself
+    if !arg.contains(['/', '\\']) && !arg.ends_with(".lua") {
+        let module: PathBuf = arg.split('.').collect();
+        let mut dirs: Vec<(PathBuf, PathBuf)> = tree_lua_versions(lua_modules)
+            .iter()
+            .map(|version| {
+                (
+                    tree_lua_dir(Path::new(""), version),
+                    tree_lib_dir(Path::new(""), version),
+                )
+            })
+            .collect();
+        dirs.push((PathBuf::new(), PathBuf::new()));
+        for (share, lib) in dirs {
+            let lua = share.join(&module);
+            candidates.push(lua.with_extension("lua"));
+            candidates.push(lua.join("init.lua"));
+            candidates.push(lib.join(&module).with_extension(native_extension()));
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_candidates() {
+        let lua_modules = Path::new("/project/lua_modules");
+
+        let from_project = candidates(lua_modules, Path::new("/project"), "lua_modules/socket.lua");
+        assert_eq!(from_project[0], PathBuf::from("socket.lua"));
+
+        let module = candidates(lua_modules, Path::new("/project"), "socket.http");
+        assert!(module.contains(&PathBuf::from("socket/http.lua")));
+        assert!(module.contains(&PathBuf::from("socket/http/init.lua")));
+
+        let relative = candidates(lua_modules, Path::new("/elsewhere"), "ltn12.lua");
+        assert_eq!(relative, vec![PathBuf::from("ltn12.lua")]);
+    }
+
+    #[test]
+    fn test_candidates_in_tree() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        fs::create_dir_all(tree_lua_dir(&lua_modules, "5.4")).unwrap();
+
+        let module = candidates(&lua_modules, temp.path(), "socket.http");
+        assert_eq!(module[1], PathBuf::from("share/lua/5.4/socket/http.lua"));
+        assert!(module.contains(&PathBuf::from(format!(
+            "lib/lua/5.4/socket/http.{}",
+            native_extension()
+        ))));
+    }
+}
@@ -1,22 +1,38 @@
 use lpm::core::path::find_project_root;
 use lpm::core::{LpmError, LpmResult};
+use lpm::luarocks::repository::LocalRepository;
 use lpm::package::manifest::PackageManifest;
 use lpm::publish::publisher::Publisher;
 use std::env;
+use std::path::PathBuf;
 
-pub async fn run(with_binaries: bool) -> LpmResult<()> {
+pub async fn run(
+    with_binaries: bool,
+    repository: Option<PathBuf>,
+    repository_url: String,
+) -> LpmResult<()> {
     let current_dir = env::current_dir()
         .map_err(|e| LpmError::Path(format!("Failed to get current directory: {}", e)))?;
 
     let project_root = find_project_root(&current_dir)?;
     let manifest = PackageManifest::load(&project_root)?;
 
-    println!(
-        "Publishing {}@{} to LuaRocks...",
-        manifest.name, manifest.version
-    );
-
-    let publisher = Publisher::new(&project_root, manifest);
+    let mut publisher = Publisher::new(&project_root, manifest.clone());
+    match repository {
+        Some(root) => {
+            println!(
+                "Publishing {}@{} to {}...",
+                manifest.name,
+                manifest.version,
+                root.display()
+            );
+            publisher = publisher.with_repository(LocalRepository::open(&root)?, &repository_url);
+        }
+        None => println!(
+            "Publishing {}@{} to LuaRocks...",
+            manifest.name, manifest.version
+        ),
+    }
     publisher.publish(with_binaries).await?;
 
     Ok(())
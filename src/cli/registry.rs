@@ -0,0 +1,73 @@
+use clap::Subcommand;
+use lpm::core::LpmResult;
+use lpm::luarocks::repository::LocalRepository;
+use lpm::luarocks::server::{RepositoryServer, DEFAULT_ADDRESS};
+use std::path::{Path, PathBuf};
+
+#[derive(Subcommand)]
+pub enum RegistryCommands {
+    /// Add rockspecs and rocks to a repository and update its manifests
+    Add {
+        /// Repository directory (created if missing)
+        repository: PathBuf,
+        /// `.rockspec`, `.src.rock` or binary `.rock` files
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Regenerate a repository's manifests from the files in it
+    Build {
+        /// Repository directory
+        repository: PathBuf,
+    },
+    /// Serve a repository over HTTP
+    Serve {
+        /// Repository directory
+        repository: PathBuf,
+        /// Address to listen on
+        #[arg(long, default_value = DEFAULT_ADDRESS)]
+        address: String,
+    },
+}
+
+pub async fn run(command: RegistryCommands) -> LpmResult<()> {
+    match command {
+        RegistryCommands::Add { repository, files } => add(&repository, &files),
+        RegistryCommands::Build { repository } => build(&repository),
+        RegistryCommands::Serve {
+            repository,
+            address,
+        } => serve(&repository, &address).await,
+    }
+}
+
+fn add(root: &Path, files: &[PathBuf]) -> LpmResult<()> {
+    let repository = LocalRepository::open(root)?;
+    for file in files {
+        let (name, version) = repository.add(file)?;
+        println!("✓ Added {} {}", name, version);
+    }
+    let count = repository.rebuild()?;
+    println!("✓ Updated manifests ({} package versions)", count);
+    Ok(())
+}
+
+fn build(root: &Path) -> LpmResult<()> {
+    let count = LocalRepository::open(root)?.rebuild()?;
+    println!(
+        "✓ Wrote manifests for {} package versions in {}",
+        count,
+        root.display()
+    );
+    Ok(())
+}
+
+async fn serve(root: &Path, address: &str) -> LpmResult<()> {
+    let repository = LocalRepository::open(root)?;
+    repository.rebuild()?;
+    let server = RepositoryServer::bind(repository.root(), address).await?;
+    let url = server.url()?;
+    println!("Serving {} at {}", root.display(), url);
+    println!("Use it as a registry in config.yaml:");
+    println!("  registries:\n    - name: local\n      url: \"{}\"", url);
+    server.run().await
+}
@@ -2,6 +2,7 @@ use lpm::core::path::find_project_root;
 use lpm::core::{LpmError, LpmResult};
 use lpm::package::installer::PackageInstaller;
 use lpm::package::manifest::PackageManifest;
+use lpm::package::rollback::with_rollback;
 use std::env;
 
 pub fn run(package: String, global: bool) -> LpmResult<()> {
@@ -28,14 +29,17 @@ pub fn run(package: String, global: bool) -> LpmResult<()> {
         )));
     }
 
-    // Actually remove package files from lua_modules/
-    let installer = PackageInstaller::new(&project_root)?;
-    if installer.is_installed(&package) {
-        installer.remove_package(&package)?;
-    }
+    // Remove the package files and save the manifest together, or not at all
+    with_rollback(&project_root, || {
+        // Actually remove package files from lua_modules/
+        let installer = PackageInstaller::new(&project_root)?;
+        if installer.is_installed(&package) {
+            installer.remove_package(&package)?;
+        }
 
-    // Save updated manifest
-    manifest.save(&project_root)?;
+        // Save updated manifest
+        manifest.save(&project_root)
+    })?;
 
     let location = if removed_from_deps && removed_from_dev {
         "dependencies and dev_dependencies"
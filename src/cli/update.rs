@@ -9,13 +9,15 @@ use lpm::package::interactive::confirm;
 use lpm::package::lockfile::Lockfile;
 use lpm::package::lockfile_builder::LockfileBuilder;
 use lpm::package::manifest::PackageManifest;
+use lpm::package::offline;
 use lpm::package::rollback::with_rollback_async;
 use lpm::package::update_diff::UpdateDiff;
 use lpm::path_setup::PathSetup;
 use lpm::resolver::DependencyResolver;
+use std::collections::HashMap;
 use std::env;
 
-pub async fn run(package: Option<String>) -> LpmResult<()> {
+pub async fn run(package: Option<String>, offline: bool) -> LpmResult<()> {
     let current_dir = env::current_dir()
         .map_err(|e| LpmError::Path(format!("Failed to get current directory: {}", e)))?;
 
@@ -30,12 +32,16 @@ pub async fn run(package: Option<String>) -> LpmResult<()> {
         let config = Config::load()?;
         let cache = Cache::new(config.get_cache_dir()?)?;
 
-        // Create LuaRocks client
-        let client = LuaRocksClient::new(&config, cache.clone());
+        // Create LuaRocks client (offline: the manifest must already be cached)
+        let client = LuaRocksClient::new(&config, cache.clone()).with_offline(offline);
         let luarocks_manifest = client.fetch_manifest().await?;
+        crate::cli::outdated::print_index_age(&client);
 
         // Create resolver
-        let resolver = DependencyResolver::new(luarocks_manifest);
+        let resolver = DependencyResolver::new(luarocks_manifest)
+            .with_root_name(&manifest.name)
+            .with_lua_version(&manifest.lua_version)
+            .with_offline(offline);
 
         // Resolve versions first to calculate diff
         let resolved_versions = if let Some(package_name) = &package {
@@ -60,6 +66,15 @@ pub async fn run(package: Option<String>) -> LpmResult<()> {
             std::collections::HashMap::new()
         };
 
+        if offline {
+            check_cached(
+                &cache,
+                &lockfile,
+                &resolved_versions,
+                &resolved_dev_versions,
+            )?;
+        }
+
         // Calculate diff
         let mut diff = UpdateDiff::calculate(&lockfile, &resolved_versions, &resolved_dev_versions);
 
@@ -84,7 +99,7 @@ pub async fn run(package: Option<String>) -> LpmResult<()> {
         }
 
         // Initialize installer
-        let installer = PackageInstaller::new(&project_root)?;
+        let installer = PackageInstaller::new(&project_root)?.with_offline(offline);
         installer.init()?;
 
         // Apply updates
@@ -120,7 +135,7 @@ pub async fn run(package: Option<String>) -> LpmResult<()> {
         manifest.save(&project_root)?;
 
         // Regenerate lockfile incrementally (include dev dependencies for updates)
-        let builder = LockfileBuilder::new(cache);
+        let builder = LockfileBuilder::new(cache).with_offline(offline);
         let new_lockfile = if let Some(existing) = &lockfile {
             builder
                 .update_lockfile(existing, &manifest, &project_root, false)
@@ -137,6 +152,37 @@ pub async fn run(package: Option<String>) -> LpmResult<()> {
     .await
 }
 
+/// Fail with the full list of artifacts an offline update would need to download
+fn check_cached(
+    cache: &Cache,
+    lockfile: &Option<Lockfile>,
+    resolved_versions: &HashMap<String, Version>,
+    resolved_dev_versions: &HashMap<String, Version>,
+) -> LpmResult<()> {
+    let wanted: Vec<(String, String, Option<String>)> = resolved_versions
+        .iter()
+        .chain(resolved_dev_versions)
+        .map(|(name, version)| {
+            let version = version.to_string();
+            // The lockfile knows the source URL if the version is unchanged
+            let source_url = lockfile
+                .as_ref()
+                .and_then(|lf| lf.get_package(name))
+                .filter(|pkg| pkg.version == version)
+                .and_then(|pkg| pkg.source_url.clone());
+            (name.clone(), version, source_url)
+        })
+        .collect();
+
+    let missing = offline::missing_artifacts(
+        cache,
+        wanted
+            .iter()
+            .map(|(name, version, url)| (name.as_str(), version.as_str(), url.as_deref())),
+    );
+    offline::ensure_nothing_missing(&missing)
+}
+
 async fn update_package(
     _project_root: &std::path::Path,
     manifest: &mut PackageManifest,
@@ -86,6 +86,7 @@ mod tests {
             size: None,
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         lockfile.add_package("test-package".to_string(), package);
         assert!(!lockfile.packages.is_empty());
@@ -0,0 +1,530 @@
+//! Where configuration values come from
+//!
+//! Settings are read, lowest precedence first, from the defaults, the user
+//! config (`config.yaml` in the lpm home), the project's `.lpmrc.yaml` and
+//! `LPM_<KEY>` environment variables. Mappings are merged key by key, so a
+//! project can add one `registry_routes` entry without repeating the others.
+//!
+//! Keys are dotted: `index_ttl`, or `lua_binary_sources.5.4.8` for one entry
+//! of a map-valued setting (the entry name may itself contain dots).
+
+use crate::config::Config;
+use crate::core::path::{config_file, find_project_root};
+use crate::core::{LpmError, LpmResult};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Project config file, next to package.yaml
+pub const PROJECT_CONFIG: &str = ".lpmrc.yaml";
+
+/// Prefix of the environment variables overriding settings (`LPM_INDEX_TTL`)
+pub const ENV_PREFIX: &str = "LPM_";
+
+/// Every setting, as named in config files
+pub const KEYS: &[&str] = &[
+    "luarocks_manifest_url",
+    "cache_dir",
+    "index_ttl",
+    "verify_checksums",
+    "binary_rocks",
+    "max_unpacked_size",
+    "max_archive_entries",
+    "show_diffs_on_update",
+    "lua_binary_source_url",
+    "lua_binary_sources",
+    "external_deps_dirs",
+    "registries",
+    "registry_routes",
+];
+
+/// Settings mapping names to strings, set one entry at a time
+const MAP_KEYS: &[&str] = &["lua_binary_sources", "registry_routes"];
+
+/// String settings whose values are never read as YAML (a URL or path may
+/// look like a number)
+const STRING_KEYS: &[&str] = &[
+    "luarocks_manifest_url",
+    "cache_dir",
+    "lua_binary_source_url",
+];
+
+/// A layer of configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    User(PathBuf),
+    Project(PathBuf),
+    Env(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::User(path) => write!(f, "user config {}", path.display()),
+            Source::Project(path) => write!(f, "project config {}", path.display()),
+            Source::Env(var) => write!(f, "environment {}", var),
+        }
+    }
+}
+
+/// The configuration layers in effect, lowest precedence first
+#[derive(Debug, Clone)]
+pub struct ConfigLayers {
+    layers: Vec<(Source, Mapping)>,
+}
+
+impl ConfigLayers {
+    /// The defaults alone
+    pub fn new() -> LpmResult<Self> {
+        let defaults = match serde_yaml::to_value(Config::default()) {
+            Ok(Value::Mapping(mapping)) => mapping,
+            Ok(_) => Mapping::new(),
+            Err(e) => {
+                return Err(LpmError::Config(format!(
+                    "Failed to serialize config: {}",
+                    e
+                )))
+            }
+        };
+        Ok(Self {
+            layers: vec![(Source::Default, defaults)],
+        })
+    }
+
+    /// The defaults, the user config, the config of the project containing
+    /// `current_dir` (if any) and the environment
+    pub fn load(current_dir: Option<&Path>) -> LpmResult<Self> {
+        let mut layers = Self::new()?;
+        let user = config_file()?;
+        layers.push(Source::User(user.clone()), read_file(&user)?);
+        if let Some(path) = current_dir.and_then(project_config_file) {
+            layers.push(Source::Project(path.clone()), read_file(&path)?);
+        }
+        layers.push_env(std::env::vars());
+        Ok(layers)
+    }
+
+    /// Add a layer taking precedence over the existing ones
+    pub fn push(&mut self, source: Source, mapping: Mapping) {
+        self.layers.push((source, mapping));
+    }
+
+    /// Add a layer for the `LPM_<KEY>` variables among `vars`
+    pub fn push_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) {
+        let vars: BTreeMap<String, String> = vars.into_iter().collect();
+        for key in KEYS {
+            let var = format!("{}{}", ENV_PREFIX, key.to_uppercase());
+            if let Some(raw) = vars.get(&var) {
+                let mut mapping = Mapping::new();
+                mapping.insert(Value::from(*key), parse_value(key, false, raw));
+                self.push(Source::Env(var), mapping);
+            }
+        }
+    }
+
+    /// The merged configuration
+    pub fn config(&self) -> LpmResult<Config> {
+        let config = deserialize(self.merged()).map_err(|e| {
+            // Name the layer that doesn't fit, when one does so on its own
+            let culprit = self.layers.iter().skip(1).find(|(_, mapping)| {
+                deserialize(merge(self.layers[0].1.clone(), mapping.clone())).is_err()
+            });
+            match culprit {
+                Some((source, _)) => LpmError::Config(format!("Invalid {}: {}", source, e)),
+                None => LpmError::Config(format!("Invalid configuration: {}", e)),
+            }
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The value of `key` and the layer it comes from, if it is set
+    pub fn get(&self, key: &str) -> LpmResult<Option<(Value, Source)>> {
+        let (field, entry) = split_key(key)?;
+        Ok(self.layers.iter().rev().find_map(|(source, mapping)| {
+            lookup(mapping, field, entry.as_deref()).map(|value| (value.clone(), source.clone()))
+        }))
+    }
+
+    /// Every setting in effect, with entries of map-valued settings listed
+    /// one by one, sorted by key
+    pub fn list(&self) -> Vec<(String, Value, Source)> {
+        let mut keys = Vec::new();
+        for (_, mapping) in &self.layers {
+            for (field, value) in mapping {
+                let Some(field) = field.as_str() else {
+                    continue;
+                };
+                match value {
+                    Value::Mapping(entries) if MAP_KEYS.contains(&field) => {
+                        for name in entries.keys() {
+                            keys.push(format!("{}.{}", field, key_string(name)));
+                        }
+                    }
+                    _ => keys.push(field.to_string()),
+                }
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .filter_map(|key| {
+                let (value, source) = self.get(&key).ok()??;
+                Some((key, value, source))
+            })
+            .collect()
+    }
+
+    /// Layers above `source` that set `key`, overriding it
+    pub fn overrides(&self, key: &str, source: &Source) -> LpmResult<Vec<Source>> {
+        let (field, entry) = split_key(key)?;
+        let above = self.layers.iter().skip_while(|(s, _)| s != source).skip(1);
+        Ok(above
+            .filter(|(_, mapping)| lookup(mapping, field, entry.as_deref()).is_some())
+            .map(|(s, _)| s.clone())
+            .collect())
+    }
+
+    fn merged(&self) -> Mapping {
+        self.layers
+            .iter()
+            .fold(Mapping::new(), |merged, (_, mapping)| {
+                merge(merged, mapping.clone())
+            })
+    }
+
+    /// Replace the layer of a config file with `mapping`, as if it had been saved
+    fn replace(&mut self, source: &Source, mapping: Mapping) {
+        match self.layers.iter_mut().find(|(s, _)| s == source) {
+            Some(layer) => layer.1 = mapping,
+            None => {
+                // A project config that doesn't exist yet goes below the environment
+                let at = self
+                    .layers
+                    .iter()
+                    .position(|(s, _)| matches!(s, Source::Env(_)))
+                    .unwrap_or(self.layers.len());
+                self.layers.insert(at, (source.clone(), mapping));
+            }
+        }
+    }
+}
+
+/// Set `key` to `raw` in the config file `path`, checking the result is a valid
+/// configuration along with `layers`
+pub fn set_in_file(
+    layers: &mut ConfigLayers,
+    source: &Source,
+    key: &str,
+    raw: &str,
+) -> LpmResult<()> {
+    let path = file_of(source)?;
+    let (field, entry) = split_key(key)?;
+    let mut mapping = read_file(path)?;
+    let value = parse_value(field, entry.is_some(), raw);
+    match entry {
+        None => {
+            mapping.insert(Value::from(field), value);
+        }
+        Some(entry) => {
+            let entries = mapping
+                .entry(Value::from(field))
+                .or_insert_with(|| Value::Mapping(Mapping::new()));
+            if !entries.is_mapping() {
+                *entries = Value::Mapping(Mapping::new());
+            }
+            if let Value::Mapping(entries) = entries {
+                entries.insert(Value::from(entry), value);
+            }
+        }
+    }
+    let mut updated = layers.clone();
+    updated.replace(source, mapping.clone());
+    updated.config()?;
+    write_file(path, &mapping)?;
+    *layers = updated;
+    Ok(())
+}
+
+/// Remove `key` from the config file `path`; returns whether it was set there
+pub fn unset_in_file(layers: &mut ConfigLayers, source: &Source, key: &str) -> LpmResult<bool> {
+    let path = file_of(source)?;
+    let (field, entry) = split_key(key)?;
+    let mut mapping = read_file(path)?;
+    let removed = match entry {
+        None => mapping.remove(field).is_some(),
+        Some(entry) => {
+            let removed = match mapping.get_mut(field) {
+                Some(Value::Mapping(entries)) => entries.remove(entry.as_str()).is_some(),
+                _ => false,
+            };
+            if mapping
+                .get(field)
+                .is_some_and(|v| v.as_mapping().is_some_and(Mapping::is_empty))
+            {
+                mapping.remove(field);
+            }
+            removed
+        }
+    };
+    if removed {
+        let mut updated = layers.clone();
+        updated.replace(source, mapping.clone());
+        updated.config()?;
+        write_file(path, &mapping)?;
+        *layers = updated;
+    }
+    Ok(removed)
+}
+
+/// The `.lpmrc.yaml` of the project containing `dir`, if there is a project
+pub fn project_config_file(dir: &Path) -> Option<PathBuf> {
+    find_project_root(dir)
+        .ok()
+        .map(|root| root.join(PROJECT_CONFIG))
+}
+
+/// A value as it is written in config files: scalars plainly, anything else
+/// as a single line of YAML
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+/// `(field, entry)` of a dotted key
+fn split_key(key: &str) -> LpmResult<(&str, Option<String>)> {
+    let (field, entry) = match key.split_once('.') {
+        Some((field, entry)) => (field, Some(entry.to_string())),
+        None => (key, None),
+    };
+    if !KEYS.contains(&field) {
+        return Err(LpmError::Config(format!(
+            "Unknown config key '{}' (known keys: {})",
+            field,
+            KEYS.join(", ")
+        )));
+    }
+    if let Some(entry) = &entry {
+        if !MAP_KEYS.contains(&field) {
+            return Err(LpmError::Config(format!(
+                "'{}' has no entries; set it as a whole",
+                field
+            )));
+        }
+        if entry.is_empty() {
+            return Err(LpmError::Config(format!("Missing entry name in '{}'", key)));
+        }
+    }
+    Ok((field, entry))
+}
+
+fn lookup<'a>(mapping: &'a Mapping, field: &str, entry: Option<&str>) -> Option<&'a Value> {
+    let value = mapping.get(field)?;
+    match entry {
+        None => Some(value),
+        Some(entry) => value.as_mapping()?.get(entry),
+    }
+}
+
+/// `raw` as YAML (so `true`, `60` and `[/opt/openssl]` keep their types),
+/// except for string settings and the entries of map-valued settings
+fn parse_value(field: &str, entry: bool, raw: &str) -> Value {
+    if entry || STRING_KEYS.contains(&field) {
+        return Value::from(raw);
+    }
+    match serde_yaml::from_str(raw) {
+        Ok(Value::Null) | Err(_) => Value::from(raw),
+        Ok(value) => value,
+    }
+}
+
+/// Merge `over` into `base`, entry by entry where both are mappings
+fn merge(mut base: Mapping, over: Mapping) -> Mapping {
+    for (key, value) in over {
+        let merged = match (base.remove(&key), value) {
+            (Some(Value::Mapping(below)), Value::Mapping(above)) => {
+                Value::Mapping(merge(below, above))
+            }
+            (_, value) => value,
+        };
+        base.insert(key, merged);
+    }
+    base
+}
+
+fn deserialize(mapping: Mapping) -> Result<Config, serde_yaml::Error> {
+    serde_yaml::from_value(Value::Mapping(mapping))
+}
+
+fn key_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => format_value(other),
+    }
+}
+
+fn file_of(source: &Source) -> LpmResult<&Path> {
+    match source {
+        Source::User(path) | Source::Project(path) => Ok(path),
+        other => Err(LpmError::Config(format!("Cannot write to {}", other))),
+    }
+}
+
+fn read_file(path: &Path) -> LpmResult<Mapping> {
+    if !path.exists() {
+        return Ok(Mapping::new());
+    }
+    let content = fs::read_to_string(path)?;
+    match serde_yaml::from_str(&content) {
+        Ok(Value::Mapping(mapping)) => Ok(mapping),
+        Ok(Value::Null) => Ok(Mapping::new()),
+        Ok(_) => Err(LpmError::Config(format!(
+            "{} must contain a mapping of settings",
+            path.display()
+        ))),
+        Err(e) => Err(LpmError::Config(format!(
+            "Failed to parse {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+fn write_file(path: &Path, mapping: &Mapping) -> LpmResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = serde_yaml::to_string(mapping)
+        .map_err(|e| LpmError::Config(format!("Failed to serialize config: {}", e)))?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn mapping(yaml: &str) -> Mapping {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn layers(user: &str, project: &str) -> ConfigLayers {
+        let mut layers = ConfigLayers::new().unwrap();
+        layers.push(Source::User(PathBuf::from("user.yaml")), mapping(user));
+        layers.push(
+            Source::Project(PathBuf::from(".lpmrc.yaml")),
+            mapping(project),
+        );
+        layers
+    }
+
+    #[test]
+    fn test_layers_merge_by_precedence() {
+        let mut layers = layers(
+            "index_ttl: 60\nverify_checksums: false\nlua_binary_sources: {5.4.8: https://a.example}",
+            "index_ttl: 120\nlua_binary_sources: {5.1.5: https://b.example}",
+        );
+        layers.push_env([
+            ("LPM_VERIFY_CHECKSUMS".to_string(), "true".to_string()),
+            ("LPM_HOME".to_string(), "/elsewhere".to_string()),
+        ]);
+
+        let config = layers.config().unwrap();
+        assert_eq!(config.index_ttl, 120);
+        assert!(config.verify_checksums);
+        let sources = config.lua_binary_sources.unwrap();
+        assert_eq!(sources["5.4.8"], "https://a.example");
+        assert_eq!(sources["5.1.5"], "https://b.example");
+
+        let (value, source) = layers.get("index_ttl").unwrap().unwrap();
+        assert_eq!(format_value(&value), "120");
+        assert_eq!(source, Source::Project(PathBuf::from(".lpmrc.yaml")));
+        let (_, source) = layers.get("verify_checksums").unwrap().unwrap();
+        assert_eq!(source, Source::Env("LPM_VERIFY_CHECKSUMS".to_string()));
+        let (_, source) = layers.get("lua_binary_sources.5.4.8").unwrap().unwrap();
+        assert_eq!(source, Source::User(PathBuf::from("user.yaml")));
+        let (_, source) = layers.get("binary_rocks").unwrap().unwrap();
+        assert_eq!(source, Source::Default);
+        assert!(layers.get("cache_dir").unwrap().is_none());
+
+        let listed: Vec<String> = layers.list().into_iter().map(|(key, ..)| key).collect();
+        assert!(listed.contains(&"lua_binary_sources.5.1.5".to_string()));
+        assert!(!listed.contains(&"lua_binary_sources".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_layer_is_named() {
+        let mut layers = layers("", "");
+        layers.push_env([("LPM_INDEX_TTL".to_string(), "soon".to_string())]);
+        let err = layers.config().unwrap_err().to_string();
+        assert!(err.contains("environment LPM_INDEX_TTL"), "{err}");
+    }
+
+    #[test]
+    fn test_keys() {
+        assert!(split_key("colour").is_err());
+        assert!(split_key("index_ttl.x").is_err());
+        assert_eq!(
+            split_key("registry_routes.acme-*").unwrap(),
+            ("registry_routes", Some("acme-*".to_string()))
+        );
+
+        // Every setting a config can have is a known key
+        let config = Config {
+            cache_dir: Some(String::new()),
+            lua_binary_source_url: Some(String::new()),
+            lua_binary_sources: Some(Default::default()),
+            external_deps_dirs: Some(Vec::new()),
+            registries: Some(Vec::new()),
+            registry_routes: Some(Default::default()),
+            ..Config::default()
+        };
+        let Value::Mapping(fields) = serde_yaml::to_value(config).unwrap() else {
+            panic!("config is not a mapping");
+        };
+        for field in fields.keys() {
+            assert!(KEYS.contains(&field.as_str().unwrap()), "{:?}", field);
+        }
+    }
+
+    #[test]
+    fn test_set_and_unset_in_file() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join(PROJECT_CONFIG);
+        let source = Source::Project(path.clone());
+        let mut layers = ConfigLayers::new().unwrap();
+
+        set_in_file(&mut layers, &source, "index_ttl", "30").unwrap();
+        set_in_file(
+            &mut layers,
+            &source,
+            "lua_binary_sources.5.4.8",
+            "https://x.example",
+        )
+        .unwrap();
+        set_in_file(&mut layers, &source, "external_deps_dirs", "[/opt/openssl]").unwrap();
+        assert!(set_in_file(&mut layers, &source, "index_ttl", "soon").is_err());
+
+        let written = read_file(&path).unwrap();
+        assert_eq!(written.get("index_ttl"), Some(&Value::from(30)));
+        let config = layers.config().unwrap();
+        assert_eq!(config.index_ttl, 30);
+        assert_eq!(config.external_deps_dirs.unwrap(), vec!["/opt/openssl"]);
+
+        assert!(unset_in_file(&mut layers, &source, "lua_binary_sources.5.4.8").unwrap());
+        assert!(!unset_in_file(&mut layers, &source, "cache_dir").unwrap());
+        let written = read_file(&path).unwrap();
+        assert!(written.get("lua_binary_sources").is_none());
+        assert!(written.get("index_ttl").is_some());
+    }
+}
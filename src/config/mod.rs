@@ -1,6 +1,11 @@
+pub mod layers;
+
+use crate::config::layers::ConfigLayers;
 use crate::core::path::{config_file, ensure_dir};
 use crate::core::{LpmError, LpmResult};
+use crate::package::extractor::ExtractLimits;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,10 +23,30 @@ pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cache_dir: Option<String>,
 
+    /// Seconds a cached manifest or rockspec is used before it is revalidated
+    /// with the registry (`lpm --refresh` revalidates immediately)
+    #[serde(default = "default_index_ttl")]
+    pub index_ttl: u64,
+
     /// Whether to verify checksums on install
     #[serde(default = "default_true")]
     pub verify_checksums: bool,
 
+    /// Whether to install the prebuilt binary rocks registries offer for this
+    /// platform instead of building from source. Binary rocks are checked
+    /// against their `rock_manifest`; the checksum in package.lock pins the
+    /// source archive, so turn this off to only install what the lock pins.
+    #[serde(default = "default_true")]
+    pub binary_rocks: bool,
+
+    /// Largest total size, in bytes, a downloaded archive may unpack to
+    #[serde(default = "default_max_unpacked_size")]
+    pub max_unpacked_size: u64,
+
+    /// Most files, directories and links a downloaded archive may contain
+    #[serde(default = "default_max_archive_entries")]
+    pub max_archive_entries: usize,
+
     /// Whether to show diffs on update
     #[serde(default = "default_true")]
     pub show_diffs_on_update: bool,
@@ -33,13 +58,78 @@ pub struct Config {
     /// Per-version Lua binary source URLs
     /// Example: { "5.4.8": "https://custom-source.com/binaries" }
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub lua_binary_sources: Option<std::collections::HashMap<String, String>>,
+    pub lua_binary_sources: Option<HashMap<String, String>>,
+
+    /// Extra prefixes searched for rockspec `external_dependencies`, before /usr/local and /usr
+    /// Example: ["/opt/openssl"]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_deps_dirs: Option<Vec<String>>,
+
+    /// Registries packages are resolved from, in order of preference
+    /// (defaults to the one serving `luarocks_manifest_url`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registries: Option<Vec<RegistryConfig>>,
+
+    /// Registry to use for a package name, or for a prefix ending in `*`
+    /// Example: { "acme-*": "internal", "penlight": "luarocks" }
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registry_routes: Option<HashMap<String, String>>,
+}
+
+/// A LuaRocks server (or anything serving the same layout)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryConfig {
+    /// Name recorded as the `source` of packages locked from this registry
+    pub name: String,
+    /// Server URL, e.g. "https://luarocks.org"
+    pub url: String,
+    /// Manifest to use below `<url>/manifests/`, e.g. "luarocks"; without one,
+    /// the manifest and rockspecs are served from `url` itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<String>,
+    /// Servers with the same content, tried in order when `url` is unreachable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<RegistryAuth>,
+}
+
+/// Bearer token sent to a registry and its mirrors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryAuth {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Environment variable holding the token, to keep it out of config.yaml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+}
+
+impl RegistryAuth {
+    pub fn token(&self) -> Option<String> {
+        self.token.clone().or_else(|| {
+            self.token_env
+                .as_ref()
+                .and_then(|var| std::env::var(var).ok())
+        })
+    }
 }
 
 fn default_luarocks_manifest_url() -> String {
     "https://luarocks.org/manifests/luarocks/manifest".to_string()
 }
 
+fn default_index_ttl() -> u64 {
+    60 * 60
+}
+
+fn default_max_unpacked_size() -> u64 {
+    ExtractLimits::default().max_size
+}
+
+fn default_max_archive_entries() -> usize {
+    ExtractLimits::default().max_entries
+}
+
 fn default_true() -> bool {
     true
 }
@@ -49,10 +139,17 @@ impl Default for Config {
         Self {
             luarocks_manifest_url: default_luarocks_manifest_url(),
             cache_dir: None,
+            index_ttl: default_index_ttl(),
             verify_checksums: true,
+            binary_rocks: true,
+            max_unpacked_size: default_max_unpacked_size(),
+            max_archive_entries: default_max_archive_entries(),
             show_diffs_on_update: true,
             lua_binary_source_url: None,
             lua_binary_sources: None,
+            external_deps_dirs: None,
+            registries: None,
+            registry_routes: None,
         }
     }
 }
@@ -64,21 +161,60 @@ impl Config {
     /// - Windows: %APPDATA%\lpm\config.yaml
     /// - Linux: ~/.config/lpm/config.yaml
     /// - macOS: ~/Library/Application Support/lpm/config.yaml
+    ///
+    /// The project's `.lpmrc.yaml` and `LPM_*` environment variables override
+    /// it (see `ConfigLayers`).
     pub fn load() -> LpmResult<Self> {
         let config_path = config_file()?;
 
         if !config_path.exists() {
             // Create default config
-            let config = Self::default();
-            config.save()?;
-            return Ok(config);
+            Self::default().save()?;
         }
 
-        let content = fs::read_to_string(&config_path)?;
-        let config: Config = serde_yaml::from_str(&content)
-            .map_err(|e| LpmError::Config(format!("Failed to parse config: {}", e)))?;
+        let current_dir = std::env::current_dir().ok();
+        ConfigLayers::load(current_dir.as_deref())?.config()
+    }
+
+    /// Check that registries have unique names and routes point at one of them
+    pub fn validate(&self) -> LpmResult<()> {
+        let registries = self.registries.as_deref().unwrap_or_default();
+        let mut names = HashSet::new();
+        for registry in registries {
+            if registry.url.is_empty() {
+                return Err(LpmError::Config(format!(
+                    "Registry '{}' has no url",
+                    registry.name
+                )));
+            }
+            if !names.insert(registry.name.as_str()) {
+                return Err(LpmError::Config(format!(
+                    "Registry '{}' is defined more than once",
+                    registry.name
+                )));
+            }
+        }
+        if let Some(registries) = &self.registries {
+            if registries.is_empty() {
+                return Err(LpmError::Config(
+                    "'registries' must list at least one registry".to_string(),
+                ));
+            }
+        }
 
-        Ok(config)
+        let known =
+            |name: &str| names.contains(name) || (self.registries.is_none() && name == "luarocks");
+        let mut routes: Vec<_> = self.registry_routes.iter().flatten().collect();
+        routes.sort();
+        for (pattern, registry) in routes {
+            if !known(registry) {
+                return Err(LpmError::Config(format!(
+                    "Route '{}' points at unknown registry '{}'",
+                    pattern, registry
+                )));
+            }
+        }
+        Ok(())
     }
 
     /// Save config to platform-specific config directory
@@ -143,4 +279,56 @@ mod tests {
 
         assert_eq!(config.luarocks_manifest_url, loaded.luarocks_manifest_url);
     }
+
+    #[test]
+    fn test_config_registries() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+registries:
+  - name: internal
+    url: https://rocks.example.com
+    mirrors: [https://rocks-mirror.example.com]
+    auth:
+      token: secret
+  - name: luarocks
+    url: https://luarocks.org
+    manifest: luarocks
+registry_routes:
+  "acme-*": internal
+"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+
+        let registries = config.registries.as_ref().unwrap();
+        assert_eq!(
+            registries[0].mirrors,
+            vec!["https://rocks-mirror.example.com"]
+        );
+        assert_eq!(
+            registries[0].auth.as_ref().unwrap().token().as_deref(),
+            Some("secret")
+        );
+        assert_eq!(registries[1].manifest.as_deref(), Some("luarocks"));
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_route() {
+        let mut config = Config {
+            registry_routes: Some(HashMap::from([(
+                "acme-*".to_string(),
+                "internal".to_string(),
+            )])),
+            ..Config::default()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("unknown registry 'internal'"), "{err}");
+
+        // The implicit default registry is called "luarocks"
+        config.registry_routes = Some(HashMap::from([(
+            "penlight".to_string(),
+            "luarocks".to_string(),
+        )]));
+        assert!(config.validate().is_ok());
+    }
 }
@@ -105,18 +105,21 @@ mod tests {
                 url: "none".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: std::collections::HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: Some(">=5.1".to_string()),
             binary_urls: std::collections::HashMap::new(),
+            ..Default::default()
         };
         assert!(PackageCompatibility::check_rockspec(&installed, &rockspec).unwrap());
     }
@@ -163,18 +166,21 @@ mod tests {
                 url: "none".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: std::collections::HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: std::collections::HashMap::new(),
+            ..Default::default()
         };
         assert!(PackageCompatibility::check_rockspec(&installed, &rockspec).unwrap());
     }
@@ -1,56 +1,421 @@
-use crate::cache::Cache;
+use crate::cache::{Cache, CacheEntryMeta};
 use crate::config::Config;
 use crate::core::{LpmError, LpmResult};
+use crate::luarocks::index::{PackageIndex, RockspecSummary};
 use crate::luarocks::manifest::Manifest;
+use crate::luarocks::registry::{Registries, Registry};
 use crate::luarocks::rockspec::Rockspec;
-use reqwest::Client;
-use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, Method, Response, StatusCode};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Cached manifests and rockspecs fetched before this are revalidated regardless of their age
+static REFRESH_SINCE: OnceLock<DateTime<Utc>> = OnceLock::new();
+
+/// Revalidate every cached manifest and rockspec this process uses (`lpm --refresh`)
+///
+/// Each entry is checked with its registry once; entries refreshed afterwards
+/// are fresh again, however many clients the command creates.
+pub fn refresh_cached_index() {
+    let _ = REFRESH_SINCE.set(Utc::now());
+}
 
 /// Client for interacting with LuaRocks
 pub struct LuaRocksClient {
     client: Client,
-    manifest_url: String,
+    registries: Registries,
     cache: Cache,
+    offline: bool,
+    ttl: Duration,
 }
 
 impl LuaRocksClient {
+    /// The cache downloads are stored in
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
     /// Create a new LuaRocks client
     pub fn new(config: &Config, cache: Cache) -> Self {
         Self {
             client: Client::new(),
-            manifest_url: config.luarocks_manifest_url.clone(),
+            registries: Registries::from_config(config),
             cache,
+            offline: false,
+            ttl: Duration::from_secs(config.index_ttl),
         }
     }
 
-    /// Fetch the LuaRocks manifest
+    /// How long cached manifests and rockspecs are used before being revalidated
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Serve everything from the cache and never touch the network
+    ///
+    /// Anything that is not cached fails with an `LpmError::Cache` error.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Resolve packages from these registries instead of the configured ones
+    pub fn with_registries(mut self, registries: Registries) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    /// Whether this client is restricted to the cache
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn registries(&self) -> &Registries {
+        &self.registries
+    }
+
+    fn offline_miss(&self, what: &str, path: &std::path::Path) -> LpmError {
+        LpmError::Cache(format!(
+            "Offline mode: {} is not cached (expected at {})",
+            what,
+            path.display()
+        ))
+    }
+
+    /// Fetch the manifests of all registries, merged into one
+    ///
+    /// A package routed to a registry only comes from that registry; any other
+    /// package comes from the first registry that has it.
     pub async fn fetch_manifest(&self) -> LpmResult<Manifest> {
-        // Check cache first
-        let cache_path = self.cache.rockspecs_dir().join("manifest.json");
+        let mut merged = Manifest {
+            repository: self.registries.primary().name.clone(),
+            ..Manifest::default()
+        };
+        for registry in self.registries.iter() {
+            let mut manifest = self.fetch_registry_manifest(registry).await?;
+            for (name, versions) in manifest.packages {
+                let wanted = match self.registries.route(&name) {
+                    Some(routed) => routed.name == registry.name,
+                    None => !merged.packages.contains_key(&name),
+                };
+                if wanted {
+                    if let Some(dependencies) = manifest.dependencies.remove(&name) {
+                        merged.dependencies.insert(name.clone(), dependencies);
+                    }
+                    if let Some(modules) = manifest.modules.remove(&name) {
+                        merged.modules.insert(name.clone(), modules);
+                    }
+                    if let Some(rocks) = manifest.rocks.remove(&name) {
+                        merged.rocks.insert(name.clone(), rocks);
+                    }
+                    merged.packages.insert(name, versions);
+                }
+            }
+        }
+        Ok(merged)
+    }
 
-        let content = if self.cache.exists(&cache_path) {
-            // Use cached version
-            String::from_utf8(self.cache.read(&cache_path)?)
-                .map_err(|e| LpmError::Cache(format!("Failed to read cached manifest: {}", e)))?
-        } else {
-            // Download manifest as JSON
-            println!("Downloading LuaRocks manifest...");
-            let url = format!("{}?format=json", self.manifest_url);
-            let response = self.client.get(&url).send().await.map_err(LpmError::Http)?;
+    /// Fetch one registry's manifest, through its package index
+    ///
+    /// The manifest is only parsed when it was (re)downloaded; otherwise the
+    /// index built from it last time is used. Servers that don't serve
+    /// `manifest` (static mirrors often only have `manifest-5.x`) are asked for
+    /// the other manifests in turn, starting with whichever worked last time.
+    async fn fetch_registry_manifest(&self, registry: &Registry) -> LpmResult<Manifest> {
+        let manifest_path = self.cache.manifest_path(registry);
+        let what = format!("the manifest of registry '{}'", registry.name);
+        let mut urls = registry.manifest_urls();
+        if let Some(meta) = self.cache.read_meta(&manifest_path) {
+            if let Some(i) = urls.iter().position(|url| *url == meta.url) {
+                let last_used = urls.remove(i);
+                urls.insert(0, last_used);
+            }
+        }
+
+        let mut urls = urls.into_iter().peekable();
+        while let Some(url) = urls.next() {
+            match self.ensure_cached(&url, &manifest_path, &what).await {
+                Err(LpmError::Http(e))
+                    if e.status() == Some(StatusCode::NOT_FOUND) && urls.peek().is_some() => {}
+                result => {
+                    result?;
+                    break;
+                }
+            }
+        }
+
+        let index_path = self.cache.index_path(registry);
+        if let Some(index) = PackageIndex::load(&index_path, &manifest_path, &registry.base_url) {
+            return Ok(index.to_manifest());
+        }
 
-            if !response.status().is_success() {
-                return Err(LpmError::Http(response.error_for_status().unwrap_err()));
+        // Parse the manifest (JSON or Lua, whichever the server sent), and index it for next time
+        let content = self.read_cached(&manifest_path, &what)?;
+        let manifest = Manifest::parse_for(&content, &registry.name, &registry.base_url)?;
+        let previous = PackageIndex::read(&index_path);
+        let index = PackageIndex::build(
+            &manifest,
+            &manifest_path,
+            &registry.base_url,
+            previous.as_ref(),
+        );
+        index.save(&index_path)?;
+        Ok(index.to_manifest())
+    }
+
+    /// Remember what rockspecs that were read declare, in the index of the registry serving them
+    ///
+    /// Later resolutions take dependencies from the index instead of reading
+    /// the rockspecs again.
+    pub fn record_rockspecs(&self, summaries: &[RockspecSummary]) -> LpmResult<()> {
+        if summaries.is_empty() {
+            return Ok(());
+        }
+        for registry in self.registries.iter() {
+            let manifest_path = self.cache.manifest_path(registry);
+            let index_path = self.cache.index_path(registry);
+            let Some(mut index) =
+                PackageIndex::load(&index_path, &manifest_path, &registry.base_url)
+            else {
+                continue;
+            };
+            let mut changed = false;
+            for summary in summaries {
+                let serves = self
+                    .registries
+                    .candidates(&summary.name)
+                    .iter()
+                    .any(|candidate| candidate.name == registry.name);
+                if serves && index.get(&summary.name, &summary.version).is_some() {
+                    changed |= index.record(summary);
+                }
             }
+            if changed {
+                index.save(&index_path)?;
+            }
+        }
+        Ok(())
+    }
 
-            let content = response.text().await.map_err(LpmError::Http)?;
+    /// When the cached manifests were last fetched or revalidated (the oldest of them)
+    ///
+    /// `None` if some registry's manifest has not been cached yet.
+    pub fn index_refreshed_at(&self) -> Option<DateTime<Utc>> {
+        self.registries
+            .iter()
+            .map(|registry| {
+                self.cache
+                    .read_meta(&self.cache.manifest_path(registry))
+                    .map(|meta| meta.fetched_at)
+            })
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .min()
+    }
 
-            // Cache it
-            self.cache.write(&cache_path, content.as_bytes())?;
-            content
+    /// Whether a cache entry can be used without asking the server
+    fn is_fresh(&self, meta: &CacheEntryMeta) -> bool {
+        let refreshed = REFRESH_SINCE
+            .get()
+            .is_some_and(|since| meta.fetched_at < *since);
+        !refreshed && meta.age() < self.ttl
+    }
+
+    /// Read `url` through the cache at `cache_path`
+    ///
+    /// Entries older than the TTL are revalidated with the server (using their
+    /// ETag or Last-Modified date) and kept if unchanged. When the server can't
+    /// be reached, a stale entry is used with a warning.
+    async fn fetch_cached(&self, url: &str, cache_path: &Path, what: &str) -> LpmResult<String> {
+        self.ensure_cached(url, cache_path, what).await?;
+        self.read_cached(cache_path, what)
+    }
+
+    /// Make sure `cache_path` holds a usable copy of `url` (see `fetch_cached`)
+    async fn ensure_cached(&self, url: &str, cache_path: &Path, what: &str) -> LpmResult<()> {
+        let meta = self.cache.read_meta(cache_path);
+        if self.offline {
+            return match meta {
+                Some(_) => Ok(()),
+                None => Err(self.offline_miss(what, cache_path)),
+            };
+        }
+
+        // An entry downloaded from another URL (e.g. another registry) is replaced
+        let cached = meta.filter(|meta| meta.url.is_empty() || meta.url == url);
+        if let Some(meta) = &cached {
+            if self.is_fresh(meta) {
+                return Ok(());
+            }
+        }
+
+        let verb = if cached.is_some() {
+            "Refreshing"
+        } else {
+            "Downloading"
         };
+        println!("{} {}", verb, what);
+        match self
+            .send_conditional(Method::GET, url, cached.as_ref())
+            .await
+        {
+            Ok(response) if response.status() == StatusCode::NOT_MODIFIED => {
+                let meta = CacheEntryMeta {
+                    fetched_at: Utc::now(),
+                    ..cached.unwrap_or_else(|| CacheEntryMeta::new(url))
+                };
+                self.cache.write_meta(cache_path, &meta)
+            }
+            Ok(response) => {
+                let header = |name| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string)
+                };
+                let meta = CacheEntryMeta {
+                    etag: header(ETAG),
+                    last_modified: header(LAST_MODIFIED),
+                    ..CacheEntryMeta::new(url)
+                };
+                let content = response.text().await.map_err(LpmError::Http)?;
+
+                // Cache it
+                self.cache.write(cache_path, content.as_bytes())?;
+                self.cache.write_meta(cache_path, &meta)
+            }
+            Err(e) => match cached {
+                Some(meta) => {
+                    eprintln!(
+                        "Warning: could not refresh {} ({}); using the copy cached at {}",
+                        what,
+                        e,
+                        meta.fetched_at.format("%Y-%m-%d %H:%M UTC")
+                    );
+                    Ok(())
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    fn read_cached(&self, cache_path: &Path, what: &str) -> LpmResult<String> {
+        String::from_utf8(self.cache.read(cache_path)?)
+            .map_err(|e| LpmError::Cache(format!("Failed to read cached {}: {}", what, e)))
+    }
+
+    /// The rockspec URL of a package version
+    ///
+    /// Taken from the manifest the version was found in, else built for the
+    /// registry the package is routed to (or the primary registry).
+    pub fn rockspec_url(&self, manifest: &Manifest, name: &str, version: &str) -> String {
+        manifest
+            .get_package_versions(name)
+            .and_then(|versions| versions.iter().find(|pv| pv.version == version))
+            .map(|pv| pv.rockspec_url.clone())
+            .unwrap_or_else(|| {
+                self.registries
+                    .route(name)
+                    .unwrap_or(self.registries.primary())
+                    .rockspec_url(name, version)
+            })
+    }
+
+    /// Find the registry that has a rockspec for a package version, without a manifest
+    ///
+    /// Registries are tried in order; offline, the first candidate is assumed.
+    pub async fn find_rockspec_url(&self, name: &str, version: &str) -> LpmResult<String> {
+        let candidates = self.registries.candidates(name);
+        if candidates.len() == 1 || self.offline {
+            return Ok(candidates[0].rockspec_url(name, version));
+        }
+        for registry in &candidates {
+            let url = registry.rockspec_url(name, version);
+            if self.send(Method::HEAD, &url).await.is_ok() {
+                return Ok(url);
+            }
+        }
+        let searched: Vec<_> = candidates.iter().map(|r| r.name.as_str()).collect();
+        Err(LpmError::Package(format!(
+            "No rockspec for {}@{} in any registry (searched: {})",
+            name,
+            version,
+            searched.join(", ")
+        )))
+    }
+
+    /// The name of the registry serving `url`, recorded as a locked package's source
+    pub fn source_name(&self, url: &str) -> String {
+        self.registries
+            .for_url(url)
+            .unwrap_or(self.registries.primary())
+            .name
+            .clone()
+    }
 
-        // Parse manifest as JSON
-        Manifest::parse_json(&content)
+    /// Verify a rockspec URL exists
+    pub async fn verify_rockspec_url(&self, url: &str) -> LpmResult<()> {
+        self.send(Method::HEAD, url)
+            .await
+            .map(|_| ())
+            .map_err(|_| LpmError::Package(format!("Rockspec not found: {}", url)))
+    }
+
+    async fn send(&self, method: Method, url: &str) -> LpmResult<Response> {
+        self.send_conditional(method, url, None).await
+    }
+
+    /// Send a request, authenticated for the registry serving `url`
+    ///
+    /// With `cached`, the server may answer 304 Not Modified. When the server
+    /// can't be reached or fails (5xx), the same file is requested from the
+    /// registry's mirrors in order.
+    async fn send_conditional(
+        &self,
+        method: Method,
+        url: &str,
+        cached: Option<&CacheEntryMeta>,
+    ) -> LpmResult<Response> {
+        let registry = self.registries.for_url(url);
+        let candidates = registry
+            .and_then(|r| r.candidate_urls(url))
+            .unwrap_or_else(|| vec![url.to_string()]);
+
+        let mut last_error = None;
+        for candidate in &candidates {
+            if let Some(failed) = &last_error {
+                println!("  {} (trying mirror {})", failed, candidate);
+            }
+            let mut request = self.client.request(method.clone(), candidate);
+            if let Some(token) = registry.and_then(|r| r.token()) {
+                request = request.bearer_auth(token);
+            }
+            if let Some(etag) = cached.and_then(|meta| meta.etag.as_deref()) {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(date) = cached.and_then(|meta| meta.last_modified.as_deref()) {
+                request = request.header(IF_MODIFIED_SINCE, date);
+            }
+            match request.send().await {
+                Ok(response) if response.status().is_server_error() => {
+                    last_error = Some(LpmError::Http(response.error_for_status().unwrap_err()));
+                }
+                Ok(response)
+                    if !response.status().is_success()
+                        && response.status() != StatusCode::NOT_MODIFIED =>
+                {
+                    return Err(LpmError::Http(response.error_for_status().unwrap_err()));
+                }
+                Ok(response) => return Ok(response),
+                Err(e) => last_error = Some(LpmError::Http(e)),
+            }
+        }
+        Err(last_error.expect("at least one URL is tried"))
     }
 
     /// Download a rockspec file
@@ -61,25 +426,8 @@ impl LuaRocksClient {
             &extract_version_from_url(url),
         );
 
-        if self.cache.exists(&cache_path) {
-            return String::from_utf8(self.cache.read(&cache_path)?)
-                .map_err(|e| LpmError::Cache(format!("Failed to read cached rockspec: {}", e)));
-        }
-
-        // Download rockspec
-        println!("Downloading rockspec: {}", url);
-        let response = self.client.get(url).send().await.map_err(LpmError::Http)?;
-
-        if !response.status().is_success() {
-            return Err(LpmError::Http(response.error_for_status().unwrap_err()));
-        }
-
-        let content = response.text().await.map_err(LpmError::Http)?;
-
-        // Cache it
-        self.cache.write(&cache_path, content.as_bytes())?;
-
-        Ok(content)
+        self.fetch_cached(url, &cache_path, &format!("rockspec {}", url))
+            .await
     }
 
     /// Parse a rockspec (sandboxed)
@@ -95,15 +443,13 @@ impl LuaRocksClient {
         if self.cache.exists(&cache_path) {
             return Ok(cache_path);
         }
+        if self.offline {
+            return Err(self.offline_miss(&format!("source {}", url), &cache_path));
+        }
 
         // Download source
         println!("Downloading source package: {}", url);
-        let response = self.client.get(url).send().await.map_err(LpmError::Http)?;
-
-        if !response.status().is_success() {
-            return Err(LpmError::Http(response.error_for_status().unwrap_err()));
-        }
-
+        let response = self.send(Method::GET, url).await?;
         let bytes = response.bytes().await.map_err(LpmError::Http)?;
 
         // Cache it
@@ -113,27 +459,42 @@ impl LuaRocksClient {
     }
 }
 
+/// Split a rockspec file name into package name and version
+///
+/// `lua-cjson-2.1.0-1.rockspec` is ("lua-cjson", "2.1.0-1"); package names may
+/// contain dashes, so the version is taken from the end.
+pub(crate) fn split_rockspec_name(url: &str) -> (&str, Option<String>) {
+    let file = url.rsplit('/').next().unwrap_or(url);
+    let stem = file.strip_suffix(".rockspec").unwrap_or(file);
+    let Some((rest, last)) = stem.rsplit_once('-') else {
+        return (stem, None);
+    };
+    if !last.is_empty() && last.chars().all(|c| c.is_ascii_digit()) {
+        if let Some((name, upstream)) = rest.rsplit_once('-') {
+            return (name, Some(format!("{}-{}", upstream, last)));
+        }
+    }
+    (rest, Some(last.to_string()))
+}
+
 /// Extract package name from rockspec URL
 fn extract_package_name_from_url(url: &str) -> String {
     // URL format: https://luarocks.org/manifests/luarocks/package-version.rockspec
-    url.rsplit('/')
-        .next()
-        .and_then(|f| f.split('-').next())
-        .unwrap_or("unknown")
-        .to_string()
+    match split_rockspec_name(url) {
+        ("", _) => "unknown".to_string(),
+        (name, _) => name.to_string(),
+    }
 }
 
 /// Extract version from rockspec URL
 fn extract_version_from_url(url: &str) -> String {
     // URL format: https://luarocks.org/manifests/luarocks/package-version.rockspec
-    url.rsplit('/')
-        .next()
-        .and_then(|f| {
-            f.strip_suffix(".rockspec")
-                .and_then(|s| s.split('-').nth(1))
-        })
-        .unwrap_or("unknown")
-        .to_string()
+    if !url.ends_with(".rockspec") {
+        return "unknown".to_string();
+    }
+    split_rockspec_name(url)
+        .1
+        .unwrap_or_else(|| "unknown".to_string())
 }
 
 #[cfg(test)]
@@ -144,15 +505,15 @@ mod tests {
     fn test_extract_package_name_from_url() {
         let url = "https://luarocks.org/manifests/luarocks/test-package-1.0.0.rockspec";
         let name = extract_package_name_from_url(url);
-        // The function splits on '-' and takes the first part, so "test-package-1.0.0.rockspec" -> "test"
-        assert_eq!(name, "test");
+        // Package names may contain dashes; the version is taken from the end
+        assert_eq!(name, "test-package");
     }
 
     #[test]
     fn test_extract_package_name_from_url_invalid() {
         let url = "invalid-url";
         let name = extract_package_name_from_url(url);
-        // The function splits on '-' and takes the first part, so "invalid-url" -> "invalid"
+        // Without a version the last dash-separated part is treated as one
         assert_eq!(name, "invalid");
     }
 
@@ -160,11 +521,7 @@ mod tests {
     fn test_extract_version_from_url() {
         let url = "https://luarocks.org/manifests/luarocks/test-package-1.0.0.rockspec";
         let version = extract_version_from_url(url);
-        // The function strips ".rockspec" then splits on '-' and takes the second part
-        // "test-package-1.0.0" -> split on '-' -> ["test", "package", "1.0.0"] -> nth(1) = "package"
-        // Actually, it should be "1.0.0" but the implementation takes nth(1) which is "package"
-        // This test documents the current behavior
-        assert_eq!(version, "package");
+        assert_eq!(version, "1.0.0");
     }
 
     #[test]
@@ -185,19 +542,20 @@ mod tests {
     fn test_extract_package_name_from_url_complex() {
         let url = "https://luarocks.org/manifests/luarocks/complex-package-name-1.2.3.rockspec";
         let name = extract_package_name_from_url(url);
-        // Current implementation splits on '-' and takes first part
-        assert_eq!(name, "complex");
+        assert_eq!(name, "complex-package-name");
     }
 
     #[test]
     fn test_extract_version_from_url_with_revision() {
         let url = "https://luarocks.org/manifests/luarocks/test-1.2.3-1.rockspec";
         let version = extract_version_from_url(url);
-        // Current implementation takes nth(1) after splitting on '-'
-        // "test-1.2.3-1.rockspec" -> strip ".rockspec" -> "test-1.2.3-1" -> split('-') -> nth(1) = "1.2.3"
-        // Actually, it takes the second element after splitting, which would be "1"
-        // This test documents current behavior
-        assert!(!version.is_empty());
+        assert_eq!(version, "1.2.3-1");
+        assert_eq!(
+            extract_package_name_from_url(
+                "https://luarocks.org/manifests/luarocks/lua-cjson-2.1.0.10-1.rockspec"
+            ),
+            "lua-cjson"
+        );
     }
 
     #[test]
@@ -220,7 +578,7 @@ mod tests {
         let client = LuaRocksClient::new(&config, cache);
 
         // Verify client was created
-        assert!(!client.manifest_url.is_empty());
+        assert!(!client.registries().primary().base_url.is_empty());
     }
 
     #[tokio::test]
@@ -260,8 +618,6 @@ mod tests {
         let client = LuaRocksClient::new(&config, cache.clone());
 
         // Create cached rockspec
-        // extract_package_name_from_url("test-1.0.0.rockspec") splits on '-' and takes first -> "test"
-        // extract_version_from_url("test-1.0.0.rockspec") strips ".rockspec", splits on '-', takes nth(1) -> "1.0.0"
         let url = "https://luarocks.org/manifests/luarocks/test-1.0.0.rockspec";
         let cache_path = cache.rockspec_path("test", "1.0.0");
         fs::create_dir_all(cache_path.parent().unwrap()).unwrap();
@@ -323,4 +679,351 @@ build = {
         assert_eq!(rockspec.package, "test-package");
         assert_eq!(rockspec.version, "1.0.0");
     }
+
+    #[tokio::test]
+    async fn test_offline_client_never_downloads() {
+        use crate::cache::Cache;
+        use crate::config::Config;
+        use std::fs;
+        use tempfile::TempDir;
+
+        let temp = TempDir::new().unwrap();
+        let config = Config::load().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        let client = LuaRocksClient::new(&config, cache.clone()).with_offline(true);
+        assert!(client.is_offline());
+
+        let err = client.fetch_manifest().await.unwrap_err().to_string();
+        assert!(err.contains("Offline mode"), "{err}");
+
+        let url = "https://luarocks.org/manifests/luarocks/lua-cjson-2.1.0-1.rockspec";
+        let err = client.download_rockspec(url).await.unwrap_err().to_string();
+        assert!(err.contains("lua-cjson-2.1.0-1.rockspec"), "{err}");
+
+        // Cached files are still served
+        let cache_path = cache.rockspec_path("lua-cjson", "2.1.0-1");
+        fs::create_dir_all(cache_path.parent().unwrap()).unwrap();
+        fs::write(&cache_path, "package = 'lua-cjson'").unwrap();
+        assert!(client.download_rockspec(url).await.is_ok());
+
+        let err = client
+            .download_source("https://example.com/a.tar.gz")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LpmError::Cache(_)));
+    }
+
+    fn manifest_json(packages: &[(&str, &str)]) -> String {
+        let entries: Vec<String> = packages
+            .iter()
+            .map(|(name, version)| {
+                format!(r#""{}": {{"{}": [{{"arch": "rockspec"}}]}}"#, name, version)
+            })
+            .collect();
+        format!(r#"{{"repository": {{{}}}}}"#, entries.join(", "))
+    }
+
+    async fn serve_manifest(server: &wiremock::MockServer, packages: &[(&str, &str)]) {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, ResponseTemplate};
+
+        Mock::given(method("GET"))
+            .and(path("/manifest"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(manifest_json(packages)))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_manifest_merges_registries_and_routes() {
+        use crate::cache::Cache;
+        use crate::config::Config;
+        use tempfile::TempDir;
+        use wiremock::MockServer;
+
+        let internal = MockServer::start().await;
+        let public = MockServer::start().await;
+        serve_manifest(&internal, &[("acme-log", "1.0-1"), ("penlight", "0.1-1")]).await;
+        serve_manifest(&public, &[("acme-log", "9.0-1"), ("penlight", "1.13-1")]).await;
+
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        let registries = Registries::new(vec![
+            Registry::new("public", &public.uri()),
+            Registry::new("internal", &internal.uri()),
+        ])
+        .with_route("acme-*", "internal");
+        let client = LuaRocksClient::new(&Config::default(), cache).with_registries(registries);
+
+        let manifest = client.fetch_manifest().await.unwrap();
+        // Routed packages only come from their registry, others from the first that has them
+        let acme = &manifest.get_package_versions("acme-log").unwrap()[0];
+        assert_eq!(acme.version, "1.0-1");
+        assert_eq!(client.source_name(&acme.rockspec_url), "internal");
+        let penlight = &manifest.get_package_versions("penlight").unwrap()[0];
+        assert_eq!(penlight.version, "1.13-1");
+        assert_eq!(client.source_name(&penlight.rockspec_url), "public");
+
+        // Without a manifest entry, the routed registry is used
+        assert_eq!(
+            client.rockspec_url(&manifest, "acme-new", "1.0-1"),
+            format!("{}/acme-new-1.0-1.rockspec", internal.uri())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_mirror_with_auth() {
+        use crate::cache::Cache;
+        use crate::config::Config;
+        use tempfile::TempDir;
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let primary = MockServer::start().await;
+        let mirror = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&primary)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/manifest"))
+            .and(header("authorization", "Bearer s3cret"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(manifest_json(&[("acme-log", "1.0-1")])),
+            )
+            .mount(&mirror)
+            .await;
+
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        let registry = Registry::new("internal", &primary.uri())
+            .with_mirrors(vec![mirror.uri()])
+            .with_token(Some("s3cret".to_string()));
+        let client = LuaRocksClient::new(&Config::default(), cache)
+            .with_registries(Registries::new(vec![registry]));
+
+        let manifest = client.fetch_manifest().await.unwrap();
+        assert!(manifest.get_package_versions("acme-log").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_do_not_fall_back() {
+        use crate::cache::Cache;
+        use crate::config::Config;
+        use tempfile::TempDir;
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let primary = MockServer::start().await;
+        let mirror = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&primary)
+            .await;
+        serve_manifest(&mirror, &[("acme-log", "1.0-1")]).await;
+
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        let registry = Registry::new("internal", &primary.uri()).with_mirrors(vec![mirror.uri()]);
+        let client = LuaRocksClient::new(&Config::default(), cache)
+            .with_registries(Registries::new(vec![registry]));
+
+        let err = client.fetch_manifest().await.unwrap_err().to_string();
+        assert!(err.contains("401"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_static_server_with_lua_manifest() {
+        use crate::cache::Cache;
+        use crate::config::Config;
+        use tempfile::TempDir;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/manifest"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/manifest-5.1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"repository = { ["acme-log"] = { ["1.0-1"] = { { arch = "rockspec" } } } }
+modules = { ["acme.log"] = { "acme-log/1.0-1" } }
+dependencies = { ["acme-log"] = { ["1.0-1"] = { { name = "lua", constraints = { { op = ">=", version = { string = "5.1" } } } } } } }
+"#,
+            ))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let temp = TempDir::new().unwrap();
+        let registries = Registries::new(vec![Registry::new("static", &server.uri())]);
+        let client = LuaRocksClient::new(
+            &Config::default(),
+            Cache::new(temp.path().to_path_buf()).unwrap(),
+        )
+        .with_registries(registries.clone());
+
+        let manifest = client.fetch_manifest().await.unwrap();
+        assert_eq!(
+            manifest.dependencies_of("acme-log", "1.0-1"),
+            Some(&vec!["lua >= 5.1".to_string()])
+        );
+        assert_eq!(
+            manifest.modules_of("acme-log", "1.0-1"),
+            Some(&vec!["acme.log".to_string()])
+        );
+
+        // Revalidation goes straight to the manifest that worked
+        let client = LuaRocksClient::new(
+            &Config::default(),
+            Cache::new(temp.path().to_path_buf()).unwrap(),
+        )
+        .with_registries(registries)
+        .with_ttl(Duration::ZERO);
+        let manifest = client.fetch_manifest().await.unwrap();
+        assert!(manifest.get_package_versions("acme-log").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_stale_manifest_is_revalidated() {
+        use crate::cache::Cache;
+        use crate::config::Config;
+        use tempfile::TempDir;
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/manifest"))
+            .and(header("if-none-match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/manifest"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string(manifest_json(&[("penlight", "1.13-1")])),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        let registry = Registry::new("internal", &server.uri());
+        let cache_path = cache.manifest_path(&registry);
+        let client = LuaRocksClient::new(&Config::default(), cache.clone())
+            .with_registries(Registries::new(vec![registry]));
+
+        // Downloaded once, then served from the cache while fresh
+        client.fetch_manifest().await.unwrap();
+        client.fetch_manifest().await.unwrap();
+        let fetched_at = cache.read_meta(&cache_path).unwrap().fetched_at;
+        assert_eq!(client.index_refreshed_at(), Some(fetched_at));
+
+        // Once stale, a 304 keeps the cached copy and restarts the TTL
+        let client = client.with_ttl(Duration::ZERO);
+        let manifest = client.fetch_manifest().await.unwrap();
+        assert!(manifest.get_package_versions("penlight").is_some());
+        let meta = cache.read_meta(&cache_path).unwrap();
+        assert!(meta.fetched_at > fetched_at);
+        assert_eq!(meta.etag.as_deref(), Some("\"v1\""));
+    }
+
+    #[tokio::test]
+    async fn test_stale_rockspec_used_when_registry_is_down() {
+        use crate::cache::Cache;
+        use crate::config::Config;
+        use std::fs;
+        use tempfile::TempDir;
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        let client = LuaRocksClient::new(&Config::default(), cache.clone())
+            .with_registries(Registries::new(vec![Registry::new(
+                "internal",
+                &server.uri(),
+            )]))
+            .with_ttl(Duration::ZERO);
+
+        let url = format!("{}/penlight-1.13-1.rockspec", server.uri());
+        assert!(client.download_rockspec(&url).await.is_err());
+
+        let cache_path = cache.rockspec_path("penlight", "1.13-1");
+        fs::create_dir_all(cache_path.parent().unwrap()).unwrap();
+        fs::write(&cache_path, "package = 'penlight'").unwrap();
+        let content = client.download_rockspec(&url).await.unwrap();
+        assert!(content.contains("penlight"));
+    }
+
+    #[tokio::test]
+    async fn test_manifest_is_indexed_with_recorded_rockspecs() {
+        use crate::cache::Cache;
+        use crate::config::Config;
+        use std::fs;
+        use tempfile::TempDir;
+        use wiremock::MockServer;
+
+        let server = MockServer::start().await;
+        serve_manifest(&server, &[("penlight", "1.13-1")]).await;
+
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        let registry = Registry::new("internal", &server.uri());
+        let manifest_path = cache.manifest_path(&registry);
+        let index_path = cache.index_path(&registry);
+        let client = LuaRocksClient::new(&Config::default(), cache)
+            .with_registries(Registries::new(vec![registry]));
+
+        let manifest = client.fetch_manifest().await.unwrap();
+        assert!(index_path.exists());
+        assert_eq!(manifest.dependencies_of("penlight", "1.13-1"), None);
+
+        client
+            .record_rockspecs(&[RockspecSummary {
+                name: "penlight".to_string(),
+                version: "1.13-1".to_string(),
+                dependencies: vec!["luafilesystem".to_string()],
+                modules: vec!["pl".to_string()],
+            }])
+            .unwrap();
+
+        let manifest = client.fetch_manifest().await.unwrap();
+        assert_eq!(
+            manifest.dependencies_of("penlight", "1.13-1"),
+            Some(&vec!["luafilesystem".to_string()])
+        );
+
+        // A rewritten manifest is indexed again, keeping what is known about listed versions
+        fs::write(
+            &manifest_path,
+            manifest_json(&[("penlight", "1.13-1"), ("lpeg", "1.1.0-1")]),
+        )
+        .unwrap();
+        let manifest = client.fetch_manifest().await.unwrap();
+        assert!(manifest.get_package_versions("lpeg").is_some());
+        assert_eq!(
+            manifest.dependencies_of("penlight", "1.13-1"),
+            Some(&vec!["luafilesystem".to_string()])
+        );
+    }
 }
@@ -0,0 +1,340 @@
+use crate::core::{LpmError, LpmResult};
+use crate::luarocks::manifest::{Manifest, PackageVersion};
+use crate::luarocks::rockspec::Rockspec;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Bumped whenever the layout changes; indexes in another format are rebuilt
+const INDEX_FORMAT: u32 = 2;
+
+/// Compact, binary form of a registry manifest
+///
+/// Built once from the downloaded manifest and stored next to it, so later
+/// runs skip parsing it. JSON manifests only list versions; dependencies and
+/// module names come from Lua manifests that list them, or are added as
+/// rockspecs are read.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackageIndex {
+    format: u32,
+    /// Size and modification time of the manifest the index was built from
+    stamp: ManifestStamp,
+    pub repository: String,
+    pub base_url: String,
+    packages: BTreeMap<String, Vec<IndexedVersion>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+struct ManifestStamp {
+    len: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl ManifestStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedVersion {
+    pub version: String,
+    /// Rockspec dependencies, e.g. "luafilesystem >= 1.8", once the rockspec has been read
+    pub dependencies: Option<Vec<String>>,
+    /// Lua modules the rock provides, once the rockspec has been read
+    pub modules: Option<Vec<String>>,
+    /// Arches the registry has a `.rock` for, e.g. "src" or "linux-x86_64"
+    pub rocks: Vec<String>,
+}
+
+/// What the index keeps from a rockspec
+#[derive(Debug, Clone, PartialEq)]
+pub struct RockspecSummary {
+    pub name: String,
+    pub version: String,
+    pub dependencies: Vec<String>,
+    pub modules: Vec<String>,
+}
+
+impl RockspecSummary {
+    pub fn new(name: &str, version: &str, rockspec: &Rockspec) -> Self {
+        let mut modules: Vec<String> = rockspec
+            .build
+            .modules
+            .keys()
+            .chain(rockspec.build.native_modules.keys())
+            .cloned()
+            .collect();
+        modules.sort();
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            dependencies: rockspec.dependencies.clone(),
+            modules,
+        }
+    }
+}
+
+impl PackageIndex {
+    /// Index a parsed manifest that was read from `manifest_path`
+    ///
+    /// Dependencies and modules the manifest lists are taken from it; otherwise
+    /// those already known from `previous` (the index of an older copy of the
+    /// manifest) are kept for versions that are still listed.
+    pub fn build(
+        manifest: &Manifest,
+        manifest_path: &Path,
+        base_url: &str,
+        previous: Option<&PackageIndex>,
+    ) -> Self {
+        let packages = manifest
+            .packages
+            .iter()
+            .map(|(name, versions)| {
+                let mut versions: Vec<IndexedVersion> = versions
+                    .iter()
+                    .map(|pv| {
+                        let known = previous.and_then(|index| index.get(name, &pv.version));
+                        IndexedVersion {
+                            version: pv.version.clone(),
+                            dependencies: manifest
+                                .dependencies_of(name, &pv.version)
+                                .cloned()
+                                .or_else(|| known.and_then(|v| v.dependencies.clone())),
+                            modules: manifest
+                                .modules_of(name, &pv.version)
+                                .cloned()
+                                .or_else(|| known.and_then(|v| v.modules.clone())),
+                            rocks: manifest.rocks_of(name, &pv.version).to_vec(),
+                        }
+                    })
+                    .collect();
+                versions.sort_by(|a, b| a.version.cmp(&b.version));
+                (name.clone(), versions)
+            })
+            .collect();
+
+        Self {
+            format: INDEX_FORMAT,
+            stamp: ManifestStamp::of(manifest_path).unwrap_or_default(),
+            repository: manifest.repository.clone(),
+            base_url: base_url.to_string(),
+            packages,
+        }
+    }
+
+    /// Read the index at `path`, whatever manifest it was built from
+    pub fn read(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        bincode::deserialize::<Self>(&bytes)
+            .ok()
+            .filter(|index| index.format == INDEX_FORMAT)
+    }
+
+    /// Read the index at `path` if it was built from the manifest now at `manifest_path`
+    pub fn load(path: &Path, manifest_path: &Path, base_url: &str) -> Option<Self> {
+        let stamp = ManifestStamp::of(manifest_path)?;
+        Self::read(path).filter(|index| index.stamp == stamp && index.base_url == base_url)
+    }
+
+    pub fn save(&self, path: &Path) -> LpmResult<()> {
+        let bytes = bincode::serialize(self)
+            .map_err(|e| LpmError::Cache(format!("Failed to serialize package index: {}", e)))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file first so a concurrent reader never sees half an index
+        let tmp = path.with_extension("index.tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str, version: &str) -> Option<&IndexedVersion> {
+        self.packages
+            .get(name)?
+            .iter()
+            .find(|v| v.version == version)
+    }
+
+    pub fn versions(&self, name: &str) -> Option<&[IndexedVersion]> {
+        self.packages.get(name).map(Vec::as_slice)
+    }
+
+    pub fn package_names(&self) -> impl Iterator<Item = &str> {
+        self.packages.keys().map(String::as_str)
+    }
+
+    /// Remember a rockspec's dependencies and modules; false if the version isn't indexed
+    pub fn record(&mut self, summary: &RockspecSummary) -> bool {
+        let Some(entry) = self
+            .packages
+            .get_mut(&summary.name)
+            .and_then(|versions| versions.iter_mut().find(|v| v.version == summary.version))
+        else {
+            return false;
+        };
+        entry.dependencies = Some(summary.dependencies.clone());
+        entry.modules = Some(summary.modules.clone());
+        true
+    }
+
+    /// Packages (and versions) known to provide a Lua module, e.g. "lfs"
+    pub fn packages_providing(&self, module: &str) -> Vec<(&str, &str)> {
+        self.packages
+            .iter()
+            .flat_map(|(name, versions)| {
+                versions
+                    .iter()
+                    .filter(|v| v.modules.iter().flatten().any(|m| m == module))
+                    .map(move |v| (name.as_str(), v.version.as_str()))
+            })
+            .collect()
+    }
+
+    /// The index as a `Manifest`, with the dependencies and modules known so far
+    pub fn to_manifest(&self) -> Manifest {
+        let mut packages = HashMap::with_capacity(self.packages.len());
+        let mut dependencies = HashMap::new();
+        let mut modules = HashMap::new();
+        let mut rocks = HashMap::new();
+        for (name, versions) in &self.packages {
+            let mut known = HashMap::new();
+            let mut known_modules = HashMap::new();
+            let mut known_rocks = HashMap::new();
+            let package_versions = versions
+                .iter()
+                .map(|v| {
+                    if let Some(deps) = &v.dependencies {
+                        known.insert(v.version.clone(), deps.clone());
+                    }
+                    if let Some(provided) = &v.modules {
+                        known_modules.insert(v.version.clone(), provided.clone());
+                    }
+                    if !v.rocks.is_empty() {
+                        known_rocks.insert(v.version.clone(), v.rocks.clone());
+                    }
+                    PackageVersion {
+                        version: v.version.clone(),
+                        rockspec_url: format!("{}/{}-{}.rockspec", self.base_url, name, v.version),
+                        archive_url: None,
+                    }
+                })
+                .collect();
+            packages.insert(name.clone(), package_versions);
+            if !known.is_empty() {
+                dependencies.insert(name.clone(), known);
+            }
+            if !known_modules.is_empty() {
+                modules.insert(name.clone(), known_modules);
+            }
+            if !known_rocks.is_empty() {
+                rocks.insert(name.clone(), known_rocks);
+            }
+        }
+        Manifest {
+            repository: self.repository.clone(),
+            packages,
+            dependencies,
+            modules,
+            rocks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const JSON: &str = r#"{"repository": {
+        "luafilesystem": {"1.8.0-1": [{"arch": "rockspec"}, {"arch": "linux-x86_64"}], "1.7.0-2": [{"arch": "rockspec"}]},
+        "penlight": {"1.13.1-1": [{"arch": "rockspec"}]}
+    }}"#;
+
+    fn build(dir: &Path, previous: Option<&PackageIndex>) -> PackageIndex {
+        let manifest_path = dir.join("manifest.json");
+        fs::write(&manifest_path, JSON).unwrap();
+        let manifest = Manifest::parse_json_for(JSON, "luarocks", "https://example.com").unwrap();
+        PackageIndex::build(&manifest, &manifest_path, "https://example.com", previous)
+    }
+
+    fn summary(name: &str, version: &str, deps: &[&str], modules: &[&str]) -> RockspecSummary {
+        RockspecSummary {
+            name: name.to_string(),
+            version: version.to_string(),
+            dependencies: deps.iter().map(|d| d.to_string()).collect(),
+            modules: modules.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let index = build(temp.path(), None);
+        let versions: Vec<_> = index
+            .versions("luafilesystem")
+            .unwrap()
+            .iter()
+            .map(|v| v.version.as_str())
+            .collect();
+        assert_eq!(versions, ["1.7.0-2", "1.8.0-1"]);
+        assert_eq!(
+            index.to_manifest().rocks_of("luafilesystem", "1.8.0-1"),
+            ["linux-x86_64"]
+        );
+
+        let path = temp.path().join("manifest.index");
+        index.save(&path).unwrap();
+        let manifest_path = temp.path().join("manifest.json");
+        assert_eq!(
+            PackageIndex::load(&path, &manifest_path, "https://example.com"),
+            Some(index.clone())
+        );
+        assert!(PackageIndex::load(&path, &manifest_path, "https://other.example.com").is_none());
+
+        // A rewritten manifest makes the index stale
+        fs::write(&manifest_path, format!("{} ", JSON)).unwrap();
+        assert!(PackageIndex::load(&path, &manifest_path, "https://example.com").is_none());
+        assert_eq!(PackageIndex::read(&path), Some(index));
+    }
+
+    #[test]
+    fn test_recorded_rockspecs_survive_rebuilds() {
+        let temp = TempDir::new().unwrap();
+        let mut index = build(temp.path(), None);
+        assert!(index.record(&summary(
+            "penlight",
+            "1.13.1-1",
+            &["luafilesystem"],
+            &["pl"]
+        )));
+        assert!(!index.record(&summary("penlight", "9.9-1", &[], &[])));
+        assert_eq!(index.packages_providing("pl"), [("penlight", "1.13.1-1")]);
+
+        let rebuilt = build(temp.path(), Some(&index));
+        let manifest = rebuilt.to_manifest();
+        assert_eq!(
+            manifest.dependencies_of("penlight", "1.13.1-1"),
+            Some(&vec!["luafilesystem".to_string()])
+        );
+        assert_eq!(manifest.dependencies_of("luafilesystem", "1.8.0-1"), None);
+        assert_eq!(
+            manifest.modules_of("penlight", "1.13.1-1"),
+            Some(&vec!["pl".to_string()])
+        );
+        assert_eq!(
+            manifest.get_package_versions("penlight").unwrap()[0].rockspec_url,
+            "https://example.com/penlight-1.13.1-1.rockspec"
+        );
+    }
+}
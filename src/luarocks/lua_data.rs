@@ -0,0 +1,1448 @@
+//! Sandboxed evaluator for data-only Lua files (rockspecs, manifests)
+//!
+//! Rockspecs and LuaRocks manifests are Lua programs, but in practice they only
+//! assign literals, tables and string concatenations to global variables. This
+//! module evaluates exactly that subset: `local` and global assignments, field
+//! assignments (`build.type = "make"`), table constructors, all Lua string forms,
+//! numbers, booleans, `..` concatenation, arithmetic, comparisons and `and`/`or`/`not`.
+//!
+//! Anything that could execute code is rejected with an error instead of being run:
+//! function calls and definitions, method calls, loops, `if`, `goto`, `return`.
+//! There is no standard library, so nothing in the file can reach the filesystem,
+//! the network or the environment.
+//!
+//! [`serialize`] goes the other way, writing tables back out as such a file.
+
+use crate::core::{LpmError, LpmResult};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Maximum nesting of tables and parenthesised expressions
+const MAX_DEPTH: usize = 200;
+
+/// Maximum bytes of string data a file may produce, counting every copy
+const MAX_STRING_BYTES: usize = 64 * 1024 * 1024;
+
+/// Maximum table entries a file may produce, counting every copy
+const MAX_TABLE_ENTRIES: usize = 8 * 1024 * 1024;
+
+/// A Lua value produced by evaluation
+#[derive(Debug, Clone, PartialEq)]
+pub enum LuaValue {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Table(LuaTable),
+}
+
+/// A table key: integers, strings and booleans (float keys with an integral value
+/// are normalised to integers, as in Lua)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LuaKey {
+    Integer(i64),
+    String(String),
+    Boolean(bool),
+}
+
+/// A Lua table with value semantics
+///
+/// The sequence part (`t[1]..t[n]`) is kept in order; every other key lives in
+/// the map. Assigning a table to two variables copies it, which is
+/// indistinguishable from Lua's reference semantics for data-only files.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LuaTable {
+    sequence: Vec<LuaValue>,
+    fields: BTreeMap<LuaKey, LuaValue>,
+}
+
+impl LuaValue {
+    /// Whether the value counts as true in a condition (everything but nil and false)
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, LuaValue::Nil | LuaValue::Boolean(false))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            LuaValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&LuaTable> {
+        match self {
+            LuaValue::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// The value as a string, converting numbers the way `tostring` does
+    pub fn to_lua_string(&self) -> Option<String> {
+        match self {
+            LuaValue::String(s) => Some(s.clone()),
+            LuaValue::Number(n) => Some(format_number(*n)),
+            _ => None,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            LuaValue::Nil => "nil",
+            LuaValue::Boolean(_) => "boolean",
+            LuaValue::Number(_) => "number",
+            LuaValue::String(_) => "string",
+            LuaValue::Table(_) => "table",
+        }
+    }
+}
+
+impl fmt::Display for LuaValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LuaValue::Nil => write!(f, "nil"),
+            LuaValue::Boolean(b) => write!(f, "{}", b),
+            LuaValue::Number(n) => write!(f, "{}", format_number(*n)),
+            LuaValue::String(s) => write!(f, "{}", s),
+            LuaValue::Table(_) => write!(f, "table"),
+        }
+    }
+}
+
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n)
+    }
+}
+
+impl LuaKey {
+    fn from_value(value: LuaValue) -> LpmResult<Self> {
+        match value {
+            LuaValue::String(s) => Ok(LuaKey::String(s)),
+            LuaValue::Boolean(b) => Ok(LuaKey::Boolean(b)),
+            LuaValue::Number(n) if n.fract() == 0.0 && n.abs() < 9.0e15 => {
+                Ok(LuaKey::Integer(n as i64))
+            }
+            other => Err(LpmError::Package(format!(
+                "Unsupported table key of type {}",
+                other.type_name()
+            ))),
+        }
+    }
+}
+
+impl LuaTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up a value by key (`nil` if absent)
+    pub fn get_key(&self, key: &LuaKey) -> &LuaValue {
+        if let LuaKey::Integer(i) = key {
+            if *i >= 1 && (*i as usize) <= self.sequence.len() {
+                return &self.sequence[*i as usize - 1];
+            }
+        }
+        self.fields.get(key).unwrap_or(&LuaValue::Nil)
+    }
+
+    /// Look up a string field, e.g. `t.package`
+    pub fn get(&self, name: &str) -> &LuaValue {
+        self.fields
+            .get(&LuaKey::String(name.to_string()))
+            .unwrap_or(&LuaValue::Nil)
+    }
+
+    /// A string field; numbers are converted like `tostring` does
+    pub fn get_string(&self, name: &str) -> Option<String> {
+        self.get(name).to_lua_string()
+    }
+
+    /// A table field
+    pub fn get_table(&self, name: &str) -> Option<&LuaTable> {
+        self.get(name).as_table()
+    }
+
+    /// The value at a key, for modifying it in place; `None` if it is `nil`
+    fn get_key_mut(&mut self, key: &LuaKey) -> Option<&mut LuaValue> {
+        if let LuaKey::Integer(i) = key {
+            if *i >= 1 && *i as usize <= self.sequence.len() {
+                return Some(&mut self.sequence[*i as usize - 1]);
+            }
+        }
+        self.fields.get_mut(key)
+    }
+
+    /// A table field, for modifying it in place
+    pub fn get_table_mut(&mut self, name: &str) -> Option<&mut LuaTable> {
+        match self.fields.get_mut(&LuaKey::String(name.to_string())) {
+            Some(LuaValue::Table(t)) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// Assign a value; assigning `nil` removes the key
+    pub fn set(&mut self, key: LuaKey, value: LuaValue) {
+        if let LuaKey::Integer(i) = key {
+            let len = self.sequence.len() as i64;
+            if i >= 1 && i <= len {
+                if value == LuaValue::Nil && i == len {
+                    self.sequence.pop();
+                } else if value == LuaValue::Nil {
+                    // A hole: move the tail out of the sequence part
+                    let tail = self.sequence.split_off(i as usize);
+                    self.sequence.pop();
+                    for (offset, v) in tail.into_iter().enumerate() {
+                        self.fields
+                            .insert(LuaKey::Integer(i + 1 + offset as i64), v);
+                    }
+                } else {
+                    self.sequence[i as usize - 1] = value;
+                }
+                return;
+            }
+            if i == len + 1 && value != LuaValue::Nil {
+                self.sequence.push(value);
+                // Pull following integer keys into the sequence part
+                let mut next = i + 1;
+                while let Some(v) = self.fields.remove(&LuaKey::Integer(next)) {
+                    self.sequence.push(v);
+                    next += 1;
+                }
+                return;
+            }
+        }
+        if value == LuaValue::Nil {
+            self.fields.remove(&key);
+        } else {
+            self.fields.insert(key, value);
+        }
+    }
+
+    /// The sequence part: `t[1]`, `t[2]`, ... up to the first `nil`
+    pub fn sequence(&self) -> &[LuaValue] {
+        &self.sequence
+    }
+
+    /// All string-keyed fields in key order
+    pub fn string_pairs(&self) -> impl Iterator<Item = (&str, &LuaValue)> {
+        self.fields.iter().filter_map(|(k, v)| match k {
+            LuaKey::String(s) => Some((s.as_str(), v)),
+            _ => None,
+        })
+    }
+
+    /// Number of entries in the sequence part (Lua's `#t`)
+    pub fn len(&self) -> usize {
+        self.sequence.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequence.is_empty() && self.fields.is_empty()
+    }
+
+    /// Recursively merge `other` into this table, like LuaRocks' `util.deep_merge`
+    ///
+    /// Nested tables are merged key by key (list entries by index); any other
+    /// value in `other` replaces the existing one.
+    pub fn deep_merge(&mut self, other: &LuaTable) {
+        let indexed = other
+            .sequence
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (LuaKey::Integer(i as i64 + 1), v));
+        let entries: Vec<(LuaKey, &LuaValue)> = indexed
+            .chain(other.fields.iter().map(|(k, v)| (k.clone(), v)))
+            .collect();
+
+        for (key, value) in entries {
+            let merged = match value {
+                LuaValue::Table(table) => {
+                    let mut current = match self.get_key(&key) {
+                        LuaValue::Table(existing) => existing.clone(),
+                        _ => LuaTable::new(),
+                    };
+                    current.deep_merge(table);
+                    LuaValue::Table(current)
+                }
+                other => other.clone(),
+            };
+            self.set(key, merged);
+        }
+    }
+
+    /// Remove a string field, returning its value
+    pub fn remove(&mut self, name: &str) -> LuaValue {
+        self.fields
+            .remove(&LuaKey::String(name.to_string()))
+            .unwrap_or(LuaValue::Nil)
+    }
+}
+
+/// Evaluate a data-only Lua chunk and return its global variables
+pub fn evaluate(source: &str) -> LpmResult<LuaTable> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let mut evaluator = Evaluator {
+        tokens,
+        pos: 0,
+        globals: LuaTable::new(),
+        locals: Vec::new(),
+        depth: 0,
+        budget: Budget::default(),
+    };
+    evaluator.chunk()?;
+    Ok(evaluator.globals)
+}
+
+/// Write globals back out as a data-only Lua file, one `name = value` per global
+///
+/// The inverse of [`evaluate`], laid out like the files LuaRocks writes itself:
+/// keys in order, one entry per line, three-space indentation.
+pub fn serialize(globals: &LuaTable) -> String {
+    let mut out = String::new();
+    for (name, value) in globals.string_pairs() {
+        out.push_str(&format_key(name));
+        out.push_str(" = ");
+        write_value(&mut out, value, 0);
+        out.push('\n');
+    }
+    out
+}
+
+fn write_value(out: &mut String, value: &LuaValue, indent: usize) {
+    match value {
+        LuaValue::Nil => out.push_str("nil"),
+        LuaValue::Boolean(b) => out.push_str(&b.to_string()),
+        LuaValue::Number(n) => out.push_str(&format_number(*n)),
+        LuaValue::String(s) => out.push_str(&quote(s)),
+        LuaValue::Table(table) if table.is_empty() => out.push_str("{}"),
+        LuaValue::Table(table) => {
+            let pad = "   ".repeat(indent + 1);
+            out.push_str("{\n");
+            for item in &table.sequence {
+                out.push_str(&pad);
+                write_value(out, item, indent + 1);
+                out.push_str(",\n");
+            }
+            for (key, item) in &table.fields {
+                out.push_str(&pad);
+                match key {
+                    LuaKey::String(name) => out.push_str(&format_key(name)),
+                    LuaKey::Integer(i) => out.push_str(&format!("[{}]", i)),
+                    LuaKey::Boolean(b) => out.push_str(&format!("[{}]", b)),
+                }
+                out.push_str(" = ");
+                write_value(out, item, indent + 1);
+                out.push_str(",\n");
+            }
+            out.push_str(&"   ".repeat(indent));
+            out.push('}');
+        }
+    }
+}
+
+/// `name` as a table key: bare if it is an identifier, `["..."]` otherwise
+fn format_key(name: &str) -> String {
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name);
+    if is_identifier {
+        name.to_string()
+    } else {
+        format!("[{}]", quote(name))
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Str(String),
+    Number(f64),
+    Symbol(&'static str),
+    Eof,
+}
+
+const KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+// Longest symbols first so "..." is not lexed as ".." followed by "."
+const SYMBOLS: &[&str] = &[
+    "...", "..", "==", "~=", "<=", ">=", "//", "::", "<<", ">>", "+", "-", "*", "/", "%", "^", "#",
+    "&", "~", "|", "<", ">", "=", "(", ")", "{", "}", "[", "]", ";", ":", ",", ".",
+];
+
+struct Lexer<'a> {
+    src: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            src: source.as_bytes(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: &str) -> LpmError {
+        LpmError::Package(format!(
+            "Lua syntax error at line {}: {}",
+            self.line, message
+        ))
+    }
+
+    fn peek(&self, offset: usize) -> u8 {
+        self.src.get(self.pos + offset).copied().unwrap_or(0)
+    }
+
+    fn tokenize(mut self) -> LpmResult<Vec<(Token, usize)>> {
+        let mut tokens = Vec::new();
+        // Skip a shebang line
+        if self.src.starts_with(b"#") {
+            while self.pos < self.src.len() && self.peek(0) != b'\n' {
+                self.pos += 1;
+            }
+        }
+        loop {
+            self.skip_whitespace_and_comments()?;
+            let line = self.line;
+            if self.pos >= self.src.len() {
+                tokens.push((Token::Eof, line));
+                return Ok(tokens);
+            }
+            let c = self.peek(0);
+            let token = if c.is_ascii_alphabetic() || c == b'_' {
+                let start = self.pos;
+                while self.peek(0).is_ascii_alphanumeric() || self.peek(0) == b'_' {
+                    self.pos += 1;
+                }
+                Token::Name(String::from_utf8_lossy(&self.src[start..self.pos]).into_owned())
+            } else if c.is_ascii_digit() || (c == b'.' && self.peek(1).is_ascii_digit()) {
+                Token::Number(self.number()?)
+            } else if c == b'"' || c == b'\'' {
+                Token::Str(self.quoted_string(c)?)
+            } else if c == b'[' && (self.peek(1) == b'[' || self.peek(1) == b'=') {
+                match self.long_bracket()? {
+                    Some(s) => Token::Str(s),
+                    None => {
+                        self.pos += 1;
+                        Token::Symbol("[")
+                    }
+                }
+            } else {
+                let rest = &self.src[self.pos..];
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|s| rest.starts_with(s.as_bytes()))
+                    .ok_or_else(|| self.error(&format!("unexpected character '{}'", c as char)))?;
+                self.pos += symbol.len();
+                Token::Symbol(symbol)
+            };
+            tokens.push((token, line));
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> LpmResult<()> {
+        loop {
+            match self.peek(0) {
+                b'\n' => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                b' ' | b'\t' | b'\r' | 0x0b | 0x0c => self.pos += 1,
+                b'-' if self.peek(1) == b'-' => {
+                    self.pos += 2;
+                    if self.peek(0) == b'[' && self.long_bracket()?.is_some() {
+                        continue;
+                    }
+                    while self.pos < self.src.len() && self.peek(0) != b'\n' {
+                        self.pos += 1;
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Read `[[...]]` / `[==[...]==]`; returns `None` if this is not a long bracket
+    fn long_bracket(&mut self) -> LpmResult<Option<String>> {
+        let mut level = 0;
+        while self.peek(1 + level) == b'=' {
+            level += 1;
+        }
+        if self.peek(1 + level) != b'[' {
+            return Ok(None);
+        }
+        self.pos += 2 + level;
+        // A newline right after the opening bracket is skipped
+        if self.peek(0) == b'\r' {
+            self.pos += 1;
+        }
+        if self.peek(0) == b'\n' {
+            self.line += 1;
+            self.pos += 1;
+        }
+        let close = format!("]{}]", "=".repeat(level));
+        let start = self.pos;
+        loop {
+            if self.pos >= self.src.len() {
+                return Err(self.error("unfinished long string"));
+            }
+            if self.src[self.pos..].starts_with(close.as_bytes()) {
+                let s = String::from_utf8_lossy(&self.src[start..self.pos]).into_owned();
+                self.pos += close.len();
+                return Ok(Some(s));
+            }
+            if self.peek(0) == b'\n' {
+                self.line += 1;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn quoted_string(&mut self, quote: u8) -> LpmResult<String> {
+        self.pos += 1;
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let c = self.peek(0);
+            if self.pos >= self.src.len() || c == b'\n' {
+                return Err(self.error("unfinished string"));
+            }
+            self.pos += 1;
+            if c == quote {
+                return Ok(String::from_utf8_lossy(&out).into_owned());
+            }
+            if c != b'\\' {
+                out.push(c);
+                continue;
+            }
+            let e = self.peek(0);
+            self.pos += 1;
+            match e {
+                b'n' => out.push(b'\n'),
+                b't' => out.push(b'\t'),
+                b'r' => out.push(b'\r'),
+                b'a' => out.push(0x07),
+                b'b' => out.push(0x08),
+                b'f' => out.push(0x0c),
+                b'v' => out.push(0x0b),
+                b'\\' | b'"' | b'\'' => out.push(e),
+                b'\n' => {
+                    self.line += 1;
+                    out.push(b'\n');
+                }
+                b'z' => {
+                    while self.peek(0).is_ascii_whitespace() {
+                        if self.peek(0) == b'\n' {
+                            self.line += 1;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                b'x' => {
+                    let hex = std::str::from_utf8(
+                        &self.src[self.pos..(self.pos + 2).min(self.src.len())],
+                    )
+                    .ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                    .ok_or_else(|| self.error("invalid \\x escape"))?;
+                    self.pos += 2;
+                    out.push(hex);
+                }
+                b'u' => {
+                    if self.peek(0) != b'{' {
+                        return Err(self.error("invalid \\u escape"));
+                    }
+                    let end = self.src[self.pos..]
+                        .iter()
+                        .position(|&b| b == b'}')
+                        .ok_or_else(|| self.error("invalid \\u escape"))?;
+                    let code = std::str::from_utf8(&self.src[self.pos + 1..self.pos + end])
+                        .ok()
+                        .and_then(|h| u32::from_str_radix(h, 16).ok())
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| self.error("invalid \\u escape"))?;
+                    self.pos += end + 1;
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(code.encode_utf8(&mut buf).as_bytes());
+                }
+                d if d.is_ascii_digit() => {
+                    let mut value = (d - b'0') as u32;
+                    for _ in 0..2 {
+                        if self.peek(0).is_ascii_digit() {
+                            value = value * 10 + (self.peek(0) - b'0') as u32;
+                            self.pos += 1;
+                        }
+                    }
+                    let byte =
+                        u8::try_from(value).map_err(|_| self.error("decimal escape too large"))?;
+                    out.push(byte);
+                }
+                _ => return Err(self.error("invalid escape sequence")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> LpmResult<f64> {
+        let start = self.pos;
+        if self.peek(0) == b'0' && matches!(self.peek(1), b'x' | b'X') {
+            self.pos += 2;
+            while self.peek(0).is_ascii_hexdigit() {
+                self.pos += 1;
+            }
+            let digits = std::str::from_utf8(&self.src[start + 2..self.pos]).unwrap_or("");
+            return i64::from_str_radix(digits, 16)
+                .map(|n| n as f64)
+                .map_err(|_| self.error("malformed number"));
+        }
+        while self.peek(0).is_ascii_digit() || self.peek(0) == b'.' {
+            self.pos += 1;
+        }
+        if matches!(self.peek(0), b'e' | b'E') {
+            self.pos += 1;
+            if matches!(self.peek(0), b'+' | b'-') {
+                self.pos += 1;
+            }
+            while self.peek(0).is_ascii_digit() {
+                self.pos += 1;
+            }
+        }
+        std::str::from_utf8(&self.src[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .ok_or_else(|| self.error("malformed number"))
+    }
+}
+
+/// Binary operators with their (left, right) binding power, as in the Lua grammar
+fn binary_precedence(op: &str) -> Option<(u8, u8)> {
+    Some(match op {
+        "or" => (1, 1),
+        "and" => (2, 2),
+        "<" | ">" | "<=" | ">=" | "~=" | "==" => (3, 3),
+        ".." => (9, 8), // right associative
+        "+" | "-" => (10, 10),
+        "*" | "/" | "//" | "%" => (11, 11),
+        "^" => (14, 13), // right associative
+        _ => return None,
+    })
+}
+
+const UNARY_PRECEDENCE: u8 = 12;
+
+/// An assignment target: a variable followed by zero or more indexing steps
+struct Target {
+    name: String,
+    path: Vec<LuaKey>,
+}
+
+struct Evaluator {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    globals: LuaTable,
+    locals: Vec<(String, LuaValue)>,
+    depth: usize,
+    budget: Budget,
+}
+
+/// Data produced so far, so that `s = s .. s` or `t = {t, t}` repeated a few
+/// dozen times is an error rather than an exponential allocation
+#[derive(Default)]
+struct Budget {
+    string_bytes: usize,
+    table_entries: usize,
+}
+
+impl Budget {
+    /// Account for a new copy of `value`
+    fn charge(&mut self, value: &LuaValue, line: usize) -> LpmResult<()> {
+        let (bytes, entries) = footprint(value);
+        self.add(bytes, entries, line)
+    }
+
+    /// Account for an entry added by a table constructor
+    fn table_entry(&mut self, line: usize) -> LpmResult<()> {
+        self.add(0, 1, line)
+    }
+
+    fn add(&mut self, bytes: usize, entries: usize, line: usize) -> LpmResult<()> {
+        self.string_bytes = self.string_bytes.saturating_add(bytes);
+        self.table_entries = self.table_entries.saturating_add(entries);
+        if self.string_bytes > MAX_STRING_BYTES || self.table_entries > MAX_TABLE_ENTRIES {
+            return Err(LpmError::Package(format!(
+                "Lua error at line {}: file produces too much data (limit is {} MB of strings and {} table entries)",
+                line,
+                MAX_STRING_BYTES / (1024 * 1024),
+                MAX_TABLE_ENTRIES
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// String bytes and table entries held by a value, including nested tables
+fn footprint(value: &LuaValue) -> (usize, usize) {
+    match value {
+        LuaValue::String(s) => (s.len(), 0),
+        LuaValue::Table(t) => {
+            let keys = t.fields.keys().map(|key| match key {
+                LuaKey::String(s) => (s.len(), 0),
+                _ => (0, 0),
+            });
+            let values = t.sequence.iter().chain(t.fields.values()).map(footprint);
+            keys.chain(values).fold(
+                (0, t.sequence.len() + t.fields.len()),
+                |(bytes, entries), (b, e)| (bytes.saturating_add(b), entries.saturating_add(e)),
+            )
+        }
+        _ => (0, 0),
+    }
+}
+
+impl Evaluator {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        let index = (self.pos + offset).min(self.tokens.len() - 1);
+        &self.tokens[index].0
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Token::Symbol(s) if *s == symbol)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Name(n) if n == keyword)
+    }
+
+    fn error(&self, message: &str) -> LpmError {
+        LpmError::Package(format!(
+            "Lua syntax error at line {}: {}",
+            self.line(),
+            message
+        ))
+    }
+
+    fn unsupported(&self, what: &str) -> LpmError {
+        LpmError::Package(format!(
+            "Unsupported Lua at line {}: {} (only data assignments are allowed)",
+            self.line(),
+            what
+        ))
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> LpmResult<()> {
+        if self.is_symbol(symbol) {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", symbol)))
+        }
+    }
+
+    fn name(&mut self) -> LpmResult<String> {
+        match self.peek().clone() {
+            Token::Name(n) if !KEYWORDS.contains(&n.as_str()) => {
+                self.advance();
+                Ok(n)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    fn enter(&mut self) -> LpmResult<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        Ok(())
+    }
+
+    fn chunk(&mut self) -> LpmResult<()> {
+        while *self.peek() != Token::Eof {
+            if self.is_symbol(";") {
+                self.advance();
+                continue;
+            }
+            self.statement()?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> LpmResult<()> {
+        if let Token::Name(keyword) = self.peek().clone() {
+            match keyword.as_str() {
+                "local" => return self.local_statement(),
+                "function" => return Err(self.unsupported("function definition")),
+                "return" => return Err(self.unsupported("return statement")),
+                "if" | "while" | "for" | "repeat" | "do" | "goto" | "break" => {
+                    return Err(self.unsupported(&format!("'{}' statement", keyword)))
+                }
+                _ => {}
+            }
+        }
+        if self.is_symbol("::") {
+            return Err(self.unsupported("label"));
+        }
+
+        let mut targets = vec![self.target()?];
+        while self.is_symbol(",") {
+            self.advance();
+            targets.push(self.target()?);
+        }
+        if !self.is_symbol("=") {
+            return Err(self.unsupported("function call"));
+        }
+        self.advance();
+        let values = self.expression_list(targets.len())?;
+        for (target, value) in targets.into_iter().zip(values) {
+            self.assign(target, value)?;
+        }
+        Ok(())
+    }
+
+    fn local_statement(&mut self) -> LpmResult<()> {
+        self.advance();
+        if self.is_keyword("function") {
+            return Err(self.unsupported("function definition"));
+        }
+        let mut names = vec![self.name()?];
+        self.skip_attribute()?;
+        while self.is_symbol(",") {
+            self.advance();
+            names.push(self.name()?);
+            self.skip_attribute()?;
+        }
+        let values = if self.is_symbol("=") {
+            self.advance();
+            self.expression_list(names.len())?
+        } else {
+            vec![LuaValue::Nil; names.len()]
+        };
+        for (name, value) in names.into_iter().zip(values) {
+            self.locals.push((name, value));
+        }
+        Ok(())
+    }
+
+    /// Lua 5.4 `<const>` / `<close>` attributes carry no meaning for data
+    fn skip_attribute(&mut self) -> LpmResult<()> {
+        if self.is_symbol("<") {
+            self.advance();
+            self.name()?;
+            self.expect_symbol(">")?;
+        }
+        Ok(())
+    }
+
+    /// Evaluate an expression list, padding with `nil` to `count` values
+    fn expression_list(&mut self, count: usize) -> LpmResult<Vec<LuaValue>> {
+        let mut values = vec![self.expression(0)?];
+        while self.is_symbol(",") {
+            self.advance();
+            values.push(self.expression(0)?);
+        }
+        values.resize(count.max(values.len()), LuaValue::Nil);
+        Ok(values)
+    }
+
+    fn target(&mut self) -> LpmResult<Target> {
+        let name = self.name()?;
+        let mut path = Vec::new();
+        loop {
+            if self.is_symbol(".") {
+                self.advance();
+                path.push(LuaKey::String(self.name()?));
+            } else if self.is_symbol("[") {
+                self.advance();
+                let key = self.expression(0)?;
+                self.expect_symbol("]")?;
+                path.push(LuaKey::from_value(key).map_err(|e| self.error(&e.to_string()))?);
+            } else if self.is_symbol("(") || self.is_symbol(":") || self.starts_call_argument() {
+                return Err(self.unsupported("function call"));
+            } else {
+                return Ok(Target { name, path });
+            }
+        }
+    }
+
+    fn starts_call_argument(&self) -> bool {
+        matches!(self.peek(), Token::Str(_)) || self.is_symbol("{")
+    }
+
+    fn assign(&mut self, target: Target, value: LuaValue) -> LpmResult<()> {
+        let line = self.line();
+        let root = match self
+            .locals
+            .iter_mut()
+            .rev()
+            .find(|(n, _)| *n == target.name)
+        {
+            Some((_, local)) => local,
+            None if target.path.is_empty() => {
+                self.globals.set(LuaKey::String(target.name), value);
+                return Ok(());
+            }
+            None => match self
+                .globals
+                .get_key_mut(&LuaKey::String(target.name.clone()))
+            {
+                Some(global) => global,
+                None => return Err(index_error(line, &target.name)),
+            },
+        };
+        if target.path.is_empty() {
+            *root = value;
+            Ok(())
+        } else {
+            assign_path(root, &target.path, value, line, &target.name)
+        }
+    }
+
+    /// The value of a variable indexed by `keys`, copying only the result
+    fn lookup(&mut self, name: &str, keys: &[LuaKey]) -> LpmResult<LuaValue> {
+        let line = self.line();
+        let mut value = match self.locals.iter().rev().find(|(n, _)| n == name) {
+            Some((_, value)) => value,
+            None => self.globals.get(name),
+        };
+        for key in keys {
+            value = match value {
+                LuaValue::Table(t) => t.get_key(key),
+                other => {
+                    return Err(
+                        self.error(&format!("attempt to index a {} value", other.type_name()))
+                    )
+                }
+            };
+        }
+        self.budget.charge(value, line)?;
+        Ok(value.clone())
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> LpmResult<LuaValue> {
+        self.enter()?;
+        let mut left = if let Some(op) = self.unary_operator() {
+            self.advance();
+            let operand = self.expression(UNARY_PRECEDENCE)?;
+            self.unary(op, operand)?
+        } else {
+            self.simple_expression()?
+        };
+
+        loop {
+            let op = match self.peek() {
+                Token::Symbol(s) => *s,
+                Token::Name(n) if n == "and" => "and",
+                Token::Name(n) if n == "or" => "or",
+                _ => break,
+            };
+            let Some((left_power, right_power)) = binary_precedence(op) else {
+                break;
+            };
+            if left_power <= min_precedence {
+                break;
+            }
+            self.advance();
+            let right = self.expression(right_power)?;
+            left = self.binary(op, left, right)?;
+        }
+        self.depth -= 1;
+        Ok(left)
+    }
+
+    fn unary_operator(&self) -> Option<&'static str> {
+        match self.peek() {
+            Token::Symbol("-") => Some("-"),
+            Token::Symbol("#") => Some("#"),
+            Token::Name(n) if n == "not" => Some("not"),
+            _ => None,
+        }
+    }
+
+    fn unary(&self, op: &str, operand: LuaValue) -> LpmResult<LuaValue> {
+        match (op, operand) {
+            ("not", v) => Ok(LuaValue::Boolean(!v.is_truthy())),
+            ("-", v) => Ok(LuaValue::Number(-self.arithmetic_operand(&v)?)),
+            ("#", LuaValue::String(s)) => Ok(LuaValue::Number(s.len() as f64)),
+            ("#", LuaValue::Table(t)) => Ok(LuaValue::Number(t.len() as f64)),
+            (_, v) => Err(self.error(&format!(
+                "attempt to get length of a {} value",
+                v.type_name()
+            ))),
+        }
+    }
+
+    fn arithmetic_operand(&self, value: &LuaValue) -> LpmResult<f64> {
+        match value {
+            LuaValue::Number(n) => Ok(*n),
+            LuaValue::String(s) => s
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| self.error("attempt to perform arithmetic on a string value")),
+            other => Err(self.error(&format!(
+                "attempt to perform arithmetic on a {} value",
+                other.type_name()
+            ))),
+        }
+    }
+
+    fn binary(&mut self, op: &str, left: LuaValue, right: LuaValue) -> LpmResult<LuaValue> {
+        match op {
+            "and" => Ok(if left.is_truthy() { right } else { left }),
+            "or" => Ok(if left.is_truthy() { left } else { right }),
+            ".." => match (left.to_lua_string(), right.to_lua_string()) {
+                (Some(l), Some(r)) => {
+                    self.budget.add(l.len() + r.len(), 0, self.line())?;
+                    Ok(LuaValue::String(l + &r))
+                }
+                _ => {
+                    let culprit = if left.to_lua_string().is_none() {
+                        &left
+                    } else {
+                        &right
+                    };
+                    Err(self.error(&format!(
+                        "attempt to concatenate a {} value",
+                        culprit.type_name()
+                    )))
+                }
+            },
+            "==" => Ok(LuaValue::Boolean(left == right)),
+            "~=" => Ok(LuaValue::Boolean(left != right)),
+            "<" | ">" | "<=" | ">=" => {
+                let ordering = match (&left, &right) {
+                    (LuaValue::Number(a), LuaValue::Number(b)) => a.partial_cmp(b),
+                    (LuaValue::String(a), LuaValue::String(b)) => Some(a.cmp(b)),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    self.error(&format!(
+                        "attempt to compare {} with {}",
+                        left.type_name(),
+                        right.type_name()
+                    ))
+                })?;
+                Ok(LuaValue::Boolean(match op {
+                    "<" => ordering.is_lt(),
+                    ">" => ordering.is_gt(),
+                    "<=" => ordering.is_le(),
+                    _ => ordering.is_ge(),
+                }))
+            }
+            _ => {
+                let (a, b) = (
+                    self.arithmetic_operand(&left)?,
+                    self.arithmetic_operand(&right)?,
+                );
+                Ok(LuaValue::Number(match op {
+                    "+" => a + b,
+                    "-" => a - b,
+                    "*" => a * b,
+                    "/" => a / b,
+                    "//" => (a / b).floor(),
+                    "%" => a - (a / b).floor() * b,
+                    "^" => a.powf(b),
+                    _ => return Err(self.unsupported(&format!("operator '{}'", op))),
+                }))
+            }
+        }
+    }
+
+    fn simple_expression(&mut self) -> LpmResult<LuaValue> {
+        match self.peek().clone() {
+            Token::Number(n) => {
+                self.advance();
+                Ok(LuaValue::Number(n))
+            }
+            Token::Str(s) => {
+                let value = LuaValue::String(s);
+                self.budget.charge(&value, self.line())?;
+                self.advance();
+                Ok(value)
+            }
+            Token::Symbol("{") => self.table_constructor(),
+            Token::Symbol("...") => Err(self.unsupported("vararg expression")),
+            Token::Name(n) if n == "nil" => {
+                self.advance();
+                Ok(LuaValue::Nil)
+            }
+            Token::Name(n) if n == "true" => {
+                self.advance();
+                Ok(LuaValue::Boolean(true))
+            }
+            Token::Name(n) if n == "false" => {
+                self.advance();
+                Ok(LuaValue::Boolean(false))
+            }
+            Token::Name(n) if n == "function" => Err(self.unsupported("function definition")),
+            _ => self.prefix_expression(),
+        }
+    }
+
+    fn prefix_expression(&mut self) -> LpmResult<LuaValue> {
+        let (variable, value) = if self.is_symbol("(") {
+            self.advance();
+            let value = self.expression(0)?;
+            self.expect_symbol(")")?;
+            (None, value)
+        } else {
+            (Some(self.name()?), LuaValue::Nil)
+        };
+
+        // Collect the whole suffix chain first, so that `io.open(...)` is reported
+        // as a function call rather than as indexing the (absent) `io` table
+        let mut keys = Vec::new();
+        loop {
+            if self.is_symbol(".") {
+                self.advance();
+                keys.push(LuaKey::String(self.name()?));
+            } else if self.is_symbol("[") {
+                self.advance();
+                let key = self.expression(0)?;
+                self.expect_symbol("]")?;
+                keys.push(LuaKey::from_value(key).map_err(|e| self.error(&e.to_string()))?);
+            } else if self.is_symbol("(") || self.is_symbol(":") || self.starts_call_argument() {
+                return Err(self.unsupported("function call"));
+            } else {
+                break;
+            }
+        }
+        match variable {
+            Some(name) => self.lookup(&name, &keys),
+            None => keys
+                .into_iter()
+                .try_fold(value, |value, key| self.index(value, key)),
+        }
+    }
+
+    fn index(&self, value: LuaValue, key: LuaKey) -> LpmResult<LuaValue> {
+        match value {
+            LuaValue::Table(t) => Ok(t.get_key(&key).clone()),
+            other => Err(self.error(&format!("attempt to index a {} value", other.type_name()))),
+        }
+    }
+
+    fn table_constructor(&mut self) -> LpmResult<LuaValue> {
+        self.enter()?;
+        self.expect_symbol("{")?;
+        let mut table = LuaTable::new();
+        let mut next_index = 1i64;
+        while !self.is_symbol("}") {
+            if self.is_symbol("[") {
+                self.advance();
+                let key = self.expression(0)?;
+                self.expect_symbol("]")?;
+                self.expect_symbol("=")?;
+                let value = self.expression(0)?;
+                let key = LuaKey::from_value(key).map_err(|e| self.error(&e.to_string()))?;
+                self.budget.table_entry(self.line())?;
+                table.set(key, value);
+            } else if matches!(self.peek(), Token::Name(_))
+                && matches!(self.peek_at(1), Token::Symbol("="))
+            {
+                let name = self.name()?;
+                self.advance();
+                let value = self.expression(0)?;
+                self.budget.table_entry(self.line())?;
+                table.set(LuaKey::String(name), value);
+            } else {
+                let value = self.expression(0)?;
+                self.budget.table_entry(self.line())?;
+                table.set(LuaKey::Integer(next_index), value);
+                next_index += 1;
+            }
+
+            if self.is_symbol(",") || self.is_symbol(";") {
+                self.advance();
+            } else if !self.is_symbol("}") {
+                return Err(self.error("expected ',' or '}' in table constructor"));
+            }
+        }
+        self.advance();
+        self.depth -= 1;
+        Ok(LuaValue::Table(table))
+    }
+}
+
+fn index_error(line: usize, name: &str) -> LpmError {
+    LpmError::Package(format!(
+        "Lua error at line {}: attempt to index a nil value (variable '{}')",
+        line, name
+    ))
+}
+
+fn assign_path(
+    root: &mut LuaValue,
+    path: &[LuaKey],
+    value: LuaValue,
+    line: usize,
+    name: &str,
+) -> LpmResult<()> {
+    let LuaValue::Table(table) = root else {
+        return Err(index_error(line, name));
+    };
+    let (key, rest) = path
+        .split_first()
+        .expect("assign_path needs a non-empty path");
+    if rest.is_empty() {
+        table.set(key.clone(), value);
+        return Ok(());
+    }
+    match table.get_key_mut(key) {
+        Some(child) => assign_path(child, rest, value, line, name),
+        None => Err(index_error(line, name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(globals: &LuaTable, name: &str) -> String {
+        globals.get_string(name).unwrap()
+    }
+
+    #[test]
+    fn test_string_forms() {
+        let globals = evaluate(
+            r#"
+a = "double"
+b = 'single'
+c = [[long
+string]]
+d = [==[with ]] inside]==]
+e = "esc\"aped\n\65\x42\u{43}"
+f = "con" .. 'cat' .. 1
+"#,
+        )
+        .unwrap();
+        assert_eq!(string(&globals, "a"), "double");
+        assert_eq!(string(&globals, "b"), "single");
+        assert_eq!(string(&globals, "c"), "long\nstring");
+        assert_eq!(string(&globals, "d"), "with ]] inside");
+        assert_eq!(string(&globals, "e"), "esc\"aped\nABC");
+        assert_eq!(string(&globals, "f"), "concat1");
+    }
+
+    #[test]
+    fn test_locals_and_references() {
+        let globals = evaluate(
+            r#"
+local base = "1.2"
+local rev = 3
+version = base .. "-" .. rev
+source = { tag = "v" .. base }
+source.url = "https://example.com/" .. source.tag .. ".tar.gz"
+"#,
+        )
+        .unwrap();
+        assert_eq!(string(&globals, "version"), "1.2-3");
+        assert!(globals.get("base") == &LuaValue::Nil);
+        let source = globals.get_table("source").unwrap();
+        assert_eq!(
+            source.get_string("url").unwrap(),
+            "https://example.com/v1.2.tar.gz"
+        );
+    }
+
+    #[test]
+    fn test_table_constructors() {
+        let globals = evaluate(
+            r#"
+t = { "a", "b"; x = 1, ["y z"] = true, [10] = "ten", nested = { "c" }, }
+"#,
+        )
+        .unwrap();
+        let t = globals.get_table("t").unwrap();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.sequence()[1], LuaValue::String("b".to_string()));
+        assert_eq!(t.get("x"), &LuaValue::Number(1.0));
+        assert_eq!(t.get("y z"), &LuaValue::Boolean(true));
+        assert_eq!(
+            t.get_key(&LuaKey::Integer(10)),
+            &LuaValue::String("ten".to_string())
+        );
+        assert_eq!(t.get_table("nested").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        let globals = evaluate(
+            r#"
+-- line comment
+--[[ block
+comment ]] a = 1 --[==[ another ]==]
+b = 2
+"#,
+        )
+        .unwrap();
+        assert_eq!(globals.get("a"), &LuaValue::Number(1.0));
+        assert_eq!(globals.get("b"), &LuaValue::Number(2.0));
+    }
+
+    #[test]
+    fn test_operators() {
+        let globals = evaluate(
+            r#"
+a = 1 + 2 * 3
+b = 2 ^ 3 ^ 2
+c = not nil and "yes" or "no"
+d = #{ 1, 2, 3 }
+e = 10 // 3 % 2
+f = 1 < 2
+"#,
+        )
+        .unwrap();
+        assert_eq!(globals.get("a"), &LuaValue::Number(7.0));
+        assert_eq!(globals.get("b"), &LuaValue::Number(512.0));
+        assert_eq!(string(&globals, "c"), "yes");
+        assert_eq!(globals.get("d"), &LuaValue::Number(3.0));
+        assert_eq!(globals.get("e"), &LuaValue::Number(1.0));
+        assert_eq!(globals.get("f"), &LuaValue::Boolean(true));
+    }
+
+    #[test]
+    fn test_code_execution_is_rejected() {
+        for source in [
+            r#"os.execute("rm -rf /")"#,
+            r#"x = io.open("/etc/passwd")"#,
+            r#"print "hi""#,
+            r#"x = require "socket""#,
+            r#"function f() end"#,
+            r#"local function f() end"#,
+            r#"x = function() end"#,
+            r#"for i = 1, 10 do end"#,
+            r#"while true do end"#,
+            r#"if x then y = 1 end"#,
+            r#"x = ("%s"):format(1)"#,
+            r#"return { }"#,
+        ] {
+            let err = evaluate(source).unwrap_err().to_string();
+            assert!(err.contains("Unsupported Lua"), "{source}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors_report_line() {
+        let err = evaluate("a = 1\nb = {\n").unwrap_err().to_string();
+        assert!(err.contains("line 3"), "{err}");
+        assert!(evaluate("a = \"unterminated").is_err());
+        assert!(evaluate("a = 1 +").is_err());
+    }
+
+    #[test]
+    fn test_deep_nesting_is_rejected() {
+        let source = format!("a = {}{}", "{".repeat(500), "}".repeat(500));
+        assert!(evaluate(&source).is_err());
+    }
+
+    #[test]
+    fn test_exponential_growth_is_rejected() {
+        let strings = format!("s = 'xxxxxxxx'\n{}", "s = s .. s\n".repeat(40));
+        let err = evaluate(&strings).unwrap_err().to_string();
+        assert!(err.contains("too much data"), "{}", err);
+
+        let tables = format!("t = {{}}\n{}", "t = { t, t }\n".repeat(40));
+        let err = evaluate(&tables).unwrap_err().to_string();
+        assert!(err.contains("too much data"), "{}", err);
+
+        // Field assignments update globals in place rather than copying them
+        let fields: String = (0..20_000)
+            .map(|i| format!("t.f{} = {{ {} }}\n", i, i))
+            .collect();
+        let globals = evaluate(&format!("t = {{}}\n{}", fields)).unwrap();
+        assert_eq!(
+            globals.get_table("t").unwrap().string_pairs().count(),
+            20_000
+        );
+    }
+
+    #[test]
+    fn test_indexing_nil_is_an_error() {
+        assert!(evaluate("a.b = 1").is_err());
+        assert!(evaluate("x = a.b").is_err());
+    }
+
+    #[test]
+    fn test_deep_merge() {
+        let globals = evaluate(
+            r#"
+base = { type = "builtin", list = { "a", "b" }, modules = { x = "x.lua", y = "y.lua" } }
+over = { type = "make", list = { "c" }, modules = { y = "y_win.lua" } }
+"#,
+        )
+        .unwrap();
+        let mut base = globals.get_table("base").unwrap().clone();
+        base.deep_merge(globals.get_table("over").unwrap());
+
+        assert_eq!(base.get_string("type"), Some("make".to_string()));
+        let list: Vec<_> = base
+            .get_table("list")
+            .unwrap()
+            .sequence()
+            .iter()
+            .filter_map(|v| v.to_lua_string())
+            .collect();
+        assert_eq!(list, vec!["c", "b"]);
+        let modules = base.get_table("modules").unwrap();
+        assert_eq!(modules.get_string("x"), Some("x.lua".to_string()));
+        assert_eq!(modules.get_string("y"), Some("y_win.lua".to_string()));
+    }
+
+    #[test]
+    fn test_serialize_round_trips() {
+        let source = r#"
+repository = {
+   ["lua-cjson"] = { ["2.1.0-1"] = { { arch = "rockspec" } } },
+}
+version = { 5, 1, string = "5.1", [7] = true }
+quoted = "say \"hi\"\n\\"
+empty = {}
+"#;
+        let globals = evaluate(source).unwrap();
+        let written = serialize(&globals);
+        assert!(written.contains("[\"lua-cjson\"] = {"));
+        assert!(written.contains("   arch = \"rockspec\","));
+        assert_eq!(evaluate(&written).unwrap(), globals);
+    }
+}
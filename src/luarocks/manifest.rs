@@ -1,4 +1,5 @@
 use crate::core::{LpmError, LpmResult};
+use crate::luarocks::lua_data::{self, LuaKey, LuaTable, LuaValue};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -11,6 +12,16 @@ pub struct Manifest {
     pub repository: String,
     /// Packages in the manifest
     pub packages: HashMap<String, Vec<PackageVersion>>,
+    /// Rockspec dependencies by package and version, where the manifest or package index lists them
+    #[serde(default)]
+    pub dependencies: HashMap<String, HashMap<String, Vec<String>>>,
+    /// Lua modules by package and version, where the manifest or package index lists them
+    #[serde(default)]
+    pub modules: HashMap<String, HashMap<String, Vec<String>>>,
+    /// Rocks by package and version: the arches (`src`, `all`, `linux-x86_64`, ...)
+    /// the registry has a `.rock` for besides the rockspec
+    #[serde(default)]
+    pub rocks: HashMap<String, HashMap<String, Vec<String>>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +38,12 @@ pub struct PackageVersion {
 #[derive(Debug, Deserialize)]
 struct ManifestJson {
     repository: HashMap<String, HashMap<String, Vec<ArchInfo>>>,
+    /// Same layout as in Lua manifests; read leniently, since empty tables may
+    /// be encoded as either `{}` or `[]`
+    #[serde(default)]
+    dependencies: serde_json::Value,
+    #[serde(default)]
+    modules: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
@@ -35,6 +52,18 @@ struct ArchInfo {
 }
 
 impl Manifest {
+    /// Parse a registry's manifest in whichever format the server sent
+    ///
+    /// LuaRocks servers answer `manifest?format=json` with JSON, while static
+    /// mirrors ignore the query and serve the Lua table.
+    pub fn parse_for(content: &str, repository: &str, base_url: &str) -> LpmResult<Self> {
+        if content.trim_start().starts_with('{') {
+            Self::parse_json_for(content, repository, base_url)
+        } else {
+            Self::parse_lua_for(content, repository, base_url)
+        }
+    }
+
     /// Parse manifest from JSON format (LuaRocks API)
     ///
     /// The LuaRocks manifest API returns JSON with structure:
@@ -46,28 +75,39 @@ impl Manifest {
     ///   }
     /// }
     pub fn parse_json(content: &str) -> LpmResult<Self> {
+        Self::parse_json_for(
+            content,
+            "luarocks",
+            "https://luarocks.org/manifests/luarocks",
+        )
+    }
+
+    /// Parse a registry's JSON manifest; rockspecs are expected next to it, under `base_url`
+    pub fn parse_json_for(content: &str, repository: &str, base_url: &str) -> LpmResult<Self> {
         let json: ManifestJson = serde_json::from_str(content)
             .map_err(|e| LpmError::Package(format!("Failed to parse manifest JSON: {}", e)))?;
 
         // The JSON structure is: {"repository": {"package_name": {"version": [...]}}}
         // So json.repository is already the packages map
-        let repository_name = "luarocks".to_string(); // Default repository name
+        let repository_name = repository.to_string();
 
         // Convert JSON structure to Manifest structure
         let mut packages = HashMap::new();
+        let mut rocks = HashMap::new();
         for (package_name, versions_map) in &json.repository {
             let mut package_versions = Vec::new();
 
             for (version_str, arch_infos) in versions_map {
+                let arches: Vec<String> = arch_infos.iter().map(|ai| ai.arch.clone()).collect();
+                add_rocks(&mut rocks, package_name, version_str, &arches);
+
                 // Check if this version has a rockspec
-                let has_rockspec = arch_infos.iter().any(|ai| ai.arch == "rockspec");
+                let has_rockspec = arches.iter().any(|arch| arch == "rockspec");
 
                 if has_rockspec {
                     // Construct rockspec URL
-                    let rockspec_url = format!(
-                        "https://luarocks.org/manifests/{}/{}-{}.rockspec",
-                        repository_name, package_name, version_str
-                    );
+                    let rockspec_url =
+                        format!("{}/{}-{}.rockspec", base_url, package_name, version_str);
 
                     // Archive URL will be extracted from rockspec when downloaded
                     package_versions.push(PackageVersion {
@@ -83,17 +123,88 @@ impl Manifest {
             }
         }
 
+        let sections = |value: &serde_json::Value| match json_to_lua(value) {
+            LuaValue::Table(table) => table,
+            _ => LuaTable::new(),
+        };
         Ok(Manifest {
             repository: repository_name,
             packages,
+            dependencies: dependency_section(&sections(&json.dependencies)),
+            modules: module_section(&sections(&json.modules)),
+            rocks,
         })
     }
 
-    /// Parse manifest from Lua table format (legacy, not implemented)
-    pub fn parse_lua(_content: &str) -> LpmResult<Self> {
-        Err(LpmError::NotImplemented(
-            "Lua manifest parsing not implemented. Use JSON format instead.".to_string(),
-        ))
+    /// Parse a LuaRocks manifest in Lua table format, as served by luarocks.org
+    pub fn parse_lua(content: &str) -> LpmResult<Self> {
+        Self::parse_lua_for(
+            content,
+            "luarocks",
+            "https://luarocks.org/manifests/luarocks",
+        )
+    }
+
+    /// Parse a registry's Lua manifest (`manifest`, `manifest-5.x`)
+    ///
+    /// This is what `luarocks-admin make-manifest` writes and what static
+    /// mirrors serve:
+    ///
+    /// ```lua
+    /// repository = {
+    ///    ["lua-cjson"] = {
+    ///       ["2.1.0-1"] = { { arch = "rockspec" }, { arch = "src" } },
+    ///    },
+    /// }
+    /// modules = { cjson = { "lua-cjson/2.1.0-1" } }
+    /// dependencies = {
+    ///    ["lua-cjson"] = {
+    ///       ["2.1.0-1"] = { { name = "lua", constraints = { { op = ">=", version = { 5, 1, string = "5.1" } } } } },
+    ///    },
+    /// }
+    /// ```
+    ///
+    /// The file is evaluated by the sandboxed data-only evaluator, so it can't run code.
+    pub fn parse_lua_for(content: &str, repository: &str, base_url: &str) -> LpmResult<Self> {
+        let globals = lua_data::evaluate(content)
+            .map_err(|e| LpmError::Package(format!("Failed to parse Lua manifest: {}", e)))?;
+        let Some(repository_table) = globals.get_table("repository") else {
+            return Err(LpmError::Package(
+                "Failed to parse Lua manifest: no `repository` table".to_string(),
+            ));
+        };
+
+        let mut packages = HashMap::new();
+        let mut rocks = HashMap::new();
+        for (package_name, versions) in repository_table.string_pairs() {
+            let Some(versions) = versions.as_table() else {
+                continue;
+            };
+            let mut package_versions = Vec::new();
+            for (version, arch_infos) in versions.string_pairs() {
+                let arches = arch_list(arch_infos);
+                add_rocks(&mut rocks, package_name, version, &arches);
+                if arches.iter().any(|arch| arch == "rockspec") {
+                    package_versions.push(PackageVersion {
+                        version: version.to_string(),
+                        rockspec_url: format!("{}/{}-{}.rockspec", base_url, package_name, version),
+                        archive_url: None,
+                    });
+                }
+            }
+            if !package_versions.is_empty() {
+                packages.insert(package_name.to_string(), package_versions);
+            }
+        }
+
+        let empty = LuaTable::new();
+        Ok(Manifest {
+            repository: repository.to_string(),
+            packages,
+            dependencies: dependency_section(globals.get_table("dependencies").unwrap_or(&empty)),
+            modules: module_section(globals.get_table("modules").unwrap_or(&empty)),
+            rocks,
+        })
     }
 
     /// Get all versions of a package
@@ -108,6 +219,24 @@ impl Manifest {
             .max_by_key(|pv| &pv.version)
     }
 
+    /// Lua modules a package version provides, if the manifest lists them
+    pub fn modules_of(&self, package_name: &str, version: &str) -> Option<&Vec<String>> {
+        self.modules.get(package_name)?.get(version)
+    }
+
+    /// A version's rockspec dependencies, if known without reading the rockspec
+    pub fn dependencies_of(&self, package_name: &str, version: &str) -> Option<&Vec<String>> {
+        self.dependencies.get(package_name)?.get(version)
+    }
+
+    /// The arches a package version has a `.rock` for, e.g. `["src", "linux-x86_64"]`
+    pub fn rocks_of(&self, package_name: &str, version: &str) -> &[String] {
+        self.rocks
+            .get(package_name)
+            .and_then(|versions| versions.get(version))
+            .map_or(&[], Vec::as_slice)
+    }
+
     /// Get all version strings for a package
     pub fn get_package_version_strings(&self, package_name: &str) -> Vec<String> {
         self.get_package_versions(package_name)
@@ -121,6 +250,160 @@ impl Default for Manifest {
         Self {
             repository: "luarocks".to_string(),
             packages: HashMap::new(),
+            dependencies: HashMap::new(),
+            modules: HashMap::new(),
+            rocks: HashMap::new(),
+        }
+    }
+}
+
+/// The arches of a manifest entry, e.g. `{ { arch = "rockspec" }, { arch = "src" } }`
+fn arch_list(arch_infos: &LuaValue) -> Vec<String> {
+    arch_infos
+        .as_table()
+        .map(|infos| {
+            infos
+                .sequence()
+                .iter()
+                .filter_map(|info| info.as_table()?.get_string("arch"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Record the rocks among a version's arches
+fn add_rocks(
+    rocks: &mut HashMap<String, HashMap<String, Vec<String>>>,
+    package_name: &str,
+    version: &str,
+    arches: &[String],
+) {
+    let mut arches: Vec<String> = arches
+        .iter()
+        .filter(|arch| *arch != "rockspec")
+        .cloned()
+        .collect();
+    if arches.is_empty() {
+        return;
+    }
+    arches.sort();
+    rocks
+        .entry(package_name.to_string())
+        .or_default()
+        .insert(version.to_string(), arches);
+}
+
+/// The `dependencies` section: package → version → dependency strings like "lua >= 5.1, < 5.5"
+fn dependency_section(section: &LuaTable) -> HashMap<String, HashMap<String, Vec<String>>> {
+    let mut dependencies = HashMap::new();
+    for (package_name, versions) in section.string_pairs() {
+        let Some(versions) = versions.as_table() else {
+            continue;
+        };
+        let by_version: HashMap<String, Vec<String>> = versions
+            .string_pairs()
+            .filter_map(|(version, deps)| {
+                let deps = deps
+                    .as_table()?
+                    .sequence()
+                    .iter()
+                    .filter_map(dependency_string);
+                Some((version.to_string(), deps.collect()))
+            })
+            .collect();
+        dependencies.insert(package_name.to_string(), by_version);
+    }
+    dependencies
+}
+
+/// One dependency, given as a rockspec-style string or as a parsed
+/// `{ name = ..., constraints = { { op = ..., version = { string = ... } } } }` table
+fn dependency_string(dep: &LuaValue) -> Option<String> {
+    let table = match dep {
+        LuaValue::String(s) => return Some(s.clone()),
+        LuaValue::Table(table) => table,
+        _ => return None,
+    };
+    let name = table.get_string("name")?;
+    let constraints: Vec<String> = table
+        .get_table("constraints")
+        .map(|constraints| {
+            constraints
+                .sequence()
+                .iter()
+                .filter_map(|c| {
+                    let c = c.as_table()?;
+                    let op = c.get_string("op").unwrap_or_else(|| "==".to_string());
+                    let version = match c.get("version") {
+                        LuaValue::Table(v) => v.get_string("string").or_else(|| {
+                            let parts: Vec<String> = v
+                                .sequence()
+                                .iter()
+                                .filter_map(|p| p.to_lua_string())
+                                .collect();
+                            (!parts.is_empty()).then(|| parts.join("."))
+                        }),
+                        other => other.to_lua_string(),
+                    }?;
+                    Some(format!("{} {}", op, version))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if constraints.is_empty() {
+        Some(name)
+    } else {
+        Some(format!("{} {}", name, constraints.join(", ")))
+    }
+}
+
+/// The `modules` section (module → `{ "package/version", ... }`), turned around
+/// into package → version → modules
+fn module_section(section: &LuaTable) -> HashMap<String, HashMap<String, Vec<String>>> {
+    let mut modules: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+    for (module, providers) in section.string_pairs() {
+        let Some(providers) = providers.as_table() else {
+            continue;
+        };
+        for provider in providers.sequence().iter().filter_map(LuaValue::as_str) {
+            if let Some((package_name, version)) = provider.split_once('/') {
+                modules
+                    .entry(package_name.to_string())
+                    .or_default()
+                    .entry(version.to_string())
+                    .or_default()
+                    .push(module.to_string());
+            }
+        }
+    }
+    for versions in modules.values_mut() {
+        for list in versions.values_mut() {
+            list.sort();
+        }
+    }
+    modules
+}
+
+/// A JSON value as the Lua value it was encoded from
+fn json_to_lua(value: &serde_json::Value) -> LuaValue {
+    match value {
+        serde_json::Value::Null => LuaValue::Nil,
+        serde_json::Value::Bool(b) => LuaValue::Boolean(*b),
+        serde_json::Value::Number(n) => LuaValue::Number(n.as_f64().unwrap_or_default()),
+        serde_json::Value::String(s) => LuaValue::String(s.clone()),
+        serde_json::Value::Array(items) => {
+            let mut table = LuaTable::new();
+            for (i, item) in items.iter().enumerate() {
+                table.set(LuaKey::Integer(i as i64 + 1), json_to_lua(item));
+            }
+            LuaValue::Table(table)
+        }
+        serde_json::Value::Object(fields) => {
+            let mut table = LuaTable::new();
+            for (key, item) in fields {
+                table.set(LuaKey::String(key.clone()), json_to_lua(item));
+            }
+            LuaValue::Table(table)
         }
     }
 }
@@ -240,14 +523,113 @@ mod tests {
         assert!(empty.is_empty());
     }
 
+    const LUA_MANIFEST: &str = r#"
+commands = {}
+modules = {
+   lfs = {
+      "luafilesystem/1.8.0-1", "luafilesystem/1.7.0-2"
+   },
+   ["pl.path"] = { "penlight/1.13.1-1" },
+}
+repository = {
+   luafilesystem = {
+      ["1.8.0-1"] = {
+         { arch = "rockspec" }, { arch = "src" }
+      },
+      ["1.7.0-2"] = {
+         { arch = "rockspec" }
+      },
+   },
+   penlight = {
+      ["1.13.1-1"] = {
+         { arch = "rockspec" }
+      },
+   },
+   ["binary-only"] = {
+      ["1.0-1"] = {
+         { arch = "linux-x86_64" }
+      },
+   },
+}
+dependencies = {
+   penlight = {
+      ["1.13.1-1"] = {
+         {
+            name = "lua",
+            constraints = {
+               { op = ">=", version = { 5, 1, string = "5.1" } },
+               { op = "<", version = { 5, 5 } },
+            },
+         },
+         { name = "luafilesystem", constraints = {} },
+      },
+   },
+}
+"#;
+
+    #[test]
+    fn test_manifest_parse_lua() {
+        let manifest = Manifest::parse_lua(LUA_MANIFEST).unwrap();
+        assert_eq!(manifest.repository, "luarocks");
+        assert_eq!(
+            manifest
+                .get_package_versions("luafilesystem")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(manifest.get_package_versions("binary-only").is_none());
+        assert_eq!(
+            manifest.get_package_versions("penlight").unwrap()[0].rockspec_url,
+            "https://luarocks.org/manifests/luarocks/penlight-1.13.1-1.rockspec"
+        );
+        assert_eq!(
+            manifest.dependencies_of("penlight", "1.13.1-1"),
+            Some(&vec![
+                "lua >= 5.1, < 5.5".to_string(),
+                "luafilesystem".to_string()
+            ])
+        );
+        assert_eq!(
+            manifest.modules_of("luafilesystem", "1.7.0-2"),
+            Some(&vec!["lfs".to_string()])
+        );
+        assert_eq!(
+            manifest.modules_of("penlight", "1.13.1-1"),
+            Some(&vec!["pl.path".to_string()])
+        );
+        assert_eq!(manifest.rocks_of("luafilesystem", "1.8.0-1"), ["src"]);
+        assert!(manifest.rocks_of("luafilesystem", "1.7.0-2").is_empty());
+        assert_eq!(manifest.rocks_of("binary-only", "1.0-1"), ["linux-x86_64"]);
+
+        assert!(Manifest::parse_lua("some lua content").is_err());
+        assert!(Manifest::parse_lua("commands = {}").is_err());
+    }
+
     #[test]
-    fn test_manifest_parse_lua_not_implemented() {
-        let result = Manifest::parse_lua("some lua content");
-        assert!(result.is_err());
-        match result {
-            Err(LpmError::NotImplemented(_)) => {}
-            _ => panic!("Expected NotImplemented error"),
-        }
+    fn test_manifest_parse_for_detects_format() {
+        let lua =
+            Manifest::parse_for(LUA_MANIFEST, "mirror", "https://mirror.example.com").unwrap();
+        assert_eq!(lua.repository, "mirror");
+        assert_eq!(
+            lua.get_package_versions("penlight").unwrap()[0].rockspec_url,
+            "https://mirror.example.com/penlight-1.13.1-1.rockspec"
+        );
+
+        let json = r#"
+            {"repository": {"penlight": {"1.13.1-1": [{"arch": "rockspec"}]}},
+             "modules": {"pl": ["penlight/1.13.1-1"]},
+             "dependencies": {"penlight": {"1.13.1-1": [{"name": "luafilesystem", "constraints": [{"op": ">=", "version": {"string": "1.8"}}]}]}},
+             "commands": []}"#;
+        let json = Manifest::parse_for(json, "mirror", "https://mirror.example.com").unwrap();
+        assert_eq!(
+            json.dependencies_of("penlight", "1.13.1-1"),
+            Some(&vec!["luafilesystem >= 1.8".to_string()])
+        );
+        assert_eq!(
+            json.modules_of("penlight", "1.13.1-1"),
+            Some(&vec!["pl".to_string()])
+        );
     }
 
     #[test]
@@ -263,6 +645,7 @@ mod tests {
         let manifest = Manifest::parse_json(json).unwrap();
         // Package without rockspec should not be included
         assert!(!manifest.packages.contains_key("test-package"));
+        assert_eq!(manifest.rocks_of("test-package", "1.0.0"), ["src"]);
     }
 
     #[test]
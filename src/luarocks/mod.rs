@@ -1,11 +1,20 @@
 pub mod client;
+pub mod index;
+pub mod lua_data;
 pub mod manifest;
+pub mod registry;
+pub mod repository;
+pub mod rock;
 pub mod rockspec;
 pub mod rockspec_parser;
 pub mod search_api;
+pub mod server;
 pub mod version;
 
 pub use client::LuaRocksClient;
+pub use index::PackageIndex;
 pub use manifest::Manifest;
+pub use registry::{Registries, Registry};
+pub use repository::LocalRepository;
 pub use rockspec::Rockspec;
 pub use search_api::SearchAPI;
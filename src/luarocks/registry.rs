@@ -0,0 +1,310 @@
+use crate::config::Config;
+
+/// Name of the registry used when config.yaml doesn't list any
+pub const DEFAULT_REGISTRY: &str = "luarocks";
+
+/// A LuaRocks server that packages are resolved from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registry {
+    /// Recorded as the `source` of packages locked from this registry
+    pub name: String,
+    /// Where the manifest and rockspecs live, e.g. https://luarocks.org/manifests/luarocks
+    pub base_url: String,
+    /// Base URLs serving the same files, tried in order when `base_url` fails
+    pub mirrors: Vec<String>,
+    token: Option<String>,
+}
+
+impl Registry {
+    pub fn new(name: &str, base_url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            mirrors: Vec::new(),
+            token: None,
+        }
+    }
+
+    pub fn with_mirrors(mut self, mirrors: Vec<String>) -> Self {
+        self.mirrors = mirrors
+            .iter()
+            .map(|m| m.trim_end_matches('/').to_string())
+            .collect();
+        self
+    }
+
+    /// Send `token` as a bearer token to this registry and its mirrors
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    pub fn manifest_url(&self) -> String {
+        format!("{}/manifest", self.base_url)
+    }
+
+    /// Manifest URLs to try, in order: the JSON manifest LuaRocks servers
+    /// generate on request, then the per-Lua-version Lua manifests that
+    /// `luarocks-admin make-manifest` writes for static hosting
+    pub fn manifest_urls(&self) -> Vec<String> {
+        let mut urls = vec![format!("{}?format=json", self.manifest_url())];
+        urls.extend(
+            ["5.4", "5.3", "5.2", "5.1"]
+                .iter()
+                .map(|lua| format!("{}-{}", self.manifest_url(), lua)),
+        );
+        urls
+    }
+
+    pub fn rockspec_url(&self, package: &str, version: &str) -> String {
+        format!("{}/{}-{}.rockspec", self.base_url, package, version)
+    }
+
+    /// `url` followed by the same file on the other servers, if `url` is served by this registry
+    pub fn candidate_urls(&self, url: &str) -> Option<Vec<String>> {
+        let path = self.servers().find_map(|base| strip_base(url, base))?;
+        let mut candidates = vec![url.to_string()];
+        for base in self.servers() {
+            let candidate = format!("{}{}", base, path);
+            if !candidates.contains(&candidate) {
+                candidates.push(candidate);
+            }
+        }
+        Some(candidates)
+    }
+
+    fn servers(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.base_url).chain(&self.mirrors)
+    }
+
+    fn serves(&self, url: &str) -> bool {
+        self.servers().any(|base| strip_base(url, base).is_some())
+    }
+}
+
+/// The part of `url` after `base`, if `url` is below it
+fn strip_base<'a>(url: &'a str, base: &str) -> Option<&'a str> {
+    let rest = url.strip_prefix(base)?;
+    (rest.is_empty() || rest.starts_with('/') || rest.starts_with('?')).then_some(rest)
+}
+
+/// The configured registries, in order of preference, and the routes between them
+#[derive(Debug, Clone)]
+pub struct Registries {
+    registries: Vec<Registry>,
+    /// (pattern, registry name); a pattern is a package name or a prefix ending in `*`
+    routes: Vec<(String, String)>,
+}
+
+impl Default for Registries {
+    fn default() -> Self {
+        Self::new(vec![Registry::new(
+            DEFAULT_REGISTRY,
+            "https://luarocks.org/manifests/luarocks",
+        )])
+    }
+}
+
+impl Registries {
+    /// `registries` must not be empty; the first one is the primary registry
+    pub fn new(registries: Vec<Registry>) -> Self {
+        assert!(!registries.is_empty(), "at least one registry is required");
+        Self {
+            registries,
+            routes: Vec::new(),
+        }
+    }
+
+    /// The registries from config.yaml, or the one serving `luarocks_manifest_url`
+    pub fn from_config(config: &Config) -> Self {
+        let registries = match config.registries.as_deref() {
+            Some(configured) if !configured.is_empty() => configured
+                .iter()
+                .map(|registry| {
+                    let base_url = match &registry.manifest {
+                        Some(manifest) => format!(
+                            "{}/manifests/{}",
+                            registry.url.trim_end_matches('/'),
+                            manifest
+                        ),
+                        None => registry.url.clone(),
+                    };
+                    Registry::new(&registry.name, &base_url)
+                        .with_mirrors(registry.mirrors.clone())
+                        .with_token(registry.auth.as_ref().and_then(|auth| auth.token()))
+                })
+                .collect(),
+            _ => {
+                let url = &config.luarocks_manifest_url;
+                let base_url = url.strip_suffix("/manifest").unwrap_or(url);
+                vec![Registry::new(DEFAULT_REGISTRY, base_url)]
+            }
+        };
+
+        let mut registries = Self::new(registries);
+        for (pattern, registry) in config.registry_routes.iter().flatten() {
+            registries = registries.with_route(pattern, registry);
+        }
+        registries
+    }
+
+    /// Resolve packages matching `pattern` (a name, or a prefix ending in `*`) from `registry` only
+    pub fn with_route(mut self, pattern: &str, registry: &str) -> Self {
+        self.routes
+            .push((pattern.to_string(), registry.to_string()));
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Registry> {
+        self.registries.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Registry> {
+        self.registries.iter().find(|r| r.name == name)
+    }
+
+    pub fn primary(&self) -> &Registry {
+        &self.registries[0]
+    }
+
+    /// The registry `package` is routed to, if any route matches
+    ///
+    /// An exact name wins over prefixes, and a longer prefix over a shorter one.
+    pub fn route(&self, package: &str) -> Option<&Registry> {
+        self.routes
+            .iter()
+            .filter_map(|(pattern, registry)| {
+                let specificity = match pattern.strip_suffix('*') {
+                    Some(prefix) if package.starts_with(prefix) => prefix.len(),
+                    None if pattern == package => usize::MAX,
+                    _ => return None,
+                };
+                Some((specificity, registry))
+            })
+            .max_by_key(|(specificity, _)| *specificity)
+            .and_then(|(_, registry)| self.get(registry))
+    }
+
+    /// Registries `package` may come from, in the order they are searched
+    pub fn candidates(&self, package: &str) -> Vec<&Registry> {
+        match self.route(package) {
+            Some(registry) => vec![registry],
+            None => self.registries.iter().collect(),
+        }
+    }
+
+    /// The registry serving `url`, from its own URL or one of its mirrors
+    pub fn for_url(&self, url: &str) -> Option<&Registry> {
+        self.registries.iter().find(|r| r.serves(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{RegistryAuth, RegistryConfig};
+
+    fn registries() -> Registries {
+        Registries::new(vec![
+            Registry::new("internal", "https://rocks.example.com/")
+                .with_mirrors(vec!["https://mirror.example.com".to_string()]),
+            Registry::new("luarocks", "https://luarocks.org/manifests/luarocks"),
+        ])
+        .with_route("acme-*", "internal")
+        .with_route("acme-public-*", "luarocks")
+        .with_route("acme-public-core", "internal")
+    }
+
+    #[test]
+    fn test_urls() {
+        let registries = registries();
+        let internal = registries.get("internal").unwrap();
+        assert_eq!(
+            internal.manifest_url(),
+            "https://rocks.example.com/manifest"
+        );
+        assert_eq!(
+            internal.manifest_urls()[1..],
+            [
+                "https://rocks.example.com/manifest-5.4",
+                "https://rocks.example.com/manifest-5.3",
+                "https://rocks.example.com/manifest-5.2",
+                "https://rocks.example.com/manifest-5.1",
+            ]
+        );
+        assert_eq!(
+            internal.rockspec_url("acme-log", "1.0-1"),
+            "https://rocks.example.com/acme-log-1.0-1.rockspec"
+        );
+        assert_eq!(
+            registries
+                .primary()
+                .candidate_urls("https://rocks.example.com/manifest?format=json"),
+            Some(vec![
+                "https://rocks.example.com/manifest?format=json".to_string(),
+                "https://mirror.example.com/manifest?format=json".to_string(),
+            ])
+        );
+        assert_eq!(
+            internal.candidate_urls("https://rocks.example.com.evil/x"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_routing_prefers_most_specific_rule() {
+        let registries = registries();
+        let route = |package| registries.route(package).map(|r| r.name.as_str());
+        assert_eq!(route("acme-log"), Some("internal"));
+        assert_eq!(route("acme-public-json"), Some("luarocks"));
+        assert_eq!(route("acme-public-core"), Some("internal"));
+        assert_eq!(route("penlight"), None);
+        assert_eq!(registries.candidates("penlight").len(), 2);
+        assert_eq!(registries.candidates("acme-log").len(), 1);
+    }
+
+    #[test]
+    fn test_for_url_matches_mirrors() {
+        let registries = registries();
+        let name = |url| registries.for_url(url).map(|r| r.name.as_str());
+        assert_eq!(
+            name("https://mirror.example.com/a-1.0-1.rockspec"),
+            Some("internal")
+        );
+        assert_eq!(
+            name("https://luarocks.org/manifests/luarocks/a-1.0-1.rockspec"),
+            Some("luarocks")
+        );
+        assert_eq!(name("https://github.com/a/a/archive/v1.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let registries = Registries::from_config(&Config::default());
+        assert_eq!(registries.primary(), &Registries::default().registries[0]);
+
+        let config = Config {
+            registries: Some(vec![RegistryConfig {
+                name: "internal".to_string(),
+                url: "https://rocks.example.com".to_string(),
+                manifest: Some("team".to_string()),
+                mirrors: Vec::new(),
+                auth: Some(RegistryAuth {
+                    token: Some("secret".to_string()),
+                    token_env: None,
+                }),
+            }]),
+            ..Config::default()
+        };
+        let registries = Registries::from_config(&config);
+        assert_eq!(
+            registries.primary().base_url,
+            "https://rocks.example.com/manifests/team"
+        );
+        assert_eq!(registries.primary().token(), Some("secret"));
+    }
+}
@@ -0,0 +1,552 @@
+//! A LuaRocks-compatible rocks repository in a local directory
+//!
+//! The layout is the one `luarocks-admin` maintains: a flat directory of
+//! `<name>-<version>.rockspec` and `<name>-<version>.<arch>.rock` files, next to
+//! the manifests listing them. `manifest` and `manifest-5.x` are the Lua tables
+//! LuaRocks reads; `manifest.json` is the same data for lpm. Any static file
+//! server can host the directory, and `lpm registry serve` does so locally.
+
+use crate::core::{LpmError, LpmResult};
+use crate::luarocks::index::RockspecSummary;
+use crate::luarocks::lua_data::{self, LuaKey, LuaTable, LuaValue};
+use crate::luarocks::rock;
+use crate::luarocks::rockspec::Rockspec;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Lua versions that get a `manifest-<version>` of their own
+const LUA_VERSIONS: &[&str] = &["5.1", "5.2", "5.3", "5.4"];
+
+/// A directory of rockspecs and rocks served as a LuaRocks repository
+#[derive(Debug, Clone)]
+pub struct LocalRepository {
+    root: PathBuf,
+}
+
+/// One package version found in the repository
+#[derive(Default)]
+struct Entry {
+    /// `rockspec`, `src`, `all` or a platform such as `linux-x86_64`
+    arches: BTreeSet<String>,
+    rockspec: Option<Rockspec>,
+}
+
+impl LocalRepository {
+    /// Open the repository in `root`, creating the directory if needed
+    pub fn open(root: &Path) -> LpmResult<Self> {
+        fs::create_dir_all(root)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Copy a rockspec or rock into the repository, returning its name and version
+    ///
+    /// Rockspecs are stored under their canonical `<name>-<version>.rockspec`
+    /// name. Rocks keep theirs, and the rockspec they contain is stored next
+    /// to them, since lpm installs from rockspecs. Call [`rebuild`](Self::rebuild)
+    /// afterwards to list the new files in the manifests.
+    pub fn add(&self, file: &Path) -> LpmResult<(String, String)> {
+        let file_name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if file_name.ends_with(".rockspec") {
+            let content = fs::read_to_string(file)?;
+            let rockspec = parse_rockspec(&content, &file_name)?;
+            let dest = self.rockspec_path(&rockspec.package, &rockspec.version);
+            fs::write(dest, content)?;
+            return Ok((rockspec.package, rockspec.version));
+        }
+
+        let Some((name, version, _)) = file_name.strip_suffix(".rock").and_then(split_rock_name)
+        else {
+            return Err(LpmError::Package(format!(
+                "Not a rockspec or rock: {} (expected <name>-<version>.rockspec or <name>-<version>.<arch>.rock)",
+                file.display()
+            )));
+        };
+        let content = rock::read_rockspec(file, &name, &version)?;
+        parse_rockspec(&content, &file_name)?;
+
+        let dest = self.root.join(&file_name);
+        if !dest.exists() || !same_file(file, &dest) {
+            fs::copy(file, &dest)?;
+        }
+        let rockspec_path = self.rockspec_path(&name, &version);
+        if !rockspec_path.exists() {
+            fs::write(rockspec_path, content)?;
+        }
+        Ok((name, version))
+    }
+
+    /// Add a package from its rockspec and source archive
+    ///
+    /// Stores the rockspec, the archive (where the rockspec's `source.url`
+    /// should point) and a `.src.rock` bundling both for LuaRocks, then
+    /// rebuilds the manifests.
+    pub fn publish(&self, rockspec: &str, archive: &Path) -> LpmResult<(String, String)> {
+        let parsed = parse_rockspec(rockspec, "the published rockspec")?;
+        let (name, version) = (parsed.package, parsed.version);
+        let archive_name = archive
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| {
+                LpmError::Package(format!("Invalid archive path: {}", archive.display()))
+            })?;
+
+        fs::copy(archive, self.root.join(&archive_name))?;
+        fs::write(self.rockspec_path(&name, &version), rockspec)?;
+        rock::write_source_rock(rockspec, &name, &version, archive, &self.root)?;
+
+        self.rebuild()?;
+        Ok((name, version))
+    }
+
+    /// Regenerate the manifests from the rockspecs and rocks in the repository
+    ///
+    /// Returns how many package versions they list.
+    pub fn rebuild(&self) -> LpmResult<usize> {
+        let entries = self.scan()?;
+
+        let all = manifest_table(&entries, None);
+        let json = serde_json::to_string_pretty(&to_json(&LuaValue::Table(all.clone())))
+            .map_err(|e| LpmError::Package(format!("Failed to write manifest.json: {}", e)))?;
+        self.write("manifest.json", &json)?;
+        self.write("manifest", &lua_data::serialize(&all))?;
+        for lua in LUA_VERSIONS {
+            let manifest = manifest_table(&entries, Some(lua));
+            self.write(
+                &format!("manifest-{}", lua),
+                &lua_data::serialize(&manifest),
+            )?;
+        }
+        Ok(entries.len())
+    }
+
+    fn rockspec_path(&self, name: &str, version: &str) -> PathBuf {
+        self.root.join(format!("{}-{}.rockspec", name, version))
+    }
+
+    fn scan(&self) -> LpmResult<BTreeMap<(String, String), Entry>> {
+        let mut entries: BTreeMap<(String, String), Entry> = BTreeMap::new();
+        let mut rocks = Vec::new();
+        for file in fs::read_dir(&self.root)? {
+            let path = file?.path();
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            if let Some(stem) = file_name.strip_suffix(".rockspec") {
+                let Some((name, version)) = split_name_version(stem) else {
+                    eprintln!(
+                        "Warning: skipping {}: not named <name>-<version>.rockspec",
+                        file_name
+                    );
+                    continue;
+                };
+                let rockspec = parse_rockspec(&fs::read_to_string(&path)?, &file_name)?;
+                let entry = entries.entry((name, version)).or_default();
+                entry.arches.insert("rockspec".to_string());
+                entry.rockspec = Some(rockspec);
+            } else if let Some((name, version, arch)) =
+                file_name.strip_suffix(".rock").and_then(split_rock_name)
+            {
+                let entry = entries.entry((name.clone(), version.clone())).or_default();
+                entry.arches.insert(arch);
+                rocks.push((name, version, path));
+            }
+        }
+
+        // Rocks without a rockspec next to them still carry one inside
+        for (name, version, path) in rocks {
+            let entry = entries.get_mut(&(name.clone(), version.clone())).unwrap();
+            if entry.rockspec.is_none() {
+                let content = rock::read_rockspec(&path, &name, &version)?;
+                entry.rockspec = Some(parse_rockspec(&content, &path.display().to_string())?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Write a file atomically, so a server never hands out half a manifest
+    fn write(&self, name: &str, content: &str) -> LpmResult<()> {
+        let tmp = self.root.join(format!(".{}.tmp", name));
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, self.root.join(name))?;
+        Ok(())
+    }
+}
+
+fn parse_rockspec(content: &str, what: &str) -> LpmResult<Rockspec> {
+    Rockspec::parse_lua(content)
+        .map_err(|e| LpmError::Package(format!("Failed to parse {}: {}", what, e)))
+}
+
+/// Split `lua-cjson-2.1.0-1` into name and version (the version holds exactly one `-`)
+fn split_name_version(stem: &str) -> Option<(String, String)> {
+    let mut parts = stem.rsplitn(3, '-');
+    let revision = parts.next()?;
+    let version = parts.next()?;
+    let name = parts.next()?;
+    if name.is_empty() || version.is_empty() || revision.is_empty() {
+        return None;
+    }
+    Some((name.to_string(), format!("{}-{}", version, revision)))
+}
+
+/// Split `lua-cjson-2.1.0-1.linux-x86_64` into name, version and arch
+fn split_rock_name(stem: &str) -> Option<(String, String, String)> {
+    let (stem, arch) = stem.rsplit_once('.')?;
+    let (name, version) = split_name_version(stem)?;
+    Some((name, version, arch.to_string()))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The manifest for the packages that support `lua` (all of them if `None`)
+fn manifest_table(entries: &BTreeMap<(String, String), Entry>, lua: Option<&str>) -> LuaTable {
+    let mut repository = LuaTable::new();
+    let mut modules = LuaTable::new();
+    let mut commands = LuaTable::new();
+    let mut dependencies = LuaTable::new();
+
+    for ((name, version), entry) in entries {
+        let rockspec = entry.rockspec.as_ref();
+        if let (Some(lua), Some(rockspec)) = (lua, rockspec) {
+            if !rockspec.supports_lua(lua) {
+                continue;
+            }
+        }
+        let provider = LuaValue::String(format!("{}/{}", name, version));
+
+        let mut arches = LuaTable::new();
+        for arch in &entry.arches {
+            let mut info = LuaTable::new();
+            info.set(key("arch"), LuaValue::String(arch.clone()));
+            push(&mut arches, LuaValue::Table(info));
+        }
+        table_at(&mut repository, name).set(key(version), LuaValue::Table(arches));
+
+        let Some(rockspec) = rockspec else {
+            continue;
+        };
+        for module in RockspecSummary::new(name, version, rockspec).modules {
+            push(table_at(&mut modules, &module), provider.clone());
+        }
+        for command in rockspec.build.install.bin.keys() {
+            push(table_at(&mut commands, command), provider.clone());
+        }
+        let mut deps = LuaTable::new();
+        for dep in &rockspec.dependencies {
+            push(&mut deps, LuaValue::Table(dependency_table(dep)));
+        }
+        table_at(&mut dependencies, name).set(key(version), LuaValue::Table(deps));
+    }
+
+    let mut manifest = LuaTable::new();
+    manifest.set(key("commands"), LuaValue::Table(commands));
+    manifest.set(key("dependencies"), LuaValue::Table(dependencies));
+    manifest.set(key("modules"), LuaValue::Table(modules));
+    manifest.set(key("repository"), LuaValue::Table(repository));
+    manifest
+}
+
+/// Split "luafilesystem >= 1.8, < 2" into the name and its constraint
+fn split_dependency(dep: &str) -> (&str, &str) {
+    let dep = dep.trim();
+    let pos = dep
+        .find(|c: char| c.is_whitespace() || "<>=~!".contains(c))
+        .unwrap_or(dep.len());
+    (&dep[..pos], dep[pos..].trim())
+}
+
+/// A dependency the way LuaRocks stores it in manifests:
+/// `{ name = "lua", constraints = { { op = ">=", version = { 5, 1, string = "5.1" } } } }`
+fn dependency_table(dep: &str) -> LuaTable {
+    let (name, constraint) = split_dependency(dep);
+    let mut constraints = LuaTable::new();
+    for part in constraint
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+    {
+        let op_len = part
+            .find(|c: char| !"<>=~!".contains(c))
+            .unwrap_or(part.len());
+        let op = match &part[..op_len] {
+            "" | "=" => "==",
+            "!=" => "~=",
+            op => op,
+        };
+        let mut entry = LuaTable::new();
+        entry.set(key("op"), LuaValue::String(op.to_string()));
+        entry.set(
+            key("version"),
+            LuaValue::Table(version_table(part[op_len..].trim())),
+        );
+        push(&mut constraints, LuaValue::Table(entry));
+    }
+
+    let mut table = LuaTable::new();
+    table.set(key("name"), LuaValue::String(name.to_string()));
+    table.set(key("constraints"), LuaValue::Table(constraints));
+    table
+}
+
+/// A version the way LuaRocks parses it: numeric parts, the revision and the original string
+fn version_table(version: &str) -> LuaTable {
+    let (upstream, revision) = match version.rsplit_once('-') {
+        Some((upstream, revision)) if revision.parse::<u64>().is_ok() => {
+            (upstream, revision.parse::<f64>().ok())
+        }
+        _ => (version, None),
+    };
+    let mut table = LuaTable::new();
+    for part in upstream
+        .split('.')
+        .map_while(|part| part.parse::<f64>().ok())
+    {
+        push(&mut table, LuaValue::Number(part));
+    }
+    if let Some(revision) = revision {
+        table.set(key("revision"), LuaValue::Number(revision));
+    }
+    table.set(key("string"), LuaValue::String(version.to_string()));
+    table
+}
+
+/// The JSON form of a manifest value; mixed tables keep only their string keys
+fn to_json(value: &LuaValue) -> serde_json::Value {
+    match value {
+        LuaValue::Nil => serde_json::Value::Null,
+        LuaValue::Boolean(b) => serde_json::Value::Bool(*b),
+        LuaValue::Number(n) => serde_json::json!(n),
+        LuaValue::String(s) => serde_json::Value::String(s.clone()),
+        LuaValue::Table(table) => {
+            let fields: serde_json::Map<String, serde_json::Value> = table
+                .string_pairs()
+                .map(|(name, item)| (name.to_string(), to_json(item)))
+                .collect();
+            if fields.is_empty() && !table.sequence().is_empty() {
+                serde_json::Value::Array(table.sequence().iter().map(to_json).collect())
+            } else {
+                serde_json::Value::Object(fields)
+            }
+        }
+    }
+}
+
+fn key(name: &str) -> LuaKey {
+    LuaKey::String(name.to_string())
+}
+
+fn push(table: &mut LuaTable, value: LuaValue) {
+    table.set(LuaKey::Integer(table.len() as i64 + 1), value);
+}
+
+/// The table at `table[name]`, created if missing
+fn table_at<'a>(table: &'a mut LuaTable, name: &str) -> &'a mut LuaTable {
+    if table.get_table(name).is_none() {
+        table.set(key(name), LuaValue::Table(LuaTable::new()));
+    }
+    table.get_table_mut(name).expect("just inserted")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::luarocks::manifest::Manifest;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn rockspec(name: &str, version: &str, deps: &[&str]) -> String {
+        let deps: Vec<String> = deps.iter().map(|d| format!("\"{}\"", d)).collect();
+        format!(
+            r#"package = "{name}"
+version = "{version}"
+source = {{ url = "https://example.com/{name}-{version}.tar.gz" }}
+dependencies = {{ {} }}
+build = {{
+   type = "builtin",
+   modules = {{ ["{name}.core"] = "src/core.lua" }},
+   install = {{ bin = {{ ["{name}-cli"] = "bin/cli.lua" }} }},
+}}
+"#,
+            deps.join(", ")
+        )
+    }
+
+    fn write_rock(dir: &Path, name: &str, version: &str, arch: &str) -> PathBuf {
+        let path = dir.join(format!("{}-{}.{}.rock", name, version, arch));
+        let mut rock = zip::ZipWriter::new(File::create(&path).unwrap());
+        rock.start_file(
+            format!("{}-{}.rockspec", name, version),
+            zip::write::FileOptions::default(),
+        )
+        .unwrap();
+        rock.write_all(rockspec(name, version, &[]).as_bytes())
+            .unwrap();
+        rock.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_split_names() {
+        assert_eq!(
+            split_name_version("lua-cjson-2.1.0-1"),
+            Some(("lua-cjson".to_string(), "2.1.0-1".to_string()))
+        );
+        assert_eq!(split_name_version("nover"), None);
+        assert_eq!(
+            split_rock_name("lpeg-1.1.0-2.linux-x86_64"),
+            Some((
+                "lpeg".to_string(),
+                "1.1.0-2".to_string(),
+                "linux-x86_64".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_rebuild_writes_lua_and_json_manifests() {
+        let temp = TempDir::new().unwrap();
+        let incoming = temp.path().join("incoming");
+        fs::create_dir_all(&incoming).unwrap();
+        let repo = LocalRepository::open(&temp.path().join("repo")).unwrap();
+
+        // File names don't matter for rockspecs; the contents do
+        let spec = incoming.join("acme.rockspec");
+        fs::write(
+            &spec,
+            rockspec(
+                "acme-log",
+                "1.0-1",
+                &["lua >= 5.1, < 5.4", "penlight ~> 1.13"],
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            repo.add(&spec).unwrap(),
+            ("acme-log".to_string(), "1.0-1".to_string())
+        );
+        let rock = write_rock(&incoming, "acme-ffi", "0.2-1", "linux-x86_64");
+        repo.add(&rock).unwrap();
+        assert!(repo.add(&incoming.join("README.md")).is_err());
+        assert_eq!(repo.rebuild().unwrap(), 2);
+
+        for (file, format) in [("manifest", "lua"), ("manifest.json", "json")] {
+            let content = fs::read_to_string(repo.root().join(file)).unwrap();
+            let manifest = Manifest::parse_for(&content, "local", "http://localhost").unwrap();
+            assert_eq!(
+                manifest.dependencies_of("acme-log", "1.0-1"),
+                Some(&vec![
+                    "lua >= 5.1, < 5.4".to_string(),
+                    "penlight ~> 1.13".to_string()
+                ]),
+                "{format}"
+            );
+            assert_eq!(
+                manifest.modules_of("acme-ffi", "0.2-1"),
+                Some(&vec!["acme-ffi.core".to_string()]),
+                "{format}"
+            );
+            assert!(
+                manifest.get_package_versions("acme-ffi").is_some(),
+                "{format}"
+            );
+            assert_eq!(
+                manifest.rocks_of("acme-ffi", "0.2-1"),
+                ["linux-x86_64"],
+                "{format}"
+            );
+        }
+
+        let globals =
+            lua_data::evaluate(&fs::read_to_string(repo.root().join("manifest")).unwrap()).unwrap();
+        let lua_dep = globals
+            .get_table("dependencies")
+            .unwrap()
+            .get_table("acme-log")
+            .unwrap()
+            .get_table("1.0-1")
+            .unwrap()
+            .sequence()[0]
+            .clone();
+        let version = lua_dep
+            .as_table()
+            .unwrap()
+            .get_table("constraints")
+            .unwrap()
+            .sequence()[0]
+            .as_table()
+            .unwrap()
+            .get_table("version")
+            .unwrap()
+            .clone();
+        assert_eq!(
+            version.sequence(),
+            [LuaValue::Number(5.0), LuaValue::Number(1.0)]
+        );
+        assert!(globals
+            .get_table("commands")
+            .unwrap()
+            .get_table("acme-log-cli")
+            .is_some());
+
+        // Per-Lua-version manifests only list the packages supporting that version
+        let lua54 =
+            Manifest::parse_lua(&fs::read_to_string(repo.root().join("manifest-5.4")).unwrap())
+                .unwrap();
+        assert!(lua54.get_package_versions("acme-log").is_none());
+        assert!(lua54.get_package_versions("acme-ffi").is_some());
+        let lua51 =
+            Manifest::parse_lua(&fs::read_to_string(repo.root().join("manifest-5.1")).unwrap())
+                .unwrap();
+        assert!(lua51.get_package_versions("acme-log").is_some());
+    }
+
+    #[test]
+    fn test_publish_bundles_a_source_rock() {
+        let temp = TempDir::new().unwrap();
+        let repo = LocalRepository::open(&temp.path().join("repo")).unwrap();
+        let archive = temp.path().join("acme-log-1.0.0.tar.gz");
+        fs::write(&archive, b"archive").unwrap();
+
+        repo.publish(&rockspec("acme-log", "1.0.0-1", &[]), &archive)
+            .unwrap();
+        assert!(repo.root().join("acme-log-1.0.0.tar.gz").exists());
+        assert!(repo.root().join("acme-log-1.0.0-1.rockspec").exists());
+        let rock = repo.root().join("acme-log-1.0.0-1.src.rock");
+        assert!(rock::read_rockspec(&rock, "acme-log", "1.0.0-1").is_ok());
+
+        let manifest =
+            Manifest::parse_lua(&fs::read_to_string(repo.root().join("manifest")).unwrap())
+                .unwrap();
+        assert!(manifest.get_package_versions("acme-log").is_some());
+        let globals =
+            lua_data::evaluate(&fs::read_to_string(repo.root().join("manifest")).unwrap()).unwrap();
+        let arches = globals
+            .get_table("repository")
+            .unwrap()
+            .get_table("acme-log")
+            .unwrap()
+            .get_table("1.0.0-1")
+            .unwrap()
+            .len();
+        assert_eq!(arches, 2);
+    }
+}
@@ -0,0 +1,425 @@
+//! LuaRocks `.rock` files
+//!
+//! A rock is a zip archive named `<name>-<version>.<arch>.rock`. Source rocks
+//! (`src`) hold the rockspec and the source archive. Binary rocks (`all` for
+//! pure Lua, or a platform such as `linux-x86_64`) hold the files as they are
+//! installed, under `lua/`, `lib/`, `bin/`, `conf/` and `doc/`, next to the
+//! rockspec and a `rock_manifest` with the MD5 of every file:
+//!
+//! ```lua
+//! rock_manifest = {
+//!    lib = { ["cjson.so"] = "8c3e1f0fe9e3d4ad1f8e0e1b9b8cbd3e" },
+//!    ["lua-cjson-2.1.0-1.rockspec"] = "0d2b3e1d2a3b4c5d6e7f8091a2b3c4d5"
+//! }
+//! ```
+
+use crate::core::{LpmError, LpmResult};
+use crate::luarocks::lua_data::{self, LuaKey, LuaTable, LuaValue};
+use md5::{Digest, Md5};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The file in a binary rock listing the MD5 of every other file
+pub const ROCK_MANIFEST: &str = "rock_manifest";
+
+/// The arch of source rocks
+pub const SOURCE: &str = "src";
+
+/// The arch of binary rocks that run anywhere (pure Lua)
+pub const ALL: &str = "all";
+
+/// The LuaRocks platform lpm is running on, e.g. `linux-x86_64`
+pub fn platform() -> Option<&'static str> {
+    platform_of(std::env::consts::OS, std::env::consts::ARCH)
+}
+
+/// The LuaRocks platform of a Rust target triple, e.g. `macosx-arm64` for `aarch64-apple-darwin`
+pub fn target_platform(triple: &str) -> Option<&'static str> {
+    let arch = triple.split('-').next()?;
+    let os = if triple.contains("-linux") {
+        "linux"
+    } else if triple.contains("-apple-darwin") {
+        "macos"
+    } else if triple.contains("-windows") {
+        "windows"
+    } else {
+        return None;
+    };
+    platform_of(os, arch)
+}
+
+fn platform_of(os: &str, arch: &str) -> Option<&'static str> {
+    Some(match (os, arch) {
+        ("linux", "x86_64") => "linux-x86_64",
+        ("linux", "aarch64") => "linux-aarch64",
+        ("macos", "x86_64") => "macosx-x86_64",
+        ("macos", "aarch64") => "macosx-arm64",
+        ("windows", "x86_64") => "win32-x86_64",
+        _ => return None,
+    })
+}
+
+/// `lua-cjson-2.1.0-1.linux-x86_64.rock`
+pub fn file_name(name: &str, version: &str, arch: &str) -> String {
+    format!("{}-{}.{}.rock", name, version, arch)
+}
+
+/// The rockspec a rock carries
+pub fn read_rockspec(rock: &Path, name: &str, version: &str) -> LpmResult<String> {
+    let content = read_file(rock, &format!("{}-{}.rockspec", name, version))?;
+    String::from_utf8(content)
+        .map_err(|e| LpmError::Package(format!("Invalid rockspec in {}: {}", rock.display(), e)))
+}
+
+/// A file in a rock, such as the source archive in a source rock
+pub fn read_file(rock: &Path, name: &str) -> LpmResult<Vec<u8>> {
+    let mut archive = open(rock)?;
+    let mut file = archive.by_name(name).map_err(|e| invalid(rock, e))?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Check every file in a binary rock against its `rock_manifest`
+///
+/// Fails if a listed file is missing or has another hash, or if the rock has
+/// files its manifest doesn't list.
+pub fn verify(rock: &Path) -> LpmResult<()> {
+    let mut archive = open(rock)?;
+    let manifest = {
+        let mut file = archive.by_name(ROCK_MANIFEST).map_err(|_| {
+            LpmError::Package(format!("{} has no {}", rock.display(), ROCK_MANIFEST))
+        })?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        content
+    };
+    let globals = lua_data::evaluate(&manifest).map_err(|e| {
+        LpmError::Package(format!(
+            "Invalid {} in {}: {}",
+            ROCK_MANIFEST,
+            rock.display(),
+            e
+        ))
+    })?;
+    let mut expected = BTreeMap::new();
+    flatten(
+        globals.get_table(ROCK_MANIFEST).unwrap_or(&LuaTable::new()),
+        "",
+        &mut expected,
+    );
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| invalid(rock, e))?;
+        if file.is_dir() || file.name() == ROCK_MANIFEST {
+            continue;
+        }
+        let name = file.name().to_string();
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        match expected.remove(&name) {
+            Some(hash) if hash.eq_ignore_ascii_case(&md5_hex(&data)) => {}
+            Some(_) => {
+                return Err(LpmError::Package(format!(
+                    "{} in {} does not match its {} hash",
+                    name,
+                    rock.display(),
+                    ROCK_MANIFEST
+                )))
+            }
+            None => {
+                return Err(LpmError::Package(format!(
+                    "{} in {} is not listed in its {}",
+                    name,
+                    rock.display(),
+                    ROCK_MANIFEST
+                )))
+            }
+        }
+    }
+    if let Some(name) = expected.keys().next() {
+        return Err(LpmError::Package(format!(
+            "{} is missing from {}",
+            name,
+            rock.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Build a binary rock from the files staged in `dir` (`lua/`, `lib/`, `bin/`, ...)
+///
+/// Adds the rockspec and a `rock_manifest` to `dir`, then zips it into
+/// `out_dir`. Returns the path of the rock.
+pub fn write_binary_rock(
+    dir: &Path,
+    rockspec: &str,
+    name: &str,
+    version: &str,
+    arch: &str,
+    out_dir: &Path,
+) -> LpmResult<PathBuf> {
+    fs::write(dir.join(format!("{}-{}.rockspec", name, version)), rockspec)?;
+    let mut globals = LuaTable::new();
+    globals.set(
+        LuaKey::String(ROCK_MANIFEST.to_string()),
+        LuaValue::Table(manifest_of(dir)?),
+    );
+    fs::write(dir.join(ROCK_MANIFEST), lua_data::serialize(&globals))?;
+
+    fs::create_dir_all(out_dir)?;
+    let path = out_dir.join(file_name(name, version, arch));
+    let mut rock = zip::ZipWriter::new(File::create(&path)?);
+    let mut files: Vec<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect();
+    files.sort();
+    for file in files {
+        let relative = zip_path(dir, &file)?;
+        rock.start_file(relative, file_options(&file))
+            .map_err(|e| invalid(&path, e))?;
+        rock.write_all(&fs::read(&file)?)?;
+    }
+    rock.finish().map_err(|e| invalid(&path, e))?;
+    Ok(path)
+}
+
+/// Bundle a rockspec and its source archive as `<name>-<version>.src.rock` in `out_dir`
+pub fn write_source_rock(
+    rockspec: &str,
+    name: &str,
+    version: &str,
+    archive: &Path,
+    out_dir: &Path,
+) -> LpmResult<PathBuf> {
+    let archive_name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| LpmError::Package(format!("Invalid archive path: {}", archive.display())))?;
+
+    fs::create_dir_all(out_dir)?;
+    let path = out_dir.join(file_name(name, version, SOURCE));
+    let mut rock = zip::ZipWriter::new(File::create(&path)?);
+    let options = zip::write::FileOptions::default();
+    rock.start_file(format!("{}-{}.rockspec", name, version), options)
+        .map_err(|e| invalid(&path, e))?;
+    rock.write_all(rockspec.as_bytes())?;
+    rock.start_file(archive_name, options)
+        .map_err(|e| invalid(&path, e))?;
+    rock.write_all(&fs::read(archive)?)?;
+    rock.finish().map_err(|e| invalid(&path, e))?;
+    Ok(path)
+}
+
+/// The `rock_manifest` table for the files in `dir`: directories become
+/// nested tables, files their MD5
+fn manifest_of(dir: &Path) -> LpmResult<LuaTable> {
+    let mut manifest = LuaTable::new();
+    for entry in WalkDir::new(dir).min_depth(1).sort_by_file_name() {
+        let entry =
+            entry.map_err(|e| LpmError::Path(format!("Failed to read directory entry: {}", e)))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = zip_path(dir, entry.path())?;
+        if relative == ROCK_MANIFEST {
+            continue;
+        }
+        let hash = md5_hex(&fs::read(entry.path())?);
+
+        let mut parts: Vec<&str> = relative.split('/').collect();
+        let file = parts.pop().unwrap_or_default();
+        let mut table = &mut manifest;
+        for part in parts {
+            if table.get_table(part).is_none() {
+                table.set(
+                    LuaKey::String(part.to_string()),
+                    LuaValue::Table(LuaTable::new()),
+                );
+            }
+            table = table.get_table_mut(part).expect("just inserted");
+        }
+        table.set(LuaKey::String(file.to_string()), LuaValue::String(hash));
+    }
+    Ok(manifest)
+}
+
+/// Collect `path → hash` from a (nested) `rock_manifest` table
+fn flatten(table: &LuaTable, prefix: &str, out: &mut BTreeMap<String, String>) {
+    for (name, value) in table.string_pairs() {
+        let path = format!("{}{}", prefix, name);
+        match value {
+            LuaValue::Table(dir) => flatten(dir, &format!("{}/", path), out),
+            LuaValue::String(hash) => {
+                out.insert(path, hash.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+fn md5_hex(data: &[u8]) -> String {
+    hex::encode(Md5::digest(data))
+}
+
+/// `path` relative to `dir`, with `/` separators as zip entries use
+fn zip_path(dir: &Path, path: &Path) -> LpmResult<String> {
+    let relative = path
+        .strip_prefix(dir)
+        .map_err(|e| LpmError::Path(format!("Failed to get relative path: {}", e)))?;
+    Ok(relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+#[cfg(unix)]
+fn file_options(file: &Path) -> zip::write::FileOptions {
+    use std::os::unix::fs::PermissionsExt;
+    let options = zip::write::FileOptions::default();
+    match fs::metadata(file) {
+        Ok(metadata) => options.unix_permissions(metadata.permissions().mode()),
+        Err(_) => options,
+    }
+}
+
+#[cfg(not(unix))]
+fn file_options(_file: &Path) -> zip::write::FileOptions {
+    zip::write::FileOptions::default()
+}
+
+fn open(rock: &Path) -> LpmResult<zip::ZipArchive<File>> {
+    zip::ZipArchive::new(File::open(rock)?).map_err(|e| invalid(rock, e))
+}
+
+fn invalid(rock: &Path, e: zip::result::ZipError) -> LpmError {
+    LpmError::Package(format!("Invalid rock {}: {}", rock.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const ROCKSPEC: &str = "package = \"acme\"\nversion = \"1.0-1\"\nsource = { url = \"https://example.com/acme-1.0.tar.gz\" }\n";
+
+    fn staged(temp: &TempDir) -> PathBuf {
+        let dir = temp.path().join("stage");
+        fs::create_dir_all(dir.join("lua/acme")).unwrap();
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("lua/acme/init.lua"), "return {}").unwrap();
+        fs::write(dir.join("lib/acme_core.so"), b"\x7fELF").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_platforms() {
+        assert_eq!(
+            target_platform("x86_64-unknown-linux-gnu"),
+            Some("linux-x86_64")
+        );
+        assert_eq!(
+            target_platform("aarch64-apple-darwin"),
+            Some("macosx-arm64")
+        );
+        assert_eq!(
+            target_platform("x86_64-pc-windows-gnu"),
+            Some("win32-x86_64")
+        );
+        assert_eq!(target_platform("riscv64gc-unknown-linux-gnu"), None);
+        assert_eq!(
+            file_name("lpeg", "1.1.0-2", "linux-x86_64"),
+            "lpeg-1.1.0-2.linux-x86_64.rock"
+        );
+    }
+
+    #[test]
+    fn test_binary_rock_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let dir = staged(&temp);
+        let out = temp.path().join("dist");
+        let rock =
+            write_binary_rock(&dir, ROCKSPEC, "acme", "1.0-1", "linux-x86_64", &out).unwrap();
+        assert!(rock.ends_with("acme-1.0-1.linux-x86_64.rock"));
+        verify(&rock).unwrap();
+        assert_eq!(read_rockspec(&rock, "acme", "1.0-1").unwrap(), ROCKSPEC);
+
+        let manifest =
+            lua_data::evaluate(&fs::read_to_string(dir.join(ROCK_MANIFEST)).unwrap()).unwrap();
+        let listed = manifest.get_table(ROCK_MANIFEST).unwrap();
+        assert_eq!(
+            listed
+                .get_table("lua")
+                .and_then(|lua| lua.get_table("acme"))
+                .and_then(|acme| acme.get_string("init.lua")),
+            Some(md5_hex(b"return {}"))
+        );
+        assert!(listed.get_string("acme-1.0-1.rockspec").is_some());
+
+        assert_eq!(read_file(&rock, "lua/acme/init.lua").unwrap(), b"return {}");
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_rocks() {
+        let temp = TempDir::new().unwrap();
+        let dir = staged(&temp);
+        write_binary_rock(
+            &dir,
+            ROCKSPEC,
+            "acme",
+            "1.0-1",
+            "all",
+            &temp.path().join("a"),
+        )
+        .unwrap();
+
+        // Changed after the manifest was written
+        fs::write(dir.join("lib/acme_core.so"), b"evil").unwrap();
+        let rock = temp.path().join("tampered.rock");
+        let mut zip = zip::ZipWriter::new(File::create(&rock).unwrap());
+        for name in [ROCK_MANIFEST, "lib/acme_core.so", "acme-1.0-1.rockspec"] {
+            zip.start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(&fs::read(dir.join(name)).unwrap()).unwrap();
+        }
+        zip.finish().unwrap();
+        let err = verify(&rock).unwrap_err().to_string();
+        assert!(err.contains("lib/acme_core.so"), "{err}");
+
+        // Listed but left out
+        let rock = temp.path().join("partial.rock");
+        let mut zip = zip::ZipWriter::new(File::create(&rock).unwrap());
+        zip.start_file(ROCK_MANIFEST, zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(&fs::read(dir.join(ROCK_MANIFEST)).unwrap())
+            .unwrap();
+        zip.finish().unwrap();
+        assert!(verify(&rock).unwrap_err().to_string().contains("missing"));
+    }
+
+    #[test]
+    fn test_source_rock() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.path().join("acme-1.0.tar.gz");
+        fs::write(&archive, b"archive").unwrap();
+        let rock = write_source_rock(
+            ROCKSPEC,
+            "acme",
+            "1.0-1",
+            &archive,
+            &temp.path().join("dist"),
+        )
+        .unwrap();
+        assert!(rock.ends_with("acme-1.0-1.src.rock"));
+        assert_eq!(read_rockspec(&rock, "acme", "1.0-1").unwrap(), ROCKSPEC);
+        let mut zip = open(&rock).unwrap();
+        assert!(zip.by_name("acme-1.0.tar.gz").is_ok());
+    }
+}
@@ -1,10 +1,11 @@
+use crate::core::version::{parse_constraint, Version};
 use crate::core::LpmResult;
 use crate::package::manifest::{BuildConfig, PackageManifest};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Parsed rockspec data
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Rockspec {
     pub package: String,
     pub version: String,
@@ -17,25 +18,100 @@ pub struct Rockspec {
     pub lua_version: Option<String>,
     #[serde(default)]
     pub binary_urls: HashMap<String, String>, // target -> URL
+    /// `rockspec_format`, e.g. "3.0"
+    #[serde(default)]
+    pub rockspec_format: Option<String>,
+    /// Dependencies needed only to build the rock
+    #[serde(default)]
+    pub build_dependencies: Vec<String>,
+    /// Dependencies needed only to run the rock's tests
+    #[serde(default)]
+    pub test_dependencies: Vec<String>,
+    /// System libraries the rock needs, e.g. OPENSSL -> { header = "openssl/ssl.h" }
+    #[serde(default)]
+    pub external_dependencies: HashMap<String, HashMap<String, String>>,
+    /// Platforms the rock supports, e.g. ["unix", "!windows"]
+    #[serde(default)]
+    pub supported_platforms: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RockspecSource {
     pub url: String,
     #[serde(default)]
     pub tag: Option<String>,
     #[serde(default)]
     pub branch: Option<String>,
+    #[serde(default)]
+    pub dir: Option<String>,
+    #[serde(default)]
+    pub md5: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RockspecBuild {
     #[serde(rename = "type")]
     pub build_type: String,
+    /// `build.modules` entries given as a single file: module name -> path
     #[serde(default)]
     pub modules: HashMap<String, String>,
+    /// `build.modules` entries given as a list of sources or a table with
+    /// `sources`/`libraries`/`defines`/`incdirs`/`libdirs`
+    #[serde(default)]
+    pub native_modules: HashMap<String, BuildModule>,
     #[serde(default)]
     pub install: InstallTable,
+    /// `build.variables` (make and cmake backends)
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    /// `build.build_variables` (make backend)
+    #[serde(default)]
+    pub build_variables: HashMap<String, String>,
+    /// `build.install_variables` (make backend)
+    #[serde(default)]
+    pub install_variables: HashMap<String, String>,
+    /// `build.build_command` (command backend)
+    #[serde(default)]
+    pub build_command: Option<String>,
+    /// `build.install_command` (command backend)
+    #[serde(default)]
+    pub install_command: Option<String>,
+    /// Directories copied verbatim into the installed rock
+    #[serde(default)]
+    pub copy_directories: Vec<String>,
+}
+
+impl Default for RockspecBuild {
+    fn default() -> Self {
+        Self {
+            // LuaRocks defaults to the builtin backend when `build.type` is missing
+            build_type: "builtin".to_string(),
+            modules: HashMap::new(),
+            native_modules: HashMap::new(),
+            install: InstallTable::default(),
+            variables: HashMap::new(),
+            build_variables: HashMap::new(),
+            install_variables: HashMap::new(),
+            build_command: None,
+            install_command: None,
+            copy_directories: Vec::new(),
+        }
+    }
+}
+
+/// A native module from `build.modules`, compiled by the builtin backend
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildModule {
+    #[serde(default)]
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub libraries: Vec<String>,
+    #[serde(default)]
+    pub defines: Vec<String>,
+    #[serde(default)]
+    pub incdirs: Vec<String>,
+    #[serde(default)]
+    pub libdirs: Vec<String>,
 }
 
 /// Install table structure from rockspecs
@@ -60,36 +136,57 @@ impl InstallTable {
 }
 
 impl Rockspec {
-    /// Parse rockspec from Lua content using regex-based parsing
+    /// Parse rockspec from Lua content
     ///
-    /// Rockspecs are simple data files, so we can parse them without
-    /// embedding a Lua interpreter. This simplifies cross-compilation
-    /// and removes the need for Lua version selection.
+    /// Rockspecs are data files, so they are evaluated by a sandboxed data-only
+    /// Lua evaluator instead of an embedded interpreter. This simplifies
+    /// cross-compilation and removes the need for Lua version selection.
     pub fn parse_lua(content: &str) -> LpmResult<Self> {
         crate::luarocks::rockspec_parser::parse_rockspec(content)
     }
 
+    /// Whether the rockspec's `lua` dependency (if any) accepts Lua `lua`, e.g. "5.4"
+    pub fn supports_lua(&self, lua: &str) -> bool {
+        let Ok(lua) = Version::parse(lua) else {
+            return true;
+        };
+        self.dependencies
+            .iter()
+            .filter_map(|dep| {
+                let dep = dep.trim();
+                let pos = dep
+                    .find(|c: char| c.is_whitespace() || "<>=~!".contains(c))
+                    .unwrap_or(dep.len());
+                (&dep[..pos] == "lua").then(|| dep[pos..].trim())
+            })
+            .all(|constraint| {
+                constraint.is_empty()
+                    || parse_constraint(constraint).map_or(true, |c| lua.satisfies(&c))
+            })
+    }
+
     /// Convert rockspec to PackageManifest format
     pub fn to_package_manifest(&self) -> PackageManifest {
         // Convert dependencies from LuaRocks format to LPM format
         let mut dependencies = HashMap::new();
         for dep in &self.dependencies {
-            // Parse dependency like "lua >= 5.1" or "luasocket" or "luasocket ~> 3.0"
-            // LuaRocks uses ~> for compatible versions, which we convert to ^
+            // Parse dependency like "lua >= 5.1", "luasocket", "luasocket ~> 3.0" or
+            // "luafilesystem >= 1.6, < 2". LuaRocks operators are understood by
+            // `parse_constraint`, so constraints are kept with their original meaning.
             let dep_str = dep.trim();
-            if let Some((name, version)) = dep_str.split_once(' ') {
-                let name = name.trim().to_string();
-                let version = version.trim();
-                // Convert LuaRocks ~> to SemVer ^
-                let version = if version.starts_with("~>") {
-                    version.replacen("~>", "^", 1)
-                } else {
-                    version.to_string()
-                };
-                dependencies.insert(name, version);
-            } else {
+            let pos = dep_str
+                .find(|c: char| c.is_whitespace() || "<>=~!^".contains(c))
+                .unwrap_or(dep_str.len());
+            let name = dep_str[..pos].to_string();
+            let version = dep_str[pos..].trim();
+            if version.is_empty() {
                 // No version specified, use wildcard
-                dependencies.insert(dep_str.to_string(), "*".to_string());
+                dependencies.insert(name, "*".to_string());
+            } else {
+                let version = parse_constraint(version)
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|_| version.to_string());
+                dependencies.insert(name, version);
             }
         }
 
@@ -129,6 +226,18 @@ impl Rockspec {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_supports_lua() {
+        let rockspec = Rockspec {
+            dependencies: vec!["lua >= 5.1, < 5.4".to_string(), "lpeg".to_string()],
+            ..Default::default()
+        };
+        assert!(rockspec.supports_lua("5.1"));
+        assert!(rockspec.supports_lua("5.3"));
+        assert!(!rockspec.supports_lua("5.4"));
+        assert!(Rockspec::default().supports_lua("5.4"));
+    }
+
     #[test]
     fn test_install_table_is_empty() {
         let table = InstallTable::default();
@@ -162,18 +271,21 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec!["luasocket ~> 3.0".to_string()],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: Some("Test package".to_string()),
             homepage: Some("https://example.com".to_string()),
             license: Some("MIT".to_string()),
             lua_version: Some(">=5.1".to_string()),
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         let manifest = rockspec.to_package_manifest();
@@ -181,12 +293,8 @@ mod tests {
         assert_eq!(manifest.version, "1.0.0");
         assert_eq!(manifest.description, Some("Test package".to_string()));
         assert!(manifest.dependencies.contains_key("luasocket"));
-        // Should convert ~> to ^
-        assert!(manifest
-            .dependencies
-            .get("luasocket")
-            .unwrap()
-            .starts_with("^"));
+        // ~> keeps its LuaRocks meaning (>= 3.0, < 3.1)
+        assert_eq!(manifest.dependencies.get("luasocket").unwrap(), "~>3.0");
     }
 
     #[test]
@@ -198,18 +306,21 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec!["luasocket".to_string()],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         let manifest = rockspec.to_package_manifest();
@@ -226,18 +337,21 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         let manifest = rockspec.to_package_manifest();
@@ -254,18 +368,21 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         let manifest = rockspec.to_package_manifest();
@@ -1,33 +1,87 @@
 use crate::core::{LpmError, LpmResult};
-use crate::luarocks::rockspec::{Rockspec, RockspecBuild, RockspecSource};
-use regex::Regex;
+use crate::luarocks::lua_data::{self, LuaKey, LuaTable, LuaValue};
+use crate::luarocks::rockspec::{
+    BuildModule, InstallTable, Rockspec, RockspecBuild, RockspecSource,
+};
 use std::collections::HashMap;
 
-/// Parse a rockspec file using regex-based extraction
+/// Rockspec sections that may carry a `platforms` override table
+const OVERRIDABLE_SECTIONS: &[&str] = &[
+    "source",
+    "dependencies",
+    "build_dependencies",
+    "test_dependencies",
+    "external_dependencies",
+    "build",
+];
+
+/// Parse a rockspec file
 ///
-/// This is a basic parser that extracts common fields from rockspec files.
-/// For full sandboxed parsing, we'll need a Lua interpreter later.
+/// The rockspec is evaluated by the sandboxed data-only Lua evaluator in
+/// [`lua_data`], so string concatenation, long strings, local variables and
+/// nested tables all work, while function calls and other code are rejected.
+/// `platforms` overrides for the current platform are merged in.
 pub fn parse_rockspec(content: &str) -> LpmResult<Rockspec> {
-    let package = extract_string_field(content, r#"package\s*=\s*"([^"]+)""#)?;
-    let version = extract_string_field(content, r#"version\s*=\s*"([^"]+)""#)?;
-
-    // Parse source table
-    let source = parse_source(content)?;
-
-    // Parse dependencies
-    let dependencies = parse_dependencies(content)?;
-
-    // Parse build table
-    let build = parse_build(content)?;
+    parse_rockspec_for_platforms(content, &current_platforms())
+}
 
-    // Optional fields
-    let description = extract_string_field(content, r#"description\s*=\s*"([^"]+)""#).ok();
-    let homepage = extract_string_field(content, r#"homepage\s*=\s*"([^"]+)""#).ok();
-    let license = extract_string_field(content, r#"license\s*=\s*"([^"]+)""#).ok();
-    let lua_version = extract_string_field(content, r#"lua_version\s*=\s*"([^"]+)""#).ok();
+/// Parse a rockspec, merging the `platforms` overrides for the given platforms
+///
+/// Platforms are applied in order, so list the most specific one last
+/// (e.g. `["unix", "linux"]`).
+pub fn parse_rockspec_for_platforms(content: &str, platforms: &[&str]) -> LpmResult<Rockspec> {
+    let mut globals = lua_data::evaluate(content)?;
+    apply_platform_overrides(&mut globals, platforms);
+
+    let package = required_string(&globals, "package")?;
+    let version = required_string(&globals, "version")?;
+    let source = parse_source(&globals)?;
+    let dependencies = string_list(&globals, "dependencies")?;
+    let build = parse_build(&globals)?;
+
+    // `description` is a table in real rockspecs, but a plain string is accepted too
+    let description_table = globals.get_table("description");
+    let description = match globals.get("description") {
+        LuaValue::String(s) => Some(s.clone()),
+        _ => description_table.and_then(|d| d.get_string("summary")),
+    };
+    let from_description = |field: &str| {
+        optional_string(&globals, field).or_else(|| description_table?.get_string(field))
+    };
+    let homepage = from_description("homepage");
+    let license = from_description("license");
+    let lua_version = optional_string(&globals, "lua_version");
+
+    // binary_urls may be top-level or inside the metadata table
+    let binary_urls = match globals.get_table("binary_urls") {
+        Some(table) => string_map(table, "binary_urls")?,
+        None => match globals
+            .get_table("metadata")
+            .and_then(|m| m.get_table("binary_urls"))
+        {
+            Some(table) => string_map(table, "metadata.binary_urls")?,
+            None => HashMap::new(),
+        },
+    };
 
-    // Parse binary_urls from metadata (if present)
-    let binary_urls = parse_binary_urls(content).unwrap_or_default();
+    let mut external_dependencies = HashMap::new();
+    if let Some(table) = globals.get_table("external_dependencies") {
+        for (name, value) in table.string_pairs() {
+            if name == "platforms" {
+                continue;
+            }
+            let probes = value.as_table().ok_or_else(|| {
+                LpmError::Package(format!(
+                    "Rockspec field 'external_dependencies.{}' must be a table",
+                    name
+                ))
+            })?;
+            external_dependencies.insert(
+                name.to_string(),
+                string_map(probes, &format!("external_dependencies.{}", name))?,
+            );
+        }
+    }
 
     Ok(Rockspec {
         package,
@@ -40,215 +94,239 @@ pub fn parse_rockspec(content: &str) -> LpmResult<Rockspec> {
         license,
         lua_version,
         binary_urls,
+        rockspec_format: optional_string(&globals, "rockspec_format"),
+        build_dependencies: string_list(&globals, "build_dependencies")?,
+        test_dependencies: string_list(&globals, "test_dependencies")?,
+        external_dependencies,
+        supported_platforms: string_list(&globals, "supported_platforms")?,
     })
 }
 
-fn extract_string_field(content: &str, pattern: &str) -> LpmResult<String> {
-    let re = Regex::new(pattern)
-        .map_err(|e| LpmError::Package(format!("Invalid regex pattern: {}", e)))?;
+/// LuaRocks platform names for the running system, most generic first
+pub fn current_platforms() -> Vec<&'static str> {
+    if cfg!(target_os = "windows") {
+        vec!["windows", "win32"]
+    } else if cfg!(target_os = "macos") {
+        vec!["unix", "bsd", "macosx", "macos"]
+    } else if cfg!(target_os = "linux") {
+        vec!["unix", "linux"]
+    } else if cfg!(target_os = "freebsd") {
+        vec!["unix", "bsd", "freebsd"]
+    } else if cfg!(target_os = "openbsd") {
+        vec!["unix", "bsd", "openbsd"]
+    } else if cfg!(target_os = "netbsd") {
+        vec!["unix", "bsd", "netbsd"]
+    } else if cfg!(unix) {
+        vec!["unix"]
+    } else {
+        Vec::new()
+    }
+}
 
-    re.captures(content)
-        .and_then(|cap| cap.get(1))
-        .map(|m| m.as_str().to_string())
-        .ok_or_else(|| LpmError::Package(format!("Field not found: {}", pattern)))
+/// Merge `section.platforms[<platform>]` into each section and drop the `platforms` tables
+fn apply_platform_overrides(globals: &mut LuaTable, platforms: &[&str]) {
+    for section in OVERRIDABLE_SECTIONS {
+        let Some(table) = globals.get_table(section) else {
+            continue;
+        };
+        let mut table = table.clone();
+        if let LuaValue::Table(overrides) = table.remove("platforms") {
+            for platform in platforms {
+                if let Some(platform_table) = overrides.get_table(platform) {
+                    table.deep_merge(platform_table);
+                }
+            }
+        }
+        globals.set(LuaKey::String(section.to_string()), LuaValue::Table(table));
+    }
 }
 
-fn parse_source(content: &str) -> LpmResult<RockspecSource> {
-    // Extract source table
-    let source_block = extract_table_block(content, "source")?;
+fn required_string(table: &LuaTable, field: &str) -> LpmResult<String> {
+    optional_string(table, field)
+        .ok_or_else(|| LpmError::Package(format!("Rockspec is missing required field '{}'", field)))
+}
 
-    let url = extract_string_field(&source_block, r#"url\s*=\s*"([^"]+)""#)
-        .or_else(|_| extract_string_field(&source_block, r#"url\s*=\s*'([^']+)'"#))?;
+fn optional_string(table: &LuaTable, field: &str) -> Option<String> {
+    table.get_string(field)
+}
 
-    let tag = extract_string_field(&source_block, r#"tag\s*=\s*"([^"]+)""#)
-        .or_else(|_| extract_string_field(&source_block, r#"tag\s*=\s*'([^']+)'"#))
-        .ok();
+/// A list of strings such as `dependencies`; a missing field is an empty list
+fn string_list(table: &LuaTable, field: &str) -> LpmResult<Vec<String>> {
+    value_to_list(table.get(field), field)
+}
 
-    let branch = extract_string_field(&source_block, r#"branch\s*=\s*"([^"]+)""#)
-        .or_else(|_| extract_string_field(&source_block, r#"branch\s*=\s*'([^']+)'"#))
-        .ok();
+fn value_to_list(value: &LuaValue, field: &str) -> LpmResult<Vec<String>> {
+    match value {
+        LuaValue::Nil => Ok(Vec::new()),
+        LuaValue::String(s) => Ok(vec![s.clone()]),
+        LuaValue::Table(t) => t
+            .sequence()
+            .iter()
+            .map(|v| {
+                v.to_lua_string().ok_or_else(|| {
+                    LpmError::Package(format!(
+                        "Rockspec field '{}' must be a list of strings",
+                        field
+                    ))
+                })
+            })
+            .collect(),
+        _ => Err(LpmError::Package(format!(
+            "Rockspec field '{}' must be a list of strings",
+            field
+        ))),
+    }
+}
 
-    Ok(RockspecSource { url, tag, branch })
+/// A table of string values keyed by name, e.g. `build.variables`
+fn string_map(table: &LuaTable, field: &str) -> LpmResult<HashMap<String, String>> {
+    let mut map = HashMap::new();
+    for (key, value) in table.string_pairs() {
+        if key == "platforms" {
+            continue;
+        }
+        let value = match value {
+            LuaValue::Boolean(b) => b.to_string(),
+            other => other.to_lua_string().ok_or_else(|| {
+                LpmError::Package(format!(
+                    "Rockspec field '{}.{}' must be a string",
+                    field, key
+                ))
+            })?,
+        };
+        map.insert(key.to_string(), value);
+    }
+    Ok(map)
 }
 
-fn parse_dependencies(content: &str) -> LpmResult<Vec<String>> {
-    let deps_block = extract_table_block(content, "dependencies")?;
+fn parse_source(globals: &LuaTable) -> LpmResult<RockspecSource> {
+    let source = globals.get_table("source").ok_or_else(|| {
+        LpmError::Package("Rockspec is missing required table 'source'".to_string())
+    })?;
+
+    Ok(RockspecSource {
+        url: required_string(source, "url")
+            .map_err(|_| LpmError::Package("Rockspec is missing 'source.url'".to_string()))?,
+        tag: optional_string(source, "tag"),
+        branch: optional_string(source, "branch"),
+        dir: optional_string(source, "dir"),
+        md5: optional_string(source, "md5"),
+    })
+}
+
+fn parse_build(globals: &LuaTable) -> LpmResult<RockspecBuild> {
+    let Some(build) = globals.get_table("build") else {
+        return Ok(RockspecBuild::default());
+    };
 
-    // Match entries like: "lua >= 5.1" or 'luasocket'
-    let re = Regex::new(r#"(?m)^\s*["']([^"']+)["']"#)
-        .map_err(|e| LpmError::Package(format!("Invalid regex: {}", e)))?;
+    let mut result = RockspecBuild {
+        build_type: optional_string(build, "type").unwrap_or_else(|| "builtin".to_string()),
+        build_command: optional_string(build, "build_command"),
+        install_command: optional_string(build, "install_command"),
+        copy_directories: string_list(build, "copy_directories")?,
+        ..RockspecBuild::default()
+    };
 
-    let mut deps = Vec::new();
-    for cap in re.captures_iter(&deps_block) {
-        if let Some(m) = cap.get(1) {
-            deps.push(m.as_str().to_string());
+    if let Some(modules) = build.get_table("modules") {
+        for (name, value) in modules.string_pairs() {
+            match value {
+                LuaValue::String(path) => {
+                    result.modules.insert(name.to_string(), path.clone());
+                }
+                LuaValue::Table(spec) => {
+                    result
+                        .native_modules
+                        .insert(name.to_string(), parse_build_module(name, spec)?);
+                }
+                _ => {
+                    return Err(LpmError::Package(format!(
+                        "Rockspec field 'build.modules.{}' must be a path or a table",
+                        name
+                    )))
+                }
+            }
         }
     }
 
-    Ok(deps)
-}
-
-fn parse_build(content: &str) -> LpmResult<RockspecBuild> {
-    let build_block = extract_table_block(content, "build")?;
+    if let Some(install) = build.get_table("install") {
+        result.install = parse_install_table(install)?;
+    }
 
-    let build_type = extract_string_field(&build_block, r#"type\s*=\s*"([^"]+)""#)
-        .or_else(|_| extract_string_field(&build_block, r#"type\s*=\s*'([^']+)'"#))
-        .unwrap_or_else(|_| "builtin".to_string());
+    for (field, target) in [
+        ("variables", &mut result.variables),
+        ("build_variables", &mut result.build_variables),
+        ("install_variables", &mut result.install_variables),
+    ] {
+        if let Some(table) = build.get_table(field) {
+            *target = string_map(table, &format!("build.{}", field))?;
+        }
+    }
 
-    // Parse modules table
-    let modules = parse_modules_table(&build_block)?;
+    Ok(result)
+}
 
-    // Parse install table
-    let install = parse_install_table(&build_block)?;
+/// Parse a native module: either a list of sources or a table with
+/// `sources`, `libraries`, `defines`, `incdirs` and `libdirs`
+fn parse_build_module(name: &str, spec: &LuaTable) -> LpmResult<BuildModule> {
+    let field = format!("build.modules.{}", name);
+    if spec.get("sources") == &LuaValue::Nil {
+        return Ok(BuildModule {
+            sources: value_to_list(&LuaValue::Table(spec.clone()), &field)?,
+            ..BuildModule::default()
+        });
+    }
 
-    Ok(RockspecBuild {
-        build_type,
-        modules,
-        install,
+    let list = |key: &str| value_to_list(spec.get(key), &format!("{}.{}", field, key));
+    Ok(BuildModule {
+        sources: list("sources")?,
+        libraries: list("libraries")?,
+        defines: list("defines")?,
+        incdirs: list("incdirs")?,
+        libdirs: list("libdirs")?,
     })
 }
 
 /// Parse install table from build block
 /// Format: install = { bin = { ["name"] = "path" }, lua = { ... }, lib = { ... }, conf = { ... } }
-fn parse_install_table(build_block: &str) -> LpmResult<crate::luarocks::rockspec::InstallTable> {
-    use crate::luarocks::rockspec::InstallTable;
-
-    let install_block = extract_table_block(build_block, "install").unwrap_or_default();
-
-    let bin = parse_install_section(&install_block, "bin")?;
-    let lua = parse_install_section(&install_block, "lua")?;
-    let lib = parse_install_section(&install_block, "lib")?;
-    let conf = parse_install_section(&install_block, "conf")?;
-
+fn parse_install_table(install: &LuaTable) -> LpmResult<InstallTable> {
     Ok(InstallTable {
-        bin,
-        lua,
-        lib,
-        conf,
+        bin: parse_install_section(install, "bin")?,
+        lua: parse_install_section(install, "lua")?,
+        lib: parse_install_section(install, "lib")?,
+        conf: parse_install_section(install, "conf")?,
     })
 }
 
 /// Parse a section of the install table (bin, lua, lib, or conf)
+///
+/// Entries are either `name = "path"` pairs or plain paths, which are
+/// installed under their file name.
 fn parse_install_section(
-    install_block: &str,
+    install: &LuaTable,
     section_name: &str,
 ) -> LpmResult<HashMap<String, String>> {
-    let section_block = extract_table_block(install_block, section_name).unwrap_or_default();
-
-    // Match entries like: ["name"] = "path" or name = "path"
-    let re = Regex::new(r#"(?m)^\s*(?:\["([^"]+)"\]|(\w+))\s*=\s*["']([^"']+)["']"#)
-        .map_err(|e| LpmError::Package(format!("Invalid regex: {}", e)))?;
-
-    let mut entries = HashMap::new();
-    for cap in re.captures_iter(&section_block) {
-        let name = cap
-            .get(1)
-            .or_else(|| cap.get(2))
-            .map(|m| m.as_str().to_string())
-            .ok_or_else(|| {
-                LpmError::Package(format!("Invalid entry in install.{}", section_name))
-            })?;
-        let path = cap.get(3).map(|m| m.as_str().to_string()).ok_or_else(|| {
-            LpmError::Package(format!("Invalid path in install.{}", section_name))
-        })?;
-        entries.insert(name, path);
-    }
-
-    Ok(entries)
-}
-
-fn parse_modules_table(build_block: &str) -> LpmResult<HashMap<String, String>> {
-    // Extract modules table block
-    let modules_block = extract_table_block(build_block, "modules").unwrap_or_default();
-
-    // Match entries like: socket = "src/socket.lua"
-    let re = Regex::new(r#"(?m)^\s*(\w+)\s*=\s*["']([^"']+)["']"#)
-        .map_err(|e| LpmError::Package(format!("Invalid regex: {}", e)))?;
-
-    let mut modules = HashMap::new();
-    for cap in re.captures_iter(&modules_block) {
-        if let (Some(name), Some(path)) = (cap.get(1), cap.get(2)) {
-            modules.insert(name.as_str().to_string(), path.as_str().to_string());
-        }
-    }
-
-    Ok(modules)
-}
-
-/// Parse binary_urls table from metadata section
-/// Format: binary_urls = { ["5.4-x86_64-unknown-linux-gnu"] = "https://..." }
-/// Or directly: binary_urls = { ["5.4-x86_64-unknown-linux-gnu"] = "https://..." }
-fn parse_binary_urls(content: &str) -> LpmResult<HashMap<String, String>> {
-    // Try to find binary_urls directly first
-    let binary_urls_block = extract_table_block(content, "binary_urls")
-        .or_else(|_| {
-            // If not found, try within metadata table
-            let metadata_block = extract_table_block(content, "metadata")?;
-            extract_table_block(&metadata_block, "binary_urls")
-        })
-        .ok();
-
-    let Some(binary_urls_block) = binary_urls_block else {
+    let Some(section) = install.get_table(section_name) else {
         return Ok(HashMap::new());
     };
 
-    // Match entries like: ["5.4-x86_64-unknown-linux-gnu"] = "https://..."
-    let re = Regex::new(r#"(?m)\["([^\]]+)"\]\s*=\s*["']([^"']+)["']"#)
-        .map_err(|e| LpmError::Package(format!("Invalid regex: {}", e)))?;
-
-    let mut urls = HashMap::new();
-    for cap in re.captures_iter(&binary_urls_block) {
-        if let (Some(target), Some(url)) = (cap.get(1), cap.get(2)) {
-            urls.insert(target.as_str().to_string(), url.as_str().to_string());
-        }
-    }
-
-    Ok(urls)
-}
-
-/// Extract a table block from Lua code
-///
-/// Finds a table like:
-///   field = {
-///     ...
-///   }
-fn extract_table_block(content: &str, field_name: &str) -> LpmResult<String> {
-    let pattern = format!(r#"{}\s*=\s*\{{"#, field_name);
-    let start_re =
-        Regex::new(&pattern).map_err(|e| LpmError::Package(format!("Invalid regex: {}", e)))?;
-
-    let start_match = start_re
-        .find(content)
-        .ok_or_else(|| LpmError::Package(format!("Field '{}' not found", field_name)))?;
-
-    let start_pos = start_match.end();
-    let mut brace_count = 1;
-    let mut pos = start_pos;
-    let chars: Vec<char> = content.chars().collect();
-
-    while pos < chars.len() && brace_count > 0 {
-        match chars[pos] {
-            '{' => brace_count += 1,
-            '}' => brace_count -= 1,
-            _ => {}
-        }
-        pos += 1;
-    }
-
-    if brace_count != 0 {
-        return Err(LpmError::Package(format!(
-            "Unclosed table block for '{}'",
-            field_name
-        )));
+    let field = format!("build.install.{}", section_name);
+    let mut entries = string_map(section, &field)?;
+    for path in value_to_list(&LuaValue::Table(section.clone()), &field)? {
+        let name = path.rsplit('/').next().unwrap_or(&path).to_string();
+        entries.insert(name, path);
     }
 
-    Ok(content[start_match.start()..pos].to_string())
+    Ok(entries)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn globals(content: &str) -> LuaTable {
+        lua_data::evaluate(content).unwrap()
+    }
+
     #[test]
     fn test_parse_simple_rockspec() {
         let content = r#"
@@ -356,21 +434,6 @@ source = {
         assert!(result.is_err());
     }
 
-    #[test]
-    fn test_extract_string_field() {
-        let content = r#"package = "test-package""#;
-        let result = extract_string_field(content, r#"package\s*=\s*"([^"]+)""#);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "test-package");
-    }
-
-    #[test]
-    fn test_extract_string_field_not_found() {
-        let content = r#"version = "1.0.0""#;
-        let result = extract_string_field(content, r#"package\s*=\s*"([^"]+)""#);
-        assert!(result.is_err());
-    }
-
     #[test]
     fn test_parse_source() {
         let content = r#"
@@ -381,7 +444,7 @@ source = {
 }
 "#;
 
-        let source = parse_source(content).unwrap();
+        let source = parse_source(&globals(content)).unwrap();
         assert_eq!(source.url, "https://example.com/test.tar.gz");
         assert_eq!(source.tag, Some("v1.0.0".to_string()));
         assert_eq!(source.branch, Some("main".to_string()));
@@ -395,7 +458,7 @@ source = {
 }
 "#;
 
-        let source = parse_source(content).unwrap();
+        let source = parse_source(&globals(content)).unwrap();
         assert_eq!(source.url, "https://example.com/test.tar.gz");
         assert!(source.tag.is_none());
         assert!(source.branch.is_none());
@@ -411,7 +474,7 @@ dependencies = {
 }
 "#;
 
-        let deps = parse_dependencies(content).unwrap();
+        let deps = string_list(&globals(content), "dependencies").unwrap();
         assert_eq!(deps.len(), 3);
         assert!(deps.contains(&"luasocket >= 3.0".to_string()));
         assert!(deps.contains(&"penlight".to_string()));
@@ -423,7 +486,7 @@ dependencies = {
 dependencies = {}
 "#;
 
-        let deps = parse_dependencies(content).unwrap();
+        let deps = string_list(&globals(content), "dependencies").unwrap();
         assert!(deps.is_empty());
     }
 
@@ -438,45 +501,11 @@ build = {
 }
 "#;
 
-        let build = parse_build(content).unwrap();
+        let build = parse_build(&globals(content)).unwrap();
         assert_eq!(build.build_type, "make");
         assert_eq!(build.modules.len(), 1);
     }
 
-    #[test]
-    fn test_extract_table_block() {
-        let content = r#"
-test = {
-   field1 = "value1",
-   field2 = "value2"
-}
-"#;
-
-        let result = extract_table_block(content, "test");
-        assert!(result.is_ok());
-        let block = result.unwrap();
-        assert!(block.contains("field1"));
-        assert!(block.contains("field2"));
-    }
-
-    #[test]
-    fn test_extract_table_block_not_found() {
-        let content = r#"other = {}"#;
-        let result = extract_table_block(content, "test");
-        assert!(result.is_err());
-    }
-
-    #[test]
-    fn test_extract_table_block_unclosed() {
-        let content = r#"
-test = {
-   field = "value"
-"#;
-
-        let result = extract_table_block(content, "test");
-        assert!(result.is_err());
-    }
-
     #[test]
     fn test_parse_rockspec_with_binary_urls() {
         let content = r#"
@@ -527,7 +556,7 @@ build = {
    }
 }
 "#;
-        let build = parse_build(content).unwrap();
+        let build = parse_build(&globals(content)).unwrap();
         assert!(build.install.bin.contains_key("my-script"));
     }
 
@@ -543,7 +572,7 @@ build = {
    }
 }
 "#;
-        let build = parse_build(content).unwrap();
+        let build = parse_build(&globals(content)).unwrap();
         assert!(build.install.lua.contains_key("my-module"));
     }
 
@@ -559,7 +588,7 @@ build = {
    }
 }
 "#;
-        let build = parse_build(content).unwrap();
+        let build = parse_build(&globals(content)).unwrap();
         assert!(build.install.lib.contains_key("mylib"));
     }
 
@@ -575,7 +604,217 @@ build = {
    }
 }
 "#;
-        let build = parse_build(content).unwrap();
+        let build = parse_build(&globals(content)).unwrap();
         assert!(build.install.conf.contains_key("config"));
     }
+
+    #[test]
+    fn test_parse_rockspec_with_lua_expressions() {
+        let content = r#"
+local package_version = "1.8.0"
+local rockspec_revision = "1"
+package = 'luafilesystem'
+version = package_version .. "-" .. rockspec_revision
+source = {
+   url = "git+https://github.com/lunarmodules/luafilesystem",
+   tag = "v" .. package_version:gsub("%.", "_")
+}
+"#;
+        // Method calls are code, not data
+        assert!(parse_rockspec(content).is_err());
+
+        let content = content.replace(r#"package_version:gsub("%.", "_")"#, "package_version");
+        let rockspec = parse_rockspec(&content).unwrap();
+        assert_eq!(rockspec.package, "luafilesystem");
+        assert_eq!(rockspec.version, "1.8.0-1");
+        assert_eq!(rockspec.source.tag, Some("v1.8.0".to_string()));
+    }
+
+    #[test]
+    fn test_parse_full_rockspec() {
+        let content = r#"
+rockspec_format = "3.0"
+package = "luasec"
+version = "1.3.2-1"
+source = {
+   url = "git+https://github.com/brunoos/luasec",
+   tag = "v1.3.2",
+   dir = "luasec-1.3.2",
+}
+description = {
+   summary = "A binding for OpenSSL library to provide TLS/SSL communication over LuaSocket.",
+   detailed = [[
+      This version delegates to LuaSocket the TCP connection establishment
+      between the client and server.
+   ]],
+   homepage = "https://github.com/brunoos/luasec/wiki",
+   license = "MIT",
+}
+supported_platforms = { "unix", "windows" }
+dependencies = {
+   "lua >= 5.1", "luasocket"
+}
+build_dependencies = { "luarocks-build-extended" }
+test_dependencies = { "busted >= 2.0" }
+external_dependencies = {
+   platforms = {
+      unix = { OPENSSL = { header = "openssl/ssl.h", library = "ssl" } },
+   },
+   OPENSSL = { header = "openssl/ssl.h" },
+}
+build = {
+   type = "builtin",
+   copy_directories = { "samples" },
+   variables = { LUA_PATH = "$(LUADIR)" },
+   modules = {
+      ["ssl.https"] = "src/https.lua",
+      ssl = {
+         sources = { "src/ssl.c", "src/context.c" },
+         libraries = { "ssl", "crypto" },
+         defines = { "WITH_LUASOCKET" },
+         incdirs = { "$(OPENSSL_INCDIR)", "src/" },
+         libdirs = { "$(OPENSSL_LIBDIR)" },
+      },
+      lpeg = { "lpcap.c", "lpcode.c" },
+   },
+   platforms = {
+      windows = { modules = { ssl = { libraries = { "libssl" } } } },
+   },
+}
+"#;
+        let rockspec = parse_rockspec(content).unwrap();
+        assert_eq!(rockspec.rockspec_format, Some("3.0".to_string()));
+        assert_eq!(rockspec.source.dir, Some("luasec-1.3.2".to_string()));
+        assert!(rockspec
+            .description
+            .unwrap()
+            .starts_with("A binding for OpenSSL"));
+        assert_eq!(
+            rockspec.homepage,
+            Some("https://github.com/brunoos/luasec/wiki".to_string())
+        );
+        assert_eq!(rockspec.license, Some("MIT".to_string()));
+        assert_eq!(rockspec.supported_platforms, vec!["unix", "windows"]);
+        assert_eq!(rockspec.dependencies, vec!["lua >= 5.1", "luasocket"]);
+        assert_eq!(rockspec.build_dependencies, vec!["luarocks-build-extended"]);
+        assert_eq!(rockspec.test_dependencies, vec!["busted >= 2.0"]);
+        assert_eq!(
+            rockspec.external_dependencies["OPENSSL"]["header"],
+            "openssl/ssl.h"
+        );
+        assert_eq!(rockspec.build.copy_directories, vec!["samples"]);
+        assert_eq!(rockspec.build.variables["LUA_PATH"], "$(LUADIR)");
+        assert_eq!(rockspec.build.modules["ssl.https"], "src/https.lua");
+
+        let ssl = &rockspec.build.native_modules["ssl"];
+        assert_eq!(ssl.sources, vec!["src/ssl.c", "src/context.c"]);
+        assert_eq!(ssl.libraries, vec!["ssl", "crypto"]);
+        assert_eq!(ssl.defines, vec!["WITH_LUASOCKET"]);
+        assert_eq!(ssl.incdirs, vec!["$(OPENSSL_INCDIR)", "src/"]);
+        assert_eq!(ssl.libdirs, vec!["$(OPENSSL_LIBDIR)"]);
+        assert_eq!(
+            rockspec.build.native_modules["lpeg"].sources,
+            vec!["lpcap.c", "lpcode.c"]
+        );
+    }
+
+    #[test]
+    fn test_parse_rockspec_without_build_or_dependencies() {
+        let content = r#"
+package = "minimal"
+version = "0.1-1"
+source = { url = "https://example.com/minimal-0.1.tar.gz" }
+"#;
+        let rockspec = parse_rockspec(content).unwrap();
+        assert!(rockspec.dependencies.is_empty());
+        assert_eq!(rockspec.build.build_type, "builtin");
+    }
+
+    #[test]
+    fn test_parse_install_section_list_form() {
+        let content = r#"
+build = {
+   type = "none",
+   install = { bin = { "bin/tool", alias = "bin/other" } }
+}
+"#;
+        let build = parse_build(&globals(content)).unwrap();
+        assert_eq!(build.install.bin["tool"], "bin/tool");
+        assert_eq!(build.install.bin["alias"], "bin/other");
+    }
+
+    #[test]
+    fn test_parse_rockspec_rejects_code() {
+        let content = r#"
+package = "evil"
+version = "1.0-1"
+os.execute("touch /tmp/pwned")
+source = { url = "https://example.com/evil.tar.gz" }
+"#;
+        let err = parse_rockspec(content).unwrap_err().to_string();
+        assert!(err.contains("Unsupported Lua"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_rockspec_missing_source_url() {
+        let content = r#"
+package = "broken"
+version = "1.0-1"
+source = { tag = "v1.0" }
+"#;
+        let err = parse_rockspec(content).unwrap_err().to_string();
+        assert!(err.contains("source.url"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_rockspec_platform_overrides() {
+        let content = r#"
+package = "socket"
+version = "3.1.0-1"
+source = { url = "https://example.com/socket.tar.gz" }
+external_dependencies = {
+   platforms = {
+      unix = { OPENSSL = { header = "openssl/ssl.h", library = "ssl" } },
+   },
+}
+build = {
+   type = "builtin",
+   modules = {
+      ["socket.core"] = { sources = { "src/luasocket.c" }, defines = { "LUASOCKET_DEBUG" } },
+   },
+   platforms = {
+      unix = { modules = { ["socket.core"] = { sources = { [2] = "src/usocket.c" } } } },
+      linux = { variables = { OS = "linux" } },
+      windows = {
+         modules = { ["socket.core"] = { sources = { [2] = "src/wsocket.c" }, libraries = { "ws2_32" } } },
+      },
+   },
+}
+"#;
+        let linux = parse_rockspec_for_platforms(content, &["unix", "linux"]).unwrap();
+        let core = &linux.build.native_modules["socket.core"];
+        assert_eq!(core.sources, vec!["src/luasocket.c", "src/usocket.c"]);
+        assert_eq!(core.defines, vec!["LUASOCKET_DEBUG"]);
+        assert!(core.libraries.is_empty());
+        assert_eq!(linux.build.variables["OS"], "linux");
+        assert_eq!(linux.external_dependencies["OPENSSL"]["library"], "ssl");
+
+        let windows = parse_rockspec_for_platforms(content, &["windows", "win32"]).unwrap();
+        let core = &windows.build.native_modules["socket.core"];
+        assert_eq!(core.sources, vec!["src/luasocket.c", "src/wsocket.c"]);
+        assert_eq!(core.libraries, vec!["ws2_32"]);
+        assert!(windows.build.variables.is_empty());
+        assert!(windows.external_dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_current_platforms_most_specific_last() {
+        let platforms = current_platforms();
+        if cfg!(target_os = "linux") {
+            assert_eq!(platforms, vec!["unix", "linux"]);
+        }
+        if cfg!(unix) {
+            assert_eq!(platforms[0], "unix");
+        }
+    }
 }
@@ -1,12 +1,16 @@
+use crate::cache::Cache;
+use crate::config::Config;
 use crate::core::version::Version;
 use crate::core::{LpmError, LpmResult};
-use crate::luarocks::manifest::Manifest;
+use crate::luarocks::client::LuaRocksClient;
+use crate::luarocks::registry::{Registries, Registry, DEFAULT_REGISTRY};
 use reqwest::Client;
 
 /// Client for interacting with LuaRocks search and manifest APIs
 pub struct SearchAPI {
     client: Client,
     base_url: String,
+    cache: Option<Cache>,
 }
 
 impl Default for SearchAPI {
@@ -14,6 +18,7 @@ impl Default for SearchAPI {
         Self {
             client: Client::new(),
             base_url: "https://luarocks.org".to_string(),
+            cache: None,
         }
     }
 }
@@ -24,28 +29,31 @@ impl SearchAPI {
         Self::default()
     }
 
-    /// Get the latest version of a package by fetching the manifest
+    /// Keep the manifest in this cache instead of the configured cache directory
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Get the latest version of a package from the manifest
+    ///
+    /// The manifest is read through the cache and its package index, so it is
+    /// only downloaded again once it has expired.
     pub async fn get_latest_version(&self, package_name: &str) -> LpmResult<String> {
         // Fetch manifest
-        let manifest_url = format!("{}/manifests/luarocks/manifest?format=json", self.base_url);
-        let response = self
-            .client
-            .get(&manifest_url)
-            .send()
-            .await
-            .map_err(LpmError::Http)?;
-
-        if !response.status().is_success() {
-            return Err(LpmError::Package(format!(
-                "Failed to fetch manifest: HTTP {}",
-                response.status()
-            )));
-        }
-
-        let content = response.text().await.map_err(LpmError::Http)?;
-
-        // Parse manifest
-        let manifest = Manifest::parse_json(&content)?;
+        let config = Config::load()?;
+        let cache = match &self.cache {
+            Some(cache) => cache.clone(),
+            None => Cache::new(config.get_cache_dir()?)?,
+        };
+        let registry = Registry::new(
+            DEFAULT_REGISTRY,
+            &format!("{}/manifests/luarocks", self.base_url),
+        );
+        let manifest = LuaRocksClient::new(&config, cache)
+            .with_registries(Registries::new(vec![registry]))
+            .fetch_manifest()
+            .await?;
 
         // Get all versions for this package
         let versions = manifest.get_package_versions(package_name).ok_or_else(|| {
@@ -180,26 +188,30 @@ mod tests {
         let mock_server = MockServer::start().await;
 
         // Mock manifest response
+        let rockspec = || serde_json::json!([{"arch": "rockspec"}]);
         Mock::given(method("GET"))
             .and(path("/manifests/luarocks/manifest"))
             .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
                 "repository": {
                     "test-package": {
-                        "1.0.0": {},
-                        "2.0.0": {},
-                        "1.5.0": {}
+                        "1.0.0-1": rockspec(),
+                        "2.0.0-1": rockspec(),
+                        "1.5.0-1": rockspec()
                     }
                 }
             })))
+            .expect(1)
             .mount(&mock_server)
             .await;
 
-        let mut api = SearchAPI::new();
+        let temp = tempfile::TempDir::new().unwrap();
+        let mut api = SearchAPI::new().with_cache(Cache::new(temp.path().to_path_buf()).unwrap());
         api.base_url = mock_server.uri();
 
-        // This will fail because Manifest::parse_json expects a different format,
-        // but we're testing that the HTTP call works
-        let _result = api.get_latest_version("test-package").await;
-        // May fail on parsing, but HTTP part is tested
+        // The second lookup is served from the cached index
+        for _ in 0..2 {
+            let latest = api.get_latest_version("test-package").await.unwrap();
+            assert_eq!(latest, "2.0.0-1");
+        }
     }
 }
@@ -0,0 +1,270 @@
+//! Serves a [`LocalRepository`](crate::luarocks::repository::LocalRepository) over HTTP
+//!
+//! Just enough of HTTP/1.1 for LuaRocks clients: `GET` and `HEAD` of the files
+//! at the top of the repository directory, with `ETag`s for revalidation.
+//! `manifest?format=json` is answered with `manifest.json`, the way LuaRocks
+//! servers answer it.
+
+use crate::core::{LpmError, LpmResult};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Where `lpm registry serve` listens unless told otherwise
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+/// Requests with a longer head than this are rejected
+const MAX_HEAD: usize = 16 * 1024;
+
+pub struct RepositoryServer {
+    root: Arc<PathBuf>,
+    listener: TcpListener,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    etag: Option<String>,
+    body: Vec<u8>,
+}
+
+impl RepositoryServer {
+    /// Listen on `address` (e.g. "127.0.0.1:8080"; port 0 picks a free port)
+    pub async fn bind(root: &Path, address: &str) -> LpmResult<Self> {
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| LpmError::Package(format!("Failed to listen on {}: {}", address, e)))?;
+        Ok(Self {
+            root: Arc::new(root.to_path_buf()),
+            listener,
+        })
+    }
+
+    pub fn local_addr(&self) -> LpmResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// The URL to use as the registry's base URL
+    pub fn url(&self) -> LpmResult<String> {
+        Ok(format!("http://{}", self.local_addr()?))
+    }
+
+    /// Serve requests until the task is dropped
+    pub async fn run(self) -> LpmResult<()> {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let root = Arc::clone(&self.root);
+            tokio::spawn(async move {
+                let _ = handle(stream, &root).await;
+            });
+        }
+    }
+}
+
+async fn handle(mut stream: TcpStream, root: &Path) -> std::io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+        if head.len() > MAX_HEAD {
+            return write_response(
+                &mut stream,
+                &error("431 Request Header Fields Too Large"),
+                false,
+            )
+            .await;
+        }
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default().to_string();
+    let if_none_match = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("if-none-match")
+            .then(|| value.trim().to_string())
+    });
+
+    let response = match method.as_str() {
+        "GET" | "HEAD" => respond(root, &target, if_none_match.as_deref()),
+        _ => error("405 Method Not Allowed"),
+    };
+    println!("{} {} {}", method, target, response.status);
+    write_response(&mut stream, &response, method != "HEAD").await
+}
+
+fn respond(root: &Path, target: &str, if_none_match: Option<&str>) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let Ok(name) = urlencoding::decode(path.trim_start_matches('/')) else {
+        return error("400 Bad Request");
+    };
+    // The repository is flat: anything that isn't a plain file name is not in it
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return error("404 Not Found");
+    }
+    let wants_json = query.split('&').any(|param| param == "format=json");
+    let name = if name == "manifest" && wants_json {
+        "manifest.json".to_string()
+    } else {
+        name.to_string()
+    };
+
+    let file = root.join(&name);
+    let metadata = match fs::metadata(&file) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return error("404 Not Found"),
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let etag = format!("\"{:x}-{:x}\"", metadata.len(), modified.as_nanos());
+    if if_none_match == Some(etag.as_str()) {
+        return Response {
+            status: "304 Not Modified",
+            content_type: content_type(&name),
+            etag: Some(etag),
+            body: Vec::new(),
+        };
+    }
+
+    match fs::read(&file) {
+        Ok(body) => Response {
+            status: "200 OK",
+            content_type: content_type(&name),
+            etag: Some(etag),
+            body,
+        },
+        Err(_) => error("500 Internal Server Error"),
+    }
+}
+
+fn content_type(name: &str) -> &'static str {
+    if name.ends_with(".json") {
+        "application/json"
+    } else if name.ends_with(".rockspec") || name.starts_with("manifest") {
+        "text/x-lua; charset=utf-8"
+    } else if name.ends_with(".rock") || name.ends_with(".zip") {
+        "application/zip"
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        "application/gzip"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+fn error(status: &'static str) -> Response {
+    Response {
+        status,
+        content_type: "text/plain; charset=utf-8",
+        etag: None,
+        body: format!("{}\n", status).into_bytes(),
+    }
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    response: &Response,
+    with_body: bool,
+) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    if let Some(etag) = &response.etag {
+        head.push_str(&format!("ETag: {}\r\n", etag));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    if with_body {
+        stream.write_all(&response.body).await?;
+    }
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::Cache;
+    use crate::config::Config;
+    use crate::luarocks::client::LuaRocksClient;
+    use crate::luarocks::registry::{Registries, Registry};
+    use crate::luarocks::repository::LocalRepository;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_respond() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("manifest"), "repository = {}").unwrap();
+        fs::write(temp.path().join("manifest.json"), "{}").unwrap();
+
+        let json = respond(temp.path(), "/manifest?format=json", None);
+        assert_eq!(json.status, "200 OK");
+        assert_eq!(json.body, b"{}");
+        let lua = respond(temp.path(), "/manifest", None);
+        assert_eq!(lua.content_type, "text/x-lua; charset=utf-8");
+
+        let cached = respond(temp.path(), "/manifest", lua.etag.as_deref());
+        assert_eq!(cached.status, "304 Not Modified");
+
+        for target in [
+            "/",
+            "/../secret",
+            "/%2e%2e%2fsecret",
+            "/.manifest.tmp",
+            "/missing",
+        ] {
+            assert_eq!(
+                respond(temp.path(), target, None).status,
+                "404 Not Found",
+                "{target}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_installs_metadata_from_served_repository() {
+        let temp = TempDir::new().unwrap();
+        let repo = LocalRepository::open(&temp.path().join("repo")).unwrap();
+        let archive = temp.path().join("acme-log-1.0.0.tar.gz");
+        fs::write(&archive, b"archive").unwrap();
+        repo.publish(
+            "package = \"acme-log\"\nversion = \"1.0.0-1\"\nsource = { url = \"http://localhost/acme-log-1.0.0.tar.gz\" }\ndependencies = { \"lua >= 5.1\" }\nbuild = { type = \"builtin\", modules = { acme = \"acme.lua\" } }\n",
+            &archive,
+        )
+        .unwrap();
+
+        let server = RepositoryServer::bind(repo.root(), "127.0.0.1:0")
+            .await
+            .unwrap();
+        let url = server.url().unwrap();
+        tokio::spawn(server.run());
+
+        let cache = Cache::new(temp.path().join("cache")).unwrap();
+        let client = LuaRocksClient::new(&Config::default(), cache)
+            .with_registries(Registries::new(vec![Registry::new("local", &url)]));
+        let manifest = client.fetch_manifest().await.unwrap();
+        assert_eq!(
+            manifest.dependencies_of("acme-log", "1.0.0-1"),
+            Some(&vec!["lua >= 5.1".to_string()])
+        );
+
+        let rockspec_url = client.rockspec_url(&manifest, "acme-log", "1.0.0-1");
+        assert_eq!(rockspec_url, format!("{}/acme-log-1.0.0-1.rockspec", url));
+        let rockspec = client.download_rockspec(&rockspec_url).await.unwrap();
+        assert!(rockspec.contains("package = \"acme-log\""));
+    }
+}
@@ -1,52 +1,30 @@
 use crate::core::version::Version;
 use crate::core::{LpmError, LpmResult};
 
-/// Normalize LuaRocks version format to SemVer
+/// Parse a LuaRocks version
 ///
 /// LuaRocks uses format like "3.0-1" where:
-/// - "3.0" is the version
+/// - "3.0" is the upstream version
 /// - "-1" is the rockspec revision
 ///
-/// LPM converts this to "3.0.1" (SemVer format)
+/// Both parts are kept on the returned `Version`, so it prints back as "3.0-1".
 pub fn normalize_luarocks_version(luarocks_version: &str) -> LpmResult<Version> {
-    // Split on '-' to separate version from revision
-    let parts: Vec<&str> = luarocks_version.split('-').collect();
-
-    if parts.is_empty() {
+    if luarocks_version.trim().is_empty() {
         return Err(LpmError::Version(format!(
             "Invalid LuaRocks version format: {}",
             luarocks_version
         )));
     }
 
-    let version_str = parts[0];
-    let revision = if parts.len() > 1 {
-        parts[1].parse::<u64>().unwrap_or(0)
-    } else {
-        0
-    };
-
-    // Parse the version part
-    let mut version = Version::parse(version_str)?;
-
-    // If revision > 0, add it as patch version
-    // If version already has patch, we increment it
-    if revision > 0 {
-        version.patch += revision;
-    }
-
-    Ok(version)
+    Version::parse(luarocks_version)
 }
 
-/// Convert SemVer back to LuaRocks format (for display)
+/// Format a version as a LuaRocks version ("upstream-revision")
+///
+/// Versions without a rockspec revision get revision 1, the first revision
+/// LuaRocks assigns to a rockspec.
 pub fn to_luarocks_version(version: &Version) -> String {
-    // Simple conversion: "3.0.1" -> "3.0-1"
-    // This is a simplified approach - real conversion might be more complex
-    if version.patch > 0 && version.patch < 10 {
-        format!("{}.{}-{}", version.major, version.minor, version.patch)
-    } else {
-        format!("{}.{}", version.major, version.minor)
-    }
+    format!("{}-{}", version.upstream(), version.revision.unwrap_or(1))
 }
 
 #[cfg(test)]
@@ -56,19 +34,16 @@ mod tests {
     #[test]
     fn test_normalize_luarocks_version() {
         let v = normalize_luarocks_version("3.0-1").unwrap();
-        assert_eq!(v.major, 3);
-        assert_eq!(v.minor, 0);
-        assert_eq!(v.patch, 1);
+        assert_eq!((v.major, v.minor, v.patch), (3, 0, 0));
+        assert_eq!(v.revision, Some(1));
 
         let v = normalize_luarocks_version("1.13.1-1").unwrap();
-        assert_eq!(v.major, 1);
-        assert_eq!(v.minor, 13);
-        assert_eq!(v.patch, 2); // 1 + 1
+        assert_eq!((v.major, v.minor, v.patch), (1, 13, 1));
+        assert_eq!(v.revision, Some(1));
 
         let v = normalize_luarocks_version("3.0").unwrap();
-        assert_eq!(v.major, 3);
-        assert_eq!(v.minor, 0);
-        assert_eq!(v.patch, 0);
+        assert_eq!((v.major, v.minor, v.patch), (3, 0, 0));
+        assert_eq!(v.revision, None);
     }
 
     #[test]
@@ -78,59 +53,42 @@ mod tests {
     }
 
     #[test]
-    fn test_normalize_luarocks_version_with_revision() {
-        let v = normalize_luarocks_version("2.5-3").unwrap();
-        assert_eq!(v.major, 2);
-        assert_eq!(v.minor, 5);
-        assert_eq!(v.patch, 3);
+    fn test_normalize_luarocks_version_keeps_original_spelling() {
+        for input in ["2.5-3", "1.0rc1-1", "scm-1", "1.2.3.4-2", "0.9.2-1"] {
+            assert_eq!(
+                normalize_luarocks_version(input).unwrap().to_string(),
+                input
+            );
+        }
     }
 
     #[test]
-    fn test_to_luarocks_version() {
-        let v = Version::new(3, 0, 1);
-        assert_eq!(to_luarocks_version(&v), "3.0-1");
-
-        let v = Version::new(1, 13, 0);
-        assert_eq!(to_luarocks_version(&v), "1.13");
-
-        let v = Version::new(2, 5, 9);
-        assert_eq!(to_luarocks_version(&v), "2.5-9");
-
-        let v = Version::new(1, 0, 10);
-        assert_eq!(to_luarocks_version(&v), "1.0"); // patch >= 10, no revision
+    fn test_normalize_luarocks_version_does_not_confuse_revision_and_patch() {
+        let with_revision = normalize_luarocks_version("3.0-1").unwrap();
+        let patch_release = normalize_luarocks_version("3.0.1-1").unwrap();
+        assert_ne!(with_revision, patch_release);
+        assert!(with_revision < patch_release);
     }
 
     #[test]
-    fn test_to_luarocks_version_edge_cases() {
-        let v = Version::new(0, 0, 0);
-        assert_eq!(to_luarocks_version(&v), "0.0");
-
-        let v = Version::new(0, 0, 5);
-        assert_eq!(to_luarocks_version(&v), "0.0-5");
-
-        let v = Version::new(10, 20, 15);
-        assert_eq!(to_luarocks_version(&v), "10.20"); // patch >= 10
-
-        let v = Version::new(1, 2, 9);
-        assert_eq!(to_luarocks_version(&v), "1.2-9");
+    fn test_to_luarocks_version() {
+        assert_eq!(to_luarocks_version(&Version::new(3, 0, 1)), "3.0.1-1");
+        assert_eq!(to_luarocks_version(&Version::new(1, 13, 0)), "1.13.0-1");
+        assert_eq!(
+            to_luarocks_version(&Version::parse("3.0-2").unwrap()),
+            "3.0-2"
+        );
+        assert_eq!(
+            to_luarocks_version(&Version::parse("scm").unwrap()),
+            "scm-1"
+        );
     }
 
     #[test]
-    fn test_normalize_luarocks_version_edge_cases() {
-        // Test with large revision
-        let v = normalize_luarocks_version("1.0-100").unwrap();
-        assert_eq!(v.patch, 100);
-
-        // Test with no dash (no revision)
-        let v = normalize_luarocks_version("2.3").unwrap();
-        assert_eq!(v.major, 2);
-        assert_eq!(v.minor, 3);
-        assert_eq!(v.patch, 0);
-
-        // Test with invalid revision (should default to 0)
-        let v = normalize_luarocks_version("1.0-invalid").unwrap();
-        assert_eq!(v.major, 1);
-        assert_eq!(v.minor, 0);
-        assert_eq!(v.patch, 0); // Invalid revision defaults to 0
+    fn test_luarocks_version_round_trip() {
+        for input in ["3.0-1", "1.13.1-1", "2.0beta-1", "0.10-3"] {
+            let v = normalize_luarocks_version(input).unwrap();
+            assert_eq!(to_luarocks_version(&v), input);
+        }
     }
 }
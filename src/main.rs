@@ -9,6 +9,9 @@ mod cli;
 #[command(about = "Local package management for Lua")]
 #[command(version)]
 struct Cli {
+    /// Revalidate cached manifests and rockspecs with the registries, ignoring `index_ttl`
+    #[arg(long, global = true)]
+    refresh: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -46,6 +49,21 @@ enum Commands {
         /// Interactive mode: search and select packages
         #[arg(short, long)]
         interactive: bool,
+        /// Install from package.lock and the local cache without network access
+        #[arg(long)]
+        offline: bool,
+        /// Fail if package.lock is out of date, and install exactly what it records
+        #[arg(long)]
+        frozen: bool,
+    },
+    /// Clean install from package.lock, failing if it is out of date (same as install --frozen)
+    Ci {
+        /// Skip dev dependencies
+        #[arg(long)]
+        no_dev: bool,
+        /// Use only the local cache
+        #[arg(long)]
+        offline: bool,
     },
     /// Remove a dependency
     Remove {
@@ -59,6 +77,9 @@ enum Commands {
     Update {
         /// Package name to update (optional)
         package: Option<String>,
+        /// Resolve against the cached manifest and install from the cache only
+        #[arg(long)]
+        offline: bool,
     },
     /// List installed packages
     List {
@@ -71,10 +92,18 @@ enum Commands {
     },
     /// Verify package checksums
     Verify,
+    /// Show which package installed a file in lua_modules
+    Owns {
+        /// File (e.g. lua_modules/socket.lua or socket.lua) or module name (e.g. socket.http)
+        path: String,
+    },
     /// Show outdated packages
     Outdated,
     /// Clean lua_modules directory
     Clean,
+    /// Inspect, verify and prune the download cache
+    #[command(subcommand)]
+    Cache(cli::cache::CacheCommands),
     /// Run a script
     Run {
         /// Script name
@@ -94,11 +123,17 @@ enum Commands {
         #[arg(long)]
         all_targets: bool,
     },
-    /// Publish package to LuaRocks
+    /// Publish package to LuaRocks, or to a local repository
     Publish {
         /// Include pre-built Rust binaries
         #[arg(long)]
         with_binaries: bool,
+        /// Add the package to this local repository instead (see `lpm registry`)
+        #[arg(long)]
+        repository: Option<std::path::PathBuf>,
+        /// URL the repository is served at, for the rockspec's source URL
+        #[arg(long, requires = "repository", default_value = "http://127.0.0.1:8080")]
+        repository_url: String,
     },
     /// Login to LuaRocks
     Login,
@@ -120,6 +155,12 @@ enum Commands {
     /// Manage project templates
     #[command(subcommand)]
     Template(cli::template::TemplateCommands),
+    /// Build and serve a local LuaRocks-compatible repository
+    #[command(subcommand)]
+    Registry(cli::registry::RegistryCommands),
+    /// Show and change settings
+    #[command(subcommand)]
+    Config(cli::config::ConfigCommands),
     /// Manage plugins
     #[command(subcommand)]
     Plugin(cli::plugin::commands::PluginSubcommand),
@@ -142,6 +183,9 @@ async fn main() -> Result<(), LpmError> {
     }
 
     let cli = Cli::parse();
+    if cli.refresh {
+        lpm::luarocks::client::refresh_cached_index();
+    }
 
     let result = match cli.command {
         Commands::Init { template, yes } => cli::init::run(template, yes).await,
@@ -153,13 +197,36 @@ async fn main() -> Result<(), LpmError> {
             dev_only,
             global,
             interactive,
-        } => cli::install::run(package, dev, path, no_dev, dev_only, global, interactive).await,
+            offline,
+            frozen,
+        } => {
+            cli::install::run(
+                package,
+                dev,
+                path,
+                no_dev,
+                dev_only,
+                global,
+                interactive,
+                offline,
+                frozen,
+            )
+            .await
+        }
+        Commands::Ci { no_dev, offline } => {
+            cli::install::run(
+                None, false, None, no_dev, false, false, false, offline, true,
+            )
+            .await
+        }
         Commands::Remove { package, global } => cli::remove::run(package, global),
-        Commands::Update { package } => cli::update::run(package).await,
+        Commands::Update { package, offline } => cli::update::run(package, offline).await,
         Commands::List { tree, global } => cli::list::run(tree, global),
         Commands::Verify => cli::verify::run(),
+        Commands::Owns { path } => cli::owns::run(path),
         Commands::Outdated => cli::outdated::run().await,
         Commands::Clean => cli::clean::run(),
+        Commands::Cache(cmd) => cli::cache::run(cmd).await,
         Commands::Run { script } => cli::run::run(script),
         Commands::Exec { command } => cli::exec::run(command),
         Commands::Build {
@@ -167,7 +234,11 @@ async fn main() -> Result<(), LpmError> {
             all_targets,
         } => cli::build::run(target, all_targets),
         Commands::Package { target } => cli::package::run(target),
-        Commands::Publish { with_binaries } => cli::publish::run(with_binaries).await,
+        Commands::Publish {
+            with_binaries,
+            repository,
+            repository_url,
+        } => cli::publish::run(with_binaries, repository, repository_url).await,
         Commands::Login => cli::login::run().await,
         Commands::GenerateRockspec => cli::generate_rockspec::run(),
         Commands::Audit => cli::audit::run().await,
@@ -177,6 +248,8 @@ async fn main() -> Result<(), LpmError> {
         }
         Commands::Lua(cmd) => cli::lua::run(cmd).await,
         Commands::Template(cmd) => cli::template::run(cmd),
+        Commands::Registry(cmd) => cli::registry::run(cmd).await,
+        Commands::Config(cmd) => cli::config::run(cmd),
         Commands::Plugin(cmd) => cli::plugin::commands::run(cmd),
         Commands::External(args) => {
             if args.is_empty() {
@@ -1,5 +1,6 @@
 use crate::core::version::parse_constraint;
 use crate::core::{LpmError, LpmResult};
+use crate::package::dependency::DependencySpec;
 use crate::package::manifest::PackageManifest;
 use crate::resolver::DependencyGraph;
 use std::collections::HashMap;
@@ -47,7 +48,14 @@ impl ConflictChecker {
         let mut graph = DependencyGraph::new();
 
         for (name, version_str) in deps {
-            let constraint = parse_constraint(version_str)?;
+            // Git and path dependencies have no version constraint to check
+            let Some(version_str) = DependencySpec::parse(version_str)
+                .as_registry()
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            let constraint = parse_constraint(&version_str)?;
             graph.add_node(name.clone(), constraint);
         }
 
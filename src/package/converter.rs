@@ -45,6 +45,7 @@ mod tests {
                 url: "https://github.com/lunarmodules/luasocket/archive/v3.0.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec!["lua >= 5.1".to_string()],
             build: RockspecBuild {
@@ -55,12 +56,14 @@ mod tests {
                     m
                 },
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: Some("Network support for Lua".to_string()),
             homepage: Some("https://github.com/lunarmodules/luasocket".to_string()),
             license: Some("MIT".to_string()),
             lua_version: Some(">=5.1".to_string()),
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         let manifest = convert_rockspec_to_manifest(&rockspec, temp.path(), "luasocket").unwrap();
@@ -91,18 +94,21 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         let manifest =
@@ -121,18 +127,21 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec!["luasocket >= 3.0".to_string(), "penlight".to_string()],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         let manifest =
@@ -154,18 +163,21 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         let manifest =
             convert_rockspec_to_manifest(&rockspec, temp.path(), "test-package").unwrap();
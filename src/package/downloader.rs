@@ -143,18 +143,21 @@ impl ParallelDownloader {
                                     url: String::new(),
                                     tag: None,
                                     branch: None,
+                                    ..Default::default()
                                 },
                                 dependencies: Vec::new(),
                                 build: RockspecBuild {
                                     build_type: String::new(),
                                     modules: HashMap::new(),
                                     install: crate::luarocks::rockspec::InstallTable::default(),
+                                    ..Default::default()
                                 },
                                 description: None,
                                 homepage: None,
                                 license: None,
                                 lua_version: None,
                                 binary_urls: HashMap::new(),
+                                ..Default::default()
                             },
                             source_path: None,
                             error: Some(e),
@@ -173,18 +176,21 @@ impl ParallelDownloader {
                             url: String::new(),
                             tag: None,
                             branch: None,
+                            ..Default::default()
                         },
                         dependencies: Vec::new(),
                         build: crate::luarocks::rockspec::RockspecBuild {
                             build_type: String::new(),
                             modules: HashMap::new(),
                             install: crate::luarocks::rockspec::InstallTable::default(),
+                            ..Default::default()
                         },
                         description: None,
                         homepage: None,
                         license: None,
                         lua_version: None,
                         binary_urls: HashMap::new(),
+                        ..Default::default()
                     },
                     source_path: None,
                     error: Some(e),
@@ -475,18 +481,21 @@ mod tests {
                     url: "".to_string(),
                     tag: None,
                     branch: None,
+                    ..Default::default()
                 },
                 dependencies: vec![],
                 build: crate::luarocks::rockspec::RockspecBuild {
                     build_type: "builtin".to_string(),
                     modules: std::collections::HashMap::new(),
                     install: crate::luarocks::rockspec::InstallTable::default(),
+                    ..Default::default()
                 },
                 description: None,
                 homepage: None,
                 license: None,
                 lua_version: None,
                 binary_urls: std::collections::HashMap::new(),
+                ..Default::default()
             },
             source_path: None,
             error: Some(LpmError::Package("test error".to_string())),
@@ -506,18 +515,21 @@ mod tests {
                     url: "".to_string(),
                     tag: None,
                     branch: None,
+                    ..Default::default()
                 },
                 dependencies: vec![],
                 build: crate::luarocks::rockspec::RockspecBuild {
                     build_type: "builtin".to_string(),
                     modules: std::collections::HashMap::new(),
                     install: crate::luarocks::rockspec::InstallTable::default(),
+                    ..Default::default()
                 },
                 description: None,
                 homepage: None,
                 license: None,
                 lua_version: None,
                 binary_urls: std::collections::HashMap::new(),
+                ..Default::default()
             },
             source_path: Some(std::path::PathBuf::from("/tmp/test")),
             error: None,
@@ -645,18 +657,21 @@ mod tests {
                     url: "".to_string(),
                     tag: None,
                     branch: None,
+                    ..Default::default()
                 },
                 dependencies: vec![],
                 build: RockspecBuild {
                     build_type: "builtin".to_string(),
                     modules: std::collections::HashMap::new(),
                     install: crate::luarocks::rockspec::InstallTable::default(),
+                    ..Default::default()
                 },
                 description: None,
                 homepage: None,
                 license: None,
                 lua_version: None,
                 binary_urls: std::collections::HashMap::new(),
+                ..Default::default()
             },
             source_path: None,
             error: None,
@@ -1,48 +1,88 @@
 use crate::core::{LpmError, LpmResult};
+use bzip2::read::BzDecoder;
 use flate2::read::GzDecoder;
 use std::fs::{self, File};
-use std::path::{Path, PathBuf};
-use tar::Archive;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+use xz2::read::XzDecoder;
+
+/// Limits on what one archive may unpack to, so a small download can't fill the disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractLimits {
+    /// Total size of the unpacked files, in bytes
+    pub max_size: u64,
+    /// Number of files, directories and links
+    pub max_entries: usize,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 1024 * 1024 * 1024,
+            max_entries: 100_000,
+        }
+    }
+}
 
-/// Extracts package archives (tar.gz, zip) to temporary directories
+/// Archive formats, told apart by their first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    TarGz,
+    TarBz2,
+    TarXz,
+    Tar,
+    Zip,
+}
+
+/// Extracts package archives (tar, tar.gz, tar.bz2, tar.xz, zip, LuaRocks rocks) to temporary directories
+///
+/// Entries that would land outside the destination (absolute paths, `..`,
+/// links pointing out of it) are refused, and so are archives exceeding the
+/// [`ExtractLimits`].
 pub struct PackageExtractor {
     dest_dir: PathBuf,
+    limits: ExtractLimits,
 }
 
 impl PackageExtractor {
     /// Create a new PackageExtractor
     pub fn new(dest_dir: PathBuf) -> Self {
-        Self { dest_dir }
+        Self {
+            dest_dir,
+            limits: ExtractLimits::default(),
+        }
+    }
+
+    /// Refuse archives unpacking to more than `limits`
+    pub fn with_limits(mut self, limits: ExtractLimits) -> Self {
+        self.limits = limits;
+        self
     }
 
     /// Extract an archive file
     /// Returns the path to the root directory of the extracted archive
+    ///
+    /// The format is detected from the file's contents, not its name. Rocks
+    /// keep their files at the top level, so for them the root is the
+    /// extraction directory itself.
     pub fn extract(&self, archive_path: &Path) -> LpmResult<PathBuf> {
-        // Determine archive type from extension
-        let extension = archive_path
-            .extension()
-            .and_then(|e| e.to_str())
-            .ok_or_else(|| LpmError::Package("Unknown archive format".to_string()))?;
-
-        let result = match extension {
-            "gz" | "tgz" => self.extract_targz(archive_path),
-            "zip" => self.extract_zip(archive_path),
-            _ => Err(LpmError::Package(format!(
-                "Unsupported format: {}",
-                extension
-            ))),
+        let result = match detect_format(archive_path) {
+            Ok(Format::TarGz) => self.extract_targz(archive_path),
+            Ok(Format::TarBz2) => {
+                self.extract_tar(BzDecoder::new(File::open(archive_path)?), archive_path)
+            }
+            Ok(Format::TarXz) => {
+                self.extract_tar(XzDecoder::new(File::open(archive_path)?), archive_path)
+            }
+            Ok(Format::Tar) => self.extract_tar(File::open(archive_path)?, archive_path),
+            Ok(Format::Zip) => self.extract_zip(archive_path),
+            Err(e) => return Err(e),
         };
 
         // Cleanup temp directory on error
         if result.is_err() {
-            let temp_dir = self.dest_dir.join(format!(
-                ".tmp-{}",
-                archive_path
-                    .file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("unknown")
-            ));
-            let _ = fs::remove_dir_all(&temp_dir); // Ignore cleanup errors
+            let _ = fs::remove_dir_all(self.temp_dir(archive_path)); // Ignore cleanup errors
         }
 
         result
@@ -50,28 +90,81 @@ impl PackageExtractor {
 
     fn extract_targz(&self, archive_path: &Path) -> LpmResult<PathBuf> {
         let file = File::open(archive_path)?;
-        let decoder = GzDecoder::new(file);
-        let mut archive = Archive::new(decoder);
+        self.extract_tar(GzDecoder::new(file), archive_path)
+    }
 
-        let temp_dir = self.dest_dir.join(format!(
-            ".tmp-{}",
-            archive_path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("unknown")
-        ));
+    fn extract_tar<R: Read>(&self, reader: R, archive_path: &Path) -> LpmResult<PathBuf> {
+        let temp_dir = self.fresh_temp_dir(archive_path)?;
+        let mut unpacker = Unpacker::new(&temp_dir, self.limits);
+        let mut archive = Archive::new(reader);
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.into_owned();
+            let entry_type = entry.header().entry_type();
+            match entry_type {
+                EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                    let mode = entry.header().mode().ok();
+                    unpacker.file(&name, &mut entry, mode)?;
+                }
+                EntryType::Directory => unpacker.dir(&name)?,
+                EntryType::Symlink | EntryType::Link => {
+                    let target = entry.link_name()?.ok_or_else(|| {
+                        LpmError::Package(format!("Link without a target: {}", name.display()))
+                    })?;
+                    if entry_type == EntryType::Symlink {
+                        unpacker.symlink(&name, &target)?;
+                    } else {
+                        unpacker.hardlink(&name, &target)?;
+                    }
+                }
+                // Metadata (pax headers, GNU long names) and device files carry no package content
+                _ => {}
+            }
+        }
 
-        // Clean up any existing temp dir
-        if temp_dir.exists() {
-            fs::remove_dir_all(&temp_dir)?;
+        self.root_dir(archive_path, &temp_dir)
+    }
+
+    fn extract_zip(&self, archive_path: &Path) -> LpmResult<PathBuf> {
+        use zip::ZipArchive;
+
+        let file = File::open(archive_path)?;
+        let mut archive =
+            ZipArchive::new(file).map_err(|e| LpmError::Package(format!("Invalid zip: {}", e)))?;
+
+        let temp_dir = self.fresh_temp_dir(archive_path)?;
+        let mut unpacker = Unpacker::new(&temp_dir, self.limits);
+
+        for i in 0..archive.len() {
+            let mut file = archive
+                .by_index(i)
+                .map_err(|e| LpmError::Package(format!("Extract failed: {}", e)))?;
+            let name = PathBuf::from(file.name());
+            let mode = file.unix_mode();
+            if file.is_dir() {
+                unpacker.dir(&name)?;
+            } else if mode.is_some_and(|mode| mode & S_IFMT == S_IFLNK) {
+                let mut target = String::new();
+                file.read_to_string(&mut target)?;
+                unpacker.symlink(&name, Path::new(&target))?;
+            } else {
+                unpacker.file(&name, &mut file, mode)?;
+            }
         }
-        fs::create_dir_all(&temp_dir)?;
 
-        archive.unpack(&temp_dir)?;
+        self.root_dir(archive_path, &temp_dir)
+    }
+
+    /// Where the package's files are: the first directory for source archives
+    fn root_dir(&self, archive_path: &Path, temp_dir: &Path) -> LpmResult<PathBuf> {
+        if archive_path.extension().is_some_and(|e| e == "rock") {
+            return Ok(temp_dir.to_path_buf());
+        }
 
         // Find root directory (standardize: use first directory found)
-        let mut entries = fs::read_dir(&temp_dir)?;
-        let root = entries
+        let mut entries = fs::read_dir(temp_dir)?;
+        entries
             .find_map(|e| {
                 let entry = e.ok()?;
                 if entry.file_type().ok()?.is_dir() {
@@ -81,64 +174,307 @@ impl PackageExtractor {
                 }
             })
             .ok_or_else(|| {
-                // If no root dir, archive might have files at root - use temp_dir itself
                 LpmError::Package(
                     "Archive has no root directory. Files at root level not supported.".to_string(),
                 )
-            })?;
-
-        Ok(root)
+            })
     }
 
-    fn extract_zip(&self, archive_path: &Path) -> LpmResult<PathBuf> {
-        use zip::ZipArchive;
-
-        let file = File::open(archive_path)?;
-        let mut archive =
-            ZipArchive::new(file).map_err(|e| LpmError::Package(format!("Invalid zip: {}", e)))?;
-
-        let temp_dir = self.dest_dir.join(format!(
+    fn temp_dir(&self, archive_path: &Path) -> PathBuf {
+        self.dest_dir.join(format!(
             ".tmp-{}",
             archive_path
                 .file_stem()
                 .and_then(|s| s.to_str())
                 .unwrap_or("unknown")
-        ));
+        ))
+    }
 
+    /// An empty temporary directory for `archive_path`
+    fn fresh_temp_dir(&self, archive_path: &Path) -> LpmResult<PathBuf> {
+        let temp_dir = self.temp_dir(archive_path);
         // Clean up any existing temp dir
         if temp_dir.exists() {
             fs::remove_dir_all(&temp_dir)?;
         }
         fs::create_dir_all(&temp_dir)?;
+        Ok(temp_dir)
+    }
+}
 
-        archive
-            .extract(&temp_dir)
-            .map_err(|e| LpmError::Package(format!("Extract failed: {}", e)))?;
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+fn detect_format(archive_path: &Path) -> LpmResult<Format> {
+    let mut head = Vec::with_capacity(512);
+    File::open(archive_path)?.take(512).read_to_end(&mut head)?;
+
+    let format = if head.starts_with(&[0x1f, 0x8b]) {
+        Format::TarGz
+    } else if head.starts_with(b"BZh") {
+        Format::TarBz2
+    } else if head.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Format::TarXz
+    } else if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        Format::Zip
+    } else if head.get(257..262) == Some(b"ustar") {
+        Format::Tar
+    } else {
+        return Err(LpmError::Package(format!(
+            "Unsupported format: {} is not a tar, tar.gz, tar.bz2, tar.xz or zip archive",
+            archive_path.display()
+        )));
+    };
+    Ok(format)
+}
 
-        // Find root directory (standardize: use first directory found)
-        let mut entries = fs::read_dir(&temp_dir)?;
-        let root = entries
-            .find_map(|e| {
-                let entry = e.ok()?;
-                if entry.file_type().ok()?.is_dir() {
-                    Some(entry.path())
-                } else {
-                    None
-                }
-            })
-            .ok_or_else(|| {
-                LpmError::Package(
-                    "Archive has no root directory. Files at root level not supported.".to_string(),
-                )
+/// Writes archive entries below `dest`, enforcing the limits and refusing
+/// anything that would end up outside it
+struct Unpacker<'a> {
+    dest: &'a Path,
+    limits: ExtractLimits,
+    entries: usize,
+    size: u64,
+}
+
+impl<'a> Unpacker<'a> {
+    fn new(dest: &'a Path, limits: ExtractLimits) -> Self {
+        Self {
+            dest,
+            limits,
+            entries: 0,
+            size: 0,
+        }
+    }
+
+    fn file(&mut self, name: &Path, reader: &mut dyn Read, mode: Option<u32>) -> LpmResult<()> {
+        let path = self.path_for(name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Read one byte past the budget to tell "exactly at the limit" from "over it"
+        let budget = self.limits.max_size - self.size;
+        let mut out = File::create(&path)?;
+        let written = io::copy(&mut reader.take(budget + 1), &mut out)?;
+        self.add_size(written)?;
+        set_mode(&path, mode)
+    }
+
+    fn dir(&mut self, name: &Path) -> LpmResult<()> {
+        let path = self.path_for(name)?;
+        fs::create_dir_all(path)?;
+        Ok(())
+    }
+
+    fn symlink(&mut self, name: &Path, target: &Path) -> LpmResult<()> {
+        let path = self.path_for(name)?;
+        let relative_name = normalize(name).unwrap_or_default();
+        let base = relative_name.parent().unwrap_or(Path::new(""));
+        if self.resolve_link(base, target).is_none() {
+            return Err(LpmError::Package(format!(
+                "Archive entry {} links outside the package: {}",
+                name.display(),
+                target.display()
+            )));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        create_symlink(target, &path).map_err(|e| {
+            LpmError::Package(format!(
+                "Failed to create symlink {}: {}",
+                name.display(),
+                e
+            ))
+        })
+    }
+
+    /// Hard links become copies of a file extracted earlier
+    fn hardlink(&mut self, name: &Path, target: &Path) -> LpmResult<()> {
+        let path = self.path_for(name)?;
+        let outside = || {
+            LpmError::Package(format!(
+                "Archive entry {} links outside the package: {}",
+                name.display(),
+                target.display()
+            ))
+        };
+        let relative = self
+            .resolve_link(Path::new(""), target)
+            .ok_or_else(outside)?;
+        // Look every component up without following it, so a symlink
+        // extracted earlier can't hand us a file from elsewhere
+        let mut source = self.dest.to_path_buf();
+        let mut metadata = None;
+        for component in relative.components() {
+            source.push(component);
+            let found = fs::symlink_metadata(&source).map_err(|_| {
+                LpmError::Package(format!(
+                    "Archive entry {} links to {}, which is not in the archive",
+                    name.display(),
+                    target.display()
+                ))
             })?;
+            if found.file_type().is_symlink() {
+                return Err(outside());
+            }
+            metadata = Some(found);
+        }
+        let metadata = metadata.ok_or_else(outside)?;
+        if !metadata.is_file() {
+            return Err(LpmError::Package(format!(
+                "Archive entry {} links to {}, which is not a file",
+                name.display(),
+                target.display()
+            )));
+        }
+        self.add_size(metadata.len())?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&source, &path)?;
+        Ok(())
+    }
+
+    /// Where `target`, read from the directory `base` below `dest`, ends up
+    /// relative to `dest`; `None` if it leaves it
+    ///
+    /// `..` is only taken from directories that are already on disk and are
+    /// not symlinks. After a symlink, or a name the archive could still turn
+    /// into one, `..` goes somewhere other than the text of the path says.
+    fn resolve_link(&self, base: &Path, target: &Path) -> Option<PathBuf> {
+        let mut resolved = base.to_path_buf();
+        for component in target.components() {
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !self.is_real_dir(&resolved) || !resolved.pop() {
+                        return None;
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => return None,
+            }
+        }
+        Some(resolved)
+    }
+
+    /// Whether `relative` and every directory above it (up to `dest`) is a
+    /// plain directory on disk
+    fn is_real_dir(&self, relative: &Path) -> bool {
+        let mut path = self.dest.to_path_buf();
+        relative.components().all(|component| {
+            path.push(component);
+            fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir())
+        })
+    }
+
+    /// Where an entry goes, counting it against the entry limit
+    ///
+    /// Fails for names that leave the destination, and for names below a
+    /// symlink extracted earlier, which could point anywhere inside it and
+    /// be used to overwrite other entries.
+    fn path_for(&mut self, name: &Path) -> LpmResult<PathBuf> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(LpmError::Package(format!(
+                "Archive has more than {} entries",
+                self.limits.max_entries
+            )));
+        }
+
+        let relative = match normalize(name) {
+            Some(relative) if !name.has_root() && relative.components().next().is_some() => {
+                relative
+            }
+            _ => {
+                return Err(LpmError::Package(format!(
+                    "Archive entry escapes the destination: {}",
+                    name.display()
+                )))
+            }
+        };
+
+        let mut path = self.dest.to_path_buf();
+        for component in relative.components() {
+            if fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink()) {
+                return Err(LpmError::Package(format!(
+                    "Archive entry {} is inside a symlink",
+                    name.display()
+                )));
+            }
+            path.push(component);
+        }
+        if fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink()) {
+            fs::remove_file(&path)?;
+        }
+        Ok(path)
+    }
+
+    fn add_size(&mut self, size: u64) -> LpmResult<()> {
+        self.size += size;
+        if self.size > self.limits.max_size {
+            return Err(LpmError::Package(format!(
+                "Archive unpacks to more than {} bytes",
+                self.limits.max_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// `path` without `.` and with `..` applied; `None` if it climbs above its start
+/// or has a root or drive prefix
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
 
-        Ok(root)
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> LpmResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+    // Keep the executable bits, never setuid/setgid or world-writable
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o755))?;
     }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>) -> LpmResult<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks in archives are not supported on this platform",
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::luarocks::rock;
     use std::fs;
     use tempfile::TempDir;
 
@@ -461,6 +797,29 @@ mod tests {
         assert!(root.ends_with("package"));
     }
 
+    #[test]
+    fn test_extract_rock_keeps_top_level_files() {
+        let temp = TempDir::new().unwrap();
+        let stage = temp.path().join("stage");
+        fs::create_dir_all(stage.join("lua")).unwrap();
+        fs::write(stage.join("lua/acme.lua"), "return {}").unwrap();
+        let rock = rock::write_binary_rock(
+            &stage,
+            "package = \"acme\"\nversion = \"1.0-1\"\n",
+            "acme",
+            "1.0-1",
+            "all",
+            temp.path(),
+        )
+        .unwrap();
+
+        let extractor = PackageExtractor::new(temp.path().join("out"));
+        let root = extractor.extract(&rock).unwrap();
+        assert!(root.join("lua/acme.lua").exists());
+        assert!(root.join("acme-1.0-1.rockspec").exists());
+        assert!(root.join(rock::ROCK_MANIFEST).exists());
+    }
+
     #[test]
     fn test_extract_targz_cleanup_existing_temp() {
         let temp = TempDir::new().unwrap();
@@ -522,4 +881,182 @@ mod tests {
         // Old file should be gone
         assert!(!existing_temp_dir.join("old_file.txt").exists());
     }
+
+    /// Name, type, link target and contents of a tar entry
+    type RawEntry<'a> = (&'a str, EntryType, &'a str, &'a [u8]);
+
+    /// A tar with entries written byte for byte, bypassing the `tar` crate's
+    /// own path checks
+    fn raw_tar(entries: &[RawEntry]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        for (name, entry_type, link, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            tar.append(&header, *data).unwrap();
+        }
+        tar.into_inner().unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn extract_bytes(temp: &TempDir, file_name: &str, data: &[u8]) -> LpmResult<PathBuf> {
+        let archive = temp.path().join(file_name);
+        fs::write(&archive, data).unwrap();
+        PackageExtractor::new(temp.path().join("out")).extract(&archive)
+    }
+
+    #[test]
+    fn test_extract_rejects_escaping_tar_entries() {
+        let cases: &[&[RawEntry]] = &[
+            &[("pkg/../../evil.lua", EntryType::Regular, "", b"x")],
+            &[("/tmp/evil.lua", EntryType::Regular, "", b"x")],
+            &[("pkg/link", EntryType::Symlink, "../../outside", b"")],
+            &[("pkg/link", EntryType::Symlink, "/etc", b"")],
+            &[("pkg/hard", EntryType::Link, "../../etc/passwd", b"")],
+            // A link inside the package, then a write through it
+            &[
+                ("pkg/dir", EntryType::Symlink, ".", b""),
+                ("pkg/dir/evil.lua", EntryType::Regular, "", b"x"),
+            ],
+        ];
+        for entries in cases {
+            let temp = TempDir::new().unwrap();
+            let result = extract_bytes(&temp, "pkg.tar.gz", &gzip(&raw_tar(entries)));
+            assert!(result.is_err(), "{:?}", entries[0].0);
+            assert!(!temp.path().join("evil.lua").exists());
+            assert!(!temp.path().join("outside").exists());
+            assert!(!temp.path().join("out").join(".tmp-pkg.tar").exists());
+        }
+    }
+
+    #[test]
+    fn test_extract_rejects_links_through_earlier_symlinks() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("secret.txt"), "secret").unwrap();
+        // `pkg/e` reads as `pkg/` but goes four levels up through `pkg/d/l`,
+        // and `pkg/stolen` would copy a file from there
+        let tar = raw_tar(&[
+            ("pkg/d/", EntryType::Directory, "", b""),
+            ("pkg/d/l", EntryType::Symlink, "..", b""),
+            ("pkg/e", EntryType::Symlink, "d/l/d/l/../../../..", b""),
+            ("pkg/stolen", EntryType::Link, "pkg/e/secret.txt", b""),
+        ]);
+        assert!(extract_bytes(&temp, "pkg.tar.gz", &gzip(&tar)).is_err());
+        assert!(!temp.path().join("out").join(".tmp-pkg.tar").exists());
+
+        // The hard link alone, through a symlink that stays inside
+        let tar = raw_tar(&[
+            ("pkg/src/init.lua", EntryType::Regular, "", b"return {}"),
+            ("pkg/s", EntryType::Symlink, "src", b""),
+            ("pkg/copy.lua", EntryType::Link, "pkg/s/init.lua", b""),
+        ]);
+        assert!(extract_bytes(&temp, "pkg.tar.gz", &gzip(&tar)).is_err());
+
+        // A `..` after a name that is only made a symlink later
+        let tar = raw_tar(&[
+            ("pkg/e", EntryType::Symlink, "x/../..", b""),
+            ("pkg/x", EntryType::Symlink, ".", b""),
+        ]);
+        assert!(extract_bytes(&temp, "pkg.tar.gz", &gzip(&tar)).is_err());
+    }
+
+    #[test]
+    fn test_extract_keeps_links_inside_the_package() {
+        let temp = TempDir::new().unwrap();
+        let tar = raw_tar(&[
+            ("pkg/", EntryType::Directory, "", b""),
+            ("pkg/src/init.lua", EntryType::Regular, "", b"return {}"),
+            ("pkg/copy.lua", EntryType::Link, "pkg/src/init.lua", b""),
+            ("pkg/main.lua", EntryType::Symlink, "src/init.lua", b""),
+        ]);
+        let root = extract_bytes(&temp, "pkg.tar.gz", &gzip(&tar)).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("copy.lua")).unwrap(),
+            "return {}"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_to_string(root.join("main.lua")).unwrap(),
+            "return {}"
+        );
+    }
+
+    #[test]
+    fn test_extract_rejects_zip_slip() {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+        let temp = TempDir::new().unwrap();
+        let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("pkg/../../evil.lua", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"x").unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        let result = extract_bytes(&temp, "pkg.zip", &data);
+        assert!(matches!(result, Err(LpmError::Package(msg)) if msg.contains("escapes")));
+        assert!(!temp.path().join("evil.lua").exists());
+    }
+
+    #[test]
+    fn test_extract_enforces_limits() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.path().join("pkg.tar.gz");
+        let tar = raw_tar(&[
+            ("pkg/a.lua", EntryType::Regular, "", &[b'a'; 600]),
+            ("pkg/b.lua", EntryType::Regular, "", &[b'b'; 600]),
+        ]);
+        fs::write(&archive, gzip(&tar)).unwrap();
+        let out = temp.path().join("out");
+
+        let limited = |max_size, max_entries| {
+            PackageExtractor::new(out.clone()).with_limits(ExtractLimits {
+                max_size,
+                max_entries,
+            })
+        };
+        let too_big = limited(1000, 10).extract(&archive);
+        assert!(matches!(too_big, Err(LpmError::Package(msg)) if msg.contains("1000 bytes")));
+        let too_many = limited(10_000, 1).extract(&archive);
+        assert!(matches!(too_many, Err(LpmError::Package(msg)) if msg.contains("1 entries")));
+        assert!(limited(1200, 2).extract(&archive).is_ok());
+    }
+
+    #[test]
+    fn test_extract_detects_format_from_contents() {
+        use std::io::Write;
+        let tar = raw_tar(&[("pkg/init.lua", EntryType::Regular, "", b"return 1")]);
+
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz.write_all(&tar).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(&tar).unwrap();
+
+        // Names that say nothing, or the wrong thing, about the format
+        for (name, data) in [
+            ("pkg.tar.bz2", bz.finish().unwrap()),
+            ("pkg.zip", xz.finish().unwrap()),
+            ("pkg", tar.clone()),
+            ("pkg.tar.gz", gzip(&tar)),
+        ] {
+            let temp = TempDir::new().unwrap();
+            let root = extract_bytes(&temp, name, &data).unwrap();
+            assert_eq!(
+                fs::read_to_string(root.join("init.lua")).unwrap(),
+                "return 1",
+                "{name}"
+            );
+        }
+    }
 }
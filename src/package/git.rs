@@ -0,0 +1,413 @@
+use crate::cache::Cache;
+use crate::core::{LpmError, LpmResult};
+use crate::luarocks::rockspec::Rockspec;
+use crate::package::dependency::{DependencySpec, GitReference, GitSpec};
+use crate::package::manifest::PackageManifest;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Fetches git dependencies into the cache and pins them to commits
+///
+/// Each repository is kept as a bare mirror under the cache's `git/repos`
+/// directory. Installs use an archive of the exact commit, so the checked-out
+/// tree never depends on the state of the mirror's branches.
+pub struct GitFetcher {
+    cache: Cache,
+    offline: bool,
+}
+
+impl GitFetcher {
+    pub fn new(cache: Cache) -> Self {
+        Self {
+            cache,
+            offline: false,
+        }
+    }
+
+    /// Only use repositories already in the cache
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Resolve a tag, branch or revision to a full commit SHA
+    ///
+    /// Branches (and the default branch) are always fetched first so they
+    /// resolve to the latest commit; tags and revisions are fetched only if the
+    /// mirror doesn't know them yet.
+    pub fn resolve(&self, spec: &GitSpec) -> LpmResult<String> {
+        let mirror = self.cache.git_mirror_path(&spec.url);
+        let (revision, moves) = match &spec.reference {
+            GitReference::Tag(tag) => (format!("refs/tags/{}", tag), false),
+            GitReference::Branch(branch) => (format!("refs/heads/{}", branch), true),
+            GitReference::Rev(rev) => (rev.clone(), false),
+            GitReference::DefaultBranch => ("HEAD".to_string(), true),
+        };
+
+        let mut fetched = self.update_mirror(&spec.url, moves)?;
+        loop {
+            if let Some(commit) = rev_parse(&mirror, &revision) {
+                return Ok(commit);
+            }
+            if fetched || self.offline {
+                return Err(LpmError::Package(format!(
+                    "Git reference '{}' not found in {}",
+                    spec.reference, spec.url
+                )));
+            }
+            fetched = self.update_mirror(&spec.url, true)?;
+        }
+    }
+
+    /// Whether the cached mirror already contains a commit
+    pub fn has_commit(&self, url: &str, commit: &str) -> bool {
+        let mirror = self.cache.git_mirror_path(url);
+        mirror.exists() && rev_parse(&mirror, commit).as_deref() == Some(commit)
+    }
+
+    /// Archive a commit as `<name>/...` into the cache, fetching it if needed
+    pub fn archive(&self, url: &str, commit: &str, name: &str) -> LpmResult<PathBuf> {
+        let archive_path = self.cache.git_archive_path(url, commit);
+        if archive_path.exists() {
+            return Ok(archive_path);
+        }
+
+        if !self.has_commit(url, commit) {
+            self.update_mirror(url, true)?;
+            if !self.has_commit(url, commit) {
+                return Err(LpmError::Package(format!(
+                    "Commit {} not found in {}; it may have been removed by a force-push",
+                    commit, url
+                )));
+            }
+        }
+
+        let mirror = self.cache.git_mirror_path(url);
+        let tar = run_git(
+            Command::new("git")
+                .arg("--git-dir")
+                .arg(&mirror)
+                .arg("archive")
+                .arg("--format=tar")
+                .arg(format!("--prefix={}/", name))
+                .arg(commit),
+            &format!("archive {} from {}", commit, url),
+        )?;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        std::io::Write::write_all(&mut encoder, &tar)?;
+        self.cache.write(&archive_path, &encoder.finish()?)?;
+        Ok(archive_path)
+    }
+
+    /// Clone the mirror if missing; with `fetch`, also bring it up to date
+    ///
+    /// Returns whether the remote was contacted.
+    fn update_mirror(&self, url: &str, fetch: bool) -> LpmResult<bool> {
+        // package.lock isn't checked when loaded like package.yaml is
+        if url.starts_with('-') {
+            return Err(LpmError::Package(format!(
+                "git URL '{}' must not start with '-'",
+                url
+            )));
+        }
+        let mirror = self.cache.git_mirror_path(url);
+        if mirror.exists() && (!fetch || self.offline) {
+            return Ok(false);
+        }
+        if self.offline {
+            return Err(LpmError::Cache(format!(
+                "Offline mode: git repository {} is not cached (expected at {})",
+                url,
+                mirror.display()
+            )));
+        }
+
+        if mirror.exists() {
+            run_git(
+                Command::new("git")
+                    .arg("--git-dir")
+                    .arg(&mirror)
+                    .args(["fetch", "--quiet", "--prune", "--tags", "--force", "origin"])
+                    .arg("+refs/heads/*:refs/heads/*"),
+                &format!("fetch {}", url),
+            )?;
+        } else {
+            if let Some(parent) = mirror.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            run_git(
+                Command::new("git")
+                    .args(["clone", "--quiet", "--mirror", "--", url])
+                    .arg(&mirror),
+                &format!("clone {}", url),
+            )?;
+        }
+        Ok(true)
+    }
+}
+
+/// SHA-256 over the files in a package archive (paths and contents)
+///
+/// Unlike a checksum of the archive itself, this doesn't depend on how the
+/// archive was compressed or which git version produced it.
+pub fn tree_checksum(archive_path: &Path) -> LpmResult<String> {
+    let mut entries = Vec::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(std::fs::File::open(archive_path)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().to_string();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        entries.push((path, content));
+    }
+    entries.sort();
+
+    let mut hasher = Sha256::new();
+    for (path, content) in entries {
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        hasher.update((content.len() as u64).to_le_bytes());
+        hasher.update(&content);
+    }
+    Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+}
+
+/// The build description for a package checked out from git
+///
+/// Uses the rockspec at the top of the repository (preferring one named after
+/// the package), else the dependencies from its package.yaml. Without either,
+/// every file is installed as is.
+pub fn package_rockspec(archive_path: &Path, name: &str) -> LpmResult<Rockspec> {
+    let mut rockspecs = Vec::new();
+    let mut package_yaml = None;
+
+    let mut archive = tar::Archive::new(GzDecoder::new(std::fs::File::open(archive_path)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        // Only files directly under the `<name>/` prefix
+        if path.components().count() != 2 {
+            continue;
+        }
+        let file_name = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if file_name.ends_with(".rockspec") {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            rockspecs.push((file_name, content));
+        } else if file_name == "package.yaml" {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            package_yaml = Some(content);
+        }
+    }
+
+    rockspecs.sort_by_key(|(file_name, _)| !file_name.starts_with(&format!("{}-", name)));
+    if let Some((_, content)) = rockspecs.first() {
+        return Rockspec::parse_lua(content);
+    }
+
+    let mut rockspec = Rockspec {
+        package: name.to_string(),
+        ..Default::default()
+    };
+    rockspec.build.build_type = "builtin".to_string();
+    if let Some(content) = package_yaml {
+        let manifest: PackageManifest = serde_yaml::from_str(&content).map_err(|e| {
+            LpmError::Package(format!("Failed to parse package.yaml of '{}': {}", name, e))
+        })?;
+        rockspec.version = manifest.version.clone();
+        for (dep, spec) in &manifest.dependencies {
+            let constraint = DependencySpec::parse(spec);
+            let Some(constraint) = constraint.as_registry() else {
+                return Err(LpmError::Package(format!(
+                    "'{}' depends on '{}' from {}; only registry dependencies are supported \
+                     inside git dependencies",
+                    name, dep, spec
+                )));
+            };
+            rockspec
+                .dependencies
+                .push(format!("{} {}", dep, constraint));
+        }
+    }
+    Ok(rockspec)
+}
+
+fn rev_parse(mirror: &Path, revision: &str) -> Option<String> {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(mirror)
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{}^{{commit}}", revision))
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Run git non-interactively, returning stdout or an error with git's stderr
+fn run_git(command: &mut Command, action: &str) -> LpmResult<Vec<u8>> {
+    let output = command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|e| LpmError::Package(format!("Failed to run git (is it installed?): {}", e)))?;
+    if !output.status.success() {
+        return Err(LpmError::Package(format!(
+            "git failed to {}:\n{}",
+            action,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// A bare repository with a tagged first commit and a second commit on main
+    fn bare_repo(temp: &TempDir) -> (String, String, String) {
+        let work = temp.path().join("work");
+        std::fs::create_dir_all(&work).unwrap();
+        git(&work, &["init", "--quiet", "--initial-branch=main"]);
+        std::fs::write(work.join("mylib.lua"), "return 1\n").unwrap();
+        std::fs::write(
+            work.join("package.yaml"),
+            "name: mylib\nversion: 1.0.0\ndependencies:\n  penlight: \"^1.13.0\"\n",
+        )
+        .unwrap();
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "--quiet", "-m", "first"]);
+        git(&work, &["tag", "v1.0"]);
+        let first = git(&work, &["rev-parse", "HEAD"]);
+        std::fs::write(work.join("mylib.lua"), "return 2\n").unwrap();
+        git(&work, &["commit", "--quiet", "-am", "second"]);
+        let second = git(&work, &["rev-parse", "HEAD"]);
+
+        let bare = temp.path().join("mylib.git");
+        git(
+            temp.path(),
+            &["clone", "--quiet", "--bare", "work", bare.to_str().unwrap()],
+        );
+        (bare.to_string_lossy().to_string(), first, second)
+    }
+
+    fn spec(url: &str, reference: GitReference) -> GitSpec {
+        GitSpec {
+            url: url.to_string(),
+            reference,
+        }
+    }
+
+    #[test]
+    fn test_resolve_references() {
+        let temp = TempDir::new().unwrap();
+        let (url, first, second) = bare_repo(&temp);
+        let fetcher = GitFetcher::new(Cache::new(temp.path().join("cache")).unwrap());
+
+        let tag = fetcher
+            .resolve(&spec(&url, GitReference::Tag("v1.0".to_string())))
+            .unwrap();
+        assert_eq!(tag, first);
+        let branch = fetcher
+            .resolve(&spec(&url, GitReference::Branch("main".to_string())))
+            .unwrap();
+        assert_eq!(branch, second);
+        let head = fetcher
+            .resolve(&spec(&url, GitReference::DefaultBranch))
+            .unwrap();
+        assert_eq!(head, second);
+        let rev = fetcher
+            .resolve(&spec(&url, GitReference::Rev(first[..8].to_string())))
+            .unwrap();
+        assert_eq!(rev, first);
+
+        let err = fetcher
+            .resolve(&spec(&url, GitReference::Tag("v9".to_string())))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'tag=v9' not found"), "{err}");
+    }
+
+    #[test]
+    fn test_archive_pins_commit() {
+        let temp = TempDir::new().unwrap();
+        let (url, first, _) = bare_repo(&temp);
+        let fetcher = GitFetcher::new(Cache::new(temp.path().join("cache")).unwrap());
+
+        let archive = fetcher.archive(&url, &first, "mylib").unwrap();
+        let checksum = tree_checksum(&archive).unwrap();
+        assert!(checksum.starts_with("sha256:"));
+
+        // Re-archiving from a fresh cache gives the same tree checksum
+        let other = GitFetcher::new(Cache::new(temp.path().join("cache2")).unwrap());
+        let again = other.archive(&url, &first, "mylib").unwrap();
+        assert_eq!(tree_checksum(&again).unwrap(), checksum);
+
+        let rockspec = package_rockspec(&archive, "mylib").unwrap();
+        assert_eq!(rockspec.version, "1.0.0");
+        assert_eq!(rockspec.dependencies, vec!["penlight ^1.13.0".to_string()]);
+        assert_eq!(rockspec.build.build_type, "builtin");
+    }
+
+    #[test]
+    fn test_offline_needs_cached_mirror() {
+        let temp = TempDir::new().unwrap();
+        let (url, first, _) = bare_repo(&temp);
+        let cache = Cache::new(temp.path().join("cache")).unwrap();
+
+        let offline = GitFetcher::new(cache.clone()).with_offline(true);
+        let err = offline
+            .resolve(&spec(&url, GitReference::DefaultBranch))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Offline mode"), "{err}");
+
+        GitFetcher::new(cache)
+            .resolve(&spec(&url, GitReference::DefaultBranch))
+            .unwrap();
+        assert!(offline.has_commit(&url, &first));
+        assert!(offline.archive(&url, &first, "mylib").is_ok());
+    }
+
+    #[test]
+    fn test_url_is_not_passed_as_an_option() {
+        let temp = TempDir::new().unwrap();
+        let marker = temp.path().join("pwned");
+        let url = format!("--upload-pack=touch {}", marker.display());
+        let fetcher = GitFetcher::new(Cache::new(temp.path().join("cache")).unwrap());
+
+        let err = fetcher
+            .resolve(&spec(&url, GitReference::DefaultBranch))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("must not start with '-'"), "{err}");
+        assert!(!marker.exists());
+    }
+}
@@ -1,14 +1,31 @@
+use crate::build::builtin::{
+    find_lua_headers, native_extension, substitute_variables, NativeModuleBuilder,
+};
+use crate::build::external_deps::ExternalDependencyResolver;
 use crate::cache::Cache;
 use crate::config::Config;
-use crate::core::path::{ensure_dir, lpm_metadata_dir, lua_modules_dir, packages_metadata_dir};
+use crate::core::path::{
+    ensure_dir, lpm_metadata_dir, lua_modules_dir, packages_metadata_dir, DEFAULT_LUA_VERSION,
+};
 use crate::core::{LpmError, LpmResult};
+use crate::lua_version::detector::LuaVersionDetector;
 use crate::luarocks::client::LuaRocksClient;
-use crate::luarocks::rockspec::Rockspec;
-use crate::luarocks::search_api::SearchAPI;
-use crate::package::extractor::PackageExtractor;
-use crate::package::lockfile::Lockfile;
+use crate::luarocks::manifest::Manifest;
+use crate::luarocks::rock;
+use crate::luarocks::rockspec::{BuildModule, Rockspec};
+use crate::package::extractor::{ExtractLimits, PackageExtractor};
+use crate::package::git::{package_rockspec, tree_checksum, GitFetcher};
+use crate::package::layout::{autodetect_modules, TreeLayout};
+use crate::package::local;
+use crate::package::lockfile::{LockedPackage, Lockfile};
+use crate::package::ownership::{self, InstalledFiles};
+use crate::package::rollback;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tokio::sync::OnceCell;
 use walkdir::WalkDir;
 
 /// Install a package to lua_modules/
@@ -17,9 +34,52 @@ pub struct PackageInstaller {
     lua_modules: PathBuf,
     metadata_dir: PathBuf,
     packages_dir: PathBuf,
-    search_api: SearchAPI,
     client: LuaRocksClient,
     extractor: PackageExtractor,
+    external_deps_dirs: Vec<PathBuf>,
+    offline: bool,
+    /// Install prebuilt binary rocks when the registry has one that runs here
+    binary_rocks: bool,
+    /// The registries' manifest, fetched on first use to look up rocks
+    manifest: OnceCell<Option<Manifest>>,
+    /// `major.minor` of the Lua that binary rocks must be built for, detected on first use
+    lua_version: OnceLock<Option<String>>,
+    /// Held while a package's files are moved into lua_modules
+    commit_lock: Mutex<()>,
+    /// Set by [`PackageInstaller::cancel`]: nothing more is moved into lua_modules
+    cancelled: AtomicBool,
+}
+
+/// A package whose rockspec and verified source archive are in the cache
+#[derive(Debug, Clone)]
+pub struct FetchedPackage {
+    pub name: String,
+    pub version: String,
+    pub rockspec: Rockspec,
+    pub source_path: PathBuf,
+    pub checksum: String,
+    /// A local `path:` package: `source_path` is its directory, which is linked, not built
+    pub linked: bool,
+    /// A binary rock: `source_path` is the verified rock, whose files are installed as they are
+    pub binary: bool,
+}
+
+impl FetchedPackage {
+    /// Whether installing compiles native code, which may link against dependencies
+    pub fn is_native(&self) -> bool {
+        if self.binary {
+            return false;
+        }
+        let build = &self.rockspec.build;
+        match build.build_type.as_str() {
+            "none" => false,
+            "builtin" => {
+                !build.native_modules.is_empty()
+                    || build.modules.values().any(|source| source.ends_with(".c"))
+            }
+            _ => true,
+        }
+    }
 }
 
 impl PackageInstaller {
@@ -31,20 +91,59 @@ impl PackageInstaller {
         let config = Config::load()?;
         let cache = Cache::new(config.get_cache_dir()?)?;
         let client = LuaRocksClient::new(&config, cache);
-        let search_api = SearchAPI::new();
-        let extractor = PackageExtractor::new(lua_modules.clone());
+        let extractor = PackageExtractor::new(lua_modules.clone()).with_limits(ExtractLimits {
+            max_size: config.max_unpacked_size,
+            max_entries: config.max_archive_entries,
+        });
+        let external_deps_dirs = config
+            .external_deps_dirs
+            .iter()
+            .flatten()
+            .map(PathBuf::from)
+            .collect();
 
         Ok(Self {
             project_root: project_root.to_path_buf(),
             lua_modules,
             metadata_dir,
             packages_dir,
-            search_api,
             client,
             extractor,
+            external_deps_dirs,
+            offline: false,
+            binary_rocks: config.binary_rocks,
+            manifest: OnceCell::new(),
+            lua_version: OnceLock::new(),
+            commit_lock: Mutex::new(()),
+            cancelled: AtomicBool::new(false),
         })
     }
 
+    /// Whether to install binary rocks instead of building from source when possible
+    pub fn with_binary_rocks(mut self, binary_rocks: bool) -> Self {
+        self.binary_rocks = binary_rocks;
+        self
+    }
+
+    /// Pick binary rocks for this Lua version (e.g. "5.4") instead of the detected one
+    pub fn with_lua_version(mut self, lua_version: &str) -> Self {
+        self.lua_version = OnceLock::from(Some(lua_version.to_string()));
+        self
+    }
+
+    /// Install only from cached rockspecs and sources, without network access
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.client = self.client.with_offline(offline);
+        self.offline = offline;
+        self
+    }
+
+    /// Use a specific LuaRocks client (and its cache)
+    pub fn with_client(mut self, client: LuaRocksClient) -> Self {
+        self.client = client.with_offline(self.offline);
+        self
+    }
+
     /// Initialize the directory structure
     pub fn init(&self) -> LpmResult<()> {
         ensure_dir(&self.lua_modules)?;
@@ -55,85 +154,598 @@ impl PackageInstaller {
 
     /// Install a package
     pub async fn install_package(&self, name: &str, version: &str) -> LpmResult<PathBuf> {
+        // Verify against package.lock if the package is locked (ensures reproducible installs)
+        let lockfile = Lockfile::load(&self.project_root)?;
+        let locked = lockfile
+            .as_ref()
+            .and_then(|lockfile| lockfile.get_package(name));
+        let locked_checksum = locked.map(|locked| locked.checksum.as_str());
+
+        let rockspec_url = match locked.and_then(|locked| locked.rockspec_url.clone()) {
+            Some(url) if locked.is_some_and(|locked| locked.version == version) => url,
+            _ => self.client.find_rockspec_url(name, version).await?,
+        };
+        self.install(name, version, &rockspec_url, None, locked_checksum)
+            .await
+    }
+
+    async fn install(
+        &self,
+        name: &str,
+        version: &str,
+        rockspec_url: &str,
+        source_url: Option<&str>,
+        expected_checksum: Option<&str>,
+    ) -> LpmResult<PathBuf> {
         println!("Installing {}@{}", name, version);
 
-        // Step 1: Construct and verify rockspec URL
         println!("  Fetching package info...");
-        let rockspec_url = self.search_api.get_rockspec_url(name, version, None);
-        self.search_api.verify_rockspec_url(&rockspec_url).await?;
+        let fetched = self
+            .fetch(name, version, rockspec_url, source_url, expected_checksum)
+            .await?;
+        if expected_checksum.is_some() {
+            println!("  ✓ Checksum verified");
+        }
+
+        println!("  Installing...");
+        let path = self.install_fetched(&fetched)?;
+
+        println!("  ✓ Installed {} (checksum: {})", name, fetched.checksum);
+
+        Ok(path)
+    }
+
+    /// Download the locked rockspec and source, verifying the locked checksum
+    pub async fn fetch_locked(
+        &self,
+        name: &str,
+        locked: &LockedPackage,
+    ) -> LpmResult<FetchedPackage> {
+        match locked.source.as_str() {
+            "git" => return self.fetch_git(name, locked),
+            "path" => return self.fetch_path(name, locked),
+            _ => {}
+        }
+
+        let rockspec_url = match &locked.rockspec_url {
+            Some(url) => url.clone(),
+            None => {
+                let registries = self.client.registries();
+                registries
+                    .get(&locked.source)
+                    .unwrap_or(registries.primary())
+                    .rockspec_url(name, &locked.version)
+            }
+        };
+        self.fetch(
+            name,
+            &locked.version,
+            &rockspec_url,
+            locked.source_url.as_deref(),
+            Some(&locked.checksum),
+        )
+        .await
+    }
+
+    async fn fetch(
+        &self,
+        name: &str,
+        version: &str,
+        rockspec_url: &str,
+        source_url: Option<&str>,
+        expected_checksum: Option<&str>,
+    ) -> LpmResult<FetchedPackage> {
+        // Step 1: Verify rockspec URL
+        if !self.offline {
+            self.client.verify_rockspec_url(rockspec_url).await?;
+        }
 
         // Step 2: Download and parse rockspec to get build configuration
-        println!("  Downloading rockspec...");
-        let rockspec_content = self.client.download_rockspec(&rockspec_url).await?;
+        let rockspec_content = self.client.download_rockspec(rockspec_url).await?;
         let rockspec = self.client.parse_rockspec(&rockspec_content)?;
 
-        // Step 3: Download source archive
-        println!("  Downloading source...");
-        let source_path = self.client.download_source(&rockspec.source.url).await?;
-
-        // Step 4: Verify checksum if lockfile exists (ensures reproducible installs)
-        if let Some(lockfile) = Lockfile::load(&self.project_root)? {
-            if let Some(locked_pkg) = lockfile.get_package(name) {
-                println!("  Verifying checksum...");
-                let actual = Cache::checksum(&source_path)?;
-                if actual != locked_pkg.checksum {
-                    return Err(LpmError::Package(format!(
-                        "Checksum mismatch for {}@{}. Expected {}, got {}",
-                        name, version, locked_pkg.checksum, actual
-                    )));
+        // Step 3: Prefer a binary rock, which needs no build
+        if let Some(fetched) = self
+            .fetch_binary_rock(name, version, rockspec_url, &rockspec_content, &rockspec)
+            .await?
+        {
+            return Ok(fetched);
+        }
+
+        // Step 4: Download source archive (cached artifacts are reused), out of
+        // the source rock if the archive itself can't be downloaded
+        let source_url = source_url.unwrap_or(&rockspec.source.url);
+        let source_path = match self.client.download_source(source_url).await {
+            Ok(path) => path,
+            Err(e) => match self
+                .rock_url(name, version, rockspec_url, rock::SOURCE)
+                .await
+            {
+                Some(rock_url) => {
+                    println!("  Source unavailable ({}), using {}", e, rock_url);
+                    self.source_from_rock(&rock_url, source_url).await?
                 }
-                println!("  ✓ Checksum verified");
+                None => return Err(e),
+            },
+        };
+
+        // Step 5: Verify checksum before anything from the archive is used
+        let checksum = Cache::checksum(&source_path)?;
+        if let Some(expected) = expected_checksum {
+            if checksum != expected {
+                return Err(LpmError::Package(format!(
+                    "Checksum mismatch for {}@{}. Expected {}, got {}",
+                    name, version, expected, checksum
+                )));
             }
         }
+        // The checksum package.lock pins, for `lpm cache verify`
+        self.client
+            .cache()
+            .record_source(source_url, name, version, &checksum)?;
+
+        Ok(FetchedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            rockspec,
+            source_path,
+            checksum,
+            linked: false,
+            binary: false,
+        })
+    }
 
-        // Step 5: Extract source archive to temporary directory
-        println!("  Extracting...");
-        let extracted_path = self.extractor.extract(&source_path)?;
+    /// Download the binary rock for a package version, if the registry has one
+    /// that runs here
+    ///
+    /// A rock built for this platform is used if the rockspec accepts the Lua
+    /// version in use, a pure-Lua (`all`) rock anywhere. The rock must match
+    /// its `rock_manifest` and carry the registry's rockspec.
+    async fn fetch_binary_rock(
+        &self,
+        name: &str,
+        version: &str,
+        rockspec_url: &str,
+        rockspec_content: &str,
+        rockspec: &Rockspec,
+    ) -> LpmResult<Option<FetchedPackage>> {
+        if !self.binary_rocks {
+            return Ok(None);
+        }
+        let platform = rock::platform().filter(|_| {
+            self.lua_version()
+                .is_some_and(|lua| rockspec.supports_lua(lua))
+        });
+        let mut rock_url = None;
+        for arch in platform.into_iter().chain([rock::ALL]) {
+            rock_url = self.rock_url(name, version, rockspec_url, arch).await;
+            if rock_url.is_some() {
+                break;
+            }
+        }
+        let Some(rock_url) = rock_url else {
+            return Ok(None);
+        };
 
-        // Step 6: Build and install based on rockspec build type
-        println!("  Installing...");
-        self.install_from_source(&extracted_path, name, &rockspec)?;
+        let rock_path = match self.client.download_source(&rock_url).await {
+            Ok(path) => path,
+            Err(e) => {
+                println!("  Binary rock unavailable ({}), building from source", e);
+                return Ok(None);
+            }
+        };
+        rock::verify(&rock_path)?;
+        if rock::read_rockspec(&rock_path, name, version)? != rockspec_content {
+            return Err(LpmError::Package(format!(
+                "{} does not contain the registry's rockspec for {}@{}",
+                rock_url, name, version
+            )));
+        }
 
-        // Step 7: Calculate checksum for lockfile generation
-        let checksum = Cache::checksum(&source_path)?;
+        Ok(Some(FetchedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            rockspec: rockspec.clone(),
+            checksum: Cache::checksum(&rock_path)?,
+            source_path: rock_path,
+            linked: false,
+            binary: true,
+        }))
+    }
+
+    /// The URL of a package version's `arch` rock, if the registry lists one;
+    /// rocks are next to their rockspec
+    async fn rock_url(
+        &self,
+        name: &str,
+        version: &str,
+        rockspec_url: &str,
+        arch: &str,
+    ) -> Option<String> {
+        let manifest = self
+            .manifest
+            .get_or_init(|| async { self.client.fetch_manifest().await.ok() })
+            .await
+            .as_ref()?;
+        if !manifest.rocks_of(name, version).iter().any(|a| a == arch) {
+            return None;
+        }
+        let base = rockspec_url.strip_suffix(".rockspec")?;
+        Some(format!("{}.{}.rock", base, arch))
+    }
+
+    /// Take the source archive out of a source rock, caching it as if
+    /// downloaded from `source_url`
+    async fn source_from_rock(&self, rock_url: &str, source_url: &str) -> LpmResult<PathBuf> {
+        let rock_path = self.client.download_source(rock_url).await?;
+        let archive_name = source_url
+            .split(['?', '#'])
+            .next()
+            .and_then(|url| url.rsplit('/').next())
+            .unwrap_or_default();
+        let archive = rock::read_file(&rock_path, archive_name)?;
+        let cache = self.client.cache();
+        let source_path = cache.source_path(source_url);
+        cache.write(&source_path, &archive)?;
+        Ok(source_path)
+    }
+
+    /// The `major.minor` of the Lua in use, if there is one
+    fn lua_version(&self) -> Option<&str> {
+        self.lua_version
+            .get_or_init(|| {
+                LuaVersionDetector::detect()
+                    .ok()
+                    .map(|version| version.major_minor())
+            })
+            .as_deref()
+    }
+
+    /// Archive the locked commit of a git package and verify its tree checksum
+    fn fetch_git(&self, name: &str, locked: &LockedPackage) -> LpmResult<FetchedPackage> {
+        let (Some(url), Some(commit)) = (&locked.source_url, &locked.commit) else {
+            return Err(LpmError::Package(format!(
+                "package.lock entry for git package '{}' needs source_url and commit",
+                name
+            )));
+        };
 
-        println!("  ✓ Installed {} (checksum: {})", name, checksum);
+        let fetcher = GitFetcher::new(self.client.cache().clone()).with_offline(self.offline);
+        let source_path = fetcher.archive(url, commit, name)?;
+        let checksum = tree_checksum(&source_path)?;
+        if checksum != locked.checksum {
+            return Err(LpmError::Package(format!(
+                "Checksum mismatch for {} at commit {}. Expected {}, got {}",
+                name, commit, locked.checksum, checksum
+            )));
+        }
+
+        Ok(FetchedPackage {
+            name: name.to_string(),
+            version: locked.version.clone(),
+            rockspec: package_rockspec(&source_path, name)?,
+            source_path,
+            checksum,
+            linked: false,
+            binary: false,
+        })
+    }
+
+    /// Check that a local package still matches its lockfile entry
+    fn fetch_path(&self, name: &str, locked: &LockedPackage) -> LpmResult<FetchedPackage> {
+        let Some(path) = &locked.source_url else {
+            return Err(LpmError::Package(format!(
+                "package.lock entry for local package '{}' needs source_url",
+                name
+            )));
+        };
+
+        let source_path = self.project_root.join(path);
+        let checksum = local::manifest_checksum(&source_path)?;
+        if checksum != locked.checksum {
+            return Err(LpmError::Package(format!(
+                "package.lock is out of date for local package '{}' ({} changed). \
+                 Run `lpm install` to update it.",
+                name,
+                source_path.join("package.yaml").display()
+            )));
+        }
+
+        let mut rockspec = Rockspec {
+            package: name.to_string(),
+            version: locked.version.clone(),
+            ..Default::default()
+        };
+        rockspec.build.build_type = "none".to_string();
+
+        Ok(FetchedPackage {
+            name: name.to_string(),
+            version: locked.version.clone(),
+            rockspec,
+            source_path,
+            checksum,
+            linked: true,
+            binary: false,
+        })
+    }
+
+    /// Extract, build and install a downloaded package into lua_modules/
+    ///
+    /// The package is built into a staging tree of its own, so different
+    /// packages can be built concurrently. Its files are then moved into
+    /// lua_modules, unless another package already installed one of them.
+    pub fn install_fetched(&self, fetched: &FetchedPackage) -> LpmResult<PathBuf> {
+        self.check_cancelled()?;
+        let dest = self.lua_modules.join(&fetched.name);
+        if fetched.linked {
+            let _commit = self.commit_lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.check_cancelled()?;
+            rollback::touch(&self.project_root, &fetched.name, None)?;
+            local::link(
+                &local::source_root(&fetched.source_path, &fetched.name),
+                &dest,
+            )?;
+            InstalledFiles::linked(&fetched.name, &fetched.version)
+                .save(&self.packages_dir, &fetched.name)?;
+            return Ok(dest);
+        }
+
+        let staging = self.metadata_dir.join("staging").join(&fetched.name);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+
+        let result = self
+            .build_into(fetched, &staging)
+            .and_then(|()| self.commit_staged(&fetched.name, &fetched.version, &staging));
+        let _ = fs::remove_dir_all(&staging); // Ignore cleanup errors
+        result?;
+
+        Ok(dest)
+    }
+
+    /// Stop moving packages into lua_modules, once a commit in progress is done
+    ///
+    /// For an install that is abandoned (e.g. on Ctrl-C) while builds still
+    /// run on other threads: after this returns, lua_modules can be rolled
+    /// back without a build committing into it afterwards.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        drop(self.commit_lock.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    fn check_cancelled(&self) -> LpmResult<()> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(LpmError::Package("Interrupted".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Build a fetched package into the lua_modules-shaped tree `lua_modules`
+    fn build_into(&self, fetched: &FetchedPackage, lua_modules: &Path) -> LpmResult<()> {
+        if fetched.binary {
+            return self.install_binary_rock(fetched, lua_modules);
+        }
+
+        // Step 6: Extract source archive to temporary directory
+        let extracted_path = self.extractor.extract(&fetched.source_path)?;
+
+        // Step 7: Build and install based on rockspec build type
+        self.install_from_source(
+            &extracted_path,
+            &fetched.name,
+            &fetched.rockspec,
+            lua_modules,
+        )
+    }
+
+    /// Move a package's staged files into lua_modules and record them
+    ///
+    /// Fails without touching lua_modules if another package owns any of the
+    /// files. The package's files from an earlier install are replaced: the new
+    /// files are moved in first, setting aside what they replace, and the old
+    /// files are only removed once the new ones and their record are in place.
+    /// If anything fails before that, the earlier install is put back.
+    fn commit_staged(&self, name: &str, version: &str, staging: &Path) -> LpmResult<()> {
+        let files = InstalledFiles::scan(staging, version)?;
+
+        // One package at a time, so two can't claim the same file
+        let _commit = self.commit_lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.check_cancelled()?;
+        let collisions = ownership::collisions(&self.packages_dir, name, &files)?;
+        if !collisions.is_empty() {
+            let listed: Vec<String> = collisions
+                .iter()
+                .map(|(path, owner)| format!("  {} (installed by {})", path, owner))
+                .collect();
+            return Err(LpmError::Package(format!(
+                "{} would overwrite files of other packages:\n{}",
+                name,
+                listed.join("\n")
+            )));
+        }
+        rollback::touch(&self.project_root, name, Some(&files))?;
+
+        let previous = InstalledFiles::load(&self.packages_dir, name)?;
+        let backup = self.metadata_dir.join("replaced").join(name);
+        if backup.exists() {
+            fs::remove_dir_all(&backup)?;
+        }
+        let package_dir = self.lua_modules.join(name);
+        let had_package_dir = fs::symlink_metadata(&package_dir).is_ok();
+        let mut swap = Swap::new(&self.lua_modules, &backup);
+
+        let result = (|| {
+            // A local package's link, or the directory of a package installed
+            // before files were recorded, must not have files moved into it
+            let in_the_way = match &previous {
+                Some(previous) => previous.link.is_some(),
+                None => had_package_dir,
+            };
+            if in_the_way {
+                swap.set_aside(name)?;
+            }
+            for path in files.files.keys() {
+                swap.move_in(staging, path)?;
+            }
+            // `lpm list` and friends look for the package's directory
+            fs::create_dir_all(&package_dir)?;
+            files.save(&self.packages_dir, name)
+        })();
+        if let Err(e) = result {
+            swap.undo();
+            if !had_package_dir {
+                let _ = fs::remove_dir(&package_dir);
+            }
+            match &previous {
+                Some(previous) => previous.save(&self.packages_dir, name)?,
+                None => {
+                    let _ = fs::remove_file(self.packages_dir.join(name).join(ownership::FILES));
+                }
+            }
+            let _ = fs::remove_dir_all(&backup);
+            return Err(e);
+        }
+
+        // The new version is in place: drop the old files it no longer ships
+        if let Some(previous) = previous {
+            let stale = InstalledFiles {
+                files: previous
+                    .files
+                    .into_iter()
+                    .filter(|(path, _)| !files.files.contains_key(path))
+                    .collect(),
+                ..InstalledFiles::default()
+            };
+            ownership::remove(&self.lua_modules, &stale)?;
+        }
+        let _ = fs::remove_dir_all(&backup);
+        Ok(())
+    }
+
+    /// The layout of the lua_modules this installer installs into
+    pub fn layout(&self) -> TreeLayout {
+        self.tree(&self.lua_modules)
+    }
+
+    /// The LuaRocks-style layout of `lua_modules` for the Lua in use
+    fn tree(&self, lua_modules: &Path) -> TreeLayout {
+        TreeLayout::new(
+            lua_modules,
+            self.lua_version().unwrap_or(DEFAULT_LUA_VERSION),
+        )
+    }
+
+    /// Install the files of a binary rock: `lua/` and `lib/` where
+    /// `package.path` and `package.cpath` find them, the rest (`bin/`, `conf/`,
+    /// `doc/` and the rockspec) in the package's own directory
+    fn install_binary_rock(&self, fetched: &FetchedPackage, lua_modules: &Path) -> LpmResult<()> {
+        let unpacked = self.extractor.extract(&fetched.source_path)?;
+        let tree = self.tree(lua_modules);
+        let dest = tree.package_dir(&fetched.name);
+        fs::create_dir_all(&dest)?;
+
+        for entry in fs::read_dir(&unpacked)? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap_or_default();
+            match file_name.to_str() {
+                Some("lua") => copy_dir_recursive(&path, &tree.lua_dir())?,
+                Some("lib") => {
+                    for entry in WalkDir::new(&path) {
+                        let entry = entry?;
+                        if !entry.file_type().is_file() {
+                            continue;
+                        }
+                        let relative = entry
+                            .path()
+                            .strip_prefix(&path)
+                            .map_err(|e| LpmError::Path(e.to_string()))?;
+                        // LuaRocks names modules `.so` on macOS too, where the cpath wants `.dylib`
+                        let mut dst = tree.lib_dir().join(relative);
+                        if dst.extension().is_some_and(|e| e == "so") {
+                            dst.set_extension(native_extension());
+                        }
+                        if let Some(parent) = dst.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::copy(entry.path(), &dst)?;
+                    }
+                }
+                Some(rock::ROCK_MANIFEST) => {}
+                _ if path.is_dir() => copy_dir_recursive(&path, &dest.join(file_name))?,
+                _ => {
+                    fs::copy(&path, dest.join(file_name))?;
+                }
+            }
+        }
 
-        Ok(self.lua_modules.join(name))
+        fs::remove_dir_all(&unpacked)?;
+        Ok(())
     }
 
+    /// Build the package and install it into `lua_modules` (the project's, or a staging tree)
     fn install_from_source(
         &self,
         source_path: &Path,
         package_name: &str,
         rockspec: &Rockspec,
+        lua_modules: &Path,
     ) -> LpmResult<()> {
+        let tree = self.tree(lua_modules);
+        fs::create_dir_all(tree.package_dir(package_name))?;
+
+        // Fail before building if a required system library is missing
+        let variables = self.build_variables(rockspec, &tree, package_name)?;
+
         match rockspec.build.build_type.as_str() {
             "none" | "builtin" => {
-                // Pure Lua modules: copy files directly without building.
-                self.install_builtin(source_path, package_name, rockspec)
+                // Pure Lua modules are copied; C modules are compiled.
+                self.install_builtin(source_path, package_name, rockspec, &variables, &tree)
             },
             "make" => {
                 // Build using Makefile.
-                self.build_with_make(source_path, package_name, rockspec)
+                self.build_with_make(source_path, package_name, rockspec, &variables, &tree)
             },
             "cmake" => {
                 // Build using CMake.
-                self.build_with_cmake(source_path, package_name, rockspec)
+                self.build_with_cmake(source_path, package_name, rockspec, &variables, &tree)
             },
             "command" => {
                 // Build using custom command specified in rockspec.
-                self.build_with_command(source_path, package_name, rockspec)
+                self.build_with_command(source_path, package_name, rockspec, &variables, &tree)
             },
             "rust" | "rust-mlua" => {
                 // Rust extensions using mlua: build with cargo.
-                self.build_with_rust(source_path, package_name, rockspec)
+                self.build_with_rust(source_path, package_name, rockspec, &tree)
             },
             _ => Err(LpmError::NotImplemented(format!(
                 "Build type '{}' not supported. Supported types: builtin, none, make, cmake, command, rust.",
                 rockspec.build.build_type
             ))),
+        }?;
+
+        tree.copy_directories(source_path, package_name, &rockspec.build.copy_directories)
+    }
+
+    /// Variables for the build: the tree's install directories, probed
+    /// `external_dependencies`, then `build.variables`
+    fn build_variables(
+        &self,
+        rockspec: &Rockspec,
+        tree: &TreeLayout,
+        package_name: &str,
+    ) -> LpmResult<HashMap<String, String>> {
+        let mut variables = tree.variables(package_name);
+        variables.extend(
+            ExternalDependencyResolver::system(&self.external_deps_dirs)
+                .resolve(&rockspec.external_dependencies)?,
+        );
+        for (name, value) in &rockspec.build.variables {
+            let value = substitute_variables(value, &variables);
+            variables.insert(name.clone(), value);
         }
+        Ok(variables)
     }
 
     fn build_with_make(
@@ -141,6 +753,8 @@ impl PackageInstaller {
         source_path: &Path,
         package_name: &str,
         rockspec: &Rockspec,
+        variables: &HashMap<String, String>,
+        tree: &TreeLayout,
     ) -> LpmResult<()> {
         use std::process::Command;
 
@@ -148,6 +762,7 @@ impl PackageInstaller {
 
         let mut make_cmd = Command::new("make");
         make_cmd.current_dir(source_path);
+        make_cmd.args(make_variables(variables, &rockspec.build.build_variables));
 
         let status = make_cmd
             .status()
@@ -158,13 +773,14 @@ impl PackageInstaller {
         }
 
         // Install using make install (if install target exists) or copy built files
-        let dest = self.lua_modules.join(package_name);
-        fs::create_dir_all(&dest)?;
+        let dest = tree.package_dir(package_name);
 
-        // Attempt make install first, fall back to manual file copying if needed
+        // Attempt make install first, fall back to manual file copying if needed.
+        // LUADIR and LIBDIR point make install at the tree's module directories.
         let mut install_cmd = Command::new("make");
         install_cmd.arg("install");
         install_cmd.current_dir(source_path);
+        install_cmd.args(make_variables(variables, &rockspec.build.install_variables));
         install_cmd.env("PREFIX", &dest);
 
         if install_cmd.status().is_ok() {
@@ -173,106 +789,13 @@ impl PackageInstaller {
         }
 
         // Fall back to copying files based on rockspec.build.install or build.modules.
-        // Handle install table sections: bin, lua, lib, conf.
-        let has_install = !rockspec.build.install.bin.is_empty()
-            || !rockspec.build.install.lua.is_empty()
-            || !rockspec.build.install.lib.is_empty()
-            || !rockspec.build.install.conf.is_empty();
-
-        if has_install {
-            // Copy files from install.bin (executables).
-            for source_path_str in rockspec.build.install.bin.values() {
-                let src = source_path.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    if src.is_dir() {
-                        copy_dir_recursive(&src, &dst)?;
-                    } else {
-                        fs::copy(&src, &dst)?;
-                    }
-                }
-            }
-
-            // Copy files from install.lua (Lua modules).
-            for source_path_str in rockspec.build.install.lua.values() {
-                let src = source_path.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    if src.is_dir() {
-                        copy_dir_recursive(&src, &dst)?;
-                    } else {
-                        fs::copy(&src, &dst)?;
-                    }
-                }
-            }
-
-            // Copy files from install.lib (native libraries).
-            for source_path_str in rockspec.build.install.lib.values() {
-                let src = source_path.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    fs::copy(&src, &dst)?;
-                }
-            }
-
-            // Copy files from install.conf (configuration files).
-            for source_path_str in rockspec.build.install.conf.values() {
-                let src = source_path.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    if src.is_dir() {
-                        copy_dir_recursive(&src, &dst)?;
-                    } else {
-                        fs::copy(&src, &dst)?;
-                    }
-                }
-            }
+        if !rockspec.build.install.is_empty() {
+            tree.install(source_path, package_name, &rockspec.build.install)?;
         } else if !rockspec.build.modules.is_empty() {
-            // Copy modules specified in build.modules.
-            for source_file in rockspec.build.modules.values() {
-                let src = source_path.join(source_file);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-                    fs::copy(&src, &dst)?;
+            // Install modules specified in build.modules.
+            for (module, source_file) in &rockspec.build.modules {
+                if source_path.join(source_file).exists() {
+                    tree.install_lua_module(source_path, module, source_file)?;
                 }
             }
         } else {
@@ -289,6 +812,8 @@ impl PackageInstaller {
         source_path: &Path,
         package_name: &str,
         rockspec: &Rockspec,
+        variables: &HashMap<String, String>,
+        tree: &TreeLayout,
     ) -> LpmResult<()> {
         use std::process::Command;
 
@@ -301,6 +826,11 @@ impl PackageInstaller {
         // Run cmake configure step.
         let mut cmake_cmd = Command::new("cmake");
         cmake_cmd.arg("..");
+        cmake_cmd.args(
+            sorted_variables(variables)
+                .into_iter()
+                .map(|(name, value)| format!("-D{}={}", name, value)),
+        );
         cmake_cmd.current_dir(&build_dir);
 
         let status = cmake_cmd
@@ -325,8 +855,7 @@ impl PackageInstaller {
         }
 
         // Install built files to destination.
-        let dest = self.lua_modules.join(package_name);
-        fs::create_dir_all(&dest)?;
+        let dest = tree.package_dir(package_name);
 
         // Attempt cmake install first.
         let mut install_cmd = Command::new("cmake");
@@ -339,64 +868,8 @@ impl PackageInstaller {
         }
 
         // Fall back to copying from build directory.
-        let has_install = !rockspec.build.install.bin.is_empty()
-            || !rockspec.build.install.lua.is_empty()
-            || !rockspec.build.install.lib.is_empty()
-            || !rockspec.build.install.conf.is_empty();
-
-        if has_install {
-            // Copy from install table sections.
-            for source_path_str in rockspec.build.install.bin.values() {
-                let src = build_dir.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(&build_dir)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    if src.is_dir() {
-                        copy_dir_recursive(&src, &dst)?;
-                    } else {
-                        fs::copy(&src, &dst)?;
-                    }
-                }
-            }
-
-            for source_path_str in rockspec.build.install.lua.values() {
-                let src = build_dir.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(&build_dir)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    fs::copy(&src, &dst)?;
-                }
-            }
-
-            for source_path_str in rockspec.build.install.lib.values() {
-                let src = build_dir.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(&build_dir)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    fs::copy(&src, &dst)?;
-                }
-            }
+        if !rockspec.build.install.is_empty() {
+            tree.install(&build_dir, package_name, &rockspec.build.install)?;
         } else {
             // Copy built files from build directory.
             copy_dir_recursive(&build_dir, &dest)?;
@@ -411,6 +884,8 @@ impl PackageInstaller {
         source_path: &Path,
         package_name: &str,
         rockspec: &Rockspec,
+        variables: &HashMap<String, String>,
+        tree: &TreeLayout,
     ) -> LpmResult<()> {
         use std::process::Command;
 
@@ -427,6 +902,7 @@ impl PackageInstaller {
             let mut cmd = Command::new("sh");
             cmd.arg(&build_script);
             cmd.current_dir(source_path);
+            cmd.envs(variables);
 
             let status = cmd
                 .status()
@@ -443,54 +919,11 @@ impl PackageInstaller {
         }
 
         // Install built files to destination.
-        let dest = self.lua_modules.join(package_name);
-        fs::create_dir_all(&dest)?;
-
-        let has_install = !rockspec.build.install.bin.is_empty()
-            || !rockspec.build.install.lua.is_empty()
-            || !rockspec.build.install.lib.is_empty()
-            || !rockspec.build.install.conf.is_empty();
-
-        if has_install {
-            // Copy from install table sections.
-            for source_path_str in rockspec.build.install.bin.values() {
-                let src = source_path.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    if src.is_dir() {
-                        copy_dir_recursive(&src, &dst)?;
-                    } else {
-                        fs::copy(&src, &dst)?;
-                    }
-                }
-            }
-
-            for source_path_str in rockspec.build.install.lua.values() {
-                let src = source_path.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    fs::copy(&src, &dst)?;
-                }
-            }
+        if !rockspec.build.install.is_empty() {
+            tree.install(source_path, package_name, &rockspec.build.install)?;
         } else {
             // Copy everything as fallback.
-            copy_dir_recursive(source_path, &dest)?;
+            copy_dir_recursive(source_path, &tree.package_dir(package_name))?;
         }
 
         println!("  ✓ Installed built package");
@@ -502,6 +935,7 @@ impl PackageInstaller {
         source_path: &Path,
         package_name: &str,
         rockspec: &Rockspec,
+        tree: &TreeLayout,
     ) -> LpmResult<()> {
         use std::process::Command;
 
@@ -531,13 +965,7 @@ impl PackageInstaller {
         // Find the built library in target/release/.
         // Look for platform-specific extensions: .so, .dylib, or .dll.
         let target_dir = source_path.join("target").join("release");
-        let lib_ext = if cfg!(target_os = "windows") {
-            "dll"
-        } else if cfg!(target_os = "macos") {
-            "dylib"
-        } else {
-            "so"
-        };
+        let lib_ext = native_extension();
 
         // Search for the built library file in target/release/.
         let lib_file = std::fs::read_dir(&target_dir)?
@@ -550,134 +978,131 @@ impl PackageInstaller {
                     .unwrap_or(false)
             });
 
-        // Install built files to destination.
-        let dest = self.lua_modules.join(package_name);
-        fs::create_dir_all(&dest)?;
+        // build.modules maps module names to Lua files, or to the crate's library
+        let (lua_modules, native_modules): (Vec<_>, Vec<_>) = rockspec
+            .build
+            .modules
+            .iter()
+            .partition(|(_, source)| source.ends_with(".lua"));
 
         if let Some(lib_entry) = lib_file {
-            // Copy the built library to destination.
             let lib_path = lib_entry.path();
-            let lib_name = lib_path
-                .file_name()
-                .ok_or_else(|| LpmError::Package("Invalid library path".to_string()))?;
-            let dest_lib = dest.join(lib_name);
-            fs::copy(&lib_path, &dest_lib)?;
-            println!("  ✓ Copied library: {}", lib_name.to_string_lossy());
-        }
-
-        // Copy Lua files if specified in modules.
-        if !rockspec.build.modules.is_empty() {
-            for source_file in rockspec.build.modules.values() {
-                let src = source_path.join(source_file);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-                    fs::copy(&src, &dst)?;
+            let mut module_names: Vec<String> = native_modules
+                .iter()
+                .map(|(module, _)| module.to_string())
+                .collect();
+            if module_names.is_empty() {
+                // libfoo.so is module foo
+                let stem = lib_path
+                    .file_stem()
+                    .ok_or_else(|| LpmError::Package("Invalid library path".to_string()))?
+                    .to_string_lossy();
+                module_names.push(stem.strip_prefix("lib").unwrap_or(&stem).to_string());
+            }
+            for module in module_names {
+                let dst = tree.native_module_path(&module);
+                if let Some(parent) = dst.parent() {
+                    fs::create_dir_all(parent)?;
                 }
+                fs::copy(&lib_path, &dst)?;
+                println!("  ✓ Installed library as module {}", module);
             }
         }
 
-        // Copy any other files specified in install table.
-        let has_install = !rockspec.build.install.bin.is_empty()
-            || !rockspec.build.install.lua.is_empty()
-            || !rockspec.build.install.lib.is_empty()
-            || !rockspec.build.install.conf.is_empty();
-
-        if has_install {
-            for source_path_str in rockspec.build.install.bin.values() {
-                let src = source_path.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
-
-                    fs::copy(&src, &dst)?;
-                }
+        // Install Lua files specified in modules.
+        for (module, source_file) in lua_modules {
+            if source_path.join(source_file).exists() {
+                tree.install_lua_module(source_path, module, source_file)?;
             }
+        }
 
-            for source_path_str in rockspec.build.install.lua.values() {
-                let src = source_path.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
+        // Install any other files specified in install table.
+        tree.install(source_path, package_name, &rockspec.build.install)?;
 
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
+        println!("  ✓ Installed Rust extension");
+        Ok(())
+    }
 
-                    fs::copy(&src, &dst)?;
-                }
+    fn install_builtin(
+        &self,
+        source_path: &Path,
+        package_name: &str,
+        rockspec: &Rockspec,
+        variables: &HashMap<String, String>,
+        tree: &TreeLayout,
+    ) -> LpmResult<()> {
+        // String entries pointing at C files are native modules, like in LuaRocks
+        let mut native_modules: Vec<(String, BuildModule)> = Vec::new();
+        let mut lua_files = Vec::new();
+        for (module_name, source_file) in &rockspec.build.modules {
+            if source_file.ends_with(".c") {
+                native_modules.push((
+                    module_name.clone(),
+                    BuildModule {
+                        sources: vec![source_file.clone()],
+                        ..BuildModule::default()
+                    },
+                ));
+            } else {
+                lua_files.push((module_name.clone(), source_file.clone()));
             }
+        }
+        for (module_name, module) in &rockspec.build.native_modules {
+            native_modules.push((module_name.clone(), module.clone()));
+        }
+        native_modules.sort_by(|a, b| a.0.cmp(&b.0));
 
-            for source_path_str in rockspec.build.install.lib.values() {
-                let src = source_path.join(source_path_str);
-                if src.exists() {
-                    let relative = src
-                        .strip_prefix(source_path)
-                        .map_err(|e| LpmError::Path(e.to_string()))?;
-                    let dst = dest.join(relative);
-
-                    if let Some(parent) = dst.parent() {
-                        fs::create_dir_all(parent)?;
-                    }
+        if lua_files.is_empty() && native_modules.is_empty() && rockspec.build.install.is_empty() {
+            // No modules listed: install the Lua files LuaRocks would find
+            lua_files = autodetect_modules(source_path);
+        }
 
-                    fs::copy(&src, &dst)?;
-                }
+        // Install the Lua modules at the paths their names map to.
+        for (module_name, source_file) in &lua_files {
+            if !source_path.join(source_file).exists() {
+                return Err(LpmError::Package(format!(
+                    "Module file not found in source: {}",
+                    source_file
+                )));
             }
+            tree.install_lua_module(source_path, module_name, source_file)?;
         }
 
-        println!("  ✓ Installed Rust extension");
-        Ok(())
+        if !native_modules.is_empty() {
+            self.build_native_modules(source_path, package_name, variables, &native_modules, tree)?;
+        }
+
+        tree.install(source_path, package_name, &rockspec.build.install)
     }
 
-    fn install_builtin(
+    /// Compile C modules and place them where `package.cpath` finds them
+    fn build_native_modules(
         &self,
         source_path: &Path,
         package_name: &str,
-        rockspec: &Rockspec,
+        variables: &HashMap<String, String>,
+        modules: &[(String, BuildModule)],
+        tree: &TreeLayout,
     ) -> LpmResult<()> {
-        let dest = self.lua_modules.join(package_name);
-        fs::create_dir_all(&dest)?;
-
-        if rockspec.build.modules.is_empty() {
-            // Copy everything (standard case for most packages).
-            copy_dir_recursive(source_path, &dest)?;
-        } else {
-            // Copy only the specified modules.
-            for source_file in rockspec.build.modules.values() {
-                let src = source_path.join(source_file);
-                if !src.exists() {
-                    return Err(LpmError::Package(format!(
-                        "Module file not found in source: {}",
-                        source_file
-                    )));
-                }
+        let lua_incdir = find_lua_headers(&self.project_root)?;
+        let builder = NativeModuleBuilder::new(&lua_incdir).with_variables(variables);
+        let build_dir = self.metadata_dir.join("build").join(package_name);
+        if build_dir.exists() {
+            fs::remove_dir_all(&build_dir)?;
+        }
 
-                let relative = src
-                    .strip_prefix(source_path)
-                    .map_err(|e| LpmError::Path(e.to_string()))?;
-                let dst = dest.join(relative);
+        for (module_name, module) in modules {
+            println!("  Compiling {} ({})", module_name, package_name);
+            let library = builder.compile(source_path, &build_dir, module_name, module)?;
 
-                if let Some(parent) = dst.parent() {
-                    fs::create_dir_all(parent)?;
-                }
-                fs::copy(&src, &dst)?;
+            let dst = tree.native_module_path(module_name);
+            if let Some(parent) = dst.parent() {
+                fs::create_dir_all(parent)?;
             }
+            fs::copy(&library, &dst)?;
         }
 
+        fs::remove_dir_all(&build_dir)?;
         Ok(())
     }
 
@@ -689,27 +1114,138 @@ impl PackageInstaller {
     /// Check if a package is installed
     pub fn is_installed(&self, name: &str) -> bool {
         self.lua_modules.join(name).exists()
+            || self.packages_dir.join(name).join(ownership::FILES).exists()
     }
 
-    /// Remove a package
+    /// Remove a package: exactly the files it installed, and its metadata
     pub fn remove_package(&self, name: &str) -> LpmResult<()> {
-        let package_dir = self.lua_modules.join(name);
+        rollback::touch(&self.project_root, name, None)?;
+        self.remove_files(name)?;
+
         let metadata_dir = self.packages_dir.join(name);
+        if metadata_dir.exists() {
+            fs::remove_dir_all(&metadata_dir)?;
+        }
+
+        Ok(())
+    }
 
-        if package_dir.exists() {
-            fs::remove_dir_all(&package_dir)?;
+    /// Remove the files a package installed, leaving other packages' alone
+    fn remove_files(&self, name: &str) -> LpmResult<()> {
+        match InstalledFiles::load(&self.packages_dir, name)? {
+            Some(files) => {
+                ownership::remove(&self.lua_modules, &files)?;
+                // Created for every package, even one with no files of its own in it
+                let _ = fs::remove_dir(self.lua_modules.join(name));
+                Ok(())
+            }
+            None => {
+                // Installed before files were recorded: all we know is its directory
+                let package_dir = self.lua_modules.join(name);
+                if fs::symlink_metadata(&package_dir).is_ok() {
+                    local::unlink(&package_dir)?;
+                }
+                if package_dir.exists() {
+                    fs::remove_dir_all(&package_dir)?;
+                }
+                Ok(())
+            }
         }
+    }
+}
 
-        if metadata_dir.exists() {
-            fs::remove_dir_all(&metadata_dir)?;
+/// The files one commit moved into lua_modules, and what they replaced, so
+/// that a failed commit can be undone
+struct Swap<'a> {
+    lua_modules: &'a Path,
+    backup: &'a Path,
+    moved_in: Vec<String>,
+    set_aside: Vec<String>,
+}
+
+impl<'a> Swap<'a> {
+    fn new(lua_modules: &'a Path, backup: &'a Path) -> Self {
+        Self {
+            lua_modules,
+            backup,
+            moved_in: Vec::new(),
+            set_aside: Vec::new(),
         }
+    }
 
+    /// Move `path` (relative to lua_modules) into the backup directory
+    fn set_aside(&mut self, path: &str) -> LpmResult<()> {
+        let backup = self.backup.join(path);
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(self.lua_modules.join(path), backup)?;
+        self.set_aside.push(path.to_string());
         Ok(())
     }
+
+    /// Move a staged file into place, setting aside the file it replaces
+    fn move_in(&mut self, staging: &Path, path: &str) -> LpmResult<()> {
+        let dst = self.lua_modules.join(path);
+        if fs::symlink_metadata(&dst).is_ok_and(|metadata| !metadata.is_dir()) {
+            self.set_aside(path)?;
+        }
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(staging.join(path), &dst)?;
+        self.moved_in.push(path.to_string());
+        Ok(())
+    }
+
+    /// Take the moved-in files out again and put back what they replaced
+    fn undo(self) {
+        let moved_in = InstalledFiles {
+            files: self
+                .moved_in
+                .into_iter()
+                .map(|path| (path, String::new()))
+                .collect(),
+            ..InstalledFiles::default()
+        };
+        let _ = ownership::remove(self.lua_modules, &moved_in);
+        for path in self.set_aside.iter().rev() {
+            let dst = self.lua_modules.join(path);
+            if let Some(parent) = dst.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            let _ = fs::rename(self.backup.join(path), dst);
+        }
+    }
+}
+
+/// Build variables sorted by name, with `$(NAME)` references expanded
+fn sorted_variables(variables: &HashMap<String, String>) -> Vec<(String, String)> {
+    let mut sorted: Vec<_> = variables
+        .iter()
+        .map(|(name, value)| (name.clone(), substitute_variables(value, variables)))
+        .collect();
+    sorted.sort();
+    sorted
+}
+
+/// `NAME=value` arguments for make, including `build_variables`/`install_variables`
+fn make_variables(
+    variables: &HashMap<String, String>,
+    extra: &HashMap<String, String>,
+) -> Vec<String> {
+    let mut all = variables.clone();
+    for (name, value) in extra {
+        all.insert(name.clone(), substitute_variables(value, variables));
+    }
+    sorted_variables(&all)
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect()
 }
 
 /// Copy directory recursively from source to destination
-fn copy_dir_recursive(src: &Path, dst: &Path) -> LpmResult<()> {
+pub(crate) fn copy_dir_recursive(src: &Path, dst: &Path) -> LpmResult<()> {
     for entry in WalkDir::new(src) {
         let entry = entry?;
         let path = entry.path();
@@ -1014,26 +1550,35 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_builtin(&source_path, "test-package", &rockspec)
+            .install_builtin(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &HashMap::new(),
+                &installer.tree(&installer.lua_modules),
+            )
             .unwrap();
 
-        // Verify files were copied
-        let dest = installer.lua_modules.join("test-package");
+        // Verify files were installed where require finds them
+        let dest = installer.tree(&installer.lua_modules).lua_dir();
         assert!(dest.join("module1.lua").exists());
         assert!(dest.join("module2.lua").exists());
         assert_eq!(
@@ -1056,7 +1601,7 @@ mod tests {
         fs::write(source_path.join("file1.lua"), "content1").unwrap();
         fs::write(source_path.join("file2.lua"), "content2").unwrap();
 
-        // Create rockspec without modules (should copy everything)
+        // Create rockspec without modules (should find the Lua files)
         use crate::luarocks::rockspec::{Rockspec, RockspecBuild, RockspecSource};
         use std::collections::HashMap;
 
@@ -1067,26 +1612,35 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_builtin(&source_path, "test-package", &rockspec)
+            .install_builtin(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &HashMap::new(),
+                &installer.tree(&installer.lua_modules),
+            )
             .unwrap();
 
-        // Verify all files were copied
-        let dest = installer.lua_modules.join("test-package");
+        // Verify all Lua files were installed as modules
+        let dest = installer.tree(&installer.lua_modules).lua_dir();
         assert!(dest.join("file1.lua").exists());
         assert!(dest.join("file2.lua").exists());
     }
@@ -1115,21 +1669,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let result = installer.install_builtin(&source_path, "test-package", &rockspec);
+        let result = installer.install_builtin(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
         assert!(result.is_err());
         match result {
             Err(LpmError::Package(msg)) => {
@@ -1160,21 +1723,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "unsupported".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
         match result {
             Err(LpmError::NotImplemented(msg)) => {
@@ -1184,6 +1755,71 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_install_from_source_missing_external_dependency() {
+        let temp = TempDir::new().unwrap();
+        setup_test_env(&temp);
+
+        let installer = PackageInstaller::new(temp.path()).unwrap();
+        installer.init().unwrap();
+
+        let source_path = temp.path().join("source");
+        fs::create_dir_all(&source_path).unwrap();
+        fs::write(source_path.join("Makefile"), "all:\n\ttouch built\n").unwrap();
+
+        use crate::luarocks::rockspec::RockspecBuild;
+
+        let mut probes = HashMap::new();
+        probes.insert(
+            "header".to_string(),
+            "lpm-test-missing/missing.h".to_string(),
+        );
+        let rockspec = Rockspec {
+            package: "test-package".to_string(),
+            version: "1.0.0".to_string(),
+            build: RockspecBuild {
+                build_type: "make".to_string(),
+                ..Default::default()
+            },
+            external_dependencies: HashMap::from([("LPMTESTMISSING".to_string(), probes)]),
+            ..Default::default()
+        };
+
+        let err = installer
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Missing system library LPMTESTMISSING"),
+            "{err}"
+        );
+        // Nothing was built
+        assert!(!source_path.join("built").exists());
+    }
+
+    #[test]
+    fn test_make_variables_expand_references() {
+        let mut variables = HashMap::new();
+        variables.insert("OPENSSL_INCDIR".to_string(), "/opt/ssl/include".to_string());
+        variables.insert("CFLAGS".to_string(), "-I$(OPENSSL_INCDIR)".to_string());
+        let mut build_variables = HashMap::new();
+        build_variables.insert("LIBFLAG".to_string(), "-shared".to_string());
+
+        assert_eq!(
+            make_variables(&variables, &build_variables),
+            vec![
+                "CFLAGS=-I/opt/ssl/include",
+                "LIBFLAG=-shared",
+                "OPENSSL_INCDIR=/opt/ssl/include",
+            ]
+        );
+    }
+
     #[test]
     fn test_install_from_source_none_build_type() {
         let temp = TempDir::new().unwrap();
@@ -1206,26 +1842,34 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "none".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
 
-        // Should copy everything
-        let dest = installer.lua_modules.join("test-package");
+        // Should install the Lua files it finds
+        let dest = installer.tree(&installer.lua_modules).lua_dir();
         assert!(dest.join("module.lua").exists());
     }
 
@@ -1264,27 +1908,36 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
 
         let dest = installer.lua_modules.join("test-package");
         assert!(dest.join("bin").join("myapp").exists());
-        assert!(dest.join("src").join("module.lua").exists());
+        let share = installer.tree(&installer.lua_modules).lua_dir();
+        assert!(share.join("module.lua").exists());
     }
 
     #[test]
@@ -1312,25 +1965,33 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
 
-        let dest = installer.lua_modules.join("test-package");
+        let dest = installer.tree(&installer.lua_modules).lua_dir();
         assert!(dest.join("mymodule.lua").exists());
     }
 
@@ -1366,27 +2027,35 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
 
-        let dest = installer.lua_modules.join("test-package");
-        assert!(dest.join("init.lua").exists());
-        assert!(dest.join("lib").join("lib.so").exists());
+        let tree = installer.tree(&installer.lua_modules);
+        assert!(tree.lua_dir().join("init.lua").exists());
+        assert!(tree.native_module_path("lib").exists());
     }
 
     #[test]
@@ -1412,25 +2081,33 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
 
-        let dest = installer.lua_modules.join("test-package");
+        let dest = installer.tree(&installer.lua_modules).lua_dir();
         assert!(dest.join("file1.lua").exists());
         assert!(dest.join("file2.lua").exists());
     }
@@ -1478,22 +2155,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // This will fail because make/Makefile doesn't exist, but tests the code path
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
     }
 
@@ -1518,22 +2203,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // This will fail because cmake/CMakeLists.txt doesn't exist, but tests the code path
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
     }
 
@@ -1558,22 +2251,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // This will fail because build.sh doesn't exist
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
     }
 
@@ -1598,22 +2299,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // This will fail because Cargo.toml doesn't exist
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
         if let Err(LpmError::Package(msg)) = result {
             assert!(msg.contains("Cargo.toml") || msg.contains("Rust build"));
@@ -1652,22 +2361,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
 
         let dest = installer.lua_modules.join("test-package");
@@ -1702,22 +2419,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
 
         let dest = installer.lua_modules.join("test-package");
@@ -1749,21 +2474,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
         if let Err(LpmError::Package(msg)) = result {
             assert!(msg.contains("Module file not found") || msg.contains("missing"));
@@ -1795,26 +2528,36 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
 
-        let dest = installer.lua_modules.join("test-package");
-        assert!(dest.join("src").join("module.lua").exists());
+        // Installed by module name, not by its path in the source
+        let dest = installer.tree(&installer.lua_modules).lua_dir();
+        assert!(dest.join("module.lua").exists());
+        assert!(!dest.join("src").exists());
     }
 
     #[test]
@@ -1839,27 +2582,33 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
         assert!(installer
-            .lua_modules
-            .join("test-package")
-            .join("lib")
-            .join("lib.so")
+            .tree(&installer.lua_modules)
+            .native_module_path("lib")
             .exists());
     }
 
@@ -1885,21 +2634,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // make will fail, but tests the install table fallback path
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -1924,21 +2681,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // cmake will fail, but tests the install table fallback path
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -1964,21 +2729,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // build.sh will fail, but tests the install table path
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2003,20 +2776,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2041,20 +2822,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2079,20 +2868,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2115,20 +2912,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2151,20 +2956,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2194,21 +3007,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
         // The directory structure is preserved, so subdir/script should exist
         let dest = installer.lua_modules.join("test-package");
@@ -2245,20 +3066,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2289,20 +3118,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2324,20 +3161,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
     }
 
@@ -2363,20 +3208,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
     }
 
@@ -2439,25 +3292,34 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         installer
-            .install_builtin(&source_path, "test-package", &rockspec)
+            .install_builtin(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &HashMap::new(),
+                &installer.tree(&installer.lua_modules),
+            )
             .unwrap();
         assert!(installer
-            .lua_modules
-            .join("test-package")
+            .tree(&installer.lua_modules)
+            .lua_dir()
             .join("test.lua")
             .exists());
     }
@@ -2479,20 +3341,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
     }
 
@@ -2513,20 +3383,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
     }
 
@@ -2547,20 +3425,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
     }
 
@@ -2583,20 +3469,28 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules,
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err());
     }
 
@@ -2623,21 +3517,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // make install will fail, but tests the install table fallback path
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2668,21 +3570,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // cmake install will fail, but tests the install table path
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2717,21 +3627,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // build.sh will fail, but tests the install table path
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2763,21 +3681,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules,
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // cargo build will fail, but tests the install table path
-        let _ = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let _ = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
     }
 
     #[test]
@@ -2798,22 +3724,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // make will fail without Makefile, but tests the error path
-        let result = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let result = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
         assert!(result.is_err());
     }
 
@@ -2835,22 +3770,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // cmake will fail without CMakeLists.txt, but tests the error path
-        let result = installer.build_with_cmake(&source_path, "test-package", &rockspec);
+        let result = installer.build_with_cmake(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
         assert!(result.is_err());
     }
 
@@ -2877,22 +3821,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // make will fail, but tests the install table copy path
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -2918,22 +3871,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // cmake will fail, but tests the install table copy path
-        let _ = installer.build_with_cmake(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -2954,22 +3916,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Tests the routing to build_with_make
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err()); // make will fail without Makefile
     }
 
@@ -2991,22 +3961,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Tests the routing to build_with_cmake
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err()); // cmake will fail without CMakeLists.txt
     }
 
@@ -3096,22 +4074,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Tests the routing to build_with_rust
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err()); // cargo will fail without Cargo.toml
     }
 
@@ -3133,22 +4119,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust-mlua".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Tests the routing to build_with_rust (rust-mlua uses same handler)
-        let result = installer.install_from_source(&source_path, "test-package", &rockspec);
+        let result = installer.install_from_source(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.lua_modules,
+        );
         assert!(result.is_err()); // cargo will fail without Cargo.toml
     }
 
@@ -3182,20 +4176,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.build_with_command(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3221,20 +4224,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.build_with_command(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3260,20 +4272,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.build_with_command(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3299,20 +4320,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3338,20 +4368,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.build_with_cmake(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3377,21 +4416,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // Will fail without Cargo.toml, but tests install.lib path
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3417,21 +4464,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // Will fail without Cargo.toml, but tests install.conf path
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3457,20 +4512,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3496,20 +4560,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.build_with_cmake(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3533,20 +4606,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3568,21 +4650,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // Will fail without make, but tests fallback path
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3606,20 +4697,29 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
-        let _ = installer.build_with_cmake(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3641,21 +4741,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
         // Will fail without cmake, but tests fallback path
-        let _ = installer.build_with_cmake(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3678,22 +4787,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Should fail because module file doesn't exist
-        let result = installer.install_builtin(&source_path, "test-package", &rockspec);
+        let result = installer.install_builtin(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -3722,25 +4840,34 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_builtin(&source_path, "test-package", &rockspec)
+            .install_builtin(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &HashMap::new(),
+                &installer.tree(&installer.lua_modules),
+            )
             .unwrap();
-        let dest = installer.lua_modules.join("test-package");
-        assert!(dest.join("subdir").join("module.lua").exists());
+        let dest = installer.tree(&installer.lua_modules).lua_dir();
+        assert!(dest.join("mymodule.lua").exists());
     }
 
     #[test]
@@ -3766,22 +4893,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cargo build will fail, but tests the path
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3810,22 +4945,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cargo build will fail, but tests modules path
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3847,22 +4990,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test-package", &rockspec)
+            .install_from_source(
+                &source_path,
+                "test-package",
+                &rockspec,
+                &installer.lua_modules,
+            )
             .unwrap();
         assert!(installer.is_installed("test-package"));
     }
@@ -3888,22 +5039,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because make build will fail, but tests the make install path
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3930,22 +5090,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because make build will fail, but tests install.bin dir path
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -3972,22 +5141,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because make build will fail, but tests install.lua dir path
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4014,22 +5192,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because make build will fail, but tests install.conf dir path
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4059,22 +5246,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cmake will fail, but tests install.bin dir path
-        let _ = installer.build_with_cmake(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4100,22 +5296,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cmake will fail, but tests install.lib path
-        let _ = installer.build_with_cmake(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4149,22 +5354,31 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Tests install.lua path in build_with_command
-        let _ = installer.build_with_command(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4193,22 +5407,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cargo build will fail, but tests install.bin path
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4239,22 +5461,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cargo build will fail, but tests install.lua path
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4285,22 +5515,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cargo build will fail, but tests install.lib path
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4326,22 +5564,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cargo build will fail, but tests the path where lib_file is None
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4370,22 +5616,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules,
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cargo build will fail, but tests modules path when lib_file is None
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4416,22 +5670,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         // Will fail because cargo build will fail, but tests install table path when lib_file is None
-        let _ = installer.build_with_rust(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4465,21 +5727,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_command(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4513,21 +5784,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_command(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4557,21 +5837,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_command(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4592,21 +5881,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install: Default::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let result = installer.build_with_command(&source_path, "test-package", &rockspec);
+        let result = installer.build_with_command(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("build script"));
     }
@@ -4638,21 +5936,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_cmake(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4678,21 +5985,30 @@ mod tests {
                 url: "https://example.com/test.tar.gz".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_make(&source_path, "test-package", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test-package",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[tokio::test]
@@ -4778,24 +6094,37 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: crate::luarocks::rockspec::InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_builtin(&source_path, "test", &rockspec)
+            .install_builtin(
+                &source_path,
+                "test",
+                &rockspec,
+                &HashMap::new(),
+                &installer.tree(&installer.lua_modules),
+            )
             .unwrap();
-        assert!(installer.lua_modules.join("test").join("file.lua").exists());
+        assert!(installer
+            .tree(&installer.lua_modules)
+            .lua_dir()
+            .join("file.lua")
+            .exists());
     }
 
     #[test]
@@ -4819,21 +6148,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: crate::luarocks::rockspec::InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let result = installer.install_builtin(&source_path, "test", &rockspec);
+        let result = installer.install_builtin(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -4904,21 +6242,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_cmake(&source_path, "test", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4945,21 +6292,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_command(&source_path, "test", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -4990,21 +6346,29 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_rust(&source_path, "test", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5028,21 +6392,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_cmake(&source_path, "test", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5070,21 +6443,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_cmake(&source_path, "test", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5112,21 +6494,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_cmake(&source_path, "test", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5155,21 +6546,29 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules,
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_rust(&source_path, "test", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5200,21 +6599,29 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_rust(&source_path, "test", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5240,21 +6647,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_make(&source_path, "test", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5280,21 +6696,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_make(&source_path, "test", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5322,21 +6747,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_cmake(&source_path, "test", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5367,21 +6801,29 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "rust".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_rust(&source_path, "test", &rockspec);
+        let _ = installer.build_with_rust(
+            &source_path,
+            "test",
+            &rockspec,
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5405,21 +6847,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "cmake".to_string(),
                 modules: HashMap::new(),
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_cmake(&source_path, "test", &rockspec);
+        let _ = installer.build_with_cmake(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5447,21 +6898,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "make".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_make(&source_path, "test", &rockspec);
+        let _ = installer.build_with_make(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5488,21 +6948,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install,
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_command(&source_path, "test", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5525,21 +6994,30 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "command".to_string(),
                 modules: HashMap::new(),
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let _ = installer.build_with_command(&source_path, "test", &rockspec);
+        let _ = installer.build_with_command(
+            &source_path,
+            "test",
+            &rockspec,
+            &HashMap::new(),
+            &installer.tree(&installer.lua_modules),
+        );
     }
 
     #[test]
@@ -5565,32 +7043,41 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_builtin(&source_path, "test", &rockspec)
+            .install_builtin(
+                &source_path,
+                "test",
+                &rockspec,
+                &HashMap::new(),
+                &installer.tree(&installer.lua_modules),
+            )
             .unwrap();
         assert!(installer
-            .lua_modules
-            .join("test")
-            .join("module1.lua")
+            .tree(&installer.lua_modules)
+            .lua_dir()
+            .join("mod1.lua")
             .exists());
         assert!(installer
-            .lua_modules
-            .join("test")
-            .join("module2.lua")
+            .tree(&installer.lua_modules)
+            .lua_dir()
+            .join("mod2.lua")
             .exists());
     }
 
@@ -5613,24 +7100,31 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: crate::luarocks::rockspec::InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test", &rockspec)
+            .install_from_source(&source_path, "test", &rockspec, &installer.lua_modules)
             .unwrap();
-        assert!(installer.lua_modules.join("test").join("file.lua").exists());
+        assert!(installer
+            .tree(&installer.lua_modules)
+            .lua_dir()
+            .join("file.lua")
+            .exists());
     }
 
     #[test]
@@ -5652,24 +7146,31 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "none".to_string(),
                 modules: HashMap::new(),
                 install: crate::luarocks::rockspec::InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_from_source(&source_path, "test", &rockspec)
+            .install_from_source(&source_path, "test", &rockspec, &installer.lua_modules)
             .unwrap();
-        assert!(installer.lua_modules.join("test").join("file.lua").exists());
+        assert!(installer
+            .tree(&installer.lua_modules)
+            .lua_dir()
+            .join("file.lua")
+            .exists());
     }
 
     #[test]
@@ -5690,21 +7191,25 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "unsupported".to_string(),
                 modules: HashMap::new(),
                 install: crate::luarocks::rockspec::InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
-        let result = installer.install_from_source(&source_path, "test", &rockspec);
+        let result =
+            installer.install_from_source(&source_path, "test", &rockspec, &installer.lua_modules);
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("not supported"));
     }
@@ -5730,27 +7235,35 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules,
                 install: crate::luarocks::rockspec::InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_builtin(&source_path, "test", &rockspec)
+            .install_builtin(
+                &source_path,
+                "test",
+                &rockspec,
+                &HashMap::new(),
+                &installer.tree(&installer.lua_modules),
+            )
             .unwrap();
         assert!(installer
-            .lua_modules
-            .join("test")
-            .join("subdir")
+            .tree(&installer.lua_modules)
+            .lua_dir()
             .join("module.lua")
             .exists());
     }
@@ -5780,40 +7293,276 @@ mod tests {
                 url: "".to_string(),
                 tag: None,
                 branch: None,
+                ..Default::default()
             },
             dependencies: vec![],
             build: RockspecBuild {
                 build_type: "builtin".to_string(),
                 modules: HashMap::new(),
                 install: crate::luarocks::rockspec::InstallTable::default(),
+                ..Default::default()
             },
             description: None,
             homepage: None,
             license: None,
             lua_version: None,
             binary_urls: HashMap::new(),
+            ..Default::default()
         };
 
         installer
-            .install_builtin(&source_path, "test", &rockspec)
+            .install_builtin(
+                &source_path,
+                "test",
+                &rockspec,
+                &HashMap::new(),
+                &installer.tree(&installer.lua_modules),
+            )
             .unwrap();
         assert!(installer
-            .lua_modules
-            .join("test")
+            .tree(&installer.lua_modules)
+            .lua_dir()
             .join("file1.lua")
             .exists());
         assert!(installer
-            .lua_modules
-            .join("test")
+            .tree(&installer.lua_modules)
+            .lua_dir()
             .join("dir1")
             .join("file2.lua")
             .exists());
         assert!(installer
-            .lua_modules
-            .join("test")
+            .tree(&installer.lua_modules)
+            .lua_dir()
             .join("dir1")
             .join("dir2")
             .join("file3.lua")
             .exists());
     }
+
+    /// A served repository with `acme` 1.0-1: its rockspec, source archive,
+    /// source rock and a pure-Lua binary rock
+    async fn serve_rocks(temp: &TempDir) -> (crate::luarocks::LocalRepository, String) {
+        use crate::luarocks::server::RepositoryServer;
+        use crate::luarocks::LocalRepository;
+
+        let repo = LocalRepository::open(&temp.path().join("repo")).unwrap();
+        let server = RepositoryServer::bind(repo.root(), "127.0.0.1:0")
+            .await
+            .unwrap();
+        let url = server.url().unwrap();
+        tokio::spawn(server.run());
+
+        let rockspec = format!(
+            "package = \"acme\"\nversion = \"1.0-1\"\nsource = {{ url = \"{}/acme-1.0.tar.gz\" }}\nbuild = {{ type = \"builtin\", modules = {{ acme = \"acme.lua\" }} }}\n",
+            url
+        );
+        let stage = temp.path().join("stage");
+        fs::create_dir_all(stage.join("lua")).unwrap();
+        fs::write(stage.join("lua/acme.lua"), "return 'binary'").unwrap();
+        let all =
+            rock::write_binary_rock(&stage, &rockspec, "acme", "1.0-1", rock::ALL, temp.path())
+                .unwrap();
+        repo.add(&all).unwrap();
+        let archive = temp.path().join("acme-1.0.tar.gz");
+        fs::write(&archive, b"source archive").unwrap();
+        repo.publish(&rockspec, &archive).unwrap();
+        (repo, url)
+    }
+
+    fn rock_installer(temp: &TempDir, url: &str) -> PackageInstaller {
+        use crate::luarocks::registry::{Registries, Registry};
+
+        setup_test_env(temp);
+        let cache = Cache::new(temp.path().join("rock-cache")).unwrap();
+        let client = LuaRocksClient::new(&Config::default(), cache)
+            .with_registries(Registries::new(vec![Registry::new("local", url)]));
+        let installer = PackageInstaller::new(temp.path())
+            .unwrap()
+            .with_client(client);
+        installer.init().unwrap();
+        installer
+    }
+
+    #[tokio::test]
+    async fn test_install_from_binary_rock() {
+        let temp = TempDir::new().unwrap();
+        let (repo, url) = serve_rocks(&temp).await;
+        // Nothing is built, so the source archive isn't needed
+        fs::remove_file(repo.root().join("acme-1.0.tar.gz")).unwrap();
+
+        let installer = rock_installer(&temp, &url);
+        let rockspec_url = format!("{}/acme-1.0-1.rockspec", url);
+        let fetched = installer
+            .fetch("acme", "1.0-1", &rockspec_url, None, None)
+            .await
+            .unwrap();
+        assert!(fetched.binary);
+        assert!(!fetched.is_native());
+
+        installer.install_fetched(&fetched).unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        let share = installer.tree(&lua_modules).lua_dir();
+        assert_eq!(
+            fs::read_to_string(share.join("acme.lua")).unwrap(),
+            "return 'binary'"
+        );
+        assert!(lua_modules.join("acme/acme-1.0-1.rockspec").exists());
+        assert!(!lua_modules.join(rock::ROCK_MANIFEST).exists());
+        assert!(installer.is_installed("acme"));
+    }
+
+    #[tokio::test]
+    async fn test_source_comes_from_source_rock() {
+        let temp = TempDir::new().unwrap();
+        let (repo, url) = serve_rocks(&temp).await;
+        let checksum = Cache::checksum(&repo.root().join("acme-1.0.tar.gz")).unwrap();
+        fs::remove_file(repo.root().join("acme-1.0.tar.gz")).unwrap();
+
+        let installer = rock_installer(&temp, &url).with_binary_rocks(false);
+        let rockspec_url = format!("{}/acme-1.0-1.rockspec", url);
+        let fetched = installer
+            .fetch("acme", "1.0-1", &rockspec_url, None, Some(&checksum))
+            .await
+            .unwrap();
+        assert!(!fetched.binary);
+        assert_eq!(fetched.checksum, checksum);
+        assert_eq!(
+            fs::read(&fetched.source_path).unwrap(),
+            b"source archive".to_vec()
+        );
+    }
+
+    /// Key of a Lua file in the records of installed files
+    fn lua_key(installer: &PackageInstaller, file: &str) -> String {
+        let version = installer.lua_version().unwrap_or(DEFAULT_LUA_VERSION);
+        format!("share/lua/{}/{}", version, file)
+    }
+
+    /// A fetched `.all.rock` shipping the given files under `lua/`
+    fn binary_package(temp: &TempDir, name: &str, version: &str, files: &[&str]) -> FetchedPackage {
+        let stage = temp.path().join(format!("stage-{}-{}", name, version));
+        for file in files {
+            let path = stage.join("lua").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("-- {} {}", name, version)).unwrap();
+        }
+        let rockspec = format!("package = \"{}\"\nversion = \"{}\"\n", name, version);
+        let source_path =
+            rock::write_binary_rock(&stage, &rockspec, name, version, rock::ALL, temp.path())
+                .unwrap();
+        FetchedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            rockspec: Rockspec::default(),
+            source_path,
+            checksum: String::new(),
+            linked: false,
+            binary: true,
+        }
+    }
+
+    #[test]
+    fn test_install_refuses_files_of_other_packages() {
+        let temp = TempDir::new().unwrap();
+        setup_test_env(&temp);
+        let installer = PackageInstaller::new(temp.path()).unwrap();
+        installer.init().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        let share = installer.tree(&lua_modules).lua_dir();
+
+        let socket = binary_package(&temp, "luasocket", "3.1-1", &["socket.lua", "ltn12.lua"]);
+        installer.install_fetched(&socket).unwrap();
+        let files = InstalledFiles::load(&installer.packages_dir, "luasocket")
+            .unwrap()
+            .unwrap();
+        assert!(files.files.contains_key(&lua_key(&installer, "ltn12.lua")));
+        assert!(files
+            .files
+            .contains_key("luasocket/luasocket-3.1-1.rockspec"));
+
+        let other = binary_package(&temp, "other", "1.0-1", &["ltn12.lua", "other.lua"]);
+        let err = installer.install_fetched(&other).unwrap_err().to_string();
+        let collision = format!(
+            "{} (installed by luasocket)",
+            lua_key(&installer, "ltn12.lua")
+        );
+        assert!(err.contains(&collision), "{err}");
+        // Nothing of the refused package was written
+        assert!(!share.join("other.lua").exists());
+        assert!(!installer.is_installed("other"));
+        assert_eq!(
+            fs::read_to_string(share.join("ltn12.lua")).unwrap(),
+            "-- luasocket 3.1-1"
+        );
+    }
+
+    #[test]
+    fn test_reinstall_and_remove_touch_only_own_files() {
+        let temp = TempDir::new().unwrap();
+        setup_test_env(&temp);
+        let installer = PackageInstaller::new(temp.path()).unwrap();
+        installer.init().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        let share = installer.tree(&lua_modules).lua_dir();
+
+        let old = binary_package(&temp, "luasocket", "3.0-1", &["socket.lua", "ltn12.lua"]);
+        installer.install_fetched(&old).unwrap();
+        let other = binary_package(&temp, "other", "1.0-1", &["socket/extra.lua"]);
+        installer.install_fetched(&other).unwrap();
+        fs::write(share.join("mine.lua"), "-- not from a package").unwrap();
+
+        // The new version no longer ships ltn12.lua
+        let new = binary_package(
+            &temp,
+            "luasocket",
+            "3.1-1",
+            &["socket.lua", "socket/core.lua"],
+        );
+        installer.install_fetched(&new).unwrap();
+        assert!(!share.join("ltn12.lua").exists());
+        assert_eq!(
+            fs::read_to_string(share.join("socket.lua")).unwrap(),
+            "-- luasocket 3.1-1"
+        );
+
+        installer.remove_package("luasocket").unwrap();
+        assert!(!installer.is_installed("luasocket"));
+        assert!(!share.join("socket.lua").exists());
+        assert!(!share.join("socket/core.lua").exists());
+        assert!(!lua_modules.join("luasocket").exists());
+        assert!(share.join("socket/extra.lua").exists());
+        assert!(share.join("mine.lua").exists());
+    }
+
+    #[test]
+    fn test_failed_reinstall_keeps_earlier_version() {
+        let temp = TempDir::new().unwrap();
+        setup_test_env(&temp);
+        let installer = PackageInstaller::new(temp.path()).unwrap();
+        installer.init().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        let share = installer.tree(&lua_modules).lua_dir();
+
+        let old = binary_package(&temp, "luasocket", "3.0-1", &["socket.lua", "ltn12.lua"]);
+        installer.install_fetched(&old).unwrap();
+        // A directory where the new version puts its last file makes the move fail
+        // after the first files are already in place
+        fs::create_dir_all(share.join("zzz.lua")).unwrap();
+        fs::write(share.join("zzz.lua/keep"), "").unwrap();
+
+        let new = binary_package(&temp, "luasocket", "3.1-1", &["socket.lua", "zzz.lua"]);
+        assert!(installer.install_fetched(&new).is_err());
+        assert_eq!(
+            fs::read_to_string(share.join("socket.lua")).unwrap(),
+            "-- luasocket 3.0-1"
+        );
+        assert!(share.join("ltn12.lua").exists());
+        assert!(share.join("zzz.lua/keep").exists());
+        let files = InstalledFiles::load(&installer.packages_dir, "luasocket")
+            .unwrap()
+            .unwrap();
+        assert_eq!(files.version, "3.0-1");
+        assert!(files.files.contains_key(&lua_key(&installer, "ltn12.lua")));
+        assert!(installer.is_installed("luasocket"));
+    }
 }
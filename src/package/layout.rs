@@ -0,0 +1,319 @@
+//! Where installed files go in lua_modules, the way LuaRocks lays out a tree
+//!
+//! Lua modules go to `share/lua/<version>/` and native modules to
+//! `lib/lua/<version>/`, each at the path `require` maps its module name to:
+//! `socket.http` is `share/lua/5.4/socket/http.lua`, `socket.core` is
+//! `lib/lua/5.4/socket/core.so`. A package's scripts, configuration and
+//! copied directories stay in `lua_modules/<package>/`.
+
+use crate::build::builtin::native_module_path;
+use crate::core::path::{tree_lib_dir, tree_lua_dir};
+use crate::core::LpmResult;
+use crate::luarocks::rockspec::InstallTable;
+use crate::package::installer::copy_dir_recursive;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Top-level directories of a source tree that never hold the package's modules
+const NOT_MODULES: &[&str] = &[
+    "spec", "test", "tests", "example", "examples", "doc", "docs", "bin", "build",
+];
+
+/// The layout of one lua_modules tree (the project's, or a staging tree) for one Lua version
+#[derive(Debug, Clone)]
+pub struct TreeLayout {
+    root: PathBuf,
+    version: String,
+}
+
+impl TreeLayout {
+    pub fn new(lua_modules: &Path, version: &str) -> Self {
+        Self {
+            root: lua_modules.to_path_buf(),
+            version: version.to_string(),
+        }
+    }
+
+    /// `share/lua/<version>`
+    pub fn lua_dir(&self) -> PathBuf {
+        tree_lua_dir(&self.root, &self.version)
+    }
+
+    /// `lib/lua/<version>`
+    pub fn lib_dir(&self) -> PathBuf {
+        tree_lib_dir(&self.root, &self.version)
+    }
+
+    /// `lua_modules/<package>`: scripts, configuration and copied directories
+    pub fn package_dir(&self, package: &str) -> PathBuf {
+        self.root.join(package)
+    }
+
+    /// Where Lua module `module`, built from `source`, is installed
+    ///
+    /// As in LuaRocks, a source named `init.lua` becomes `<module>/init.lua`.
+    pub fn lua_module_path(&self, module: &str, source: &str) -> PathBuf {
+        let source = Path::new(source);
+        let mut path = self.lua_dir();
+        path.extend(module.split('.'));
+        if source.file_name().is_some_and(|n| n == "init.lua") && !module.ends_with(".init") {
+            path.push("init.lua");
+        } else {
+            let extension = source.extension().unwrap_or("lua".as_ref());
+            path.set_extension(extension);
+        }
+        path
+    }
+
+    /// Where native module `module` is installed, with this platform's extension
+    pub fn native_module_path(&self, module: &str) -> PathBuf {
+        let module = ["so", "dll", "dylib"]
+            .iter()
+            .find_map(|ext| module.strip_suffix(&format!(".{}", ext)))
+            .unwrap_or(module);
+        native_module_path(&self.lib_dir(), module)
+    }
+
+    /// The directories LuaRocks passes to `make install`, CMake and build commands
+    pub fn variables(&self, package: &str) -> HashMap<String, String> {
+        let package_dir = self.package_dir(package);
+        [
+            ("PREFIX", package_dir.clone()),
+            ("LUADIR", self.lua_dir()),
+            ("LIBDIR", self.lib_dir()),
+            ("BINDIR", package_dir.join("bin")),
+            ("CONFDIR", package_dir.join("conf")),
+        ]
+        .into_iter()
+        .map(|(name, path)| (name.to_string(), path.to_string_lossy().to_string()))
+        .collect()
+    }
+
+    /// Install a Lua module from `base`
+    pub fn install_lua_module(&self, base: &Path, module: &str, source: &str) -> LpmResult<()> {
+        let dst = self.lua_module_path(module, source);
+        copy_entry(&base.join(source), &dst)
+    }
+
+    /// Install the files of a rockspec's `build.install` table found under
+    /// `base`; entries whose file is missing are skipped
+    ///
+    /// Plain list entries, keyed by their file name, go to the top of the
+    /// module directories, as in LuaRocks.
+    pub fn install(&self, base: &Path, package: &str, install: &InstallTable) -> LpmResult<()> {
+        let is_plain =
+            |key: &str, source: &str| Path::new(source).file_name() == Some(key.as_ref());
+        for (module, source) in &install.lua {
+            let src = base.join(source);
+            if !src.exists() {
+                continue;
+            }
+            if is_plain(module, source) {
+                copy_entry(&src, &self.lua_dir().join(module))?;
+            } else {
+                self.install_lua_module(base, module, source)?;
+            }
+        }
+        for (module, source) in &install.lib {
+            let src = base.join(source);
+            if !src.exists() {
+                continue;
+            }
+            if src.is_dir() {
+                copy_entry(&src, &self.lib_dir().join(module))?;
+            } else {
+                copy_entry(&src, &self.native_module_path(module))?;
+            }
+        }
+        let package_dir = self.package_dir(package);
+        for (section, entries) in [("bin", &install.bin), ("conf", &install.conf)] {
+            for (name, source) in entries {
+                let src = base.join(source);
+                if src.exists() {
+                    copy_entry(&src, &package_dir.join(section).join(name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Install a rockspec's `build.copy_directories` into the package's directory
+    pub fn copy_directories(
+        &self,
+        base: &Path,
+        package: &str,
+        directories: &[String],
+    ) -> LpmResult<()> {
+        for dir in directories {
+            let src = base.join(dir);
+            if src.is_dir() {
+                copy_dir_recursive(&src, &self.package_dir(package).join(dir))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The Lua modules of a source tree without `build.modules`, as
+/// `(module, path relative to source)`, like LuaRocks' builtin backend finds them
+///
+/// Modules are looked for in the first of `lua/`, `src/` and `lib/` that
+/// exists, else in the whole tree; tests, examples and docs are skipped.
+pub fn autodetect_modules(source: &Path) -> Vec<(String, String)> {
+    let prefix = ["lua", "src", "lib"]
+        .into_iter()
+        .find(|dir| source.join(dir).is_dir());
+    let base = prefix.map_or_else(|| source.to_path_buf(), |dir| source.join(dir));
+
+    let mut modules = Vec::new();
+    let walker = WalkDir::new(&base)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            entry.depth() == 0
+                || !(name.starts_with('.')
+                    || entry.depth() == 1
+                        && entry.file_type().is_dir()
+                        && NOT_MODULES.contains(&name.as_ref()))
+        });
+    for entry in walker.flatten() {
+        if !entry.file_type().is_file() || entry.path().extension().is_none_or(|e| e != "lua") {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(&base) else {
+            continue;
+        };
+        let parts: Vec<String> = relative
+            .with_extension("")
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        let mut file: Vec<&str> = prefix.into_iter().collect();
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        file.push(&relative);
+        modules.push((parts.join("."), file.join("/")));
+    }
+    modules.sort();
+    modules
+}
+
+/// Copy a file, or a directory's contents, to `dst`
+fn copy_entry(src: &Path, dst: &Path) -> LpmResult<()> {
+    if src.is_dir() {
+        return copy_dir_recursive(src, dst);
+    }
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(src, dst)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::builtin::native_extension;
+    use tempfile::TempDir;
+
+    fn write(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "return {}").unwrap();
+    }
+
+    #[test]
+    fn test_lua_module_path() {
+        let tree = TreeLayout::new(Path::new("/p/lua_modules"), "5.4");
+        let share = PathBuf::from("/p/lua_modules/share/lua/5.4");
+
+        assert_eq!(
+            tree.lua_module_path("socket.http", "src/http.lua"),
+            share.join("socket").join("http.lua")
+        );
+        assert_eq!(
+            tree.lua_module_path("pl", "lua/pl/init.lua"),
+            share.join("pl").join("init.lua")
+        );
+        assert_eq!(
+            tree.lua_module_path("pl.init", "lua/pl/init.lua"),
+            share.join("pl").join("init.lua")
+        );
+        assert_eq!(
+            tree.native_module_path("socket.core"),
+            PathBuf::from("/p/lua_modules/lib/lua/5.4")
+                .join("socket")
+                .join(format!("core.{}", native_extension()))
+        );
+        assert_eq!(
+            tree.native_module_path("lfs.so"),
+            tree.native_module_path("lfs")
+        );
+    }
+
+    #[test]
+    fn test_install_table() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("source");
+        write(&source.join("src/ltn12.lua"));
+        write(&source.join("src/http.lua"));
+        write(&source.join("bin/tool"));
+        write(&source.join("build/lfs.so"));
+
+        let mut install = InstallTable::default();
+        install
+            .lua
+            .insert("ltn12.lua".to_string(), "src/ltn12.lua".to_string());
+        install
+            .lua
+            .insert("socket.http".to_string(), "src/http.lua".to_string());
+        install
+            .lib
+            .insert("lfs".to_string(), "build/lfs.so".to_string());
+        install
+            .bin
+            .insert("tool".to_string(), "bin/tool".to_string());
+        install
+            .conf
+            .insert("missing.conf".to_string(), "missing.conf".to_string());
+
+        let lua_modules = temp.path().join("lua_modules");
+        let tree = TreeLayout::new(&lua_modules, "5.1");
+        tree.install(&source, "pkg", &install).unwrap();
+
+        assert!(tree.lua_dir().join("ltn12.lua").exists());
+        assert!(tree.lua_dir().join("socket/http.lua").exists());
+        assert!(tree.native_module_path("lfs").exists());
+        assert!(lua_modules.join("pkg/bin/tool").exists());
+        assert!(!lua_modules.join("pkg/conf").exists());
+    }
+
+    #[test]
+    fn test_autodetect_modules() {
+        let temp = TempDir::new().unwrap();
+        write(&temp.path().join("src/foo.lua"));
+        write(&temp.path().join("src/foo/bar.lua"));
+        write(&temp.path().join("src/foo/init.lua"));
+        write(&temp.path().join("src/test/foo_spec.lua"));
+        write(&temp.path().join("spec/foo_spec.lua"));
+
+        assert_eq!(
+            autodetect_modules(temp.path()),
+            vec![
+                ("foo".to_string(), "src/foo.lua".to_string()),
+                ("foo.bar".to_string(), "src/foo/bar.lua".to_string()),
+                ("foo.init".to_string(), "src/foo/init.lua".to_string()),
+            ]
+        );
+
+        let flat = TempDir::new().unwrap();
+        write(&flat.path().join("mylib.lua"));
+        write(&flat.path().join("examples/demo.lua"));
+        write(&flat.path().join(".hidden/x.lua"));
+        assert_eq!(
+            autodetect_modules(flat.path()),
+            vec![("mylib".to_string(), "mylib.lua".to_string())]
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use crate::cache::Cache;
+use crate::core::{LpmError, LpmResult};
+use crate::package::dependency::DependencySpec;
+use crate::package::lockfile::LockedPackage;
+use crate::package::manifest::PackageManifest;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Marker left in copied local packages, holding the checksum of what was copied
+const COPY_MARKER: &str = ".lpm-source-checksum";
+
+/// Join a `path:` dependency onto the directory of the package declaring it
+///
+/// `base` is that package's path relative to the project root (`None` for the
+/// project itself). `.` and `..` are folded away, so every local package is
+/// recorded under a single spelling; absolute paths stay absolute.
+pub fn join_path(base: Option<&str>, path: &str) -> String {
+    let joined = match base {
+        Some(base) => Path::new(base).join(path),
+        None => PathBuf::from(path),
+    };
+
+    let mut parts: Vec<Component> = Vec::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match parts.last() {
+                Some(Component::Normal(_)) => {
+                    parts.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => parts.push(component),
+            },
+            _ => parts.push(component),
+        }
+    }
+
+    if parts.is_empty() {
+        return ".".to_string();
+    }
+    parts
+        .iter()
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Read the local package at `path` (relative to `project_root`) into a lockfile entry
+///
+/// Its `path:` dependencies are rewritten relative to the project root, so the
+/// entry's dependencies can be locked without knowing where it lives.
+pub fn lock(project_root: &Path, path: &str) -> LpmResult<(LockedPackage, PackageManifest)> {
+    let dir = project_root.join(path);
+    if !dir.join("package.yaml").is_file() {
+        return Err(LpmError::Package(format!(
+            "Local dependency '{}' has no package.yaml (looked in {})",
+            path,
+            dir.display()
+        )));
+    }
+    let manifest = PackageManifest::load(&dir)?;
+
+    let dependencies: HashMap<String, String> = manifest
+        .dependencies
+        .iter()
+        .map(|(name, spec)| {
+            let spec = match DependencySpec::parse(spec) {
+                DependencySpec::Path(nested) => format!("path:{}", join_path(Some(path), &nested)),
+                _ => spec.clone(),
+            };
+            (name.clone(), spec)
+        })
+        .collect();
+
+    let locked = LockedPackage {
+        version: manifest.version.clone(),
+        source: "path".to_string(),
+        rockspec_url: None,
+        source_url: Some(path.to_string()),
+        checksum: manifest_checksum(&dir)?,
+        size: None,
+        dependencies,
+        build: None,
+        commit: None,
+    };
+    Ok((locked, manifest))
+}
+
+/// Checksum of a local package's package.yaml
+///
+/// Sources are linked rather than locked, so package.lock only changes when
+/// the package's metadata or dependencies do.
+pub fn manifest_checksum(dir: &Path) -> LpmResult<String> {
+    let manifest = dir.join("package.yaml");
+    if !manifest.is_file() {
+        return Err(LpmError::Package(format!(
+            "Local package not found: {} has no package.yaml",
+            dir.display()
+        )));
+    }
+    Cache::checksum(&manifest)
+}
+
+/// The directory holding a local package's Lua modules
+///
+/// The first of `lua/`, `src/` and `lib/` that exists (or `<dir>/<name>/`
+/// inside it, for the `lua/<name>/init.lua` layout), else the package itself.
+pub fn source_root(dir: &Path, name: &str) -> PathBuf {
+    for sub in ["lua", "src", "lib"] {
+        let candidate = dir.join(sub);
+        if candidate.is_dir() {
+            let nested = candidate.join(name);
+            return if nested.is_dir() { nested } else { candidate };
+        }
+    }
+    dir.to_path_buf()
+}
+
+/// Make `dest` (in lua_modules) point at a local package's sources
+///
+/// Symlinks where possible, so edits show up immediately. Elsewhere the sources
+/// are copied, and copied again only when they have changed.
+pub fn link(source: &Path, dest: &Path) -> LpmResult<()> {
+    let source = fs::canonicalize(source).map_err(|e| {
+        LpmError::Path(format!(
+            "Failed to resolve local package {}: {}",
+            source.display(),
+            e
+        ))
+    })?;
+
+    #[cfg(unix)]
+    {
+        if fs::read_link(dest).ok().as_deref() == Some(source.as_path()) {
+            return Ok(());
+        }
+        remove(dest)?;
+        if std::os::unix::fs::symlink(&source, dest).is_ok() {
+            return Ok(());
+        }
+    }
+
+    copy_on_change(&source, dest)
+}
+
+/// Remove `dest` if it is a link to a local package
+///
+/// Keeps later installs from writing through the link into the package itself.
+pub fn unlink(dest: &Path) -> LpmResult<()> {
+    let is_symlink = fs::symlink_metadata(dest)
+        .map(|metadata| metadata.file_type().is_symlink())
+        .unwrap_or(false);
+    if is_symlink {
+        fs::remove_file(dest)?;
+    }
+    Ok(())
+}
+
+fn copy_on_change(source: &Path, dest: &Path) -> LpmResult<()> {
+    let checksum = tree_checksum(source)?;
+    let marker = dest.join(COPY_MARKER);
+    if fs::read_to_string(&marker).ok().as_deref() == Some(checksum.as_str()) {
+        return Ok(());
+    }
+
+    remove(dest)?;
+    for entry in WalkDir::new(source)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+    {
+        let entry = entry?;
+        let target = dest.join(entry.path().strip_prefix(source).unwrap());
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    fs::write(&marker, checksum)?;
+    Ok(())
+}
+
+/// Hash of every (non-hidden) file under `dir`: relative paths and contents
+fn tree_checksum(dir: &Path) -> LpmResult<String> {
+    let mut hasher = Sha256::new();
+    for entry in WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+    {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(dir).unwrap();
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(entry.path())?);
+    }
+    Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+}
+
+/// Remove whatever is at `dest`: a link, a file or a directory
+fn remove(dest: &Path) -> LpmResult<()> {
+    match fs::symlink_metadata(dest) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(dest)?,
+        Ok(_) => fs::remove_file(dest)?,
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_join_path_normalizes() {
+        assert_eq!(join_path(None, "./libs/a"), "libs/a");
+        assert_eq!(join_path(None, "../shared"), "../shared");
+        assert_eq!(join_path(Some("../libs/a"), "../b"), "../libs/b");
+        assert_eq!(join_path(Some("libs/a"), "../.."), ".");
+        assert_eq!(join_path(Some("libs/a"), "/opt/lib"), "/opt/lib");
+    }
+
+    #[test]
+    fn test_lock_rewrites_nested_paths() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().join("libs/a");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("package.yaml"),
+            "name: a\nversion: 0.2.0\ndependencies:\n  b: \"path:../b\"\n  lpeg: \"^1.0\"\n",
+        )
+        .unwrap();
+
+        let (locked, manifest) = lock(temp.path(), "libs/a").unwrap();
+        assert_eq!(manifest.name, "a");
+        assert_eq!(locked.source, "path");
+        assert_eq!(locked.version, "0.2.0");
+        assert_eq!(locked.source_url.as_deref(), Some("libs/a"));
+        assert_eq!(locked.dependencies["b"], "path:libs/b");
+        assert_eq!(locked.dependencies["lpeg"], "^1.0");
+
+        assert!(lock(temp.path(), "libs/missing").is_err());
+    }
+
+    #[test]
+    fn test_source_root_prefers_module_directories() {
+        let temp = TempDir::new().unwrap();
+        assert_eq!(source_root(temp.path(), "a"), temp.path());
+
+        fs::create_dir_all(temp.path().join("src")).unwrap();
+        assert_eq!(source_root(temp.path(), "a"), temp.path().join("src"));
+
+        fs::create_dir_all(temp.path().join("lua/a")).unwrap();
+        assert_eq!(source_root(temp.path(), "a"), temp.path().join("lua/a"));
+    }
+
+    #[test]
+    fn test_copy_on_change() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("src");
+        let dest = temp.path().join("lua_modules/a");
+        fs::create_dir_all(source.join(".git")).unwrap();
+        fs::write(source.join("a.lua"), "return 1").unwrap();
+        fs::write(source.join(".git/HEAD"), "ref").unwrap();
+
+        copy_on_change(&source, &dest).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("a.lua")).unwrap(), "return 1");
+        assert!(!dest.join(".git").exists());
+
+        // Unchanged sources are left alone
+        fs::write(dest.join("a.lua"), "edited in place").unwrap();
+        copy_on_change(&source, &dest).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("a.lua")).unwrap(),
+            "edited in place"
+        );
+
+        fs::write(source.join("a.lua"), "return 2").unwrap();
+        copy_on_change(&source, &dest).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("a.lua")).unwrap(), "return 2");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_and_unlink() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("src");
+        let dest = temp.path().join("a");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("a.lua"), "return 1").unwrap();
+
+        link(&source, &dest).unwrap();
+        assert!(fs::symlink_metadata(&dest)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        fs::write(source.join("a.lua"), "return 2").unwrap();
+        assert_eq!(fs::read_to_string(dest.join("a.lua")).unwrap(), "return 2");
+
+        unlink(&dest).unwrap();
+        assert!(!dest.exists());
+        assert!(source.join("a.lua").exists());
+    }
+}
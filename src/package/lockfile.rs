@@ -1,7 +1,7 @@
 use crate::core::{LpmError, LpmResult};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 
@@ -21,6 +21,7 @@ fn default_lockfile_version() -> u32 {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LockedPackage {
     pub version: String,
+    /// `git`, `path`, or the name of the registry the package was resolved from
     pub source: String,
     #[serde(default)]
     pub rockspec_url: Option<String>,
@@ -33,6 +34,16 @@ pub struct LockedPackage {
     pub dependencies: HashMap<String, String>,
     #[serde(default)]
     pub build: Option<LockedBuild>,
+    /// Resolved commit SHA, for `source: git` packages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+impl LockedPackage {
+    /// Whether the package was resolved from a registry rather than git or a local path
+    pub fn is_registry(&self) -> bool {
+        !matches!(self.source.as_str(), "git" | "path")
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,6 +108,79 @@ impl Lockfile {
     pub fn has_package(&self, name: &str) -> bool {
         self.packages.contains_key(name)
     }
+
+    /// The locked packages reachable from `roots`, including the roots themselves
+    ///
+    /// Roots and dependencies that aren't locked (e.g. `path:` dependencies) are skipped.
+    pub fn dependency_closure<'a>(
+        &self,
+        roots: impl IntoIterator<Item = &'a str>,
+    ) -> HashSet<String> {
+        let mut closure = HashSet::new();
+        let mut stack: Vec<&str> = roots.into_iter().collect();
+        while let Some(name) = stack.pop() {
+            let Some(package) = self.packages.get(name) else {
+                continue;
+            };
+            if closure.insert(name.to_string()) {
+                stack.extend(package.dependencies.keys().map(String::as_str));
+            }
+        }
+        closure
+    }
+
+    /// All locked packages ordered so that every package comes after its dependencies
+    ///
+    /// Packages that don't depend on each other are ordered by name, so the
+    /// order is stable across runs.
+    pub fn install_order(&self) -> LpmResult<Vec<String>> {
+        let mut remaining: HashMap<&str, usize> = HashMap::new();
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (name, package) in &self.packages {
+            let locked_deps: HashSet<&str> = package
+                .dependencies
+                .keys()
+                .map(String::as_str)
+                .filter(|dep| self.packages.contains_key(*dep) && dep != name)
+                .collect();
+            remaining.insert(name, locked_deps.len());
+            for dep in locked_deps {
+                dependents.entry(dep).or_default().push(name);
+            }
+        }
+
+        let mut ready: BTreeSet<&str> = remaining
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(name, _)| *name)
+            .collect();
+        let mut order = Vec::with_capacity(self.packages.len());
+        while let Some(name) = ready.pop_first() {
+            order.push(name.to_string());
+            for dependent in dependents.get(name).into_iter().flatten() {
+                let count = remaining.get_mut(dependent).expect("dependent is locked");
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+
+        if order.len() < self.packages.len() {
+            let mut cycle: Vec<&str> = remaining
+                .iter()
+                .filter(|(_, count)| **count > 0)
+                .map(|(name, _)| *name)
+                .collect();
+            cycle.sort();
+            return Err(LpmError::Package(format!(
+                "package.lock contains a dependency cycle between: {}",
+                cycle.join(", ")
+            )));
+        }
+
+        Ok(order)
+    }
 }
 
 impl Default for Lockfile {
@@ -110,6 +194,24 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_is_registry() {
+        let mut package = LockedPackage {
+            version: "1.0.0".to_string(),
+            source: "internal".to_string(),
+            rockspec_url: None,
+            source_url: None,
+            checksum: "sha256:abc".to_string(),
+            size: None,
+            dependencies: HashMap::new(),
+            build: None,
+            commit: None,
+        };
+        assert!(package.is_registry());
+        package.source = "git".to_string();
+        assert!(!package.is_registry());
+    }
+
     #[test]
     fn test_lockfile_new() {
         let lockfile = Lockfile::new();
@@ -129,6 +231,7 @@ mod tests {
             size: Some(1024),
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
 
         lockfile.add_package("test-package".to_string(), package);
@@ -147,6 +250,7 @@ mod tests {
             size: None,
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
 
         lockfile.add_package("test-package".to_string(), package.clone());
@@ -167,6 +271,7 @@ mod tests {
             size: None,
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
 
         assert!(!lockfile.has_package("test-package"));
@@ -187,6 +292,7 @@ mod tests {
             size: Some(1024),
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
 
         lockfile.add_package("test-package".to_string(), package);
@@ -222,6 +328,7 @@ mod tests {
             size: None,
             dependencies,
             build: None,
+            commit: None,
         };
 
         assert_eq!(package.dependencies.len(), 2);
@@ -246,6 +353,7 @@ mod tests {
             size: None,
             dependencies: std::collections::HashMap::new(),
             build: Some(build),
+            commit: None,
         };
 
         assert!(package.build.is_some());
@@ -293,4 +401,64 @@ mod tests {
         let lockfile = Lockfile::new();
         assert!(!lockfile.has_package("nonexistent"));
     }
+
+    fn locked_with_deps(deps: &[&str]) -> LockedPackage {
+        LockedPackage {
+            version: "1.0.0-1".to_string(),
+            source: "luarocks".to_string(),
+            rockspec_url: None,
+            source_url: None,
+            checksum: "sha256:00".to_string(),
+            size: None,
+            dependencies: deps
+                .iter()
+                .map(|d| (d.to_string(), "*".to_string()))
+                .collect(),
+            build: None,
+            commit: None,
+        }
+    }
+
+    #[test]
+    fn test_lockfile_install_order() {
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package(
+            "app-lib".to_string(),
+            locked_with_deps(&["penlight", "lpeg"]),
+        );
+        lockfile.add_package("penlight".to_string(), locked_with_deps(&["luafilesystem"]));
+        lockfile.add_package("luafilesystem".to_string(), locked_with_deps(&[]));
+        lockfile.add_package("lpeg".to_string(), locked_with_deps(&["lua"]));
+
+        assert_eq!(
+            lockfile.install_order().unwrap(),
+            vec!["lpeg", "luafilesystem", "penlight", "app-lib"]
+        );
+    }
+
+    #[test]
+    fn test_lockfile_install_order_cycle() {
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package("a".to_string(), locked_with_deps(&["b"]));
+        lockfile.add_package("b".to_string(), locked_with_deps(&["a"]));
+        lockfile.add_package("c".to_string(), locked_with_deps(&[]));
+
+        let err = lockfile.install_order().unwrap_err().to_string();
+        assert!(err.contains("cycle between: a, b"), "{err}");
+    }
+
+    #[test]
+    fn test_lockfile_dependency_closure() {
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package("penlight".to_string(), locked_with_deps(&["luafilesystem"]));
+        lockfile.add_package("luafilesystem".to_string(), locked_with_deps(&[]));
+        lockfile.add_package("busted".to_string(), locked_with_deps(&["say"]));
+        lockfile.add_package("say".to_string(), locked_with_deps(&[]));
+
+        let closure = lockfile.dependency_closure(["penlight", "local-pkg"]);
+        assert_eq!(
+            closure,
+            HashSet::from(["penlight".to_string(), "luafilesystem".to_string()])
+        );
+    }
 }
@@ -1,23 +1,36 @@
 use crate::cache::Cache;
 use crate::config::Config;
-use crate::core::LpmResult;
+use crate::core::{LpmError, LpmResult};
 use crate::luarocks::client::LuaRocksClient;
+use crate::luarocks::manifest::Manifest;
 use crate::luarocks::rockspec::Rockspec;
-use crate::luarocks::search_api::SearchAPI;
+use crate::package::dependency::DependencySpec;
+use crate::package::git::{package_rockspec, tree_checksum, GitFetcher};
+use crate::package::local;
 use crate::package::lockfile::{LockedPackage, Lockfile};
 use crate::package::manifest::PackageManifest;
 use crate::resolver::DependencyResolver;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::path::Path;
 
 /// Builder for creating lockfiles from manifests
 pub struct LockfileBuilder {
     cache: Cache,
+    offline: bool,
 }
 
 impl LockfileBuilder {
     pub fn new(cache: Cache) -> Self {
-        Self { cache }
+        Self {
+            cache,
+            offline: false,
+        }
+    }
+
+    /// Build only from the cached manifest, rockspecs and sources
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
     }
 
     /// Generate a lockfile from a manifest
@@ -32,39 +45,60 @@ impl LockfileBuilder {
     pub async fn build_lockfile(
         &self,
         manifest: &PackageManifest,
-        _project_root: &Path,
+        project_root: &Path,
         exclude_dev: bool,
     ) -> LpmResult<Lockfile> {
         let mut lockfile = Lockfile::new();
 
         // Setup clients for fetching rockspecs
         let config = Config::load()?;
-        let client = LuaRocksClient::new(&config, self.cache.clone());
-        let search_api = SearchAPI::new();
-
-        // Fetch manifest for resolver
-        let luarocks_manifest = client.fetch_manifest().await?;
-        let resolver = DependencyResolver::new(luarocks_manifest.clone());
-
-        // Resolve all dependencies
-        let resolved_versions = resolver.resolve(&manifest.dependencies).await?;
-        let resolved_dev_versions = if !exclude_dev {
-            resolver.resolve(&manifest.dev_dependencies).await?
+        let client = LuaRocksClient::new(&config, self.cache.clone()).with_offline(self.offline);
+
+        // Git and local dependencies are pinned first; their own requirements join the registry roots
+        let roots = self.lock_source_dependencies(
+            &manifest.dependencies,
+            project_root,
+            None,
+            &mut lockfile,
+        )?;
+        let dev_roots = if !exclude_dev {
+            self.lock_source_dependencies(
+                &manifest.dev_dependencies,
+                project_root,
+                None,
+                &mut lockfile,
+            )?
         } else {
             HashMap::new()
         };
 
-        // Use parallel downloads for better performance
-        use crate::package::downloader::{DownloadTask, ParallelDownloader};
-        let parallel_downloader = ParallelDownloader::new(client, Some(10));
+        // Fetch manifest for resolver
+        let luarocks_manifest = self.registry_manifest(&client, &roots, &dev_roots).await?;
+        let resolver = DependencyResolver::new(luarocks_manifest.clone())
+            .with_root_name(&manifest.name)
+            .with_lua_version(&manifest.lua_version)
+            .with_offline(self.offline);
 
-        // Get source URLs from manifest for parallel downloads (already fetched above)
+        // Resolve all dependencies
+        let resolved_versions = resolver.resolve(&roots).await?;
+        let resolved_dev_versions = resolver.resolve(&dev_roots).await?;
+        // A package pinned from git or a local path is never replaced by a registry version
+        let is_pinned = |name: &String| lockfile.get_package(name).is_some();
+        let resolved_versions: HashMap<_, _> = resolved_versions
+            .into_iter()
+            .filter(|(name, _)| !is_pinned(name))
+            .collect();
+        let resolved_dev_versions: HashMap<_, _> = resolved_dev_versions
+            .into_iter()
+            .filter(|(name, _)| !is_pinned(name))
+            .collect();
 
         // Create download tasks for all packages
+        use crate::package::downloader::{DownloadTask, ParallelDownloader};
         let mut download_tasks = Vec::new();
         for (name, version) in &resolved_versions {
             let version_str = version.to_string();
-            let rockspec_url = search_api.get_rockspec_url(name, &version_str, None);
+            let rockspec_url = client.rockspec_url(&luarocks_manifest, name, &version_str);
 
             // Try to get source URL from manifest
             let source_url = luarocks_manifest
@@ -88,7 +122,7 @@ impl LockfileBuilder {
         if !exclude_dev {
             for (name, version) in &resolved_dev_versions {
                 let version_str = version.to_string();
-                let rockspec_url = search_api.get_rockspec_url(name, &version_str, None);
+                let rockspec_url = client.rockspec_url(&luarocks_manifest, name, &version_str);
 
                 // Try to get source URL from manifest
                 let source_url =
@@ -111,7 +145,17 @@ impl LockfileBuilder {
             }
         }
 
+        // Remember which registry each rockspec comes from before the client is handed off
+        let registry_sources: HashMap<String, (String, String)> = download_tasks
+            .iter()
+            .map(|task| {
+                let source = client.source_name(&task.rockspec_url);
+                (task.name.clone(), (task.rockspec_url.clone(), source))
+            })
+            .collect();
+
         // Download all packages in parallel
+        let parallel_downloader = ParallelDownloader::new(client, Some(10));
         let download_results = parallel_downloader
             .download_packages(download_tasks, None)
             .await;
@@ -139,41 +183,20 @@ impl LockfileBuilder {
                 .and_then(|p| std::fs::metadata(p).ok())
                 .map(|m| m.len());
 
-            // Parse dependencies from rockspec
-            let mut dependencies = HashMap::new();
-            for dep in &result.rockspec.dependencies {
-                // Skip lua runtime dependencies
-                if dep.trim().starts_with("lua")
-                    && (dep.contains(">=")
-                        || dep.contains(">")
-                        || dep.contains("==")
-                        || dep.contains("~>"))
-                {
-                    continue;
-                }
-
-                // Parse dependency string
-                if let Some(pos) = dep.find(char::is_whitespace) {
-                    let dep_name = dep[..pos].trim().to_string();
-                    let dep_version = dep[pos..].trim().to_string();
-                    dependencies.insert(dep_name, dep_version);
-                } else {
-                    // No whitespace - treat as dependency name only with wildcard version
-                    dependencies.insert(dep.trim().to_string(), "*".to_string());
-                }
-            }
+            let dependencies = locked_dependencies(&result.rockspec);
 
-            let version = result.version.clone();
             let name = result.name.clone();
+            let (rockspec_url, source) = registry_sources[&name].clone();
             let locked_package = crate::package::lockfile::LockedPackage {
-                version: version.clone(),
-                source: "luarocks".to_string(),
-                rockspec_url: Some(search_api.get_rockspec_url(&name, &version, None)),
+                version: result.version.clone(),
+                source,
+                rockspec_url: Some(rockspec_url),
                 source_url: result.rockspec.source.url.clone().into(),
                 checksum,
                 size,
                 dependencies,
                 build: None,
+                commit: None,
             };
 
             lockfile.add_package(name, locked_package);
@@ -186,12 +209,12 @@ impl LockfileBuilder {
     async fn build_locked_package(
         &self,
         client: &LuaRocksClient,
-        search_api: &SearchAPI,
+        manifest: &Manifest,
         name: &str,
         version: &str,
     ) -> LpmResult<LockedPackage> {
         // Get rockspec URL and fetch it
-        let rockspec_url = search_api.get_rockspec_url(name, version, None);
+        let rockspec_url = client.rockspec_url(manifest, name, version);
         let rockspec_content = client.download_rockspec(&rockspec_url).await?;
         let rockspec: Rockspec = client.parse_rockspec(&rockspec_content)?;
 
@@ -204,67 +227,225 @@ impl LockfileBuilder {
         // Get file size
         let size = std::fs::metadata(&source_path).ok().map(|m| m.len());
 
-        // Parse dependencies from rockspec
-        let mut dependencies = HashMap::new();
-        for dep in &rockspec.dependencies {
-            // Skip lua runtime dependencies
-            if dep.trim().starts_with("lua")
-                && (dep.contains(">=")
-                    || dep.contains(">")
-                    || dep.contains("==")
-                    || dep.contains("~>"))
-            {
-                continue;
-            }
-
-            // Parse dependency string
-            if let Some(pos) = dep.find(char::is_whitespace) {
-                let dep_name = dep[..pos].trim().to_string();
-                let dep_version = dep[pos..].trim().to_string();
-                dependencies.insert(dep_name, dep_version);
-            } else {
-                dependencies.insert(dep.trim().to_string(), "*".to_string());
-            }
-        }
+        let dependencies = locked_dependencies(&rockspec);
 
         Ok(LockedPackage {
             version: version.to_string(),
-            source: "luarocks".to_string(),
+            source: client.source_name(&rockspec_url),
             rockspec_url: Some(rockspec_url),
             source_url: Some(rockspec.source.url.clone()),
             checksum,
             size,
             dependencies,
             build: None,
+            commit: None,
         })
     }
 
+    /// The registry manifest, fetched only when something must be resolved from it
+    async fn registry_manifest(
+        &self,
+        client: &LuaRocksClient,
+        roots: &HashMap<String, String>,
+        dev_roots: &HashMap<String, String>,
+    ) -> LpmResult<Manifest> {
+        if roots.is_empty() && dev_roots.is_empty() {
+            return Ok(Manifest::default());
+        }
+        client.fetch_manifest().await
+    }
+
+    /// Pin the git and local path dependencies among `dependencies` into `lockfile`
+    ///
+    /// A git entry in `existing` is reused while its repository and reference
+    /// are unchanged, so the same commit is installed until the spec changes or
+    /// the lockfile is regenerated. Local packages are re-read every time, and
+    /// their own dependencies are followed. Returns the registry dependencies to
+    /// resolve: the declared ones plus those the pinned packages require.
+    fn lock_source_dependencies(
+        &self,
+        dependencies: &HashMap<String, String>,
+        project_root: &Path,
+        existing: Option<&Lockfile>,
+        lockfile: &mut Lockfile,
+    ) -> LpmResult<HashMap<String, String>> {
+        let fetcher = GitFetcher::new(self.cache.clone()).with_offline(self.offline);
+        let mut roots = HashMap::new();
+        let mut requirements = HashMap::new();
+
+        let mut names: Vec<_> = dependencies.keys().collect();
+        names.sort();
+        let mut pending: VecDeque<(String, String, Option<String>)> = names
+            .into_iter()
+            .map(|name| (name.clone(), dependencies[name].clone(), None))
+            .collect();
+
+        while let Some((name, spec, required_by)) = pending.pop_front() {
+            let locked = match DependencySpec::parse(&spec) {
+                DependencySpec::Registry(constraint) => {
+                    if required_by.is_none() {
+                        roots.insert(name, constraint);
+                    } else {
+                        requirements.entry(name).or_insert(constraint);
+                    }
+                    continue;
+                }
+                DependencySpec::Path(path) => {
+                    // Nested local paths were already rebased by local::lock
+                    let path = local::join_path(None, &path);
+                    if self.already_pinned(
+                        lockfile,
+                        &name,
+                        "path",
+                        &path,
+                        required_by.as_deref(),
+                    )? {
+                        continue;
+                    }
+                    let (locked, _) = local::lock(project_root, &path)?;
+                    let mut deps: Vec<_> = locked.dependencies.iter().collect();
+                    deps.sort();
+                    for (dep, dep_spec) in deps {
+                        pending.push_back((dep.clone(), dep_spec.clone(), Some(name.clone())));
+                    }
+                    locked
+                }
+                DependencySpec::Git(git) => {
+                    if self.already_pinned(
+                        lockfile,
+                        &name,
+                        "git",
+                        &git.url,
+                        required_by.as_deref(),
+                    )? {
+                        continue;
+                    }
+                    let reference = git.reference.to_string();
+                    let reusable = existing
+                        .and_then(|lockfile| lockfile.get_package(&name))
+                        .filter(|locked| {
+                            locked.source == "git"
+                                && locked.source_url.as_deref() == Some(git.url.as_str())
+                                && locked.version == reference
+                                && locked.commit.is_some()
+                        });
+                    let locked = match reusable {
+                        Some(locked) => locked.clone(),
+                        None => {
+                            let commit = fetcher.resolve(&git)?;
+                            let archive = fetcher.archive(&git.url, &commit, &name)?;
+                            let rockspec = package_rockspec(&archive, &name)?;
+                            LockedPackage {
+                                version: reference,
+                                source: "git".to_string(),
+                                rockspec_url: None,
+                                source_url: Some(git.url.clone()),
+                                checksum: tree_checksum(&archive)?,
+                                size: std::fs::metadata(&archive).ok().map(|m| m.len()),
+                                dependencies: locked_dependencies(&rockspec),
+                                build: None,
+                                commit: Some(commit),
+                            }
+                        }
+                    };
+                    requirements.extend(locked.dependencies.clone());
+                    locked
+                }
+            };
+            lockfile.add_package(name, locked);
+        }
+
+        for (name, constraint) in requirements {
+            let declared_elsewhere =
+                dependencies.contains_key(&name) || lockfile.get_package(&name).is_some();
+            if !declared_elsewhere {
+                roots.entry(name).or_insert(constraint);
+            }
+        }
+
+        Ok(roots)
+    }
+
+    /// Whether `name` is already pinned to the same git repository or local path
+    ///
+    /// The same package pinned to two different places can't be installed.
+    fn already_pinned(
+        &self,
+        lockfile: &Lockfile,
+        name: &str,
+        source: &str,
+        location: &str,
+        required_by: Option<&str>,
+    ) -> LpmResult<bool> {
+        let Some(pinned) = lockfile.get_package(name) else {
+            return Ok(false);
+        };
+        if pinned.source == source && pinned.source_url.as_deref() == Some(location) {
+            return Ok(true);
+        }
+        Err(LpmError::Package(format!(
+            "'{}' is required from both {} and {}{}",
+            name,
+            pinned.source_url.as_deref().unwrap_or(&pinned.source),
+            location,
+            required_by
+                .map(|parent| format!(" (by {})", parent))
+                .unwrap_or_default()
+        )))
+    }
+
     /// Update lockfile incrementally - only rebuild changed packages
     pub async fn update_lockfile(
         &self,
         existing: &Lockfile,
         manifest: &PackageManifest,
-        _project_root: &Path,
+        project_root: &Path,
         exclude_dev: bool,
     ) -> LpmResult<Lockfile> {
         let mut new_lockfile = Lockfile::new();
 
         // Setup clients
         let config = Config::load()?;
-        let client = LuaRocksClient::new(&config, self.cache.clone());
-        let search_api = SearchAPI::new();
+        let client = LuaRocksClient::new(&config, self.cache.clone()).with_offline(self.offline);
+
+        // Git dependencies keep their locked commit while their spec is unchanged
+        let roots = self.lock_source_dependencies(
+            &manifest.dependencies,
+            project_root,
+            Some(existing),
+            &mut new_lockfile,
+        )?;
+        let dev_roots = if !exclude_dev {
+            self.lock_source_dependencies(
+                &manifest.dev_dependencies,
+                project_root,
+                Some(existing),
+                &mut new_lockfile,
+            )?
+        } else {
+            HashMap::new()
+        };
 
         // Fetch manifest for resolver
-        let luarocks_manifest = client.fetch_manifest().await?;
-        let resolver = DependencyResolver::new(luarocks_manifest);
+        let luarocks_manifest = self.registry_manifest(&client, &roots, &dev_roots).await?;
+        let resolver = DependencyResolver::new(luarocks_manifest.clone())
+            .with_root_name(&manifest.name)
+            .with_lua_version(&manifest.lua_version)
+            .with_offline(self.offline);
 
         // Resolve all dependencies
-        let resolved_versions = resolver.resolve(&manifest.dependencies).await?;
-        let resolved_dev_versions = if !exclude_dev {
-            resolver.resolve(&manifest.dev_dependencies).await?
-        } else {
-            HashMap::new()
-        };
+        let resolved_versions = resolver.resolve(&roots).await?;
+        let resolved_dev_versions = resolver.resolve(&dev_roots).await?;
+        // A package pinned from git or a local path is never replaced by a registry version
+        let is_pinned = |name: &String| new_lockfile.get_package(name).is_some();
+        let resolved_versions: HashMap<_, _> = resolved_versions
+            .into_iter()
+            .filter(|(name, _)| !is_pinned(name))
+            .collect();
+        let resolved_dev_versions: HashMap<_, _> = resolved_dev_versions
+            .into_iter()
+            .filter(|(name, _)| !is_pinned(name))
+            .collect();
 
         // Combine all dependencies
         let mut all_dependencies = resolved_versions.clone();
@@ -281,7 +462,7 @@ impl LockfileBuilder {
 
             // Check if package exists in existing lockfile with same version
             if let Some(existing_pkg) = existing.get_package(name) {
-                if existing_pkg.version == version_str {
+                if existing_pkg.is_registry() && existing_pkg.version == version_str {
                     // Version unchanged - reuse existing entry
                     new_lockfile.add_package(name.clone(), existing_pkg.clone());
                     processed.insert(name.clone());
@@ -299,7 +480,7 @@ impl LockfileBuilder {
 
             // Build new lockfile entry
             let locked_package = self
-                .build_locked_package(&client, &search_api, package_name, &version_str)
+                .build_locked_package(&client, &luarocks_manifest, package_name, &version_str)
                 .await?;
 
             new_lockfile.add_package(package_name.clone(), locked_package);
@@ -310,6 +491,30 @@ impl LockfileBuilder {
     }
 }
 
+/// A rockspec's dependencies as name → constraint, without the Lua runtime itself
+///
+/// Handles "luasocket >= 3.0", "penlight" (any version) and "lua>=5.1".
+fn locked_dependencies(rockspec: &Rockspec) -> HashMap<String, String> {
+    let mut dependencies = HashMap::new();
+    for dep in &rockspec.dependencies {
+        let dep = dep.trim();
+        let pos = dep
+            .find(|c: char| c.is_whitespace() || "<>=~!^".contains(c))
+            .unwrap_or(dep.len());
+        let (name, constraint) = (&dep[..pos], dep[pos..].trim());
+        if name.is_empty() || name == "lua" {
+            continue;
+        }
+        let constraint = if constraint.is_empty() {
+            "*"
+        } else {
+            constraint
+        };
+        dependencies.insert(name.to_string(), constraint.to_string());
+    }
+    dependencies
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -351,10 +556,8 @@ mod tests {
         let mut config = Config::load().unwrap();
         config.luarocks_manifest_url = format!("{}/manifest", mock_server.uri());
         let client = LuaRocksClient::new(&config, cache.clone());
-        let search_api = SearchAPI::new();
-        // We can't easily change search_api base_url, but we can mock the rockspec URL it generates
-        // The rockspec URL will be: https://luarocks.org/manifests/luarocks/test-pkg-1.0.0.rockspec
-        // But we need to mock it at the mock server
+        // Not in the manifest, so the rockspec URL is built for the mock registry
+        let manifest = Manifest::default();
 
         // Mock manifest endpoint
         let manifest_json = r#"{"repository": {"packages": {"test-pkg": {"1.0.0": {}}}}}"#;
@@ -382,7 +585,7 @@ dependencies = {{
     "dep2"
 }}
 build = {{
-    type = "builtin"
+    type = "builtin",
     modules = {{}}
 }}
 "#,
@@ -409,7 +612,7 @@ build = {{
 
         // Now build_locked_package should work - it will use cached rockspec, download source from mock
         let result = builder
-            .build_locked_package(&client, &search_api, "testpkg", "1.0.0")
+            .build_locked_package(&client, &manifest, "testpkg", "1.0.0")
             .await;
 
         // Should succeed - uses cached rockspec, downloads source from mock server
@@ -433,6 +636,23 @@ build = {{
         // Should have checksum and size from downloaded source
         assert!(!locked.checksum.is_empty());
         assert!(locked.size.is_some());
+        assert_eq!(locked.source, "luarocks");
+
+        // The registry the rockspec came from is recorded as the source
+        let registries = crate::luarocks::Registries::new(vec![crate::luarocks::Registry::new(
+            "internal",
+            &mock_server.uri(),
+        )]);
+        let client = LuaRocksClient::new(&config, cache).with_registries(registries);
+        let locked = builder
+            .build_locked_package(&client, &manifest, "testpkg", "1.0.0")
+            .await
+            .unwrap();
+        assert_eq!(locked.source, "internal");
+        assert_eq!(
+            locked.rockspec_url,
+            Some(format!("{}/testpkg-1.0.0.rockspec", mock_server.uri()))
+        );
     }
 
     #[tokio::test]
@@ -476,6 +696,7 @@ build = {{
             size: Some(1000),
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         existing.add_package("test-dep".to_string(), locked_pkg);
 
@@ -515,6 +736,23 @@ build = {{
         // The actual parsing happens in build_lockfile which requires network
     }
 
+    #[test]
+    fn test_locked_dependencies_skips_only_lua_runtime() {
+        let rockspec = Rockspec::parse_lua(
+            r#"package = "app"
+version = "1.0-1"
+source = { url = "https://example.com/app.tar.gz" }
+dependencies = { "lua >= 5.1", "lua>=5.1, < 5.5", "luafilesystem >= 1.8", "penlight" }
+"#,
+        )
+        .unwrap();
+
+        let deps = locked_dependencies(&rockspec);
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps["luafilesystem"], ">= 1.8");
+        assert_eq!(deps["penlight"], "*");
+    }
+
     #[test]
     fn test_build_lockfile_dependency_parsing_without_version() {
         // Test dependency parsing for deps without version constraints
@@ -681,6 +919,7 @@ build = {{
             size: None,
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         existing.add_package("test-pkg".to_string(), locked_pkg);
 
@@ -705,11 +944,11 @@ build = {{
         // (lua runtime skip, whitespace parsing, no whitespace parsing)
         let config = Config::load().unwrap();
         let client = LuaRocksClient::new(&config, cache);
-        let search_api = SearchAPI::new();
+        let manifest = Manifest::default();
 
         // Will fail on network, but tests dependency parsing structure
         let _result = builder
-            .build_locked_package(&client, &search_api, "test", "1.0.0")
+            .build_locked_package(&client, &manifest, "test", "1.0.0")
             .await;
     }
 
@@ -746,7 +985,7 @@ dependencies = {{
     "lua >= 5.1"
 }}
 build = {{
-    type = "builtin"
+    type = "builtin",
     modules = {{}}
 }}
 "#,
@@ -808,7 +1047,7 @@ source = {{
 }}
 dependencies = {{}}
 build = {{
-    type = "builtin"
+    type = "builtin",
     modules = {{}}
 }}
 "#,
@@ -1094,6 +1333,7 @@ build = {
             size: None,
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         existing.add_package("testpkg".to_string(), locked_pkg);
 
@@ -1126,6 +1366,7 @@ build = {
             size: None,
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         existing.add_package("test-pkg".to_string(), locked_pkg);
 
@@ -1204,7 +1445,7 @@ source = {{
 }}
 dependencies = {{}}
 build = {{
-    type = "builtin"
+    type = "builtin",
     modules = {{}}
 }}
 "#,
@@ -1258,7 +1499,7 @@ dependencies = {{
     "dep2 >= 1.0.0"
 }}
 build = {{
-    type = "builtin"
+    type = "builtin",
     modules = {{}}
 }}
 "#,
@@ -1287,8 +1528,7 @@ build = {{
         // This should execute the dependency parsing path for "dep1" (no whitespace -> wildcard)
         let result = builder.build_lockfile(&manifest, temp.path(), false).await;
         // May fail on dependency resolution, but executes the parsing path
-        if result.is_ok() {
-            let lockfile = result.unwrap();
+        if let Ok(lockfile) = result {
             let pkg = lockfile.get_package("testpkg");
             if let Some(pkg) = pkg {
                 // Should have dep1 with "*" version and dep2 with ">= 1.0.0"
@@ -1335,7 +1575,7 @@ dependencies = {{
     "dep1 >= 1.0.0"
 }}
 build = {{
-    type = "builtin"
+    type = "builtin",
     modules = {{}}
 }}
 "#,
@@ -1364,8 +1604,7 @@ build = {{
         // This should execute the lua runtime skip path (line 146-152)
         let result = builder.build_lockfile(&manifest, temp.path(), false).await;
         // May fail on dependency resolution, but executes the skip path
-        if result.is_ok() {
-            let lockfile = result.unwrap();
+        if let Ok(lockfile) = result {
             let pkg = lockfile.get_package("testpkg");
             if let Some(pkg) = pkg {
                 // Should have dep1 but not lua
@@ -1405,7 +1644,7 @@ source = {{
 }}
 dependencies = {{}}
 build = {{
-    type = "builtin"
+    type = "builtin",
     modules = {{}}
 }}
 "#,
@@ -1469,7 +1708,7 @@ source = {{
 }}
 dependencies = {{}}
 build = {{
-    type = "builtin"
+    type = "builtin",
     modules = {{}}
 }}
 "#,
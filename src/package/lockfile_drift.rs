@@ -0,0 +1,415 @@
+use crate::core::version::{parse_constraint, Version};
+use crate::package::dependency::DependencySpec;
+use crate::package::local;
+use crate::package::lockfile::{LockedPackage, Lockfile};
+use crate::package::manifest::PackageManifest;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+/// One way in which package.lock no longer matches package.yaml
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drift {
+    /// Declared in package.yaml but not locked
+    Added { name: String, constraint: String },
+    /// Locked, but nothing in package.yaml requires it anymore
+    Removed { name: String, version: String },
+    /// The locked version no longer satisfies the declared constraint
+    Unsatisfied {
+        name: String,
+        constraint: String,
+        version: String,
+        required_by: Option<String>,
+    },
+    /// A locked package depends on something that isn't locked
+    MissingTransitive { name: String, required_by: String },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::Added { name, constraint } => {
+                write!(f, "+ {} {} (in package.yaml, not locked)", name, constraint)
+            }
+            Drift::Removed { name, version } => {
+                write!(f, "- {} {} (locked, no longer required)", name, version)
+            }
+            Drift::Unsatisfied {
+                name,
+                constraint,
+                version,
+                required_by,
+            } => {
+                write!(
+                    f,
+                    "~ {}: locked {} does not satisfy '{}'",
+                    name, version, constraint
+                )?;
+                if let Some(parent) = required_by {
+                    write!(f, " (required by {})", parent)?;
+                }
+                Ok(())
+            }
+            Drift::MissingTransitive { name, required_by } => {
+                write!(f, "+ {} (required by {}, not locked)", name, required_by)
+            }
+        }
+    }
+}
+
+/// Differences between package.yaml and package.lock
+#[derive(Debug, Clone, Default)]
+pub struct LockfileDrift {
+    pub changes: Vec<Drift>,
+}
+
+impl LockfileDrift {
+    /// Compare the manifest's dependencies with the locked dependency graph
+    ///
+    /// Without `include_dev`, dev dependencies need not be locked, but locked
+    /// ones still count as required.
+    pub fn detect(manifest: &PackageManifest, lockfile: &Lockfile, include_dev: bool) -> Self {
+        let all_declared: HashMap<&String, &String> = manifest
+            .dependencies
+            .iter()
+            .chain(manifest.dev_dependencies.iter())
+            .collect();
+        let declared: HashMap<&String, &String> = all_declared
+            .iter()
+            .filter(|(name, _)| include_dev || manifest.dependencies.contains_key(**name))
+            .map(|(name, constraint)| (*name, *constraint))
+            .collect();
+
+        let mut changes = Vec::new();
+
+        let mut names: Vec<_> = declared.keys().copied().collect();
+        names.sort();
+        for name in names {
+            let constraint = declared[name];
+            match lockfile.get_package(name) {
+                None => changes.push(Drift::Added {
+                    name: name.clone(),
+                    constraint: constraint.clone(),
+                }),
+                Some(locked) => {
+                    if !satisfies_spec(locked, constraint) {
+                        changes.push(Drift::Unsatisfied {
+                            name: name.clone(),
+                            constraint: constraint.clone(),
+                            version: locked.version.clone(),
+                            required_by: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        // Walk the locked graph from the declared roots
+        let mut reachable = HashSet::new();
+        let mut stack: Vec<String> = all_declared.keys().map(|n| n.to_string()).collect();
+        let mut seen_edges = BTreeSet::new();
+        while let Some(name) = stack.pop() {
+            if !reachable.insert(name.clone()) {
+                continue;
+            }
+            let Some(locked) = lockfile.get_package(&name) else {
+                continue;
+            };
+            let mut deps: Vec<_> = locked.dependencies.iter().collect();
+            deps.sort();
+            for (dep, constraint) in deps {
+                if !seen_edges.insert((name.clone(), dep.clone())) {
+                    continue;
+                }
+                match lockfile.get_package(dep) {
+                    None => changes.push(Drift::MissingTransitive {
+                        name: dep.clone(),
+                        required_by: name.clone(),
+                    }),
+                    Some(dep_locked) => {
+                        if !satisfies_requirement(dep_locked, constraint) {
+                            changes.push(Drift::Unsatisfied {
+                                name: dep.clone(),
+                                constraint: constraint.clone(),
+                                version: dep_locked.version.clone(),
+                                required_by: Some(name.clone()),
+                            });
+                        }
+                        stack.push(dep.clone());
+                    }
+                }
+            }
+        }
+
+        let mut orphans: Vec<_> = lockfile
+            .packages
+            .iter()
+            .filter(|(name, _)| !reachable.contains(*name))
+            .collect();
+        orphans.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, locked) in orphans {
+            changes.push(Drift::Removed {
+                name: name.clone(),
+                version: locked.version.clone(),
+            });
+        }
+
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for LockfileDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "  {}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a locked package still matches what package.yaml declares
+///
+/// Git dependencies match while the repository and reference are unchanged,
+/// local ones while they point at the same directory.
+fn satisfies_spec(locked: &LockedPackage, spec: &str) -> bool {
+    match DependencySpec::parse(spec) {
+        DependencySpec::Git(git) => {
+            locked.source == "git"
+                && locked.source_url.as_deref() == Some(git.url.as_str())
+                && locked.version == git.reference.to_string()
+        }
+        DependencySpec::Path(path) => {
+            locked.source == "path"
+                && locked.source_url.as_deref() == Some(local::join_path(None, &path).as_str())
+        }
+        DependencySpec::Registry(constraint) => {
+            locked.is_registry() && satisfies(&locked.version, &constraint)
+        }
+    }
+}
+
+/// Whether a locked package satisfies another locked package's requirement
+///
+/// A package pinned from git or a local path stands in for any registry
+/// version of the same name.
+fn satisfies_requirement(locked: &LockedPackage, spec: &str) -> bool {
+    match DependencySpec::parse(spec) {
+        DependencySpec::Registry(_) if !locked.is_registry() => true,
+        _ => satisfies_spec(locked, spec),
+    }
+}
+
+/// Whether a locked version satisfies a constraint; unparseable input counts as drift
+fn satisfies(version: &str, constraint: &str) -> bool {
+    match (Version::parse(version), parse_constraint(constraint)) {
+        (Ok(version), Ok(constraint)) => version.satisfies(&constraint),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked(version: &str, deps: &[(&str, &str)]) -> LockedPackage {
+        LockedPackage {
+            version: version.to_string(),
+            source: "luarocks".to_string(),
+            rockspec_url: None,
+            source_url: None,
+            checksum: "sha256:00".to_string(),
+            size: None,
+            dependencies: deps
+                .iter()
+                .map(|(n, c)| (n.to_string(), c.to_string()))
+                .collect(),
+            build: None,
+            commit: None,
+        }
+    }
+
+    fn manifest(deps: &[(&str, &str)], dev: &[(&str, &str)]) -> PackageManifest {
+        let mut manifest = PackageManifest::default("app".to_string());
+        for (n, c) in deps {
+            manifest.dependencies.insert(n.to_string(), c.to_string());
+        }
+        for (n, c) in dev {
+            manifest
+                .dev_dependencies
+                .insert(n.to_string(), c.to_string());
+        }
+        manifest
+    }
+
+    fn local_package(path: &str, deps: &[(&str, &str)]) -> LockedPackage {
+        let mut package = locked("0.1.0", deps);
+        package.source = "path".to_string();
+        package.source_url = Some(path.to_string());
+        package
+    }
+
+    fn lockfile(packages: Vec<(&str, LockedPackage)>) -> Lockfile {
+        let mut lockfile = Lockfile::new();
+        for (name, pkg) in packages {
+            lockfile.add_package(name.to_string(), pkg);
+        }
+        lockfile
+    }
+
+    #[test]
+    fn test_no_drift() {
+        let manifest = manifest(&[("penlight", "^1.13.0")], &[("busted", "*")]);
+        let lockfile = lockfile(vec![
+            ("penlight", locked("1.13.1-1", &[("luafilesystem", "*")])),
+            ("luafilesystem", locked("1.8.0-1", &[])),
+            ("busted", locked("2.2.0-1", &[])),
+        ]);
+        assert!(LockfileDrift::detect(&manifest, &lockfile, true).is_empty());
+    }
+
+    #[test]
+    fn test_detects_added_removed_and_unsatisfied() {
+        let manifest = manifest(
+            &[
+                ("penlight", "^2.0.0"),
+                ("lpeg", "*"),
+                ("local", "path:../local"),
+            ],
+            &[],
+        );
+        let lockfile = lockfile(vec![
+            ("penlight", locked("1.13.1-1", &[])),
+            ("luasocket", locked("3.1.0-1", &[])),
+            ("local", local_package("../local", &[])),
+        ]);
+
+        let drift = LockfileDrift::detect(&manifest, &lockfile, true);
+        assert_eq!(
+            drift.changes,
+            vec![
+                Drift::Added {
+                    name: "lpeg".to_string(),
+                    constraint: "*".to_string()
+                },
+                Drift::Unsatisfied {
+                    name: "penlight".to_string(),
+                    constraint: "^2.0.0".to_string(),
+                    version: "1.13.1-1".to_string(),
+                    required_by: None,
+                },
+                Drift::Removed {
+                    name: "luasocket".to_string(),
+                    version: "3.1.0-1".to_string()
+                },
+            ]
+        );
+        let text = drift.to_string();
+        assert!(text.contains("+ lpeg *"), "{text}");
+        assert!(text.contains("- luasocket 3.1.0-1"), "{text}");
+        assert!(text.contains("~ penlight: locked 1.13.1-1 does not satisfy '^2.0.0'"));
+    }
+
+    #[test]
+    fn test_detects_transitive_drift() {
+        let manifest = manifest(&[("penlight", "*")], &[]);
+        let lockfile = lockfile(vec![(
+            "penlight",
+            locked("1.13.1-1", &[("luafilesystem", ">= 1.8")]),
+        )]);
+        let drift = LockfileDrift::detect(&manifest, &lockfile, true);
+        assert_eq!(
+            drift.changes,
+            vec![Drift::MissingTransitive {
+                name: "luafilesystem".to_string(),
+                required_by: "penlight".to_string()
+            }]
+        );
+
+        let lockfile = lockfile_with_old_lfs();
+        let drift = LockfileDrift::detect(&manifest, &lockfile, true);
+        assert!(drift.to_string().contains("(required by penlight)"));
+    }
+
+    fn lockfile_with_old_lfs() -> Lockfile {
+        lockfile(vec![
+            (
+                "penlight",
+                locked("1.13.1-1", &[("luafilesystem", ">= 1.8")]),
+            ),
+            ("luafilesystem", locked("1.6.3-2", &[])),
+        ])
+    }
+
+    #[test]
+    fn test_dev_dependencies_excluded() {
+        let manifest = manifest(&[], &[("busted", "*")]);
+        let lockfile = lockfile(vec![]);
+        assert!(LockfileDrift::detect(&manifest, &lockfile, false).is_empty());
+        assert!(!LockfileDrift::detect(&manifest, &lockfile, true).is_empty());
+
+        // A locked dev dependency is not reported as removed
+        let lockfile = self::lockfile(vec![("busted", locked("2.2.0-1", &[]))]);
+        assert!(LockfileDrift::detect(&manifest, &lockfile, false).is_empty());
+    }
+
+    #[test]
+    fn test_git_dependency_drift() {
+        let mut git = locked("tag=v1.2", &[]);
+        git.source = "git".to_string();
+        git.source_url = Some("https://example.com/mylib.git".to_string());
+        git.commit = Some("0123456789abcdef0123456789abcdef01234567".to_string());
+        let lockfile = lockfile(vec![("mylib", git)]);
+
+        let manifest = manifest(
+            &[("mylib", "git+https://example.com/mylib.git#tag=v1.2")],
+            &[],
+        );
+        assert!(LockfileDrift::detect(&manifest, &lockfile, true).is_empty());
+
+        let manifest = manifest_with_tag("v1.3");
+        let drift = LockfileDrift::detect(&manifest, &lockfile, true);
+        assert!(
+            drift.to_string().contains(
+                "locked tag=v1.2 does not satisfy 'git+https://example.com/mylib.git#tag=v1.3'"
+            ),
+            "{drift}"
+        );
+    }
+
+    fn manifest_with_tag(tag: &str) -> PackageManifest {
+        let spec = format!("git+https://example.com/mylib.git#tag={}", tag);
+        manifest(&[("mylib", spec.as_str())], &[])
+    }
+
+    #[test]
+    fn test_path_dependency_drift() {
+        let lockfile = lockfile(vec![
+            (
+                "mylib",
+                local_package("../mylib", &[("lpeg", "^1.0"), ("util", "*")]),
+            ),
+            ("lpeg", locked("1.1.0-1", &[])),
+            ("util", local_package("../util", &[])),
+        ]);
+
+        // Spelled differently, same directory; a local package can satisfy a registry requirement
+        let same = manifest(&[("mylib", "path:./../mylib")], &[]);
+        assert!(LockfileDrift::detect(&same, &lockfile, true).is_empty());
+
+        let moved = manifest(&[("mylib", "path:../other")], &[]);
+        let drift = LockfileDrift::detect(&moved, &lockfile, true);
+        assert!(
+            drift
+                .to_string()
+                .contains("~ mylib: locked 0.1.0 does not satisfy 'path:../other'"),
+            "{drift}"
+        );
+
+        // Switching from a local package back to the registry is drift too
+        let registry = manifest(&[("mylib", "^0.1.0")], &[]);
+        assert!(!LockfileDrift::detect(&registry, &lockfile, true).is_empty());
+    }
+}
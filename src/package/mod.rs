@@ -1,17 +1,28 @@
 pub mod checksum;
 pub mod conflict_checker;
 pub mod converter;
+// dependency specs live in lpm-core next to the manifest
+pub mod dependency {
+    pub use lpm_core::package::dependency::*;
+}
 pub mod downloader;
 pub mod extractor;
+pub mod git;
 pub mod installer;
 pub mod interactive;
+pub mod layout;
+pub mod local;
 pub mod lockfile;
 pub mod lockfile_builder;
+pub mod lockfile_drift;
 // manifest moved to lpm-core, re-export for backward compatibility
 pub mod manifest {
     pub use lpm_core::package::manifest::*;
 }
+pub mod offline;
+pub mod ownership;
 pub mod packager;
+pub mod pipeline;
 pub mod rollback;
 pub mod update_diff;
 pub mod validator;
@@ -24,7 +35,9 @@ pub use extractor::PackageExtractor;
 pub use installer::PackageInstaller;
 pub use lockfile::Lockfile;
 pub use lockfile_builder::LockfileBuilder;
+pub use lockfile_drift::LockfileDrift;
 pub use manifest::PackageManifest;
+pub use pipeline::InstallPipeline;
 pub use rollback::{with_rollback, RollbackManager};
 pub use validator::ManifestValidator;
 pub use verifier::{PackageVerifier, VerificationResult};
@@ -0,0 +1,263 @@
+use crate::cache::Cache;
+use crate::core::{LpmError, LpmResult};
+use crate::luarocks::client::LuaRocksClient;
+use crate::luarocks::manifest::Manifest;
+use crate::luarocks::rockspec::Rockspec;
+use crate::package::git::GitFetcher;
+use crate::package::lockfile::Lockfile;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// A cached file that an offline install needs but the cache doesn't have
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingArtifact {
+    pub package: String,
+    pub version: String,
+    pub kind: ArtifactKind,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArtifactKind {
+    Rockspec,
+    Source,
+    GitCommit,
+}
+
+impl fmt::Display for MissingArtifact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ArtifactKind::Rockspec => "rockspec",
+            ArtifactKind::Source => "source archive",
+            ArtifactKind::GitCommit => "git commit",
+        };
+        write!(
+            f,
+            "{}@{}: {} ({})",
+            self.package,
+            self.version,
+            kind,
+            self.path.display()
+        )
+    }
+}
+
+/// Find the rockspecs and sources missing from the cache for these packages
+///
+/// `source_url` comes from the lockfile when known; otherwise it is read from
+/// the cached rockspec.
+pub fn missing_artifacts<'a>(
+    cache: &Cache,
+    packages: impl IntoIterator<Item = (&'a str, &'a str, Option<&'a str>)>,
+) -> Vec<MissingArtifact> {
+    let mut missing = Vec::new();
+    let mut packages: Vec<_> = packages.into_iter().collect();
+    packages.sort();
+
+    for (name, version, source_url) in packages {
+        let rockspec_path = cache.rockspec_path(name, version);
+        let cached_rockspec = fs::read_to_string(&rockspec_path).ok();
+        if cached_rockspec.is_none() {
+            missing.push(MissingArtifact {
+                package: name.to_string(),
+                version: version.to_string(),
+                kind: ArtifactKind::Rockspec,
+                path: rockspec_path,
+            });
+        }
+
+        let source_url = match source_url {
+            Some(url) => Some(url.to_string()),
+            None => cached_rockspec
+                .and_then(|content| Rockspec::parse_lua(&content).ok())
+                .map(|rockspec| rockspec.source.url),
+        };
+        // Without a rockspec the source URL is unknown; the rockspec entry covers it
+        if let Some(url) = source_url {
+            let source_path = cache.source_path(&url);
+            if !source_path.exists() {
+                missing.push(MissingArtifact {
+                    package: name.to_string(),
+                    version: version.to_string(),
+                    kind: ArtifactKind::Source,
+                    path: source_path,
+                });
+            }
+        }
+    }
+
+    missing
+}
+
+/// Check that everything in the lockfile can be installed from the cache
+pub fn check_lockfile_cached(cache: &Cache, lockfile: &Lockfile) -> LpmResult<()> {
+    // Local packages come from the project's own tree, not the cache
+    let (git, registry): (Vec<_>, Vec<_>) = lockfile
+        .packages
+        .iter()
+        .filter(|(_, pkg)| pkg.source != "path")
+        .partition(|(_, pkg)| pkg.source == "git");
+
+    let mut missing = missing_artifacts(
+        cache,
+        registry.into_iter().map(|(name, pkg)| {
+            (
+                name.as_str(),
+                pkg.version.as_str(),
+                pkg.source_url.as_deref(),
+            )
+        }),
+    );
+
+    let fetcher = GitFetcher::new(cache.clone()).with_offline(true);
+    let mut git: Vec<_> = git.into_iter().collect();
+    git.sort_by_key(|(name, _)| name.as_str());
+    for (name, pkg) in git {
+        let url = pkg.source_url.as_deref().unwrap_or_default();
+        let commit = pkg.commit.as_deref().unwrap_or_default();
+        if !cache.git_archive_path(url, commit).exists() && !fetcher.has_commit(url, commit) {
+            missing.push(MissingArtifact {
+                package: name.clone(),
+                version: pkg.version.clone(),
+                kind: ArtifactKind::GitCommit,
+                path: cache.git_mirror_path(url),
+            });
+        }
+    }
+
+    ensure_nothing_missing(&missing)
+}
+
+/// Turn a list of missing artifacts into an error listing every one of them
+pub fn ensure_nothing_missing(missing: &[MissingArtifact]) -> LpmResult<()> {
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let list = missing
+        .iter()
+        .map(|m| format!("  - {}", m))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(LpmError::Cache(format!(
+        "Offline mode: {} artifact(s) missing from the cache:\n{}\n\
+         Run the same command without --offline once to populate the cache.",
+        missing.len(),
+        list
+    )))
+}
+
+/// Download a package version's rockspec and source archive into the cache,
+/// so it can later be installed offline; returns the cached source
+pub async fn prefetch(
+    client: &LuaRocksClient,
+    manifest: &Manifest,
+    name: &str,
+    version: &str,
+) -> LpmResult<PathBuf> {
+    let rockspec_url = client.rockspec_url(manifest, name, version);
+    let content = client.download_rockspec(&rockspec_url).await?;
+    let rockspec = client.parse_rockspec(&content)?;
+    let source_path = client.download_source(&rockspec.source.url).await?;
+    let checksum = Cache::checksum(&source_path)?;
+    client
+        .cache()
+        .record_source(&rockspec.source.url, name, version, &checksum)?;
+    Ok(source_path)
+}
+
+/// The lockfile an offline install resolves from
+pub fn require_lockfile(lockfile: Option<Lockfile>) -> LpmResult<Lockfile> {
+    lockfile.ok_or_else(|| {
+        LpmError::Package(
+            "Offline mode needs a package.lock. Run `lpm install` with network access first."
+                .to_string(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::lockfile::LockedPackage;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn locked(version: &str, source_url: Option<&str>) -> LockedPackage {
+        LockedPackage {
+            version: version.to_string(),
+            source: "luarocks".to_string(),
+            rockspec_url: None,
+            source_url: source_url.map(|s| s.to_string()),
+            checksum: "sha256:00".to_string(),
+            size: None,
+            dependencies: HashMap::new(),
+            build: None,
+            commit: None,
+        }
+    }
+
+    #[test]
+    fn test_missing_artifacts_lists_everything() {
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        cache.init().unwrap();
+
+        // penlight is fully cached
+        let pl_url = "https://example.com/penlight.tar.gz";
+        cache
+            .write(
+                &cache.rockspec_path("penlight", "1.14.0-1"),
+                b"package = 'penlight'",
+            )
+            .unwrap();
+        cache.write(&cache.source_path(pl_url), b"archive").unwrap();
+        // luasocket has only its rockspec, whose source is read from it
+        cache
+            .write(
+                &cache.rockspec_path("luasocket", "3.1.0-1"),
+                br#"package = "luasocket"
+version = "3.1.0-1"
+source = { url = "https://example.com/luasocket.tar.gz" }
+"#,
+            )
+            .unwrap();
+
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package("penlight".to_string(), locked("1.14.0-1", Some(pl_url)));
+        lockfile.add_package("luasocket".to_string(), locked("3.1.0-1", None));
+        lockfile.add_package(
+            "lpeg".to_string(),
+            locked("1.1.0-1", Some("https://example.com/lpeg.tar.gz")),
+        );
+
+        let mut git = locked("branch=main", Some("https://example.com/mylib.git"));
+        git.source = "git".to_string();
+        git.commit = Some("0123456789abcdef0123456789abcdef01234567".to_string());
+        lockfile.add_package("mylib".to_string(), git);
+
+        let err = check_lockfile_cached(&cache, &lockfile)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("4 artifact(s) missing"), "{err}");
+        assert!(err.contains("mylib@branch=main: git commit"), "{err}");
+        assert!(err.contains("lpeg@1.1.0-1: rockspec"), "{err}");
+        assert!(err.contains("lpeg@1.1.0-1: source archive"), "{err}");
+        assert!(err.contains("luasocket@3.1.0-1: source archive"), "{err}");
+        assert!(!err.contains("penlight"), "{err}");
+    }
+
+    #[test]
+    fn test_check_lockfile_cached_ok() {
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().to_path_buf()).unwrap();
+        assert!(check_lockfile_cached(&cache, &Lockfile::new()).is_ok());
+    }
+
+    #[test]
+    fn test_require_lockfile() {
+        assert!(require_lockfile(None).is_err());
+        assert!(require_lockfile(Some(Lockfile::new())).is_ok());
+    }
+}
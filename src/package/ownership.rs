@@ -0,0 +1,283 @@
+//! Which package installed which file in lua_modules
+//!
+//! Every install records the files it put in `lua_modules/`, with their
+//! SHA-256, in `lua_modules/.lpm/packages/<name>/files.yaml`. The records are
+//! what installs check for collisions between packages, what removal deletes,
+//! and what `lpm owns` looks up.
+
+use crate::core::{LpmError, LpmResult};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path};
+use walkdir::WalkDir;
+
+/// File the record is kept in, in the package's metadata directory
+pub const FILES: &str = "files.yaml";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InstalledFiles {
+    pub version: String,
+    /// Paths relative to lua_modules, with `/` separators, and their checksums
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+    /// For a local package: the link (or copy) in lua_modules pointing at its sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+
+impl InstalledFiles {
+    /// Record every file under `root` (a staged lua_modules tree)
+    pub fn scan(root: &Path, version: &str) -> LpmResult<Self> {
+        let mut files = BTreeMap::new();
+        for entry in WalkDir::new(root).sort_by_file_name() {
+            let entry = entry?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let relative = entry
+                .path()
+                .strip_prefix(root)
+                .map_err(|e| LpmError::Path(e.to_string()))?;
+            files.insert(to_key(relative), checksum(entry.path())?);
+        }
+        Ok(Self {
+            version: version.to_string(),
+            files,
+            link: None,
+        })
+    }
+
+    /// The record of a local package, linked in as `lua_modules/<name>`
+    pub fn linked(name: &str, version: &str) -> Self {
+        Self {
+            version: version.to_string(),
+            files: BTreeMap::new(),
+            link: Some(name.to_string()),
+        }
+    }
+
+    /// Load the record of package `name`, if it has one
+    pub fn load(packages_dir: &Path, name: &str) -> LpmResult<Option<Self>> {
+        let path = packages_dir.join(name).join(FILES);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        let files = serde_yaml::from_str(&content)
+            .map_err(|e| LpmError::Package(format!("Failed to parse {}: {}", path.display(), e)))?;
+        Ok(Some(files))
+    }
+
+    pub fn save(&self, packages_dir: &Path, name: &str) -> LpmResult<()> {
+        let dir = packages_dir.join(name);
+        fs::create_dir_all(&dir)?;
+        let content = serde_yaml::to_string(self)
+            .map_err(|e| LpmError::Package(format!("Failed to serialize {}: {}", FILES, e)))?;
+        fs::write(dir.join(FILES), content)?;
+        Ok(())
+    }
+
+    /// Whether `path` (relative to lua_modules, `/`-separated) is one of this
+    /// package's files, or inside its link
+    pub fn owns(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+            || self.link.as_deref().is_some_and(|link| {
+                path == link
+                    || path
+                        .strip_prefix(link)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+    }
+}
+
+/// The records of every installed package, by package name
+pub fn load_all(packages_dir: &Path) -> LpmResult<HashMap<String, InstalledFiles>> {
+    let mut all = HashMap::new();
+    if !packages_dir.is_dir() {
+        return Ok(all);
+    }
+    for entry in fs::read_dir(packages_dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if let Some(files) = InstalledFiles::load(packages_dir, &name)? {
+            all.insert(name, files);
+        }
+    }
+    Ok(all)
+}
+
+/// The package that installed `path` (relative to lua_modules), if any
+pub fn owner_of(packages_dir: &Path, path: &Path) -> LpmResult<Option<(String, InstalledFiles)>> {
+    let key = to_key(path);
+    let mut owners: Vec<_> = load_all(packages_dir)?
+        .into_iter()
+        .filter(|(_, files)| files.owns(&key))
+        .collect();
+    owners.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(owners.into_iter().next())
+}
+
+/// Files of `files` that another package already installed, with that package
+///
+/// A package never collides with its own earlier version.
+pub fn collisions(
+    packages_dir: &Path,
+    name: &str,
+    files: &InstalledFiles,
+) -> LpmResult<Vec<(String, String)>> {
+    let mut collisions = Vec::new();
+    for (other, installed) in load_all(packages_dir)? {
+        if other == name {
+            continue;
+        }
+        for path in files.files.keys() {
+            if installed.owns(path) {
+                collisions.push((path.clone(), other.clone()));
+            }
+        }
+    }
+    collisions.sort();
+    Ok(collisions)
+}
+
+/// Delete the files `files` records from `lua_modules`, then the directories
+/// this leaves empty
+pub fn remove(lua_modules: &Path, files: &InstalledFiles) -> LpmResult<()> {
+    if let Some(link) = &files.link {
+        let path = lua_modules.join(link);
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&path)?,
+            Ok(_) => fs::remove_file(&path)?,
+            Err(_) => {}
+        }
+    }
+    for key in files.files.keys() {
+        let path = lua_modules.join(key);
+        if fs::symlink_metadata(&path).is_ok() {
+            fs::remove_file(&path)?;
+        }
+        // Up to, but not including, lua_modules itself
+        let mut dir = path.parent();
+        while let Some(current) = dir.filter(|d| *d != lua_modules) {
+            if fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+    Ok(())
+}
+
+/// SHA-256 of a file, or of the target of a symlink
+pub fn checksum(path: &Path) -> LpmResult<String> {
+    let mut hasher = Sha256::new();
+    if fs::symlink_metadata(path)?.file_type().is_symlink() {
+        hasher.update(fs::read_link(path)?.to_string_lossy().as_bytes());
+    } else {
+        hasher.update(fs::read(path)?);
+    }
+    Ok(format!("sha256:{}", hex::encode(hasher.finalize())))
+}
+
+/// `socket/http.lua`, whatever the platform's separator
+fn to_key(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_scan_and_save() {
+        let temp = TempDir::new().unwrap();
+        let staged = temp.path().join("staged");
+        write(&staged.join("socket.lua"), "return {}");
+        write(&staged.join("socket/http.lua"), "return {}");
+
+        let files = InstalledFiles::scan(&staged, "3.1.0-1").unwrap();
+        assert_eq!(
+            files.files.keys().collect::<Vec<_>>(),
+            vec!["socket.lua", "socket/http.lua"]
+        );
+        assert!(files.files["socket.lua"].starts_with("sha256:"));
+
+        let packages = temp.path().join("packages");
+        files.save(&packages, "luasocket").unwrap();
+        assert_eq!(
+            InstalledFiles::load(&packages, "luasocket").unwrap(),
+            Some(files)
+        );
+        assert_eq!(InstalledFiles::load(&packages, "penlight").unwrap(), None);
+    }
+
+    #[test]
+    fn test_collisions_and_owner() {
+        let temp = TempDir::new().unwrap();
+        let packages = temp.path().join("packages");
+        let staged = temp.path().join("staged");
+        write(&staged.join("socket.lua"), "return {}");
+        write(&staged.join("ltn12.lua"), "return {}");
+        InstalledFiles::scan(&staged, "3.1.0-1")
+            .unwrap()
+            .save(&packages, "luasocket")
+            .unwrap();
+        InstalledFiles::linked("app", "0.1.0")
+            .save(&packages, "app")
+            .unwrap();
+
+        let other = temp.path().join("other");
+        write(&other.join("ltn12.lua"), "return {}");
+        write(&other.join("other.lua"), "return {}");
+        let files = InstalledFiles::scan(&other, "1.0-1").unwrap();
+        assert_eq!(
+            collisions(&packages, "other", &files).unwrap(),
+            vec![("ltn12.lua".to_string(), "luasocket".to_string())]
+        );
+        // Reinstalling (or upgrading) a package replaces its own files
+        assert!(collisions(&packages, "luasocket", &files)
+            .unwrap()
+            .is_empty());
+
+        let owner = |path: &str| owner_of(&packages, Path::new(path)).unwrap().map(|o| o.0);
+        assert_eq!(owner("ltn12.lua").as_deref(), Some("luasocket"));
+        assert_eq!(owner("app/init.lua").as_deref(), Some("app"));
+        assert_eq!(owner("application.lua"), None);
+    }
+
+    #[test]
+    fn test_remove_only_own_files() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        write(&lua_modules.join("socket/http.lua"), "return {}");
+        write(&lua_modules.join("socket/extra.lua"), "return {}");
+        write(&lua_modules.join("socket.lua"), "return {}");
+        write(&lua_modules.join("deep/nested/only.lua"), "return {}");
+
+        let mut files = InstalledFiles::default();
+        for key in ["socket/http.lua", "socket.lua", "deep/nested/only.lua"] {
+            files.files.insert(key.to_string(), String::new());
+        }
+        remove(&lua_modules, &files).unwrap();
+
+        assert!(!lua_modules.join("socket.lua").exists());
+        assert!(!lua_modules.join("socket/http.lua").exists());
+        // Not the package's, so the directory stays
+        assert!(lua_modules.join("socket/extra.lua").exists());
+        assert!(!lua_modules.join("deep").exists());
+        assert!(lua_modules.exists());
+    }
+}
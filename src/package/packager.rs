@@ -1,7 +1,11 @@
 use crate::build::builder::RustBuilder;
 use crate::build::targets::Target;
+use crate::core::version::Version;
 use crate::core::{LpmError, LpmResult};
+use crate::luarocks::rock;
+use crate::luarocks::version::to_luarocks_version;
 use crate::package::manifest::PackageManifest;
+use crate::publish::rockspec_generator::RockspecGenerator;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -54,6 +58,9 @@ impl BinaryPackager {
         println!("✓ Packaged: {}", archive_path.display());
         println!("  Binary: {}", dest_binary.display());
         println!("  Target: {}", target.triple);
+        if let Some(rock) = self.create_rock(target, &binary_path)? {
+            println!("  Rock: {}", rock.display());
+        }
 
         Ok(archive_path)
     }
@@ -86,6 +93,68 @@ generated_at: "{}"
         Ok(())
     }
 
+    /// Write a LuaRocks binary rock for the target into dist/
+    ///
+    /// The library is installed once per module in `build.modules` (or as the
+    /// package name), next to the Lua files from `lua/`. Targets LuaRocks has
+    /// no platform name for get no rock.
+    fn create_rock(&self, target: &Target, binary_path: &Path) -> LpmResult<Option<PathBuf>> {
+        let Some(platform) = rock::target_platform(&target.triple) else {
+            return Ok(None);
+        };
+        let version = to_luarocks_version(&Version::parse(&self.manifest.version)?);
+        let dist_dir = self.project_root.join("dist");
+        let staging = dist_dir.join(format!(".rock-{}", target.triple));
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+
+        let mut modules: Vec<String> = self
+            .manifest
+            .build
+            .iter()
+            .flat_map(|build| build.modules.keys().cloned())
+            .collect();
+        if modules.is_empty() {
+            modules.push(self.manifest.name.replace('-', "_"));
+        }
+        for module in modules {
+            let mut dest = staging.join("lib");
+            dest.extend(module.split('.'));
+            dest.set_extension(target.module_extension().trim_start_matches('.'));
+            fs::create_dir_all(dest.parent().unwrap())?;
+            fs::copy(binary_path, &dest)?;
+        }
+
+        let lua_dir = self.project_root.join("lua");
+        if lua_dir.is_dir() {
+            for entry in walkdir::WalkDir::new(&lua_dir) {
+                let entry = entry?;
+                let path = entry.path();
+                if path.is_file() && path.extension().is_some_and(|e| e == "lua") {
+                    let relative = path
+                        .strip_prefix(&lua_dir)
+                        .map_err(|e| LpmError::Path(e.to_string()))?;
+                    let dest = staging.join("lua").join(relative);
+                    fs::create_dir_all(dest.parent().unwrap())?;
+                    fs::copy(path, dest)?;
+                }
+            }
+        }
+
+        let rockspec = RockspecGenerator::generate(&self.manifest)?;
+        let rock = rock::write_binary_rock(
+            &staging,
+            &rockspec,
+            &self.manifest.name,
+            &version,
+            platform,
+            &dist_dir,
+        )?;
+        fs::remove_dir_all(&staging)?;
+        Ok(Some(rock))
+    }
+
     /// Create an archive (tar.gz on Unix, zip on Windows)
     fn create_archive(&self, package_dir: &Path, package_name: &str) -> LpmResult<PathBuf> {
         let dist_dir = package_dir.parent().unwrap();
@@ -226,6 +295,42 @@ mod tests {
         assert!(content.contains("x86_64-unknown-linux-gnu"));
     }
 
+    #[test]
+    fn test_create_rock() {
+        let temp = TempDir::new().unwrap();
+        let mut manifest = PackageManifest::default("acme-fast".to_string());
+        manifest.version = "1.0.0".to_string();
+        manifest.build = Some(crate::package::manifest::BuildConfig {
+            build_type: "rust".to_string(),
+            manifest: None,
+            modules: [("acme.fast".to_string(), "target/libacme.so".to_string())].into(),
+            features: Vec::new(),
+            profile: None,
+        });
+        fs::create_dir_all(temp.path().join("lua/acme")).unwrap();
+        fs::write(temp.path().join("lua/acme/init.lua"), "return {}").unwrap();
+        let binary_path = temp.path().join("libacme.so");
+        fs::write(&binary_path, "fake binary").unwrap();
+        let packager = BinaryPackager::new(temp.path(), manifest);
+
+        let target = Target::new("aarch64-apple-darwin").unwrap();
+        let rock = packager
+            .create_rock(&target, &binary_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            rock,
+            temp.path().join("dist/acme-fast-1.0.0-1.macosx-arm64.rock")
+        );
+        rock::verify(&rock).unwrap();
+        assert_eq!(
+            rock::read_file(&rock, "lib/acme/fast.dylib").unwrap(),
+            b"fake binary"
+        );
+        assert!(rock::read_file(&rock, "lua/acme/init.lua").is_ok());
+        assert!(rock::read_rockspec(&rock, "acme-fast", "1.0.0-1").is_ok());
+    }
+
     #[test]
     fn test_create_archive_paths() {
         let temp = TempDir::new().unwrap();
@@ -0,0 +1,435 @@
+use crate::core::{LpmError, LpmResult};
+use crate::package::installer::{FetchedPackage, PackageInstaller};
+use crate::package::lockfile::Lockfile;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// Installs locked packages concurrently
+///
+/// Downloads run in parallel, and each package is extracted and built as soon
+/// as its archive is verified. Native builds additionally wait until the
+/// packages they depend on are installed, so they can find their headers and
+/// libraries; pure Lua packages never wait.
+pub struct InstallPipeline {
+    installer: Arc<PackageInstaller>,
+    max_downloads: usize,
+    max_builds: usize,
+    progress: MultiProgress,
+}
+
+impl InstallPipeline {
+    pub fn new(installer: PackageInstaller) -> Self {
+        let max_builds = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+        Self {
+            installer: Arc::new(installer),
+            max_downloads: 10,
+            max_builds,
+            progress: MultiProgress::new(),
+        }
+    }
+
+    /// Limit concurrent downloads (default 10)
+    pub fn with_max_downloads(mut self, max: usize) -> Self {
+        self.max_downloads = max.max(1);
+        self
+    }
+
+    /// Limit concurrent extractions and builds (default: number of CPUs)
+    pub fn with_max_builds(mut self, max: usize) -> Self {
+        self.max_builds = max.max(1);
+        self
+    }
+
+    /// Don't draw progress bars; completed packages are still listed
+    pub fn with_hidden_progress(self) -> Self {
+        self.progress.set_draw_target(ProgressDrawTarget::hidden());
+        self
+    }
+
+    /// Install the given locked packages, returning how many were installed
+    ///
+    /// Stops scheduling new work at the first failure, waits for builds already
+    /// running, and returns that failure.
+    pub async fn install(
+        &self,
+        lockfile: &Lockfile,
+        packages: &HashSet<String>,
+    ) -> LpmResult<usize> {
+        // Dependencies first, so builds become ready in a stable order
+        let order: Vec<String> = lockfile
+            .install_order()?
+            .into_iter()
+            .filter(|name| packages.contains(name))
+            .collect();
+        if order.is_empty() {
+            return Ok(0);
+        }
+
+        let download_bar = self.add_bar("Downloading", order.len());
+        let install_bar = self.add_bar("Installing", order.len());
+
+        // Dropped before it is disarmed (Ctrl-C drops this future), it keeps
+        // builds still running from committing
+        let cancel = CancelOnDrop {
+            installer: Arc::clone(&self.installer),
+            armed: true,
+        };
+        let mut queued: VecDeque<&String> = order.iter().collect();
+        let mut downloads = JoinSet::new();
+        let mut builds = JoinSet::new();
+        let mut fetched: HashMap<String, FetchedPackage> = HashMap::new();
+        let mut building: HashMap<String, ProgressBar> = HashMap::new();
+        let mut installed: HashSet<String> = HashSet::new();
+        let mut failure: Option<LpmError> = None;
+
+        loop {
+            if failure.is_none() {
+                while downloads.len() < self.max_downloads {
+                    let Some(name) = queued.pop_front() else {
+                        break;
+                    };
+                    let installer = Arc::clone(&self.installer);
+                    let name = name.clone();
+                    let locked = lockfile.packages[&name].clone();
+                    downloads.spawn(async move {
+                        let result = installer.fetch_locked(&name, &locked).await;
+                        (name, result)
+                    });
+                }
+
+                for name in &order {
+                    if builds.len() >= self.max_builds {
+                        break;
+                    }
+                    let Some(package) = fetched.get(name) else {
+                        continue;
+                    };
+                    if package.is_native()
+                        && !self.dependencies_installed(lockfile, name, packages, &installed)
+                    {
+                        continue;
+                    }
+
+                    let package = fetched.remove(name).expect("package was fetched");
+                    building.insert(name.clone(), self.add_spinner(&package));
+                    let installer = Arc::clone(&self.installer);
+                    builds.spawn_blocking(move || {
+                        let result = installer.install_fetched(&package);
+                        (package, result)
+                    });
+                }
+            }
+
+            if downloads.is_empty() && builds.is_empty() {
+                break;
+            }
+
+            tokio::select! {
+                Some(joined) = downloads.join_next(), if !downloads.is_empty() => {
+                    match joined {
+                        Ok((name, Ok(package))) => {
+                            download_bar.inc(1);
+                            download_bar.set_message(name.clone());
+                            fetched.insert(name, package);
+                        }
+                        Ok((_, Err(e))) => {
+                            failure.get_or_insert(e);
+                            downloads.abort_all();
+                        }
+                        Err(e) if e.is_cancelled() => {}
+                        Err(e) => {
+                            let e = LpmError::Package(format!("Download task failed: {}", e));
+                            failure.get_or_insert(e);
+                            downloads.abort_all();
+                        }
+                    }
+                }
+                Some(joined) = builds.join_next(), if !builds.is_empty() => {
+                    match joined {
+                        Ok((package, result)) => {
+                            if let Some(spinner) = building.remove(&package.name) {
+                                spinner.finish_and_clear();
+                            }
+                            match result {
+                                Ok(_) => {
+                                    install_bar.inc(1);
+                                    self.log(format!(
+                                        "  ✓ {}@{} (checksum: {})",
+                                        package.name, package.version, package.checksum
+                                    ));
+                                    installed.insert(package.name);
+                                }
+                                Err(e) => {
+                                    self.log(format!("  ✗ {}@{}", package.name, package.version));
+                                    failure.get_or_insert(e);
+                                    downloads.abort_all();
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            let e = LpmError::Package(format!("Build task failed: {}", e));
+                            failure.get_or_insert(e);
+                            downloads.abort_all();
+                        }
+                    }
+                }
+            }
+        }
+
+        cancel.disarm();
+        download_bar.finish_and_clear();
+        install_bar.finish_and_clear();
+
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        if installed.len() < order.len() {
+            // install_order() rejects cycles, so every fetched package should have become ready
+            let mut stuck: Vec<_> = fetched.into_keys().collect();
+            stuck.sort();
+            return Err(LpmError::Package(format!(
+                "Could not install {}: dependencies were not installed first",
+                stuck.join(", ")
+            )));
+        }
+
+        Ok(installed.len())
+    }
+
+    /// Whether every locked dependency of `name` that is being installed is done
+    fn dependencies_installed(
+        &self,
+        lockfile: &Lockfile,
+        name: &str,
+        packages: &HashSet<String>,
+        installed: &HashSet<String>,
+    ) -> bool {
+        lockfile.packages[name]
+            .dependencies
+            .keys()
+            .filter(|dep| packages.contains(*dep) && dep.as_str() != name)
+            .all(|dep| installed.contains(dep))
+    }
+
+    fn add_bar(&self, prefix: &'static str, len: usize) -> ProgressBar {
+        let bar = self.progress.add(ProgressBar::new(len as u64));
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template("{prefix:>12.cyan.bold} [{bar:30.cyan/blue}] {pos}/{len} {wide_msg}")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        bar.set_prefix(prefix);
+        bar
+    }
+
+    fn add_spinner(&self, package: &FetchedPackage) -> ProgressBar {
+        let spinner = self.progress.add(ProgressBar::new_spinner());
+        spinner.set_style(
+            ProgressStyle::default_spinner()
+                .template("  {spinner} {msg}")
+                .unwrap(),
+        );
+        let action = if package.is_native() {
+            "building"
+        } else {
+            "installing"
+        };
+        spinner.set_message(format!("{}@{} ({})", package.name, package.version, action));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        spinner
+    }
+
+    /// Print a line above the bars, or plainly when they aren't drawn (e.g. in CI logs)
+    fn log(&self, line: String) {
+        if self.progress.is_hidden() {
+            println!("{}", line);
+        } else {
+            let _ = self.progress.println(line);
+        }
+    }
+}
+
+/// Cancels the installer when an install is abandoned with builds running
+///
+/// Dropping the build `JoinSet` aborts the builds that haven't started, but
+/// not those running on blocking threads; cancelling the installer stops them
+/// before they move anything into lua_modules.
+struct CancelOnDrop {
+    installer: Arc<PackageInstaller>,
+    armed: bool,
+}
+
+impl CancelOnDrop {
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if self.armed {
+            self.installer.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::Cache;
+    use crate::config::Config;
+    use crate::core::path::{tree_lua_dir, tree_lua_versions};
+    use crate::luarocks::client::LuaRocksClient;
+    use crate::package::lockfile::LockedPackage;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    /// Cache a pure Lua package and return its lockfile entry
+    fn cache_package(cache: &Cache, name: &str, deps: &[&str]) -> LockedPackage {
+        let version = "1.0-1";
+        let source_url = format!("https://example.com/{}-1.0.tar.gz", name);
+        let rockspec_url = format!("https://example.com/{}-{}.rockspec", name, version);
+
+        let rockspec = format!(
+            r#"package = "{name}"
+version = "{version}"
+source = {{ url = "{source_url}" }}
+build = {{ type = "builtin", modules = {{ {name} = "{name}.lua" }} }}
+"#
+        );
+        cache
+            .write(&cache.rockspec_path(name, version), rockspec.as_bytes())
+            .unwrap();
+
+        let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let body = format!("return '{}'\n", name);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(body.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive
+            .append_data(
+                &mut header,
+                format!("{}-1.0/{}.lua", name, name),
+                body.as_bytes(),
+            )
+            .unwrap();
+        let source_path = cache.source_path(&source_url);
+        cache
+            .write(
+                &source_path,
+                &archive.into_inner().unwrap().finish().unwrap(),
+            )
+            .unwrap();
+
+        LockedPackage {
+            version: version.to_string(),
+            source: "luarocks".to_string(),
+            rockspec_url: Some(rockspec_url),
+            source_url: Some(source_url),
+            checksum: Cache::checksum(&source_path).unwrap(),
+            size: None,
+            dependencies: deps
+                .iter()
+                .map(|d| (d.to_string(), "*".to_string()))
+                .collect(),
+            build: None,
+            commit: None,
+        }
+    }
+
+    fn pipeline(project: &Path, cache: &Cache) -> InstallPipeline {
+        let client = LuaRocksClient::new(&Config::default(), cache.clone());
+        let installer = PackageInstaller::new(project)
+            .unwrap()
+            .with_offline(true)
+            .with_client(client);
+        installer.init().unwrap();
+        InstallPipeline::new(installer)
+            .with_max_downloads(2)
+            .with_hidden_progress()
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_installs_locked_packages() {
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().join("cache")).unwrap();
+        let project = temp.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package("app".to_string(), cache_package(&cache, "app", &["util"]));
+        lockfile.add_package("util".to_string(), cache_package(&cache, "util", &[]));
+        lockfile.add_package("unused".to_string(), cache_package(&cache, "unused", &[]));
+
+        let wanted = lockfile.dependency_closure(["app"]);
+        let count = pipeline(&project, &cache)
+            .install(&lockfile, &wanted)
+            .await
+            .unwrap();
+
+        assert_eq!(count, 2);
+        let lua_modules = project.join("lua_modules");
+        let versions = tree_lua_versions(&lua_modules);
+        let share = tree_lua_dir(&lua_modules, &versions[0]);
+        assert!(share.join("app.lua").exists());
+        assert!(share.join("util.lua").exists());
+        assert!(lua_modules.join("app").exists());
+        assert!(!lua_modules.join("unused").exists());
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_rejects_checksum_mismatch() {
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().join("cache")).unwrap();
+        let project = temp.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+
+        let mut tampered = cache_package(&cache, "app", &[]);
+        tampered.checksum = "sha256:0000".to_string();
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package("app".to_string(), tampered);
+
+        let wanted = HashSet::from(["app".to_string()]);
+        let err = pipeline(&project, &cache)
+            .install(&lockfile, &wanted)
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("Checksum mismatch for app@1.0-1"), "{err}");
+        assert!(!project.join("lua_modules/app").exists());
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_pipeline_commits_nothing() {
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().join("cache")).unwrap();
+        let project = temp.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+
+        let mut lockfile = Lockfile::new();
+        lockfile.add_package("app".to_string(), cache_package(&cache, "app", &[]));
+        let wanted = HashSet::from(["app".to_string()]);
+
+        // Dropped after its first poll, as on Ctrl-C
+        let pipeline = pipeline(&project, &cache);
+        tokio::select! {
+            biased;
+            _ = pipeline.install(&lockfile, &wanted) => panic!("install finished in one poll"),
+            _ = std::future::ready(()) => {}
+        }
+
+        let err = pipeline.install(&lockfile, &wanted).await.unwrap_err();
+        assert!(err.to_string().contains("Interrupted"), "{err}");
+        assert!(!project.join("lua_modules/app").exists());
+    }
+}
@@ -1,49 +1,510 @@
-use crate::core::LpmResult;
+use crate::core::path::{lpm_metadata_dir, lua_modules_dir, packages_metadata_dir};
+use crate::core::{LpmError, LpmResult};
 use crate::package::lockfile::Lockfile;
-use crate::package::manifest::PackageManifest;
-use std::path::Path;
+use crate::package::ownership::{self, InstalledFiles};
+use crate::path_setup::PathSetup;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The journal of a transaction, in `lua_modules/.lpm`
+const JOURNAL: &str = "transaction";
+/// A journal being written; an interrupted one is simply discarded
+const JOURNAL_TMP: &str = "transaction.tmp";
+/// A journal being deleted, after a commit or a rollback
+const JOURNAL_OLD: &str = "transaction.old";
+/// Names of the snapshotted paths that existed, one per line
+const EXISTED: &str = "existed";
+/// The packages saved by [`touch`], one directory each
+const PACKAGES: &str = "packages";
+/// Packages being saved; only moved to [`PACKAGES`] once complete
+const SAVING: &str = "saving";
+/// In a saved package: its files, laid out as in lua_modules
+const FILES: &str = "files";
+/// In a saved package: its metadata directory
+const METADATA: &str = "metadata";
+/// In a saved package: the files the transaction adds for it, one per line
+const INCOMING: &str = "incoming";
+/// What [`clear_lua_modules`] moved out of lua_modules
+const CLEARED: &str = "cleared";
+/// The entries [`clear_lua_modules`] moves, listed before any is moved
+const CLEARED_LIST: &str = "cleared.list";
+const CLEARED_LIST_TMP: &str = "cleared.list.tmp";
+
+const LOCKFILE: &str = "package.lock";
+const MANIFEST: &str = "package.yaml";
+const LOADER: &str = "loader.lua";
+const LUA_MODULES: &str = "lua_modules";
 
 /// Manages rollback for failed installations
+///
+/// Before an install, update or removal, package.lock, package.yaml and the
+/// lpm loader are copied into a journal in `lua_modules/.lpm/transaction`.
+/// The installer then saves each package into the journal just before it
+/// replaces or removes it (see [`touch`]), so only the packages the operation
+/// touches are copied. A rollback takes out what the operation installed and
+/// puts the saved packages back, leaving `lua_modules` as it was and
+/// consistent with package.lock. The journal stays on disk until the
+/// operation commits, so a process killed halfway (Ctrl-C, a crash) is rolled
+/// back by the next command that starts a transaction in the project (see
+/// [`recover`]).
 pub struct RollbackManager {
-    backup_lockfile: Option<Lockfile>,
-    backup_manifest: Option<PackageManifest>,
+    project_root: PathBuf,
+    backup_lockfile: bool,
+    backup_manifest: bool,
+    backup_lua_modules: bool,
 }
 
 impl RollbackManager {
     /// Create a new rollback manager and backup current state
+    ///
+    /// Rolls back an interrupted earlier transaction first.
     pub fn new(project_root: &Path) -> LpmResult<Self> {
-        // Backup lockfile if it exists
-        let backup_lockfile = Lockfile::load(project_root)?;
+        recover(project_root)?;
 
-        // Backup manifest
-        let backup_manifest = PackageManifest::load(project_root).ok();
+        // Refuse to start from a corrupt lockfile
+        Lockfile::load(project_root)?;
+
+        let had_lua_modules = lua_modules_dir(project_root).is_dir();
+        let metadata_dir = lpm_metadata_dir(project_root);
+        let tmp = metadata_dir.join(JOURNAL_TMP);
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        fs::create_dir_all(&tmp)?;
+
+        let mut existed = Vec::new();
+        for (name, path) in snapshotted(project_root) {
+            if path.is_file() {
+                fs::copy(&path, tmp.join(name))?;
+                existed.push(name);
+            }
+        }
+        if had_lua_modules {
+            existed.push(LUA_MODULES);
+        }
+        fs::write(tmp.join(EXISTED), existed.join("\n"))?;
+
+        // The snapshot only counts once it is complete
+        fs::rename(&tmp, metadata_dir.join(JOURNAL))?;
 
         Ok(Self {
-            backup_lockfile,
-            backup_manifest,
+            project_root: project_root.to_path_buf(),
+            backup_lockfile: existed.contains(&LOCKFILE),
+            backup_manifest: existed.contains(&MANIFEST),
+            backup_lua_modules: had_lua_modules,
         })
     }
 
     /// Rollback to the previous state
-    pub fn rollback(&self, project_root: &Path) -> LpmResult<()> {
-        // Restore lockfile if we had a backup
-        if let Some(ref lockfile) = self.backup_lockfile {
-            lockfile.save(project_root)?;
+    ///
+    /// Files and directories that did not exist when the snapshot was taken
+    /// are removed.
+    pub fn rollback(self) -> LpmResult<()> {
+        restore(&self.project_root)?;
+        if self.backup_lockfile {
             eprintln!("✓ Rolled back package.lock");
         }
-
-        // Restore manifest if we had a backup
-        if let Some(ref manifest) = self.backup_manifest {
-            manifest.save(project_root)?;
+        if self.backup_manifest {
             eprintln!("✓ Rolled back package.yaml");
         }
+        if self.backup_lua_modules {
+            eprintln!("✓ Rolled back lua_modules");
+        }
+        Ok(())
+    }
 
+    /// Keep the changes made since the snapshot and drop the snapshot
+    pub fn commit(self) -> LpmResult<()> {
+        discard_journal(&self.project_root)?;
+        if !self.backup_lua_modules {
+            // Only made for the journal if the operation left it empty
+            let lua_modules = lua_modules_dir(&self.project_root);
+            let _ = fs::remove_dir(lpm_metadata_dir(&self.project_root));
+            let _ = fs::remove_dir(lua_modules);
+        }
         Ok(())
     }
 
     /// Check if rollback is available
     pub fn has_backup(&self) -> bool {
-        self.backup_lockfile.is_some() || self.backup_manifest.is_some()
+        self.backup_lockfile || self.backup_manifest || self.backup_lua_modules
+    }
+}
+
+/// Save package `name` as it is, before the transaction in progress changes it
+///
+/// Saves the package's metadata and the files its record lists, or all of
+/// `lua_modules/<name>` for a linked package or one installed before files
+/// were recorded. `incoming` are the files about to be added for it, which a
+/// rollback deletes. A package is only saved the first time it is touched,
+/// and nothing happens outside a transaction.
+pub fn touch(project_root: &Path, name: &str, incoming: Option<&InstalledFiles>) -> LpmResult<()> {
+    let journal = lpm_metadata_dir(project_root).join(JOURNAL);
+    if !journal.is_dir() {
+        return Ok(());
+    }
+    let saved = journal.join(PACKAGES).join(name);
+    if !saved.exists() {
+        save_package(project_root, &journal, name)?;
+    }
+
+    if let Some(incoming) = incoming {
+        let path = saved.join(INCOMING);
+        let mut paths: BTreeSet<String> = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        paths.extend(incoming.files.keys().cloned());
+        fs::write(&path, paths.into_iter().collect::<Vec<_>>().join("\n"))?;
+    }
+    Ok(())
+}
+
+/// Empty lua_modules for a clean install
+///
+/// In a transaction, its contents are moved into the journal instead of
+/// deleted, for a rollback to move back.
+pub fn clear_lua_modules(project_root: &Path) -> LpmResult<()> {
+    let lua_modules = lua_modules_dir(project_root);
+    let journal = lpm_metadata_dir(project_root).join(JOURNAL);
+    if !journal.is_dir() {
+        if lua_modules.exists() {
+            fs::remove_dir_all(&lua_modules)?;
+        }
+        return Ok(());
+    }
+
+    let entries = tree_entries(project_root)?;
+    // Cleared earlier in this transaction: what is there now is all new
+    if journal.join(CLEARED_LIST).exists() {
+        for entry in &entries {
+            remove_path(&lua_modules.join(entry))?;
+        }
+        return Ok(());
+    }
+
+    // Listed first, so a rollback knows a listed entry still in lua_modules
+    // was never moved
+    let list = journal.join(CLEARED_LIST_TMP);
+    fs::write(&list, entries.join("\n"))?;
+    fs::rename(&list, journal.join(CLEARED_LIST))?;
+    let cleared = journal.join(CLEARED);
+    for entry in &entries {
+        let dst = cleared.join(entry);
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(lua_modules.join(entry), dst)?;
+    }
+    Ok(())
+}
+
+/// Roll back a transaction a previous lpm process didn't finish
+///
+/// Returns whether there was one.
+pub fn recover(project_root: &Path) -> LpmResult<bool> {
+    let metadata_dir = lpm_metadata_dir(project_root);
+    // Neither of these was ever a usable snapshot
+    for name in [JOURNAL_TMP, JOURNAL_OLD] {
+        let path = metadata_dir.join(name);
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+    }
+
+    if !metadata_dir.join(JOURNAL).is_dir() {
+        return Ok(false);
+    }
+    eprintln!(
+        "⚠️  The last install, update or removal in this project was interrupted. Rolling back..."
+    );
+    restore(project_root)?;
+    eprintln!("✓ Rollback completed");
+    Ok(true)
+}
+
+/// The files snapshotted when a transaction starts, by their name in the journal
+fn snapshotted(project_root: &Path) -> [(&'static str, PathBuf); 3] {
+    [
+        (LOCKFILE, project_root.join(LOCKFILE)),
+        (MANIFEST, project_root.join(MANIFEST)),
+        (LOADER, PathSetup::loader_path(project_root)),
+    ]
+}
+
+/// Put the journal's snapshot back in place and delete the journal
+///
+/// Safe to run again if it is interrupted: every step takes out what the
+/// transaction added before copying or moving back what it replaced.
+fn restore(project_root: &Path) -> LpmResult<()> {
+    let journal = lpm_metadata_dir(project_root).join(JOURNAL);
+    let existed = fs::read_to_string(journal.join(EXISTED)).map_err(|e| {
+        LpmError::Package(format!(
+            "Cannot roll back: the snapshot in {} is unreadable: {}",
+            journal.display(),
+            e
+        ))
+    })?;
+    let existed: Vec<&str> = existed.lines().collect();
+
+    let lua_modules = lua_modules_dir(project_root);
+    if !existed.contains(&LUA_MODULES) {
+        // Everything in it is new
+        for entry in tree_entries(project_root)? {
+            remove_path(&lua_modules.join(entry))?;
+        }
+    } else if journal.join(CLEARED_LIST).exists() {
+        restore_cleared(project_root, &journal)?;
+    } else if journal.join(PACKAGES).is_dir() {
+        for entry in fs::read_dir(journal.join(PACKAGES))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            restore_package(project_root, &entry.path(), &name)?;
+        }
+    }
+
+    for (name, path) in snapshotted(project_root) {
+        if existed.contains(&name) {
+            let backup = fs::read(journal.join(name))?;
+            // Leave untouched files exactly as they were
+            if fs::read(&path).ok().as_deref() != Some(backup.as_slice()) {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, backup)?;
+            }
+        } else if path.exists() {
+            fs::remove_file(&path)?;
+        }
+    }
+
+    discard_journal(project_root)?;
+    if !existed.contains(&LUA_MODULES) && lua_modules.exists() {
+        fs::remove_dir_all(&lua_modules)?;
+    }
+    Ok(())
+}
+
+/// Copy package `name` into the journal, see [`touch`]
+fn save_package(project_root: &Path, journal: &Path, name: &str) -> LpmResult<()> {
+    let lua_modules = lua_modules_dir(project_root);
+    let packages_dir = packages_metadata_dir(project_root);
+    // The package only counts as saved once it is complete
+    let tmp = journal.join(SAVING).join(name);
+    if tmp.exists() {
+        fs::remove_dir_all(&tmp)?;
+    }
+    let files = tmp.join(FILES);
+    fs::create_dir_all(&files)?;
+
+    let metadata = packages_dir.join(name);
+    if metadata.is_dir() {
+        copy_tree(&metadata, &tmp.join(METADATA))?;
+    }
+    let package_dir = lua_modules.join(name);
+    match InstalledFiles::load(&packages_dir, name)? {
+        Some(record) if record.link.is_none() => {
+            for key in record.files.keys() {
+                let path = lua_modules.join(key);
+                if fs::symlink_metadata(&path).is_ok() {
+                    copy_entry(&path, &files.join(key))?;
+                }
+            }
+            if package_dir.is_dir() {
+                fs::create_dir_all(files.join(name))?;
+            }
+        }
+        _ => {
+            if fs::symlink_metadata(&package_dir).is_ok() {
+                copy_entry(&package_dir, &files.join(name))?;
+            }
+        }
+    }
+
+    fs::create_dir_all(journal.join(PACKAGES))?;
+    fs::rename(&tmp, journal.join(PACKAGES).join(name))?;
+    Ok(())
+}
+
+/// Put package `name` back as [`save_package`] saved it in `saved`
+fn restore_package(project_root: &Path, saved: &Path, name: &str) -> LpmResult<()> {
+    let lua_modules = lua_modules_dir(project_root);
+    let packages_dir = packages_metadata_dir(project_root);
+
+    // Take out what the transaction installed: a record cut short by the
+    // interruption lists nothing, but its files are in `incoming`
+    let mut added = InstalledFiles::load(&packages_dir, name)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    if let Ok(incoming) = fs::read_to_string(saved.join(INCOMING)) {
+        added.files.extend(
+            incoming
+                .lines()
+                .map(|path| (path.to_string(), String::new())),
+        );
+    }
+    ownership::remove(&lua_modules, &added)?;
+    remove_path(&lua_modules.join(name))?;
+    let metadata = packages_dir.join(name);
+    remove_path(&metadata)?;
+
+    // Then put back what it replaced
+    copy_tree(&saved.join(FILES), &lua_modules)?;
+    if saved.join(METADATA).is_dir() {
+        copy_tree(&saved.join(METADATA), &metadata)?;
+    }
+    Ok(())
+}
+
+/// Undo [`clear_lua_modules`]: delete what was installed since and move the
+/// cleared entries back
+fn restore_cleared(project_root: &Path, journal: &Path) -> LpmResult<()> {
+    let lua_modules = lua_modules_dir(project_root);
+    let cleared = journal.join(CLEARED);
+    let listed = fs::read_to_string(journal.join(CLEARED_LIST))?;
+    let listed: Vec<&str> = listed.lines().collect();
+
+    for entry in tree_entries(project_root)? {
+        // Listed and not moved yet: it is still the original
+        let moved = fs::symlink_metadata(cleared.join(&entry)).is_ok();
+        if moved || !listed.contains(&entry.as_str()) {
+            remove_path(&lua_modules.join(&entry))?;
+        }
+    }
+    for entry in listed {
+        let src = cleared.join(entry);
+        if fs::symlink_metadata(&src).is_ok() {
+            let dst = lua_modules.join(entry);
+            if let Some(parent) = dst.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&src, dst)?;
+        }
+    }
+    Ok(())
+}
+
+/// What a clean install removes: everything in lua_modules, and everything in
+/// `lua_modules/.lpm` but the journal, relative to lua_modules
+fn tree_entries(project_root: &Path) -> LpmResult<Vec<String>> {
+    let lua_modules = lua_modules_dir(project_root);
+    let metadata_dir = lpm_metadata_dir(project_root);
+    let mut entries = Vec::new();
+    if !lua_modules.is_dir() {
+        return Ok(entries);
+    }
+    for entry in fs::read_dir(&lua_modules)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.path() != metadata_dir {
+            entries.push(name);
+            continue;
+        }
+        for inner in fs::read_dir(&metadata_dir)? {
+            let inner = inner?.file_name().to_string_lossy().to_string();
+            if ![JOURNAL, JOURNAL_TMP, JOURNAL_OLD].contains(&inner.as_str()) {
+                entries.push(format!("{}/{}", name, inner));
+            }
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Delete the journal, renaming it first so a half-deleted journal is never
+/// mistaken for a snapshot
+fn discard_journal(project_root: &Path) -> LpmResult<()> {
+    let metadata_dir = lpm_metadata_dir(project_root);
+    let old = metadata_dir.join(JOURNAL_OLD);
+    if old.exists() {
+        fs::remove_dir_all(&old)?;
+    }
+    fs::rename(metadata_dir.join(JOURNAL), &old)?;
+    fs::remove_dir_all(&old)?;
+    Ok(())
+}
+
+/// Delete a file, link or directory, if it is there
+fn remove_path(path: &Path) -> LpmResult<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+/// Copy a file, link or directory, creating the parent directories of `dst`
+fn copy_entry(src: &Path, dst: &Path) -> LpmResult<()> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file_type = fs::symlink_metadata(src)?.file_type();
+    if file_type.is_symlink() {
+        copy_symlink(src, dst)
+    } else if file_type.is_dir() {
+        copy_tree(src, dst)
+    } else {
+        fs::copy(src, dst)?;
+        Ok(())
+    }
+}
+
+/// Copy a directory tree, recreating symlinks (linked local packages) as
+/// links, over whatever is at `dst` already
+fn copy_tree(src: &Path, dst: &Path) -> LpmResult<()> {
+    for entry in WalkDir::new(src) {
+        let entry = entry?;
+        let relative = entry
+            .path()
+            .strip_prefix(src)
+            .map_err(|e| LpmError::Path(e.to_string()))?;
+        let dest = dst.join(relative);
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            fs::create_dir_all(&dest)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(entry.path(), &dest)?;
+        } else {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dst: &Path) -> LpmResult<()> {
+    remove_path(dst)?;
+    std::os::unix::fs::symlink(fs::read_link(src)?, dst)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, dst: &Path) -> LpmResult<()> {
+    fs::copy(src, dst)?;
+    Ok(())
+}
+
+/// Commit the transaction if it succeeded, roll it back otherwise
+fn finish<T>(rollback: RollbackManager, result: LpmResult<T>) -> LpmResult<T> {
+    match result {
+        Ok(result) => {
+            rollback.commit()?;
+            Ok(result)
+        }
+        Err(e) => {
+            eprintln!("\n⚠️  Operation failed. Attempting rollback...");
+            if let Err(rollback_err) = rollback.rollback() {
+                eprintln!("❌ Rollback failed: {}", rollback_err);
+            } else {
+                eprintln!("✓ Rollback completed");
+            }
+            Err(e)
+        }
     }
 }
 
@@ -55,25 +516,15 @@ where
     // Create rollback manager
     let rollback = RollbackManager::new(project_root)?;
 
-    // Execute the function
-    match f() {
-        Ok(result) => Ok(result),
-        Err(e) => {
-            // Attempt rollback
-            if rollback.has_backup() {
-                eprintln!("\n⚠️  Installation failed. Attempting rollback...");
-                if let Err(rollback_err) = rollback.rollback(project_root) {
-                    eprintln!("❌ Rollback failed: {}", rollback_err);
-                } else {
-                    eprintln!("✓ Rollback completed");
-                }
-            }
-            Err(e)
-        }
-    }
+    finish(rollback, f())
 }
 
 /// Execute an async function with automatic rollback on error
+///
+/// Ctrl-C while `f` runs rolls back too. `f` is dropped first, and an
+/// [`InstallPipeline`](crate::package::pipeline::InstallPipeline) dropped that
+/// way keeps its builds still running from committing, so nothing lands in
+/// lua_modules after the rollback.
 pub async fn with_rollback_async<F, Fut, T>(project_root: &Path, f: F) -> LpmResult<T>
 where
     F: FnOnce() -> Fut,
@@ -82,22 +533,11 @@ where
     // Create rollback manager
     let rollback = RollbackManager::new(project_root)?;
 
-    // Execute the function
-    match f().await {
-        Ok(result) => Ok(result),
-        Err(e) => {
-            // Attempt rollback
-            if rollback.has_backup() {
-                eprintln!("\n⚠️  Installation failed. Attempting rollback...");
-                if let Err(rollback_err) = rollback.rollback(project_root) {
-                    eprintln!("❌ Rollback failed: {}", rollback_err);
-                } else {
-                    eprintln!("✓ Rollback completed");
-                }
-            }
-            Err(e)
-        }
-    }
+    let result = tokio::select! {
+        result = f() => result,
+        _ = tokio::signal::ctrl_c() => Err(LpmError::Package("Interrupted".to_string())),
+    };
+    finish(rollback, result)
 }
 
 #[cfg(test)]
@@ -137,7 +577,7 @@ mod tests {
         modified.save(temp.path()).unwrap();
 
         // Rollback
-        rollback.rollback(temp.path()).unwrap();
+        rollback.rollback().unwrap();
 
         // Verify it was restored
         let restored = PackageManifest::load(temp.path()).unwrap();
@@ -213,6 +653,7 @@ mod tests {
             size: None,
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         lockfile.add_package("test-package".to_string(), package);
         lockfile.save(temp.path()).unwrap();
@@ -224,7 +665,7 @@ mod tests {
         new_lockfile.save(temp.path()).unwrap();
 
         // Rollback
-        rollback.rollback(temp.path()).unwrap();
+        rollback.rollback().unwrap();
 
         // Verify it was restored
         let restored = Lockfile::load(temp.path()).unwrap().unwrap();
@@ -259,4 +700,295 @@ mod tests {
         let restored = PackageManifest::load(temp.path()).unwrap();
         assert_eq!(restored.name, "test");
     }
+
+    /// Install package `name` with `files` (relative to lua_modules) and its record
+    fn install(project_root: &Path, name: &str, files: &[(&str, &str)]) -> InstalledFiles {
+        let lua_modules = lua_modules_dir(project_root);
+        let mut record = InstalledFiles {
+            version: "1.0-1".to_string(),
+            ..InstalledFiles::default()
+        };
+        for (path, content) in files {
+            let path_buf = lua_modules.join(path);
+            fs::create_dir_all(path_buf.parent().unwrap()).unwrap();
+            fs::write(&path_buf, content).unwrap();
+            record.files.insert(path.to_string(), String::new());
+        }
+        fs::create_dir_all(lua_modules.join(name)).unwrap();
+        record
+            .save(&packages_metadata_dir(project_root), name)
+            .unwrap();
+        record
+    }
+
+    fn journal(project_root: &Path) -> PathBuf {
+        lpm_metadata_dir(project_root).join(JOURNAL)
+    }
+
+    #[test]
+    fn test_rollback_restores_lua_modules() {
+        let temp = TempDir::new().unwrap();
+        PackageManifest::default("test".to_string())
+            .save(temp.path())
+            .unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        install(temp.path(), "old", &[("share/lua/5.4/old.lua", "return 1")]);
+
+        let result = with_rollback(temp.path(), || {
+            // A package half copied in, another half removed
+            let new = InstalledFiles {
+                files: [("share/lua/5.4/new.lua".to_string(), String::new())].into(),
+                ..InstalledFiles::default()
+            };
+            touch(temp.path(), "new", Some(&new)).unwrap();
+            fs::write(lua_modules.join("share/lua/5.4/new.lua"), "return").unwrap();
+            touch(temp.path(), "old", None).unwrap();
+            fs::remove_file(lua_modules.join("share/lua/5.4/old.lua")).unwrap();
+            Lockfile::new().save(temp.path()).unwrap();
+            Err::<(), LpmError>(LpmError::Package("build failed".to_string()))
+        });
+        assert!(result.is_err());
+
+        assert_eq!(
+            fs::read_to_string(lua_modules.join("share/lua/5.4/old.lua")).unwrap(),
+            "return 1"
+        );
+        assert!(!lua_modules.join("share/lua/5.4/new.lua").exists());
+        // It didn't exist before the install either
+        assert!(!temp.path().join("package.lock").exists());
+        assert!(!journal(temp.path()).exists());
+        assert!(!lpm_metadata_dir(temp.path()).join(JOURNAL_OLD).exists());
+    }
+
+    #[test]
+    fn test_journal_keeps_only_touched_packages() {
+        let temp = TempDir::new().unwrap();
+        install(temp.path(), "big", &[("share/lua/5.4/big.lua", "return 1")]);
+        install(
+            temp.path(),
+            "small",
+            &[("share/lua/5.4/small.lua", "return 2")],
+        );
+
+        let rollback = RollbackManager::new(temp.path()).unwrap();
+        touch(temp.path(), "small", None).unwrap();
+
+        let saved = journal(temp.path()).join(PACKAGES);
+        assert!(saved.join("small/files/share/lua/5.4/small.lua").exists());
+        assert!(!saved.join("big").exists());
+        // Nothing in the project root
+        assert_eq!(
+            fs::read_dir(temp.path()).unwrap().count(),
+            1,
+            "only lua_modules"
+        );
+        rollback.commit().unwrap();
+        assert!(!journal(temp.path()).exists());
+    }
+
+    #[test]
+    fn test_rollback_restores_replaced_package() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        install(
+            temp.path(),
+            "pkg",
+            &[
+                ("share/lua/5.4/pkg.lua", "v1"),
+                ("share/lua/5.4/pkg/old.lua", "v1"),
+            ],
+        );
+
+        let rollback = RollbackManager::new(temp.path()).unwrap();
+        // Upgraded: one file replaced, one dropped, one added
+        let v2 = [
+            ("share/lua/5.4/pkg.lua", "v2"),
+            ("share/lua/5.4/pkg/new.lua", "v2"),
+        ];
+        let incoming = InstalledFiles {
+            files: v2
+                .iter()
+                .map(|(path, _)| (path.to_string(), String::new()))
+                .collect(),
+            ..InstalledFiles::default()
+        };
+        touch(temp.path(), "pkg", Some(&incoming)).unwrap();
+        fs::remove_file(lua_modules.join("share/lua/5.4/pkg/old.lua")).unwrap();
+        install(temp.path(), "pkg", &v2);
+        rollback.rollback().unwrap();
+
+        let record = InstalledFiles::load(&packages_metadata_dir(temp.path()), "pkg")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            record.files.keys().collect::<Vec<_>>(),
+            ["share/lua/5.4/pkg.lua", "share/lua/5.4/pkg/old.lua"]
+        );
+        assert_eq!(
+            fs::read_to_string(lua_modules.join("share/lua/5.4/pkg.lua")).unwrap(),
+            "v1"
+        );
+        assert!(lua_modules.join("share/lua/5.4/pkg/old.lua").exists());
+        assert!(!lua_modules.join("share/lua/5.4/pkg/new.lua").exists());
+        assert!(lua_modules.join("pkg").is_dir());
+    }
+
+    #[test]
+    fn test_rollback_removes_new_lua_modules() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+
+        let rollback = RollbackManager::new(temp.path()).unwrap();
+        fs::create_dir_all(lua_modules.join("pkg")).unwrap();
+        rollback.rollback().unwrap();
+
+        assert!(!lua_modules.exists());
+    }
+
+    #[test]
+    fn test_commit_keeps_changes() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        fs::create_dir_all(&lua_modules).unwrap();
+
+        with_rollback(temp.path(), || {
+            fs::write(lua_modules.join("pkg.lua"), "return {}").unwrap();
+            Ok::<(), LpmError>(())
+        })
+        .unwrap();
+
+        assert!(lua_modules.join("pkg.lua").exists());
+        assert!(!journal(temp.path()).exists());
+    }
+
+    #[test]
+    fn test_commit_without_changes_leaves_no_lua_modules() {
+        let temp = TempDir::new().unwrap();
+        with_rollback(temp.path(), || Ok::<(), LpmError>(())).unwrap();
+        assert!(!temp.path().join("lua_modules").exists());
+    }
+
+    #[test]
+    fn test_rollback_undoes_clear() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        install(temp.path(), "pkg", &[("share/lua/5.4/pkg.lua", "return 1")]);
+        fs::write(lua_modules.join("stray.lua"), "return 2").unwrap();
+
+        let rollback = RollbackManager::new(temp.path()).unwrap();
+        clear_lua_modules(temp.path()).unwrap();
+        assert!(!lua_modules.join("share").exists());
+        assert!(!packages_metadata_dir(temp.path()).exists());
+        install(
+            temp.path(),
+            "other",
+            &[("share/lua/5.4/other.lua", "return 3")],
+        );
+        rollback.rollback().unwrap();
+
+        assert!(lua_modules.join("share/lua/5.4/pkg.lua").exists());
+        assert!(lua_modules.join("stray.lua").exists());
+        assert!(!lua_modules.join("share/lua/5.4/other.lua").exists());
+        assert!(!lua_modules.join("other").exists());
+        assert!(
+            InstalledFiles::load(&packages_metadata_dir(temp.path()), "pkg")
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_recover_interrupted_clear() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        install(temp.path(), "a", &[("a.lua", "return 1")]);
+        install(temp.path(), "b", &[("b.lua", "return 2")]);
+
+        // Killed after listing the entries to clear and moving only one
+        let rollback = RollbackManager::new(temp.path()).unwrap();
+        let journal = journal(temp.path());
+        let entries = tree_entries(temp.path()).unwrap();
+        fs::write(journal.join(CLEARED_LIST), entries.join("\n")).unwrap();
+        fs::create_dir_all(journal.join(CLEARED)).unwrap();
+        fs::rename(
+            lua_modules.join("a.lua"),
+            journal.join(CLEARED).join("a.lua"),
+        )
+        .unwrap();
+        std::mem::forget(rollback);
+
+        assert!(recover(temp.path()).unwrap());
+        assert!(lua_modules.join("a.lua").exists());
+        assert!(lua_modules.join("b.lua").exists());
+        assert!(lua_modules.join("a").is_dir());
+        assert!(!journal.exists());
+    }
+
+    #[test]
+    fn test_recover_interrupted_transaction() {
+        let temp = TempDir::new().unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        install(temp.path(), "pkg", &[("pkg.lua", "return 1")]);
+        Lockfile::new().save(temp.path()).unwrap();
+        let lockfile = fs::read(temp.path().join("package.lock")).unwrap();
+
+        // The process dies halfway through, without committing or rolling back
+        let rollback = RollbackManager::new(temp.path()).unwrap();
+        touch(temp.path(), "pkg", None).unwrap();
+        fs::write(lua_modules.join("pkg.lua"), "ret").unwrap();
+        fs::write(temp.path().join("package.lock"), "packages: {").unwrap();
+        std::mem::forget(rollback);
+
+        // The next transaction puts the project back first
+        assert!(recover(temp.path()).unwrap());
+        assert_eq!(
+            fs::read_to_string(lua_modules.join("pkg.lua")).unwrap(),
+            "return 1"
+        );
+        assert_eq!(
+            fs::read(temp.path().join("package.lock")).unwrap(),
+            lockfile
+        );
+        assert!(!journal(temp.path()).exists());
+    }
+
+    #[test]
+    fn test_recover_discards_incomplete_snapshot() {
+        let temp = TempDir::new().unwrap();
+        let tmp = lpm_metadata_dir(temp.path()).join(JOURNAL_TMP);
+        fs::create_dir_all(&tmp).unwrap();
+        fs::create_dir_all(temp.path().join("lua_modules/pkg")).unwrap();
+
+        assert!(!recover(temp.path()).unwrap());
+        assert!(!tmp.exists());
+        assert!(temp.path().join("lua_modules/pkg").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rollback_keeps_linked_packages_as_links() {
+        let temp = TempDir::new().unwrap();
+        let local = temp.path().join("local-pkg");
+        fs::create_dir_all(&local).unwrap();
+        fs::write(local.join("init.lua"), "return 1").unwrap();
+        let lua_modules = temp.path().join("lua_modules");
+        fs::create_dir_all(&lua_modules).unwrap();
+        std::os::unix::fs::symlink(&local, lua_modules.join("local-pkg")).unwrap();
+        InstalledFiles::linked("local-pkg", "1.0.0")
+            .save(&packages_metadata_dir(temp.path()), "local-pkg")
+            .unwrap();
+
+        let rollback = RollbackManager::new(temp.path()).unwrap();
+        touch(temp.path(), "local-pkg", None).unwrap();
+        fs::remove_file(lua_modules.join("local-pkg")).unwrap();
+        rollback.rollback().unwrap();
+
+        let link = lua_modules.join("local-pkg");
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), local);
+        assert!(local.join("init.lua").exists());
+    }
 }
@@ -1,5 +1,6 @@
 use crate::core::version::Version;
 use crate::package::lockfile::Lockfile;
+use crate::package::ownership::InstalledFiles;
 use std::collections::HashMap;
 use std::path::Path;
 
@@ -142,15 +143,9 @@ impl UpdateDiff {
 
     /// Calculate file changes for packages that will be updated
     pub fn calculate_file_changes(&mut self, project_root: &Path) {
-        use crate::core::path::lua_modules_dir;
-        use std::fs;
-
-        let lua_modules = lua_modules_dir(project_root);
-
         for change in &self.package_changes {
             match change {
                 PackageChange::Updated { name, .. } | PackageChange::Added { name, .. } => {
-                    let package_dir = lua_modules.join(name);
                     let mut file_changes = PackageFileChanges {
                         package_name: name.clone(),
                         added: Vec::new(),
@@ -160,27 +155,9 @@ impl UpdateDiff {
 
                     // If package is being updated and already exists, check for file changes
                     if let PackageChange::Updated { .. } = change {
-                        if package_dir.exists() {
-                            // Get list of current files
-                            let current_files: Vec<String> =
-                                if let Ok(entries) = fs::read_dir(&package_dir) {
-                                    entries
-                                        .filter_map(|e| e.ok())
-                                        .filter_map(|e| {
-                                            e.path()
-                                                .strip_prefix(&package_dir)
-                                                .ok()
-                                                .and_then(|p| p.to_str().map(|s| s.to_string()))
-                                        })
-                                        .collect()
-                                } else {
-                                    Vec::new()
-                                };
-
-                            // For now, we'll mark all files as potentially modified
-                            // In a full implementation, we'd compare checksums or file contents
-                            file_changes.modified = current_files;
-                        }
+                        // For now, we'll mark all files as potentially modified
+                        // In a full implementation, we'd compare checksums or file contents
+                        file_changes.modified = installed_files(project_root, name);
                     } else {
                         // New package - files will be added
                         // We can't know the exact files until download, but we can note it
@@ -195,29 +172,14 @@ impl UpdateDiff {
                     }
                 }
                 PackageChange::Removed { name, .. } => {
-                    let package_dir = lua_modules.join(name);
-                    let mut file_changes = PackageFileChanges {
+                    let file_changes = PackageFileChanges {
                         package_name: name.clone(),
                         added: Vec::new(),
                         modified: Vec::new(),
-                        deleted: Vec::new(),
+                        // Files that will be deleted
+                        deleted: installed_files(project_root, name),
                     };
 
-                    if package_dir.exists() {
-                        // Get list of files that will be deleted
-                        if let Ok(entries) = fs::read_dir(&package_dir) {
-                            file_changes.deleted = entries
-                                .filter_map(|e| e.ok())
-                                .filter_map(|e| {
-                                    e.path()
-                                        .strip_prefix(&package_dir)
-                                        .ok()
-                                        .and_then(|p| p.to_str().map(|s| s.to_string()))
-                                })
-                                .collect();
-                        }
-                    }
-
                     if !file_changes.deleted.is_empty() {
                         self.file_changes.push(file_changes);
                     }
@@ -314,6 +276,35 @@ impl Default for UpdateDiff {
     }
 }
 
+/// The files an installed package put in lua_modules
+///
+/// Read from the package's record of installed files; packages installed
+/// before files were recorded list the entries of their directory.
+fn installed_files(project_root: &Path, name: &str) -> Vec<String> {
+    use crate::core::path::{lua_modules_dir, packages_metadata_dir};
+    use std::fs;
+
+    if let Ok(Some(files)) = InstalledFiles::load(&packages_metadata_dir(project_root), name) {
+        if files.link.is_none() {
+            return files.files.into_keys().collect();
+        }
+    }
+
+    let package_dir = lua_modules_dir(project_root).join(name);
+    match fs::read_dir(&package_dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                e.path()
+                    .strip_prefix(&package_dir)
+                    .ok()
+                    .and_then(|p| p.to_str().map(|s| s.to_string()))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -331,6 +322,7 @@ mod tests {
             size: None,
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         lockfile.add_package("test-package".to_string(), package);
         lockfile
@@ -453,6 +445,7 @@ mod tests {
             size: None,
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         lockfile.add_package("dev-package".to_string(), dev_package);
 
@@ -552,6 +545,30 @@ mod tests {
         assert!(!file_change.deleted.is_empty());
     }
 
+    #[test]
+    fn test_update_diff_file_changes_from_installed_files() {
+        let temp = tempfile::TempDir::new().unwrap();
+
+        use crate::core::path::packages_metadata_dir;
+        let mut files = InstalledFiles::default();
+        files
+            .files
+            .insert("share/lua/5.4/pkg.lua".to_string(), String::new());
+        files
+            .save(&packages_metadata_dir(temp.path()), "pkg")
+            .unwrap();
+
+        let mut diff = UpdateDiff::new();
+        diff.package_changes.push(PackageChange::Removed {
+            name: "pkg".to_string(),
+            version: Version::new(1, 0, 0),
+        });
+        diff.calculate_file_changes(temp.path());
+
+        assert_eq!(diff.file_changes.len(), 1);
+        assert_eq!(diff.file_changes[0].deleted, vec!["share/lua/5.4/pkg.lua"]);
+    }
+
     #[test]
     fn test_update_diff_display() {
         let mut diff = UpdateDiff::new();
@@ -615,6 +632,7 @@ mod tests {
             size: None,
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         lockfile.add_package("test-package".to_string(), package);
         let resolved = HashMap::from([("test-package".to_string(), Version::new(2, 0, 0))]);
@@ -691,6 +709,7 @@ mod tests {
             size: None,
             dependencies: HashMap::new(),
             build: None,
+            commit: None,
         };
         lockfile.add_package("dev-pkg".to_string(), package);
         let resolved = HashMap::new();
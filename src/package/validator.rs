@@ -1,5 +1,6 @@
 use crate::core::version::parse_constraint;
 use crate::core::{LpmError, LpmResult};
+use crate::package::dependency::DependencySpec;
 use crate::package::manifest::PackageManifest;
 use std::collections::HashSet;
 
@@ -115,13 +116,15 @@ impl ManifestValidator {
             // Validate dependency name
             Self::validate_name(name)?;
 
-            // Validate version constraint
-            parse_constraint(version).map_err(|e| {
-                LpmError::Package(format!(
-                    "Invalid version constraint '{}' for dependency '{}': {}",
-                    version, name, e
-                ))
-            })?;
+            // Validate version constraint (git and path dependencies have none)
+            if let DependencySpec::Registry(constraint) = DependencySpec::parse(version) {
+                parse_constraint(&constraint).map_err(|e| {
+                    LpmError::Package(format!(
+                        "Invalid version constraint '{}' for dependency '{}': {}",
+                        version, name, e
+                    ))
+                })?;
+            }
         }
 
         Ok(())
@@ -261,6 +264,15 @@ mod tests {
         let mut deps_invalid = HashMap::new();
         deps_invalid.insert("test-pkg".to_string(), "invalid-constraint".to_string());
         assert!(ManifestValidator::validate_dependencies(&deps_invalid).is_err());
+
+        // Git and path dependencies are not version constraints
+        let mut deps_sources = HashMap::new();
+        deps_sources.insert(
+            "mylib".to_string(),
+            "git+https://example.com/mylib.git#tag=v1.2".to_string(),
+        );
+        deps_sources.insert("local".to_string(), "path:../local".to_string());
+        assert!(ManifestValidator::validate_dependencies(&deps_sources).is_ok());
     }
 
     #[test]
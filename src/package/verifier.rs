@@ -1,5 +1,6 @@
 use crate::cache::Cache;
 use crate::core::{LpmError, LpmResult};
+use crate::package::git::tree_checksum;
 use crate::package::lockfile::{LockedPackage, Lockfile};
 use std::path::Path;
 
@@ -36,7 +37,7 @@ impl PackageVerifier {
         &self,
         package_name: &str,
         package: &LockedPackage,
-        _project_root: &Path,
+        project_root: &Path,
     ) -> LpmResult<()> {
         // Extract checksum from lockfile (format: "sha256:...")
         let expected_checksum = &package.checksum;
@@ -48,9 +49,13 @@ impl PackageVerifier {
             )));
         }
 
-        // Get the source file path from cache
+        // Get the source file path from cache (local packages are checked by their package.yaml)
         let source_path = if let Some(source_url) = &package.source_url {
-            self.cache.source_path(source_url)
+            match (&package.source[..], &package.commit) {
+                ("git", Some(commit)) => self.cache.git_archive_path(source_url, commit),
+                ("path", _) => project_root.join(source_url).join("package.yaml"),
+                _ => self.cache.source_path(source_url),
+            }
         } else {
             return Err(LpmError::Package(format!(
                 "No source_url for package '{}' in lockfile",
@@ -67,8 +72,12 @@ impl PackageVerifier {
             )));
         }
 
-        // Calculate actual checksum
-        let actual_checksum = Cache::checksum(&source_path)?;
+        // Calculate actual checksum (git archives are checked by their file tree)
+        let actual_checksum = if package.source == "git" {
+            tree_checksum(&source_path)?
+        } else {
+            Cache::checksum(&source_path)?
+        };
 
         // Compare checksums
         if actual_checksum != *expected_checksum {
@@ -296,12 +305,40 @@ mod tests {
             size: None,
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
 
         let result = verifier.verify_package("test-package", &package, temp.path());
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_verify_local_package() {
+        let temp = TempDir::new().unwrap();
+        let cache = Cache::new(temp.path().join("cache")).unwrap();
+        let verifier = PackageVerifier::new(cache);
+        let project = temp.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(temp.path().join("mylib")).unwrap();
+        std::fs::write(
+            temp.path().join("mylib/package.yaml"),
+            "name: mylib\nversion: 0.1.0\n",
+        )
+        .unwrap();
+
+        let (package, _) = crate::package::local::lock(&project, "../mylib").unwrap();
+        assert!(verifier.verify_package("mylib", &package, &project).is_ok());
+
+        std::fs::write(
+            temp.path().join("mylib/package.yaml"),
+            "name: mylib\nversion: 0.2.0\n",
+        )
+        .unwrap();
+        assert!(verifier
+            .verify_package("mylib", &package, &project)
+            .is_err());
+    }
+
     #[test]
     fn test_verify_package_invalid_checksum_format() {
         let temp = TempDir::new().unwrap();
@@ -317,6 +354,7 @@ mod tests {
             size: None,
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
 
         let result = verifier.verify_package("test-package", &package, temp.path());
@@ -344,6 +382,7 @@ mod tests {
             size: None,
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
 
         let result = verifier.verify_package("test-package", &package, temp.path());
@@ -383,6 +422,7 @@ mod tests {
             size: None,
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
 
         let result = verifier.verify_package("test-package", &package, temp.path());
@@ -406,6 +446,7 @@ mod tests {
             size: None,
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
         let package2 = LockedPackage {
             version: "2.0.0".to_string(),
@@ -416,6 +457,7 @@ mod tests {
             size: None,
             dependencies: std::collections::HashMap::new(),
             build: None,
+            commit: None,
         };
 
         lockfile.add_package("pkg1".to_string(), package1);
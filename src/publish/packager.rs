@@ -2,6 +2,8 @@ use crate::build::prebuilt::PrebuiltBinaryManager;
 use crate::build::targets::Target;
 use crate::core::{LpmError, LpmResult};
 use crate::lua_version::detector::LuaVersionDetector;
+use crate::luarocks::rock;
+use crate::luarocks::rockspec::Rockspec;
 use crate::package::manifest::PackageManifest;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -55,6 +57,59 @@ impl PublishPackager {
         Ok(archive_path)
     }
 
+    /// Write the rocks LuaRocks installs from next to the archive
+    ///
+    /// Always a source rock bundling `rockspec` and the archive; for pure-Lua
+    /// packages also an `all` rock with the modules from `lua/` (or `src/`)
+    /// laid out as they are installed.
+    pub fn package_rocks(&self, rockspec: &str, archive: &Path) -> LpmResult<Vec<PathBuf>> {
+        let parsed = Rockspec::parse_lua(rockspec)?;
+        let dist_dir = archive.parent().ok_or_else(|| {
+            LpmError::Path(format!("Invalid archive path: {}", archive.display()))
+        })?;
+
+        let mut rocks = vec![rock::write_source_rock(
+            rockspec,
+            &parsed.package,
+            &parsed.version,
+            archive,
+            dist_dir,
+        )?];
+
+        let pure_lua = self
+            .manifest
+            .build
+            .as_ref()
+            .is_none_or(|build| build.build_type != "rust");
+        let lua_dir = ["lua", "src"]
+            .iter()
+            .map(|dir| self.project_root.join(dir))
+            .find(|dir| dir.is_dir());
+        if let (true, Some(lua_dir)) = (pure_lua, lua_dir) {
+            let staging = dist_dir.join(format!(".rock-{}-{}", parsed.package, parsed.version));
+            if staging.exists() {
+                fs::remove_dir_all(&staging)?;
+            }
+            self.copy_directory(&lua_dir, &staging.join("lua"), |path| {
+                path.extension().map(|e| e == "lua").unwrap_or(false)
+            })?;
+            rocks.push(rock::write_binary_rock(
+                &staging,
+                rockspec,
+                &parsed.package,
+                &parsed.version,
+                rock::ALL,
+                dist_dir,
+            )?);
+            fs::remove_dir_all(&staging)?;
+        }
+
+        for rock in &rocks {
+            println!("✓ Packaged rock: {}", rock.display());
+        }
+        Ok(rocks)
+    }
+
     /// Copy all Lua source files to the package directory
     fn copy_lua_files(&self, package_dir: &Path) -> LpmResult<()> {
         // Look for Lua files in common directories
@@ -241,6 +296,37 @@ mod tests {
         assert_eq!(packager.manifest.name, "test-package");
     }
 
+    #[test]
+    fn test_package_rocks() {
+        let temp = TempDir::new().unwrap();
+        let manifest = PackageManifest::default("acme".to_string());
+        let packager = PublishPackager::new(temp.path(), manifest);
+        fs::create_dir_all(temp.path().join("src/acme")).unwrap();
+        fs::write(temp.path().join("src/acme/init.lua"), "return {}").unwrap();
+        fs::write(temp.path().join("src/notes.txt"), "not a module").unwrap();
+        let dist = temp.path().join("dist");
+        fs::create_dir_all(&dist).unwrap();
+        let archive = dist.join("acme-1.0.0.tar.gz");
+        fs::write(&archive, b"archive").unwrap();
+
+        let rockspec = "package = \"acme\"\nversion = \"1.0-1\"\nsource = { url = \"\" }\n";
+        let rocks = packager.package_rocks(rockspec, &archive).unwrap();
+        assert_eq!(
+            rocks,
+            vec![
+                dist.join("acme-1.0-1.src.rock"),
+                dist.join("acme-1.0-1.all.rock")
+            ]
+        );
+        rock::verify(&rocks[1]).unwrap();
+        assert_eq!(
+            rock::read_file(&rocks[1], "lua/acme/init.lua").unwrap(),
+            b"return {}"
+        );
+        assert!(rock::read_file(&rocks[1], "lua/notes.txt").is_err());
+        assert!(!dist.join(".rock-acme-1.0-1").exists());
+    }
+
     #[test]
     fn test_copy_lua_files_from_src_dir() {
         let temp = TempDir::new().unwrap();
@@ -1,5 +1,6 @@
 use crate::core::credentials::CredentialStore;
 use crate::core::{LpmError, LpmResult};
+use crate::luarocks::repository::LocalRepository;
 use crate::package::manifest::PackageManifest;
 use crate::publish::packager::PublishPackager;
 use crate::publish::rockspec_generator::RockspecGenerator;
@@ -7,10 +8,12 @@ use crate::publish::validator::PublishValidator;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-/// Publishes Lua modules to LuaRocks
+/// Publishes Lua modules to LuaRocks, or to a local repository
 pub struct Publisher {
     project_root: PathBuf,
     manifest: PackageManifest,
+    /// A local repository to publish to instead, and the URL it is served at
+    repository: Option<(LocalRepository, String)>,
 }
 
 impl Publisher {
@@ -19,11 +22,22 @@ impl Publisher {
         Self {
             project_root: project_root.to_path_buf(),
             manifest,
+            repository: None,
         }
     }
 
+    /// Publish into `repository` (served at `url`) instead of uploading to LuaRocks
+    pub fn with_repository(mut self, repository: LocalRepository, url: &str) -> Self {
+        self.repository = Some((repository, url.trim_end_matches('/').to_string()));
+        self
+    }
+
     /// Publish the package to LuaRocks
     pub async fn publish(&self, include_binaries: bool) -> LpmResult<()> {
+        if let Some((repository, url)) = &self.repository {
+            return self.publish_to_repository(repository, url, include_binaries);
+        }
+
         // 1. Validate package
         println!("Validating package...");
         PublishValidator::validate(&self.manifest, &self.project_root)?;
@@ -49,13 +63,14 @@ impl Publisher {
                 &self.manifest.version
             )?)
         ));
-        fs::write(&rockspec_path, rockspec_content)?;
+        fs::write(&rockspec_path, &rockspec_content)?;
         println!("✓ Generated rockspec: {}", rockspec_path.display());
 
         // 4. Package the module
         println!("Packaging module...");
         let packager = PublishPackager::new(&self.project_root, self.manifest.clone());
         let archive_path = packager.package(include_binaries)?;
+        packager.package_rocks(&rockspec_content, &archive_path)?;
 
         // 5. Upload to LuaRocks
         println!("Uploading to LuaRocks...");
@@ -67,6 +82,45 @@ impl Publisher {
         Ok(())
     }
 
+    /// Add the package to a local repository, pointing its rockspec at the archive there
+    fn publish_to_repository(
+        &self,
+        repository: &LocalRepository,
+        url: &str,
+        include_binaries: bool,
+    ) -> LpmResult<()> {
+        println!("Validating package...");
+        PublishValidator::validate(&self.manifest, &self.project_root)?;
+
+        println!("Packaging module...");
+        let packager = PublishPackager::new(&self.project_root, self.manifest.clone());
+        let archive_path = packager.package(include_binaries)?;
+        let archive_name = archive_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let rockspec = RockspecGenerator::generate_with_source(
+            &self.manifest,
+            &format!("{}/{}", url, archive_name),
+        )?;
+        // The source rock is written by the repository itself
+        for rock in packager.package_rocks(&rockspec, &archive_path)? {
+            if !rock.to_string_lossy().ends_with(".src.rock") {
+                repository.add(&rock)?;
+            }
+        }
+        let (name, version) = repository.publish(&rockspec, &archive_path)?;
+        println!(
+            "✓ Published {} {} to {}",
+            name,
+            version,
+            repository.root().display()
+        );
+
+        Ok(())
+    }
+
     /// Upload package to LuaRocks API
     async fn upload_to_luarocks(
         &self,
@@ -225,6 +279,38 @@ mod tests {
         // Similar to above - would need URL override for full testing
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_publish_to_local_repository() {
+        use std::fs;
+
+        let temp = TempDir::new().unwrap();
+        let project = temp.path().join("project");
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::write(project.join("src/acme.lua"), "return {}").unwrap();
+        let mut manifest = PackageManifest::default("acme-log".to_string());
+        manifest.version = "1.2.0".to_string();
+        manifest.description = Some("Logging".to_string());
+        manifest.license = Some("MIT".to_string());
+
+        let repository = LocalRepository::open(&temp.path().join("repo")).unwrap();
+        Publisher::new(&project, manifest)
+            .with_repository(repository.clone(), "http://localhost:8080/")
+            .publish(false)
+            .await
+            .unwrap();
+
+        let rockspec =
+            fs::read_to_string(repository.root().join("acme-log-1.2.0-1.rockspec")).unwrap();
+        assert!(rockspec.contains("url = \"http://localhost:8080/acme-log-1.2.0.tar.gz\""));
+        assert!(repository.root().join("acme-log-1.2.0.tar.gz").exists());
+        assert!(repository.root().join("acme-log-1.2.0-1.src.rock").exists());
+        // Pure Lua, so installable without building
+        assert!(repository.root().join("acme-log-1.2.0-1.all.rock").exists());
+        let manifest = fs::read_to_string(repository.root().join("manifest")).unwrap();
+        assert!(manifest.contains("[\"acme-log\"]"));
+    }
+
     #[test]
     fn test_publisher_with_different_manifests() {
         let temp = TempDir::new().unwrap();
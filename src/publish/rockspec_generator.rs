@@ -9,6 +9,15 @@ pub struct RockspecGenerator;
 impl RockspecGenerator {
     /// Generate a rockspec file content from a PackageManifest
     pub fn generate(manifest: &PackageManifest) -> LpmResult<String> {
+        Self::render(manifest, None)
+    }
+
+    /// Generate a rockspec whose source is the archive at `source_url`
+    pub fn generate_with_source(manifest: &PackageManifest, source_url: &str) -> LpmResult<String> {
+        Self::render(manifest, Some(source_url))
+    }
+
+    fn render(manifest: &PackageManifest, source_url: Option<&str>) -> LpmResult<String> {
         // Convert version to LuaRocks format (e.g., "1.2.3" -> "1.2-1")
         let luarocks_version = to_luarocks_version(&Version::parse(&manifest.version)?);
 
@@ -38,9 +47,12 @@ impl RockspecGenerator {
         rockspec.push_str(&format!("lua_version = \"{}\"\n", manifest.lua_version));
         rockspec.push('\n');
 
-        // Source (for now, we'll use a placeholder - this should be set by the publisher)
+        // Source (a placeholder unless the publisher knows where the archive will live)
         rockspec.push_str("source = {\n");
-        rockspec.push_str("  url = \"\", -- Will be set during publish\n");
+        match source_url {
+            Some(url) => rockspec.push_str(&format!("  url = \"{}\",\n", escape_lua_string(url))),
+            None => rockspec.push_str("  url = \"\", -- Will be set during publish\n"),
+        }
         rockspec.push_str("}\n");
         rockspec.push('\n');
 
@@ -100,38 +112,53 @@ impl RockspecGenerator {
             return Ok(name.to_string());
         }
 
-        // Parse SemVer constraint and convert to LuaRocks format
         let constraint = parse_constraint(version)?;
-
-        match constraint {
-            VersionConstraint::Exact(v) => {
-                let luarocks_ver = to_luarocks_version(&v);
-                Ok(format!("{} == {}", name, luarocks_ver))
-            }
-            VersionConstraint::Compatible(v) => {
-                // ^1.2.3 -> ~>1.2.3
-                let luarocks_ver = to_luarocks_version(&v);
-                Ok(format!("{} ~> {}", name, luarocks_ver))
-            }
-            VersionConstraint::Patch(v) => {
-                // ~1.2.3 -> ~>1.2.3
-                let luarocks_ver = to_luarocks_version(&v);
-                Ok(format!("{} ~> {}", name, luarocks_ver))
-            }
-            VersionConstraint::GreaterOrEqual(v) => {
-                let luarocks_ver = to_luarocks_version(&v);
-                Ok(format!("{} >= {}", name, luarocks_ver))
-            }
-            VersionConstraint::LessThan(v) => {
-                let luarocks_ver = to_luarocks_version(&v);
-                Ok(format!("{} < {}", name, luarocks_ver))
-            }
-            VersionConstraint::AnyPatch(v) => {
-                let luarocks_ver = to_luarocks_version(&v);
-                Ok(format!("{} ~> {}", name, luarocks_ver))
-            }
+        let luarocks_constraint = Self::format_constraint(&constraint).ok_or_else(|| {
+            LpmError::Package(format!(
+                "Dependency '{} {}' cannot be expressed in a rockspec: LuaRocks has no '||' operator",
+                name, version
+            ))
+        })?;
+
+        if luarocks_constraint.is_empty() {
+            Ok(name.to_string())
+        } else {
+            Ok(format!("{} {}", name, luarocks_constraint))
         }
     }
+
+    /// Convert a constraint to LuaRocks operators (`==`, `~=`, `>=`, `>`, `<=`, `<`, `~>`)
+    ///
+    /// Returns `None` for disjunctions, which rockspecs cannot express.
+    ///
+    /// Dependency constraints only carry a revision if the manifest spelled one out.
+    fn format_constraint(constraint: &VersionConstraint) -> Option<String> {
+        Some(match constraint {
+            VersionConstraint::Exact(v) => format!("== {}", v),
+            VersionConstraint::NotEqual(v) => format!("~= {}", v),
+            VersionConstraint::GreaterOrEqual(v) => format!(">= {}", v),
+            VersionConstraint::GreaterThan(v) => format!("> {}", v),
+            VersionConstraint::LessOrEqual(v) => format!("<= {}", v),
+            VersionConstraint::LessThan(v) => format!("< {}", v),
+            VersionConstraint::Pessimistic(v) => format!("~> {}", v),
+            // ^1.2.3 -> >= 1.2.3, ~> 1
+            VersionConstraint::Compatible(v) => format!(">= {}, ~> {}", v, v.major),
+            // ~1.2.3 -> >= 1.2.3, ~> 1.2
+            VersionConstraint::Patch(v) => format!(">= {}, ~> {}.{}", v, v.major, v.minor),
+            // 1.2.x -> ~> 1.2
+            VersionConstraint::AnyPatch(v) => format!("~> {}.{}", v.major, v.minor),
+            VersionConstraint::Any => String::new(),
+            VersionConstraint::All(parts) => parts
+                .iter()
+                .map(Self::format_constraint)
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .filter(|p| !p.is_empty())
+                .collect::<Vec<_>>()
+                .join(", "),
+            VersionConstraint::AnyOf(_) => return None,
+        })
+    }
 }
 
 /// Escape special characters in Lua strings
@@ -159,8 +186,8 @@ mod tests {
 
         let rockspec = RockspecGenerator::generate(&manifest).unwrap();
         assert!(rockspec.contains("package = \"test-package\""));
-        // Version "1.2.3" converts to "1.2-3" (patch version becomes revision)
-        assert!(rockspec.contains("version = \"1.2-3\""));
+        // Version "1.2.3" becomes the first rockspec revision "1.2.3-1"
+        assert!(rockspec.contains("version = \"1.2.3-1\""));
         assert!(rockspec.contains("luasocket"));
     }
 
@@ -357,9 +384,34 @@ mod tests {
 
     #[test]
     fn test_format_dependency_any_patch() {
-        let result = RockspecGenerator::format_dependency("test-pkg", "~1.2.3").unwrap();
-        assert!(result.contains("test-pkg"));
-        assert!(result.contains("~>"));
+        let result = RockspecGenerator::format_dependency("test-pkg", "1.2.x").unwrap();
+        assert_eq!(result, "test-pkg ~> 1.2");
+    }
+
+    #[test]
+    fn test_format_dependency_keeps_range_semantics() {
+        assert_eq!(
+            RockspecGenerator::format_dependency("test-pkg", "^1.2.3").unwrap(),
+            "test-pkg >= 1.2.3, ~> 1"
+        );
+        assert_eq!(
+            RockspecGenerator::format_dependency("test-pkg", "~1.2.3").unwrap(),
+            "test-pkg >= 1.2.3, ~> 1.2"
+        );
+        assert_eq!(
+            RockspecGenerator::format_dependency("test-pkg", ">=1.6, <2").unwrap(),
+            "test-pkg >= 1.6, < 2"
+        );
+        assert_eq!(
+            RockspecGenerator::format_dependency("test-pkg", "~= 1.4").unwrap(),
+            "test-pkg ~= 1.4"
+        );
+    }
+
+    #[test]
+    fn test_format_dependency_rejects_disjunction() {
+        let result = RockspecGenerator::format_dependency("test-pkg", "^1.0 || ^2.0");
+        assert!(result.is_err());
     }
 
     #[test]
@@ -2,121 +2,211 @@ use crate::cache::Cache;
 use crate::config::Config;
 use crate::core::version::{Version, VersionConstraint};
 use crate::core::{LpmError, LpmResult};
+use crate::lua_version::{parse_lua_version_constraint, LuaVersion};
 use crate::luarocks::client::LuaRocksClient;
+use crate::luarocks::index::RockspecSummary;
 use crate::luarocks::manifest::Manifest;
 use crate::luarocks::rockspec::Rockspec;
-use crate::luarocks::search_api::SearchAPI;
+use crate::package::dependency::DependencySpec;
 use crate::resolver::dependency_graph::DependencyGraph;
-use std::collections::{HashMap, HashSet};
+use crate::resolver::solver::{
+    DependencyProvider, InMemoryProvider, MissingData, SolveError, Solver,
+};
+use std::collections::HashMap;
 
-/// Resolves dependencies and versions using SemVer algorithm
+/// The Lua runtime, as rockspecs name it in their dependencies
+const LUA: &str = "lua";
+
+/// Lua versions a project's `lua_version` can select from
+const LUA_VERSIONS: &[&str] = &["5.1", "5.2", "5.3", "5.4"];
+
+/// Resolves dependencies and versions using a backtracking solver
 pub struct DependencyResolver {
     manifest: Manifest,
+    root_name: String,
+    offline: bool,
+    lua_version: Option<String>,
 }
 
 impl DependencyResolver {
     pub fn new(manifest: Manifest) -> Self {
-        Self { manifest }
+        Self {
+            manifest,
+            root_name: "root".to_string(),
+            offline: false,
+            lua_version: None,
+        }
+    }
+
+    /// Read rockspecs only from the cache (see `LuaRocksClient::with_offline`)
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Set the name used for the project in conflict explanations
+    pub fn with_root_name(mut self, name: impl Into<String>) -> Self {
+        self.root_name = name.into();
+        self
+    }
+
+    /// Only select package versions whose rockspec supports a Lua version
+    /// matching `constraint` (the project's `lua_version`)
+    pub fn with_lua_version(mut self, constraint: impl Into<String>) -> Self {
+        self.lua_version = Some(constraint.into());
+        self
     }
 
     /// Resolve all dependencies from a package manifest
     ///
-    /// This implements a simplified SemVer resolution algorithm:
-    /// 1. Build dependency graph
-    /// 2. For each package, find all available versions
-    /// 3. Select the highest version that satisfies all constraints
-    /// 4. Fetch rockspec and parse transitive dependencies
-    /// 5. Detect conflicts and circular dependencies
+    /// Resolution runs the backtracking `Solver` against an `InMemoryProvider`.
+    /// Whenever the solver asks for data it doesn't have yet, the versions are read
+    /// from the LuaRocks manifest or the rockspec is fetched, and the search restarts
+    /// (all earlier data stays cached, so restarts are cheap).
+    ///
+    /// If no solution exists, the error contains a derivation tree explaining which
+    /// requirements conflict.
+    ///
+    /// With a Lua version set, Lua takes part in resolution as the package `lua`:
+    /// the project requires one of the Lua versions its constraint allows, and each
+    /// rockspec's `lua >= 5.1, < 5.4` dependency rules out the package versions
+    /// that don't support it.
     pub async fn resolve(
         &self,
         dependencies: &HashMap<String, String>,
     ) -> LpmResult<HashMap<String, Version>> {
-        let mut graph = DependencyGraph::new();
-        let mut resolved = HashMap::new();
-
-        // Build initial graph from direct dependencies
-        for (name, constraint_str) in dependencies {
-            let constraint =
-                crate::core::version::parse_constraint(constraint_str).map_err(|e| {
-                    LpmError::Version(format!("Invalid constraint for {}: {}", name, e))
-                })?;
-            graph.add_node(name.clone(), constraint);
-        }
-
-        // Setup clients for fetching rockspecs
-        let config = Config::load()?;
-        let cache = Cache::new(config.get_cache_dir()?)?;
-        let client = LuaRocksClient::new(&config, cache);
-        let search_api = SearchAPI::new();
-
-        // Build full dependency graph by parsing rockspecs
-        let mut to_process: Vec<(String, VersionConstraint)> = dependencies
+        // Git and path dependencies aren't in the registry
+        let mut root = dependencies
             .iter()
-            .map(|(n, v)| {
-                let constraint = crate::core::version::parse_constraint(v).map_err(|e| {
-                    LpmError::Version(format!("Invalid constraint for {}: {}", n, e))
-                })?;
-                Ok((n.clone(), constraint))
+            .filter(|(_, spec)| DependencySpec::parse(spec).is_registry())
+            .map(|(name, constraint_str)| {
+                let constraint =
+                    crate::core::version::parse_constraint(constraint_str).map_err(|e| {
+                        LpmError::Version(format!("Invalid constraint for {}: {}", name, e))
+                    })?;
+                Ok((name.clone(), constraint))
             })
             .collect::<LpmResult<Vec<_>>>()?;
-        let mut processed = HashSet::new();
 
-        while let Some((package_name, constraint)) = to_process.pop() {
-            if processed.contains(&package_name) {
-                continue;
-            }
-            processed.insert(package_name.clone());
-
-            // Get available versions from manifest
-            let available_versions = self.get_available_versions(&package_name)?;
-            if available_versions.is_empty() {
-                return Err(LpmError::Package(format!(
-                    "No versions available for package '{}'",
-                    package_name
-                )));
+        let mut provider = InMemoryProvider::new();
+        if let Some(constraint) = &self.lua_version {
+            let allowed = allowed_lua_versions(constraint)?;
+            let mut versions = LUA_VERSIONS
+                .iter()
+                .map(|version| Version::parse(version))
+                .collect::<LpmResult<Vec<_>>>()?;
+            versions.reverse();
+            for version in &versions {
+                provider.add_dependencies(LUA, version.clone(), Vec::new());
             }
+            provider.add_versions(LUA, versions);
+            let mut exact = allowed.into_iter().map(VersionConstraint::Exact);
+            let constraint = match exact.len() {
+                1 => exact.next().unwrap(),
+                _ => VersionConstraint::AnyOf(exact.collect()),
+            };
+            root.push((LUA.to_string(), constraint));
+        }
+        root.sort_by(|a, b| a.0.cmp(&b.0));
 
-            // Find the highest version that satisfies the constraint
-            let selected_version = self.select_version(&available_versions, &constraint)?;
-            graph.add_node(package_name.clone(), constraint.clone());
-            graph.set_resolved_version(&package_name, selected_version.clone())?;
-            resolved.insert(package_name.clone(), selected_version.clone());
-
-            // Get rockspec and parse dependencies
-            let rockspec = get_rockspec(
-                &client,
-                &search_api,
-                &package_name,
-                &selected_version.to_string(),
-            )
-            .await?;
-
-            for dep in &rockspec.dependencies {
-                // Skip lua runtime dependency (standardize: any dep starting with "lua" and containing version operators)
-                if dep.trim().starts_with("lua")
-                    && (dep.contains(">=")
-                        || dep.contains(">")
-                        || dep.contains("==")
-                        || dep.contains("~>"))
-                {
-                    continue;
+        // Setup clients for fetching rockspecs
+        let config = Config::load()?;
+        let cache = Cache::new(config.get_cache_dir()?)?;
+        let client = LuaRocksClient::new(&config, cache).with_offline(self.offline);
+
+        // Rockspecs read during this resolution, remembered in the package index afterwards
+        let mut read = Vec::new();
+
+        let solution = loop {
+            match Solver::new(&provider).solve(&root) {
+                Ok(solution) => break solution,
+                Err(SolveError::Missing(missing)) => match *missing {
+                    MissingData::Versions(name) => {
+                        provider.add_versions(&name, self.get_published_versions(&name)?);
+                    }
+                    MissingData::Dependencies(name, version) => {
+                        // Versions print exactly as published, e.g. "3.0-1"
+                        let version_str = version.to_string();
+                        let keep_lua = self.lua_version.is_some();
+                        let package = format!("{} {}", name, version_str);
+                        let deps = match self.manifest.dependencies_of(&name, &version_str) {
+                            Some(deps) => rockspec_dependencies(&package, deps, keep_lua)?,
+                            None => {
+                                let rockspec =
+                                    get_rockspec(&client, &self.manifest, &name, &version_str)
+                                        .await?;
+                                read.push(RockspecSummary::new(&name, &version_str, &rockspec));
+                                rockspec_dependencies(&package, &rockspec.dependencies, keep_lua)?
+                            }
+                        };
+                        provider.add_dependencies(&name, version, deps);
+                    }
+                },
+                Err(SolveError::NoSolution(derivation)) => {
+                    let mut message = format!(
+                        "Dependency resolution failed: no version satisfies all constraints.\n\n{}",
+                        derivation.explain(&self.root_name)
+                    );
+                    if let Some(constraint) = &self.lua_version {
+                        if derivation.mentions(LUA) {
+                            message.push_str(&format!(
+                                "\n\n{} targets Lua {} (lua_version in package.yaml); packages \
+                                 declare the Lua versions they support with their `lua` dependency.",
+                                self.root_name, constraint
+                            ));
+                        }
+                    }
+                    return Err(LpmError::Version(message));
                 }
+                Err(SolveError::TooComplex(steps)) => {
+                    return Err(LpmError::Version(format!(
+                        "Dependency resolution gave up after {} attempts; try narrowing your version constraints",
+                        steps
+                    )));
+                }
+            }
+        };
 
-                // Parse dependency string: "luasocket >= 3.0" or "penlight" or "luasocket ~> 3.0"
-                let (dep_name, dep_constraint) = parse_dependency_string(dep)?;
-
-                graph.add_dependency(&package_name, dep_name.clone())?;
+        // Lua is the runtime, not something to install
+        let mut solution = solution;
+        solution.remove(LUA);
 
-                if !resolved.contains_key(&dep_name) {
-                    to_process.push((dep_name, dep_constraint));
+        // Build the graph of the selected versions and detect circular dependencies
+        let mut graph = DependencyGraph::new();
+        for name in solution.keys() {
+            let constraint = root
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, c)| c.clone())
+                .unwrap_or(VersionConstraint::GreaterOrEqual(Version::new(0, 0, 0)));
+            graph.add_node(name.clone(), constraint);
+        }
+        for (name, version) in &solution {
+            graph.set_resolved_version(name, version.clone())?;
+            if let Ok(deps) = provider.dependencies(name, version) {
+                for (dep_name, _) in deps.into_iter().filter(|(dep, _)| dep != LUA) {
+                    graph.add_dependency(name, dep_name)?;
                 }
             }
         }
-
-        // Detect circular dependencies
         graph.detect_circular_dependencies()?;
+        client.record_rockspecs(&read)?;
+
+        Ok(solution.into_iter().collect())
+    }
 
-        Ok(resolved)
+    /// Get available versions as published in the manifest
+    ///
+    /// Unknown packages yield an empty list so the solver can explain who required them.
+    fn get_published_versions(&self, package_name: &str) -> LpmResult<Vec<Version>> {
+        let mut versions = Vec::new();
+        for version_str in self.manifest.get_package_version_strings(package_name) {
+            versions.push(crate::luarocks::version::normalize_luarocks_version(
+                &version_str,
+            )?);
+        }
+        versions.sort_by(|a, b| b.cmp(a));
+        Ok(versions)
     }
 
     /// Get all available versions for a package from the manifest
@@ -144,7 +234,7 @@ impl DependencyResolver {
     }
 
     /// Select the highest version that satisfies the constraint
-    fn select_version(
+    pub fn select_version(
         &self,
         available_versions: &[Version],
         constraint: &VersionConstraint,
@@ -197,42 +287,86 @@ impl DependencyResolver {
 }
 
 /// Parse a dependency string from a rockspec
-/// Handles formats like: "luasocket >= 3.0", "penlight", "luasocket ~> 3.0"
-fn parse_dependency_string(dep: &str) -> LpmResult<(String, VersionConstraint)> {
+/// Handles formats like: "luasocket >= 3.0", "penlight", "luasocket ~> 3.0",
+/// "luafilesystem >= 1.6, < 2" and "lua>=5.1"
+fn parse_dependency_string(dep: &str) -> Result<(String, VersionConstraint), String> {
     let dep = dep.trim();
 
-    // Find first whitespace or version operator
-    if let Some(pos) = dep.find(char::is_whitespace) {
-        let name = dep[..pos].trim().to_string();
-        let version_part = dep[pos..].trim();
-
-        // Convert LuaRocks ~> to SemVer ^
-        let version_part = if version_part.starts_with("~>") {
-            version_part.replacen("~>", "^", 1)
-        } else {
-            version_part.to_string()
-        };
+    // The name ends at the first whitespace or operator character
+    let pos = dep
+        .find(|c: char| c.is_whitespace() || "<>=~!^".contains(c))
+        .unwrap_or(dep.len());
+    let name = dep[..pos].to_string();
+    let version_part = dep[pos..].trim();
+
+    // A missing constraint means any version; one that doesn't parse is an
+    // error rather than being widened to any version
+    let constraint = if version_part.is_empty() {
+        VersionConstraint::GreaterOrEqual(Version::new(0, 0, 0))
+    } else {
+        crate::core::version::parse_constraint(version_part).map_err(|_| {
+            format!(
+                "invalid version constraint '{}' for dependency '{}'",
+                version_part, name
+            )
+        })?
+    };
+    Ok((name, constraint))
+}
 
-        let constraint = crate::core::version::parse_constraint(&version_part)
-            .unwrap_or(VersionConstraint::GreaterOrEqual(Version::new(0, 0, 0)));
-        Ok((name, constraint))
+/// The Lua versions (of `LUA_VERSIONS`) a project's `lua_version` allows
+///
+/// LuaJIT implements Lua 5.1.
+fn allowed_lua_versions(constraint: &str) -> LpmResult<Vec<Version>> {
+    let allowed: Vec<&str> = if constraint.trim().to_lowercase().starts_with("luajit") {
+        vec!["5.1"]
     } else {
-        // No version specified
-        Ok((
-            dep.to_string(),
-            VersionConstraint::GreaterOrEqual(Version::new(0, 0, 0)),
-        ))
+        let parsed = parse_lua_version_constraint(constraint)?;
+        LUA_VERSIONS
+            .iter()
+            .copied()
+            .filter(|version| {
+                LuaVersion::parse(version).is_ok_and(|version| parsed.matches(&version))
+            })
+            .collect()
+    };
+    if allowed.is_empty() {
+        return Err(LpmError::Version(format!(
+            "lua_version '{}' matches none of the Lua versions lpm knows ({})",
+            constraint,
+            LUA_VERSIONS.join(", ")
+        )));
     }
+    allowed.into_iter().map(Version::parse).collect()
+}
+
+/// Parse the dependency strings of `package`'s rockspec; the Lua runtime
+/// itself is kept only when `keep_lua` is set
+fn rockspec_dependencies(
+    package: &str,
+    dependencies: &[String],
+    keep_lua: bool,
+) -> LpmResult<Vec<(String, VersionConstraint)>> {
+    let mut deps = Vec::new();
+    for dep in dependencies {
+        let (name, constraint) = parse_dependency_string(dep)
+            .map_err(|e| LpmError::Version(format!("{} in the rockspec of {}", e, package)))?;
+        if name == LUA && !keep_lua {
+            continue;
+        }
+        deps.push((name, constraint));
+    }
+    Ok(deps)
 }
 
 /// Fetch and parse a rockspec for a package version
 async fn get_rockspec(
     client: &LuaRocksClient,
-    search_api: &SearchAPI,
+    manifest: &Manifest,
     name: &str,
     version: &str,
 ) -> LpmResult<Rockspec> {
-    let rockspec_url = search_api.get_rockspec_url(name, version, None);
+    let rockspec_url = client.rockspec_url(manifest, name, version);
     let content = client.download_rockspec(&rockspec_url).await?;
     client.parse_rockspec(&content)
 }
@@ -491,6 +625,9 @@ mod tests {
         let mut manifest = Manifest {
             repository: "test".to_string(),
             packages: std::collections::HashMap::new(),
+            dependencies: HashMap::new(),
+            modules: HashMap::new(),
+            rocks: HashMap::new(),
         };
         let versions = vec![
             PackageVersion {
@@ -529,6 +666,9 @@ mod tests {
         let mut manifest = Manifest {
             repository: "test".to_string(),
             packages: std::collections::HashMap::new(),
+            dependencies: HashMap::new(),
+            modules: HashMap::new(),
+            rocks: HashMap::new(),
         };
         let versions = vec![PackageVersion {
             version: "1.0.0".to_string(),
@@ -554,6 +694,9 @@ mod tests {
         let mut manifest = Manifest {
             repository: "test".to_string(),
             packages: std::collections::HashMap::new(),
+            dependencies: HashMap::new(),
+            modules: HashMap::new(),
+            rocks: HashMap::new(),
         };
         let versions = vec![
             PackageVersion {
@@ -591,9 +734,67 @@ mod tests {
     fn test_parse_dependency_string_with_tilde_operator() {
         let (name, constraint) = parse_dependency_string("luasocket ~> 3.0").unwrap();
         assert_eq!(name, "luasocket");
-        // ~> should be converted to ^
-        let test_version = Version::new(3, 1, 0);
-        assert!(test_version.satisfies(&constraint));
+        // ~> 3.0 means >= 3.0, < 3.1 in LuaRocks
+        assert!(Version::new(3, 0, 5).satisfies(&constraint));
+        assert!(!Version::new(3, 1, 0).satisfies(&constraint));
+    }
+
+    #[test]
+    fn test_parse_dependency_string_keeps_every_comparator() {
+        let (name, constraint) = parse_dependency_string("luafilesystem >= 1.6, < 2").unwrap();
+        assert_eq!(name, "luafilesystem");
+        assert!(Version::parse("1.8.0-1").unwrap().satisfies(&constraint));
+        assert!(!Version::parse("2.0.0-1").unwrap().satisfies(&constraint));
+        assert!(!Version::parse("1.5.0-1").unwrap().satisfies(&constraint));
+    }
+
+    #[test]
+    fn test_parse_dependency_string_without_space() {
+        let (name, constraint) = parse_dependency_string("lua>=5.1").unwrap();
+        assert_eq!(name, "lua");
+        assert!(matches!(constraint, VersionConstraint::GreaterOrEqual(_)));
+    }
+
+    #[test]
+    fn test_rockspec_dependencies_skips_only_lua_runtime() {
+        use crate::luarocks::rockspec::{Rockspec, RockspecBuild, RockspecSource};
+        let mut rockspec = Rockspec {
+            package: "app".to_string(),
+            version: "1.0-1".to_string(),
+            source: RockspecSource {
+                url: "https://example.com/app-1.0.tar.gz".to_string(),
+                tag: None,
+                branch: None,
+                ..Default::default()
+            },
+            dependencies: vec![
+                "lua >= 5.1".to_string(),
+                "luasocket >= 3.0".to_string(),
+                "luafilesystem ~> 1.8".to_string(),
+            ],
+            build: RockspecBuild {
+                build_type: "builtin".to_string(),
+                modules: Default::default(),
+                install: Default::default(),
+                ..Default::default()
+            },
+            description: None,
+            homepage: None,
+            license: None,
+            lua_version: None,
+            binary_urls: Default::default(),
+            ..Default::default()
+        };
+        let deps = rockspec_dependencies("app 1.0-1", &rockspec.dependencies, false).unwrap();
+        let names: Vec<&str> = deps.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["luasocket", "luafilesystem"]);
+
+        rockspec.dependencies.clear();
+        assert!(
+            rockspec_dependencies("app 1.0-1", &rockspec.dependencies, false)
+                .unwrap()
+                .is_empty()
+        );
     }
 
     #[test]
@@ -636,13 +837,15 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_dependency_string_invalid_constraint_fallback() {
-        // Invalid constraint should fallback to >=0.0.0
-        let (name, constraint) = parse_dependency_string("luasocket invalid-constraint").unwrap();
-        assert_eq!(name, "luasocket");
-        // Should fallback to >=0.0.0
-        let test_version = Version::new(1, 0, 0);
-        assert!(test_version.satisfies(&constraint));
+    fn test_parse_dependency_string_invalid_constraint_is_an_error() {
+        let err = parse_dependency_string("luasocket invalid-constraint").unwrap_err();
+        assert!(err.contains("'invalid-constraint'"), "{}", err);
+        assert!(err.contains("'luasocket'"), "{}", err);
+
+        let err = rockspec_dependencies("app 1.0-1", &["luasocket >== 3.0".to_string()], false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("in the rockspec of app 1.0-1"), "{}", err);
     }
 
     #[test]
@@ -712,7 +915,7 @@ mod tests {
     fn test_parse_dependency_string_with_tilde_operator_v2() {
         let (name, constraint) = parse_dependency_string("luasocket ~> 3.0").unwrap();
         assert_eq!(name, "luasocket");
-        let test_version = Version::new(3, 5, 0);
+        let test_version = Version::parse("3.0-1").unwrap();
         assert!(test_version.satisfies(&constraint));
     }
 
@@ -734,4 +937,117 @@ mod tests {
         let result = resolver.select_version(&versions, &constraint);
         assert!(result.is_err());
     }
+    /// A manifest whose packages' dependencies are all known, so resolution
+    /// needs no rockspecs
+    fn manifest_with(packages: &[(&str, &str, &[&str])]) -> Manifest {
+        let mut manifest = Manifest::default();
+        for (name, version, deps) in packages {
+            manifest
+                .packages
+                .entry(name.to_string())
+                .or_default()
+                .push(PackageVersion {
+                    version: version.to_string(),
+                    rockspec_url: "".to_string(),
+                    archive_url: None,
+                });
+            manifest
+                .dependencies
+                .entry(name.to_string())
+                .or_default()
+                .insert(
+                    version.to_string(),
+                    deps.iter().map(|d| d.to_string()).collect(),
+                );
+        }
+        manifest
+    }
+
+    #[test]
+    fn test_allowed_lua_versions() {
+        let versions = |c: &str| -> Vec<String> {
+            allowed_lua_versions(c)
+                .unwrap()
+                .iter()
+                .map(|v| v.to_string())
+                .collect()
+        };
+        assert_eq!(versions("5.4"), vec!["5.4"]);
+        assert_eq!(versions(">=5.3"), vec!["5.3", "5.4"]);
+        assert_eq!(versions("5.1 || 5.4"), vec!["5.1", "5.4"]);
+        assert_eq!(versions("LuaJIT"), vec!["5.1"]);
+        assert!(allowed_lua_versions("6.0").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_skips_versions_for_other_lua() {
+        let manifest = manifest_with(&[
+            ("compat53", "0.7-1", &["lua >= 5.1, < 5.4"]),
+            ("compat53", "0.8-1", &["lua >= 5.1, < 5.4"]),
+            ("penlight", "1.13.1-1", &["lua >= 5.1"]),
+            ("penlight", "1.14.0-1", &["lua >= 5.1"]),
+            ("bit32", "5.3.0-1", &["lua == 5.1"]),
+        ]);
+        let deps = HashMap::from([("penlight".to_string(), "*".to_string())]);
+
+        let resolver = DependencyResolver::new(manifest.clone()).with_lua_version("5.4");
+        let solution = resolver.resolve(&deps).await.unwrap();
+        assert_eq!(solution.len(), 1);
+        assert_eq!(solution["penlight"].to_string(), "1.14.0-1");
+
+        let deps = HashMap::from([("compat53".to_string(), ">=0.7".to_string())]);
+        let solution = DependencyResolver::new(manifest.clone())
+            .with_lua_version(">=5.1")
+            .resolve(&deps)
+            .await
+            .unwrap();
+        assert_eq!(solution["compat53"].to_string(), "0.8-1");
+        assert!(!solution.contains_key("lua"));
+
+        // Without a Lua version, rockspec Lua requirements are ignored
+        let solution = DependencyResolver::new(manifest)
+            .resolve(&deps)
+            .await
+            .unwrap();
+        assert_eq!(solution["compat53"].to_string(), "0.8-1");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_picks_older_version_for_project_lua() {
+        let manifest = manifest_with(&[
+            ("lpeg", "1.0.2-1", &["lua >= 5.1"]),
+            ("lpeg", "1.1.0-1", &["lua >= 5.3"]),
+        ]);
+        let deps = HashMap::from([("lpeg".to_string(), ">=1.0".to_string())]);
+
+        let solution = DependencyResolver::new(manifest.clone())
+            .with_lua_version("5.1")
+            .resolve(&deps)
+            .await
+            .unwrap();
+        assert_eq!(solution["lpeg"].to_string(), "1.0.2-1");
+
+        let solution = DependencyResolver::new(manifest)
+            .with_lua_version("5.4")
+            .resolve(&deps)
+            .await
+            .unwrap();
+        assert_eq!(solution["lpeg"].to_string(), "1.1.0-1");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_explains_unsupported_lua() {
+        let manifest = manifest_with(&[("bit32", "5.3.0-1", &["lua == 5.1"])]);
+        let deps = HashMap::from([("bit32".to_string(), "*".to_string())]);
+
+        let err = DependencyResolver::new(manifest)
+            .with_root_name("app")
+            .with_lua_version("5.4")
+            .resolve(&deps)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("bit32 5.3.0-1 depends on lua"), "{}", err);
+        assert!(err.contains("app targets Lua 5.4"), "{}", err);
+    }
 }
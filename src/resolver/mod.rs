@@ -1,5 +1,7 @@
 pub mod dependency_graph;
 pub mod dependency_resolver;
+pub mod solver;
 
 pub use dependency_graph::DependencyGraph;
 pub use dependency_resolver::DependencyResolver;
+pub use solver::{DependencyProvider, Derivation, InMemoryProvider, Solver};
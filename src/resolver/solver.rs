@@ -0,0 +1,692 @@
+use crate::core::version::{Version, VersionConstraint};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+/// Upper bound on the number of version decisions before giving up
+const DEFAULT_MAX_STEPS: usize = 100_000;
+
+/// Data the solver needs but the provider has not loaded yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MissingData {
+    /// Available versions of a package
+    Versions(String),
+    /// Dependencies declared by a specific package version
+    Dependencies(String, Version),
+}
+
+/// Source of package metadata for the solver
+///
+/// Providers return `MissingData` instead of blocking on I/O, so the solver stays
+/// synchronous and the caller decides how (and whether) to fetch the data.
+pub trait DependencyProvider {
+    /// Available versions of a package, highest first (empty if the package is unknown)
+    fn versions(&self, package: &str) -> Result<Vec<Version>, MissingData>;
+
+    /// Dependencies of a package version as `(name, constraint)` pairs
+    fn dependencies(
+        &self,
+        package: &str,
+        version: &Version,
+    ) -> Result<Vec<(String, VersionConstraint)>, MissingData>;
+}
+
+/// Provider backed by in-memory maps, filled incrementally by the caller
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryProvider {
+    versions: HashMap<String, Vec<Version>>,
+    dependencies: HashMap<(String, Version), Vec<(String, VersionConstraint)>>,
+}
+
+impl InMemoryProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the available versions of a package
+    pub fn add_versions(&mut self, package: &str, mut versions: Vec<Version>) {
+        versions.sort_by(|a, b| b.cmp(a));
+        versions.dedup();
+        self.versions.insert(package.to_string(), versions);
+    }
+
+    /// Register the dependencies of a package version
+    pub fn add_dependencies(
+        &mut self,
+        package: &str,
+        version: Version,
+        dependencies: Vec<(String, VersionConstraint)>,
+    ) {
+        self.dependencies
+            .insert((package.to_string(), version), dependencies);
+    }
+}
+
+impl DependencyProvider for InMemoryProvider {
+    fn versions(&self, package: &str) -> Result<Vec<Version>, MissingData> {
+        self.versions
+            .get(package)
+            .cloned()
+            .ok_or_else(|| MissingData::Versions(package.to_string()))
+    }
+
+    fn dependencies(
+        &self,
+        package: &str,
+        version: &Version,
+    ) -> Result<Vec<(String, VersionConstraint)>, MissingData> {
+        self.dependencies
+            .get(&(package.to_string(), version.clone()))
+            .cloned()
+            .ok_or_else(|| MissingData::Dependencies(package.to_string(), version.clone()))
+    }
+}
+
+/// A constraint placed on a package, along with who placed it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    /// Package version that declared the dependency (`None` for the root project)
+    pub requirer: Option<(String, Version)>,
+    pub package: String,
+    pub constraint: VersionConstraint,
+}
+
+impl Requirement {
+    fn describe(&self, root: &str) -> String {
+        match &self.requirer {
+            Some((name, version)) => format!(
+                "{} {} depends on {} {}",
+                name, version, self.package, self.constraint
+            ),
+            None => format!("{} depends on {} {}", root, self.package, self.constraint),
+        }
+    }
+}
+
+/// Explanation of why a (partial) resolution failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Derivation {
+    /// No available version of `package` satisfies every requirement on it
+    NoMatchingVersion {
+        package: String,
+        requirements: Vec<Requirement>,
+        available: Vec<Version>,
+    },
+    /// A new requirement excludes the version already selected for `package`
+    SelectedVersionConflict {
+        package: String,
+        selected: Version,
+        conflicting: Box<Requirement>,
+    },
+    /// Every candidate version of `package` led to a conflict
+    AllCandidatesFailed {
+        package: String,
+        requirements: Vec<Requirement>,
+        attempts: Vec<(Version, Derivation)>,
+    },
+}
+
+impl Derivation {
+    /// Packages whose version choice contributed to this failure
+    ///
+    /// Used for backjumping: if a failure below a decision does not involve that
+    /// decision, trying other versions of it cannot help.
+    fn culprits(&self) -> BTreeSet<String> {
+        let requirers = |reqs: &[Requirement]| -> BTreeSet<String> {
+            reqs.iter()
+                .filter_map(|r| r.requirer.as_ref().map(|(name, _)| name.clone()))
+                .collect()
+        };
+
+        match self {
+            Derivation::NoMatchingVersion { requirements, .. } => requirers(requirements),
+            Derivation::SelectedVersionConflict {
+                package,
+                conflicting,
+                ..
+            } => {
+                let mut set = requirers(std::slice::from_ref(conflicting.as_ref()));
+                set.insert(package.clone());
+                set
+            }
+            Derivation::AllCandidatesFailed {
+                package,
+                requirements,
+                attempts,
+            } => {
+                let mut set = requirers(requirements);
+                for (_, cause) in attempts {
+                    set.extend(cause.culprits());
+                }
+                set.remove(package);
+                set
+            }
+        }
+    }
+
+    /// Whether `package` is required, selected or tried anywhere in this derivation
+    pub fn mentions(&self, package: &str) -> bool {
+        let required = |reqs: &[Requirement]| reqs.iter().any(|r| r.package == package);
+        match self {
+            Derivation::NoMatchingVersion {
+                package: p,
+                requirements,
+                ..
+            } => p == package || required(requirements),
+            Derivation::SelectedVersionConflict {
+                package: p,
+                conflicting,
+                ..
+            } => p == package || conflicting.package == package,
+            Derivation::AllCandidatesFailed {
+                package: p,
+                requirements,
+                attempts,
+            } => {
+                p == package
+                    || required(requirements)
+                    || attempts.iter().any(|(_, cause)| cause.mentions(package))
+            }
+        }
+    }
+
+    /// Render the derivation as an indented, human-readable explanation
+    pub fn explain(&self, root: &str) -> String {
+        let mut out = String::new();
+        self.write_explanation(root, 0, &mut out);
+        out.trim_end().to_string()
+    }
+
+    fn write_explanation(&self, root: &str, indent: usize, out: &mut String) {
+        let pad = "  ".repeat(indent);
+        match self {
+            Derivation::NoMatchingVersion {
+                package,
+                requirements,
+                available,
+            } => {
+                if available.is_empty() {
+                    out.push_str(&format!(
+                        "{}Package '{}' not found in manifest (required because {}).\n",
+                        pad,
+                        package,
+                        join_requirements(requirements, root)
+                    ));
+                } else {
+                    let available: Vec<String> = available.iter().map(|v| v.to_string()).collect();
+                    out.push_str(&format!(
+                        "{}Because {}, no version of {} satisfies all constraints (available: {}).\n",
+                        pad,
+                        join_requirements(requirements, root),
+                        package,
+                        available.join(", ")
+                    ));
+                }
+            }
+            Derivation::SelectedVersionConflict {
+                package,
+                selected,
+                conflicting,
+            } => {
+                out.push_str(&format!(
+                    "{}{}, which excludes the already selected {} {}.\n",
+                    pad,
+                    conflicting.describe(root),
+                    package,
+                    selected
+                ));
+            }
+            Derivation::AllCandidatesFailed {
+                package,
+                requirements,
+                attempts,
+            } => {
+                out.push_str(&format!(
+                    "{}Because {}, one of these versions of {} is needed, but none of them work:\n",
+                    pad,
+                    join_requirements(requirements, root),
+                    package
+                ));
+                for (version, cause) in attempts {
+                    out.push_str(&format!("{}  {} {}:\n", pad, package, version));
+                    cause.write_explanation(root, indent + 2, out);
+                }
+            }
+        }
+    }
+}
+
+fn join_requirements(requirements: &[Requirement], root: &str) -> String {
+    let described: Vec<String> = requirements.iter().map(|r| r.describe(root)).collect();
+    match described.len() {
+        0 => "nothing requires it".to_string(),
+        1 => described[0].clone(),
+        n => format!("{} and {}", described[..n - 1].join(", "), described[n - 1]),
+    }
+}
+
+/// Why the solver stopped without a solution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolveError {
+    /// The provider needs more data before the search can continue
+    Missing(Box<MissingData>),
+    /// The dependencies cannot be satisfied
+    NoSolution(Box<Derivation>),
+    /// The search exceeded its step budget
+    TooComplex(usize),
+}
+
+/// Backtracking dependency solver
+///
+/// Constraints from every requirer are merged per package. Packages are decided
+/// most-constrained first, trying the highest matching version; on conflict the
+/// solver backtracks, skipping decisions that did not contribute to the failure
+/// (conflict-directed backjumping). When no solution exists the returned
+/// `Derivation` explains which requirements clash.
+pub struct Solver<'a, P: DependencyProvider> {
+    provider: &'a P,
+    max_steps: usize,
+}
+
+struct SearchState {
+    requirements: BTreeMap<String, Vec<Requirement>>,
+    selected: BTreeMap<String, Version>,
+    steps: usize,
+}
+
+impl<'a, P: DependencyProvider> Solver<'a, P> {
+    pub fn new(provider: &'a P) -> Self {
+        Self {
+            provider,
+            max_steps: DEFAULT_MAX_STEPS,
+        }
+    }
+
+    /// Limit the number of version decisions the solver may try
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Find versions for every package reachable from the root requirements
+    pub fn solve(
+        &self,
+        root: &[(String, VersionConstraint)],
+    ) -> Result<BTreeMap<String, Version>, SolveError> {
+        let mut state = SearchState {
+            requirements: BTreeMap::new(),
+            selected: BTreeMap::new(),
+            steps: 0,
+        };
+        for (name, constraint) in root {
+            state
+                .requirements
+                .entry(name.clone())
+                .or_default()
+                .push(Requirement {
+                    requirer: None,
+                    package: name.clone(),
+                    constraint: constraint.clone(),
+                });
+        }
+
+        self.search(&mut state)?;
+        Ok(state.selected)
+    }
+
+    fn search(&self, state: &mut SearchState) -> Result<(), SolveError> {
+        let Some((package, candidates)) = self.next_decision(state)? else {
+            return Ok(());
+        };
+        let requirements = state.requirements[&package].clone();
+
+        if candidates.is_empty() {
+            return Err(SolveError::NoSolution(Box::new(
+                Derivation::NoMatchingVersion {
+                    package: package.clone(),
+                    requirements,
+                    available: self
+                        .provider
+                        .versions(&package)
+                        .map_err(|missing| SolveError::Missing(Box::new(missing)))?,
+                },
+            )));
+        }
+
+        let mut attempts = Vec::new();
+        for version in candidates {
+            state.steps += 1;
+            if state.steps > self.max_steps {
+                return Err(SolveError::TooComplex(self.max_steps));
+            }
+
+            let dependencies = self
+                .provider
+                .dependencies(&package, &version)
+                .map_err(|missing| SolveError::Missing(Box::new(missing)))?;
+
+            let cause = match self.check_selected(state, &package, &version, &dependencies) {
+                Some(conflict) => conflict,
+                None => {
+                    self.apply(state, &package, &version, &dependencies);
+                    match self.search(state) {
+                        Ok(()) => return Ok(()),
+                        Err(SolveError::NoSolution(cause)) => {
+                            self.undo(state, &package, &dependencies);
+                            *cause
+                        }
+                        Err(other) => return Err(other),
+                    }
+                }
+            };
+
+            if !cause.culprits().contains(&package) {
+                // This failure does not depend on our choice for `package`;
+                // other versions of it would fail the same way.
+                return Err(SolveError::NoSolution(Box::new(cause)));
+            }
+            attempts.push((version, cause));
+        }
+
+        Err(SolveError::NoSolution(Box::new(
+            Derivation::AllCandidatesFailed {
+                package,
+                requirements,
+                attempts,
+            },
+        )))
+    }
+
+    /// Pick the undecided package with the fewest matching versions
+    fn next_decision(
+        &self,
+        state: &SearchState,
+    ) -> Result<Option<(String, Vec<Version>)>, SolveError> {
+        let mut best: Option<(String, Vec<Version>)> = None;
+        for (package, requirements) in &state.requirements {
+            if state.selected.contains_key(package) {
+                continue;
+            }
+            let candidates: Vec<Version> = self
+                .provider
+                .versions(package)
+                .map_err(|missing| SolveError::Missing(Box::new(missing)))?
+                .into_iter()
+                .filter(|v| requirements.iter().all(|r| v.satisfies(&r.constraint)))
+                .collect();
+            let better = match &best {
+                Some((_, current)) => candidates.len() < current.len(),
+                None => true,
+            };
+            if better {
+                let done = candidates.is_empty();
+                best = Some((package.clone(), candidates));
+                if done {
+                    break;
+                }
+            }
+        }
+        Ok(best)
+    }
+
+    /// Check new requirements against versions that are already selected
+    fn check_selected(
+        &self,
+        state: &SearchState,
+        package: &str,
+        version: &Version,
+        dependencies: &[(String, VersionConstraint)],
+    ) -> Option<Derivation> {
+        dependencies.iter().find_map(|(dep, constraint)| {
+            let selected = if dep == package {
+                Some(version)
+            } else {
+                state.selected.get(dep)
+            }?;
+            if selected.satisfies(constraint) {
+                return None;
+            }
+            Some(Derivation::SelectedVersionConflict {
+                package: dep.clone(),
+                selected: selected.clone(),
+                conflicting: Box::new(Requirement {
+                    requirer: Some((package.to_string(), version.clone())),
+                    package: dep.clone(),
+                    constraint: constraint.clone(),
+                }),
+            })
+        })
+    }
+
+    fn apply(
+        &self,
+        state: &mut SearchState,
+        package: &str,
+        version: &Version,
+        dependencies: &[(String, VersionConstraint)],
+    ) {
+        state.selected.insert(package.to_string(), version.clone());
+        for (dep, constraint) in dependencies {
+            state
+                .requirements
+                .entry(dep.clone())
+                .or_default()
+                .push(Requirement {
+                    requirer: Some((package.to_string(), version.clone())),
+                    package: dep.clone(),
+                    constraint: constraint.clone(),
+                });
+        }
+    }
+
+    fn undo(
+        &self,
+        state: &mut SearchState,
+        package: &str,
+        dependencies: &[(String, VersionConstraint)],
+    ) {
+        state.selected.remove(package);
+        for (dep, _) in dependencies {
+            if let Some(reqs) = state.requirements.get_mut(dep) {
+                reqs.pop();
+                if reqs.is_empty() {
+                    state.requirements.remove(dep);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for MissingData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MissingData::Versions(name) => write!(f, "versions of {}", name),
+            MissingData::Dependencies(name, version) => {
+                write!(f, "dependencies of {} {}", name, version)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::version::parse_constraint;
+
+    fn v(s: &str) -> Version {
+        Version::parse(s).unwrap()
+    }
+
+    fn dep(name: &str, constraint: &str) -> (String, VersionConstraint) {
+        (name.to_string(), parse_constraint(constraint).unwrap())
+    }
+
+    /// (package, version, [(dependency, constraint)])
+    type PackageSpec<'a> = (&'a str, &'a str, &'a [(&'a str, &'a str)]);
+
+    fn provider(packages: &[PackageSpec]) -> InMemoryProvider {
+        let mut provider = InMemoryProvider::new();
+        let mut versions: BTreeMap<&str, Vec<Version>> = BTreeMap::new();
+        for (name, version, deps) in packages {
+            versions.entry(name).or_default().push(v(version));
+            provider.add_dependencies(
+                name,
+                v(version),
+                deps.iter().map(|(d, c)| dep(d, c)).collect(),
+            );
+        }
+        for (name, list) in versions {
+            provider.add_versions(name, list);
+        }
+        provider
+    }
+
+    #[test]
+    fn test_solve_picks_highest_versions() {
+        let provider = provider(&[
+            ("foo", "1.0.0", &[("bar", "^1.0.0")]),
+            ("foo", "1.2.0", &[("bar", "^1.1.0")]),
+            ("bar", "1.0.0", &[]),
+            ("bar", "1.1.0", &[]),
+            ("bar", "2.0.0", &[]),
+        ]);
+        let solution = Solver::new(&provider)
+            .solve(&[dep("foo", "^1.0.0")])
+            .unwrap();
+        assert_eq!(solution["foo"], v("1.2.0"));
+        assert_eq!(solution["bar"], v("1.1.0"));
+    }
+
+    #[test]
+    fn test_solve_backtracks_on_transitive_conflict() {
+        // foo 2.x needs an old penlight, so the solver must fall back to foo 1.x
+        let provider = provider(&[
+            ("foo", "1.0.0", &[("penlight", ">=1.0.0")]),
+            ("foo", "2.0.0", &[("penlight", "<1.3.0")]),
+            ("penlight", "1.2.0", &[]),
+            ("penlight", "1.5.0", &[]),
+            ("penlight", "1.6.0", &[]),
+        ]);
+        let solution = Solver::new(&provider)
+            .solve(&[dep("foo", ">=1.0.0"), dep("penlight", "^1.5.0")])
+            .unwrap();
+        assert_eq!(solution["foo"], v("1.0.0"));
+        assert_eq!(solution["penlight"], v("1.6.0"));
+    }
+
+    #[test]
+    fn test_solve_merges_constraints_from_all_requirers() {
+        let provider = provider(&[
+            ("a", "1.0.0", &[("c", ">=1.1.0")]),
+            ("b", "1.0.0", &[("c", "<1.3.0")]),
+            ("c", "1.0.0", &[]),
+            ("c", "1.2.0", &[]),
+            ("c", "1.4.0", &[]),
+        ]);
+        let solution = Solver::new(&provider)
+            .solve(&[dep("a", "1.0.0"), dep("b", "1.0.0")])
+            .unwrap();
+        assert_eq!(solution["c"], v("1.2.0"));
+    }
+
+    #[test]
+    fn test_solve_reports_conflict_explanation() {
+        let provider = provider(&[
+            ("foo", "2.0.0", &[("penlight", "<1.3.0")]),
+            ("penlight", "1.2.0", &[]),
+            ("penlight", "1.5.0", &[]),
+        ]);
+        let err = Solver::new(&provider)
+            .solve(&[dep("penlight", "^1.5.0"), dep("foo", "^2.0.0")])
+            .unwrap_err();
+        let SolveError::NoSolution(derivation) = err else {
+            panic!("expected NoSolution, got {:?}", err);
+        };
+        let explanation = derivation.explain("app");
+        assert!(explanation.contains("app depends on penlight ^1.5.0"));
+        assert!(explanation.contains("foo 2.0.0 depends on penlight <1.3.0"));
+    }
+
+    #[test]
+    fn test_solve_unknown_package() {
+        let mut provider = provider(&[]);
+        provider.add_versions("ghost", vec![]);
+        let err = Solver::new(&provider)
+            .solve(&[dep("ghost", "^1.0.0")])
+            .unwrap_err();
+        let SolveError::NoSolution(derivation) = err else {
+            panic!("expected NoSolution");
+        };
+        assert!(derivation
+            .explain("app")
+            .contains("Package 'ghost' not found in manifest"));
+    }
+
+    #[test]
+    fn test_solve_requests_missing_data() {
+        let provider = InMemoryProvider::new();
+        let err = Solver::new(&provider)
+            .solve(&[dep("foo", "^1.0.0")])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            SolveError::Missing(Box::new(MissingData::Versions("foo".to_string())))
+        );
+
+        let provider = provider_with_versions_only();
+        let err = Solver::new(&provider)
+            .solve(&[dep("foo", "^1.0.0")])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            SolveError::Missing(Box::new(MissingData::Dependencies(
+                "foo".to_string(),
+                v("1.0.0")
+            )))
+        );
+    }
+
+    fn provider_with_versions_only() -> InMemoryProvider {
+        let mut provider = InMemoryProvider::new();
+        provider.add_versions("foo", vec![v("1.0.0")]);
+        provider
+    }
+
+    #[test]
+    fn test_solve_backjumps_over_unrelated_decisions() {
+        // `noise` is decided first but is unrelated to the conflict on `c`, so
+        // its second version must not be tried: 1 + 3 steps instead of 2 * (1 + 3).
+        let provider = provider(&[
+            ("noise", "1.0.0", &[]),
+            ("noise", "1.1.0", &[]),
+            ("a", "1.0.0", &[("c", ">=2.0.0")]),
+            ("a", "1.1.0", &[("c", ">=2.0.0")]),
+            ("a", "1.2.0", &[("c", ">=2.0.0")]),
+            ("c", "1.0.0", &[]),
+        ]);
+        let err = Solver::new(&provider)
+            .with_max_steps(5)
+            .solve(&[dep("noise", ">=1.0.0"), dep("a", ">=1.0.0")])
+            .unwrap_err();
+        assert!(matches!(err, SolveError::NoSolution(_)));
+    }
+
+    #[test]
+    fn test_solve_handles_cycles() {
+        let provider = provider(&[
+            ("a", "1.0.0", &[("b", "^1.0.0")]),
+            ("b", "1.0.0", &[("a", "^1.0.0")]),
+        ]);
+        let solution = Solver::new(&provider).solve(&[dep("a", "^1.0.0")]).unwrap();
+        assert_eq!(solution.len(), 2);
+    }
+
+    #[test]
+    fn test_solve_too_complex() {
+        let provider = provider(&[("a", "1.0.0", &[("b", ">=2.0.0")]), ("b", "1.0.0", &[])]);
+        let err = Solver::new(&provider)
+            .with_max_steps(0)
+            .solve(&[dep("a", "1.0.0")])
+            .unwrap_err();
+        assert_eq!(err, SolveError::TooComplex(0));
+    }
+}
@@ -100,7 +100,7 @@ pub fn format_report(report: &VulnerabilityReport) -> String {
 
     // Sort vulnerabilities by severity (critical first)
     let mut vulns = report.vulnerabilities.clone();
-    vulns.sort_by(|a, b| b.severity.cmp(&a.severity));
+    vulns.sort_by_key(|v| std::cmp::Reverse(v.severity));
 
     // Count by severity
     let counts = report.count_by_severity();
@@ -177,3 +177,354 @@ fn install_nonexistent_package_fails() {
                 .or(predicate::str::contains("Package")),
         );
 }
+
+#[test]
+fn install_offline_without_lockfile_fails() {
+    if !TestContext::has_lua() {
+        TestContext::skip_test("Lua not available on PATH");
+        return;
+    }
+    let ctx = TestContext::new();
+
+    ctx.create_package_yaml("name: test\nversion: 1.0.0\ndependencies:\n  penlight: \"1.13.1\"\n");
+
+    ctx.lpm()
+        .arg("install")
+        .arg("--offline")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Offline mode needs a package.lock",
+        ));
+}
+
+#[test]
+fn install_offline_lists_missing_artifacts() {
+    if !TestContext::has_lua() {
+        TestContext::skip_test("Lua not available on PATH");
+        return;
+    }
+    let ctx = TestContext::new();
+
+    ctx.create_package_yaml("name: test\nversion: 1.0.0\ndependencies:\n  penlight: \"1.13.1\"\n");
+    ctx.temp
+        .child("package.lock")
+        .write_str(
+            r#"version: 1
+generated_at: 2024-01-01T00:00:00Z
+packages:
+  penlight:
+    version: 1.13.1-1
+    source: luarocks
+    source_url: https://example.com/penlight-1.13.1.zip
+    checksum: sha256:00
+  luafilesystem:
+    version: 1.8.0-1
+    source: luarocks
+    source_url: https://example.com/luafilesystem-1.8.0.tar.gz
+    checksum: sha256:00
+"#,
+        )
+        .unwrap();
+
+    // Nothing is cached, so every artifact is reported and nothing is fetched
+    ctx.lpm()
+        .arg("install")
+        .arg("--offline")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "4 artifact(s) missing from the cache",
+        ))
+        .stderr(predicate::str::contains("luafilesystem@1.8.0-1: rockspec"))
+        .stderr(predicate::str::contains(
+            "penlight@1.13.1-1: source archive",
+        ));
+}
+
+#[test]
+fn install_frozen_offline_keeps_lua_modules_when_uncached() {
+    if !TestContext::has_lua() {
+        TestContext::skip_test("Lua not available on PATH");
+        return;
+    }
+    let ctx = TestContext::new();
+
+    ctx.create_package_yaml("name: test\nversion: 1.0.0\ndependencies:\n  penlight: \"1.13.1\"\n");
+    ctx.temp
+        .child("package.lock")
+        .write_str(
+            r#"version: 1
+generated_at: 2024-01-01T00:00:00Z
+packages:
+  penlight:
+    version: 1.13.1-1
+    source: luarocks
+    source_url: https://example.com/penlight-1.13.1.zip
+    checksum: sha256:00
+"#,
+        )
+        .unwrap();
+    ctx.temp
+        .child("lua_modules/keep.lua")
+        .write_str("return {}")
+        .unwrap();
+
+    ctx.lpm()
+        .args(["install", "--frozen", "--offline"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing from the cache"));
+
+    // The cache check comes before the clean install empties lua_modules
+    ctx.temp
+        .child("lua_modules/keep.lua")
+        .assert(predicate::path::exists());
+}
+
+#[test]
+fn install_frozen_reports_lockfile_drift() {
+    if !TestContext::has_lua() {
+        TestContext::skip_test("Lua not available on PATH");
+        return;
+    }
+    let ctx = TestContext::new();
+
+    ctx.create_package_yaml(
+        "name: test\nversion: 1.0.0\ndependencies:\n  penlight: \"^2.0.0\"\n  lpeg: \"*\"\n",
+    );
+    let lockfile = r#"version: 1
+generated_at: 2024-01-01T00:00:00Z
+packages:
+  penlight:
+    version: 1.13.1-1
+    source: luarocks
+    checksum: sha256:00
+  luasocket:
+    version: 3.1.0-1
+    source: luarocks
+    checksum: sha256:00
+"#;
+    ctx.temp.child("package.lock").write_str(lockfile).unwrap();
+    ctx.temp
+        .child("lua_modules/stale/init.lua")
+        .write_str("return {}")
+        .unwrap();
+
+    ctx.lpm()
+        .arg("ci")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("package.lock is out of date"))
+        .stderr(predicate::str::contains("+ lpeg *"))
+        .stderr(predicate::str::contains("- luasocket 3.1.0-1"))
+        .stderr(predicate::str::contains(
+            "~ penlight: locked 1.13.1-1 does not satisfy '^2.0.0'",
+        ));
+
+    // Neither the lockfile nor lua_modules was touched
+    ctx.temp.child("package.lock").assert(lockfile);
+    ctx.temp
+        .child("lua_modules/stale/init.lua")
+        .assert(predicate::path::exists());
+}
+
+#[test]
+fn install_frozen_requires_lockfile() {
+    if !TestContext::has_lua() {
+        TestContext::skip_test("Lua not available on PATH");
+        return;
+    }
+    let ctx = TestContext::new();
+
+    ctx.create_package_yaml("name: test\nversion: 1.0.0\n");
+
+    ctx.lpm()
+        .arg("install")
+        .arg("--frozen")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--frozen requires package.lock"));
+    ctx.temp
+        .child("package.lock")
+        .assert(predicate::path::missing());
+}
+
+/// Run git in `dir` with a fixed identity
+fn git(dir: &std::path::Path, args: &[&str]) -> String {
+    let output = StdCommand::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn install_git_dependency_pins_commit() {
+    if !TestContext::has_lua() {
+        TestContext::skip_test("Lua not available on PATH");
+        return;
+    }
+    let ctx = TestContext::new();
+
+    // A local repository standing in for a remote, served through a bare clone
+    let work = ctx.temp.child("mylib-src").to_path_buf();
+    std::fs::create_dir_all(&work).unwrap();
+    git(&work, &["init", "--quiet", "--initial-branch=main"]);
+    std::fs::write(work.join("mylib.lua"), "return 'first'\n").unwrap();
+    git(&work, &["add", "."]);
+    git(&work, &["commit", "--quiet", "-m", "first"]);
+    let first = git(&work, &["rev-parse", "HEAD"]);
+    let bare = ctx.temp.child("mylib.git").to_path_buf();
+    git(
+        ctx.temp.path(),
+        &["clone", "--quiet", "--bare", "mylib-src", "mylib.git"],
+    );
+
+    ctx.temp
+        .child("app/package.yaml")
+        .write_str(&format!(
+            "name: app\nversion: 1.0.0\ndependencies:\n  mylib: {{ git: \"{}\", branch: main }}\n",
+            bare.display()
+        ))
+        .unwrap();
+    let app = ctx.temp.child("app").to_path_buf();
+
+    ctx.lpm()
+        .current_dir(&app)
+        .arg("install")
+        .assert()
+        .success();
+
+    let lockfile = Lockfile::load(&app).unwrap().unwrap();
+    let locked = lockfile.get_package("mylib").unwrap();
+    assert_eq!(locked.source, "git");
+    assert_eq!(locked.version, "branch=main");
+    assert_eq!(locked.commit.as_deref(), Some(first.as_str()));
+    let installed = TestContext::tree_of(&app).lua_dir().join("mylib.lua");
+    assert_eq!(
+        std::fs::read_to_string(&installed).unwrap(),
+        "return 'first'\n"
+    );
+
+    // The branch moves on, but installs keep using the locked commit
+    std::fs::write(work.join("mylib.lua"), "return 'second'\n").unwrap();
+    git(&work, &["commit", "--quiet", "-am", "second"]);
+    git(&work, &["push", "--quiet", bare.to_str().unwrap(), "main"]);
+    std::fs::remove_dir_all(app.join("lua_modules")).unwrap();
+
+    ctx.lpm()
+        .current_dir(&app)
+        .arg("install")
+        .assert()
+        .success();
+
+    let lockfile = Lockfile::load(&app).unwrap().unwrap();
+    assert_eq!(
+        lockfile.get_package("mylib").unwrap().commit.as_deref(),
+        Some(first.as_str())
+    );
+    assert_eq!(
+        std::fs::read_to_string(&installed).unwrap(),
+        "return 'first'\n"
+    );
+
+    // lpm ci reproduces the same commit from the lockfile
+    ctx.lpm().current_dir(&app).arg("ci").assert().success();
+    assert_eq!(
+        std::fs::read_to_string(&installed).unwrap(),
+        "return 'first'\n"
+    );
+}
+
+#[test]
+fn install_path_dependency_links_and_resyncs() {
+    if !TestContext::has_lua() {
+        TestContext::skip_test("Lua not available on PATH");
+        return;
+    }
+    let ctx = TestContext::new();
+
+    // app -> mylib (local) -> util (local, relative to mylib)
+    ctx.temp
+        .child("libs/util/package.yaml")
+        .write_str("name: util\nversion: 0.1.0\n")
+        .unwrap();
+    ctx.temp
+        .child("libs/util/src/util.lua")
+        .write_str("return 'util'\n")
+        .unwrap();
+    ctx.temp
+        .child("libs/mylib/package.yaml")
+        .write_str("name: mylib\nversion: 0.1.0\ndependencies:\n  util: \"path:../util\"\n")
+        .unwrap();
+    ctx.temp
+        .child("libs/mylib/mylib.lua")
+        .write_str("return 'first'\n")
+        .unwrap();
+    ctx.temp
+        .child("app/package.yaml")
+        .write_str("name: app\nversion: 1.0.0\ndependencies:\n  mylib: \"path:../libs/mylib\"\n")
+        .unwrap();
+    let app = ctx.temp.child("app").to_path_buf();
+
+    ctx.lpm()
+        .current_dir(&app)
+        .arg("install")
+        .assert()
+        .success();
+
+    let lockfile = Lockfile::load(&app).unwrap().unwrap();
+    let mylib = lockfile.get_package("mylib").unwrap();
+    assert_eq!(mylib.source, "path");
+    assert_eq!(mylib.source_url.as_deref(), Some("../libs/mylib"));
+    assert_eq!(mylib.dependencies["util"], "path:../libs/util");
+    let util = lockfile.get_package("util").unwrap();
+    assert_eq!(util.source_url.as_deref(), Some("../libs/util"));
+
+    let lua_modules = app.join("lua_modules");
+    assert_eq!(
+        std::fs::read_to_string(lua_modules.join("util/util.lua")).unwrap(),
+        "return 'util'\n"
+    );
+
+    // Edits to the local package's sources are picked up
+    ctx.temp
+        .child("libs/mylib/mylib.lua")
+        .write_str("return 'second'\n")
+        .unwrap();
+    ctx.lpm()
+        .current_dir(&app)
+        .arg("install")
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(lua_modules.join("mylib/mylib.lua")).unwrap(),
+        "return 'second'\n"
+    );
+
+    // A changed package.yaml makes the lockfile stale until the next install
+    ctx.temp
+        .child("libs/mylib/package.yaml")
+        .write_str("name: mylib\nversion: 0.2.0\ndependencies:\n  util: \"path:../util\"\n")
+        .unwrap();
+    ctx.lpm()
+        .current_dir(&app)
+        .args(["install", "--frozen"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "package.lock is out of date for local package 'mylib'",
+        ));
+
+    ctx.lpm()
+        .current_dir(&app)
+        .arg("install")
+        .assert()
+        .success();
+    let lockfile = Lockfile::load(&app).unwrap().unwrap();
+    assert_eq!(lockfile.get_package("mylib").unwrap().version, "0.2.0");
+}
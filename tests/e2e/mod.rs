@@ -1,7 +1,10 @@
 use ::predicates::prelude::*;
 use assert_cmd::Command;
 use assert_fs::{prelude::*, TempDir};
-use std::path::PathBuf;
+use lpm::core::path::DEFAULT_LUA_VERSION;
+use lpm::lua_version::detector::LuaVersionDetector;
+use lpm::package::layout::TreeLayout;
+use std::path::{Path, PathBuf};
 use std::process::Command as StdCommand;
 
 // Declare submodules
@@ -96,45 +99,53 @@ impl TestContext {
         self.temp.child(constants::LUA_MODULES).to_path_buf()
     }
 
+    /// Layout of the test project's lua_modules, see [`TestContext::tree_of`]
+    pub fn tree(&self) -> TreeLayout {
+        Self::tree_of(self.temp.path())
+    }
+
+    /// Layout of a project's lua_modules for the Lua on PATH, as lpm installs into it
+    pub fn tree_of(project_root: &Path) -> TreeLayout {
+        let version = LuaVersionDetector::detect()
+            .map(|version| version.major_minor())
+            .unwrap_or_else(|_| DEFAULT_LUA_VERSION.to_string());
+        TreeLayout::new(&project_root.join(constants::LUA_MODULES), &version)
+    }
+
     /// Generate Lua package.path configuration string
     ///
-    /// Returns a string that adds lua_modules to Lua's package.path.
+    /// Returns a string that adds lua_modules' `share/lua/<version>` to Lua's package.path.
     /// Uses absolute paths for reliability across different working directories.
     pub fn lua_package_path(&self) -> String {
-        let lua_modules = self.lua_modules();
-        let lua_modules_str = lua_modules.to_string_lossy().replace('\\', "/");
+        let lua_dir = self.tree().lua_dir();
+        let lua_dir_str = lua_dir.to_string_lossy().replace('\\', "/");
 
         format!(
-            "package.path = '{}/?.lua;{}/?/init.lua;{}/?/?.lua;' .. package.path",
-            lua_modules_str, lua_modules_str, lua_modules_str
+            "package.path = '{}/?.lua;{}/?/init.lua;' .. package.path",
+            lua_dir_str, lua_dir_str
         )
     }
 
     /// Generate Lua package.cpath configuration string
     ///
-    /// Returns a string that adds lua_modules to Lua's package.cpath for native modules.
-    /// Handles platform-specific extensions (.so, .dylib, .dll).
+    /// Returns a string that adds lua_modules' `lib/lua/<version>` to Lua's package.cpath
+    /// for native modules. Handles platform-specific extensions (.so, .dylib, .dll).
     pub fn lua_package_cpath(&self) -> String {
-        let lua_modules = self.lua_modules();
-        let lua_modules_str = lua_modules.to_string_lossy().replace('\\', "/");
+        let lib_dir = self.tree().lib_dir();
+        let lib_dir_str = lib_dir.to_string_lossy().replace('\\', "/");
 
-        if cfg!(target_os = "windows") {
-            format!(
-                "package.cpath = '{}/?.dll;{}/?/init.dll;' .. package.cpath",
-                lua_modules_str, lua_modules_str
-            )
+        let extension = if cfg!(target_os = "windows") {
+            "dll"
         } else if cfg!(target_os = "macos") {
-            format!(
-                "package.cpath = '{}/?.dylib;{}/?/init.dylib;' .. package.cpath",
-                lua_modules_str, lua_modules_str
-            )
+            "dylib"
         } else {
             // Linux and other Unix-like
-            format!(
-                "package.cpath = '{}/?.so;{}/?/init.so;' .. package.cpath",
-                lua_modules_str, lua_modules_str
-            )
-        }
+            "so"
+        };
+        format!(
+            "package.cpath = '{}/?.{};' .. package.cpath",
+            lib_dir_str, extension
+        )
     }
 
     /// Generate complete Lua path setup code
@@ -16,7 +16,8 @@ fn test_plugin_execute() {
     // Execute a plugin command
     // This test may need adjustment based on actual plugin system
     // For now, we'll just verify the command structure works
-    ctx.lpm()
+    let _ = ctx
+        .lpm()
         .arg("plugin")
         .arg("run")
         .arg("test-plugin")
@@ -0,0 +1,94 @@
+//! Tests for `lpm cache` command
+
+use super::common::{isolated_lpm_command, stdout};
+use lpm::cache::Cache;
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+use tempfile::TempDir;
+
+/// `lpm cache <args>` in `dir`, with the cache in `cache_dir`
+fn cache_command(cache_dir: &Path, dir: &Path, args: &[&str]) -> Output {
+    isolated_lpm_command(dir, dir)
+        .arg("cache")
+        .args(args)
+        .env("LPM_CACHE_DIR", cache_dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_cache_ls_prune_clear() {
+    let temp = TempDir::new().unwrap();
+    let cache_dir = temp.path().join("cache");
+    let cache = Cache::new(cache_dir.clone()).unwrap();
+    let url = "https://example.com/penlight-1.14.0.tar.gz";
+    cache
+        .write(
+            &cache.rockspec_path("penlight", "1.14.0-1"),
+            format!("package = 'penlight'\nversion = '1.14.0-1'\nsource = {{ url = '{url}' }}\n")
+                .as_bytes(),
+        )
+        .unwrap();
+    cache.write(&cache.source_path(url), &[0; 2048]).unwrap();
+
+    let ls = stdout(&cache_command(&cache_dir, temp.path(), &["ls"]));
+    assert!(ls.contains("sources"), "{ls}");
+    assert!(ls.contains("penlight 1.14.0-1: rockspecs"), "{ls}");
+    assert!(ls.contains("sources 2.0 KB"), "{ls}");
+
+    // prune needs a limit
+    assert!(!cache_command(&cache_dir, temp.path(), &["prune"])
+        .status
+        .success());
+    let prune = stdout(&cache_command(
+        &cache_dir,
+        temp.path(),
+        &["prune", "--max-age", "30"],
+    ));
+    assert!(prune.contains("Removed 0 cache entries"), "{prune}");
+    stdout(&cache_command(
+        &cache_dir,
+        temp.path(),
+        &["prune", "--max-size", "0"],
+    ));
+    assert!(!cache.source_path(url).exists());
+
+    cache.write(&cache.source_path(url), b"archive").unwrap();
+    stdout(&cache_command(&cache_dir, temp.path(), &["clear"]));
+    assert!(!cache.source_path(url).exists());
+    assert!(stdout(&cache_command(&cache_dir, temp.path(), &["ls"])).contains("(empty)"));
+}
+
+#[test]
+fn test_cache_verify_against_lockfile() {
+    let temp = TempDir::new().unwrap();
+    let cache_dir = temp.path().join("cache");
+    let cache = Cache::new(cache_dir.clone()).unwrap();
+    let url = "https://example.com/inspect-3.1.3.tar.gz";
+    cache.write(&cache.source_path(url), b"tampered").unwrap();
+
+    let project = temp.path().join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join("package.yaml"), "name: app\nversion: 0.1.0\n").unwrap();
+    fs::write(
+        project.join("package.lock"),
+        format!(
+            "version: 1\ngenerated_at: 2026-01-01T00:00:00Z\npackages:\n  inspect:\n    version: 3.1.3-0\n    source: luarocks\n    source_url: {url}\n    checksum: sha256:0000\n    dependencies: {{}}\n"
+        ),
+    )
+    .unwrap();
+
+    let output = cache_command(&cache_dir, &project, &["verify"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("inspect@3.1.3-0"));
+
+    let output = stdout(&cache_command(
+        &cache_dir,
+        &project,
+        &["verify", "--remove"],
+    ));
+    assert!(output.contains("Removed 1 corrupted source"), "{output}");
+    assert!(!cache.source_path(url).exists());
+    assert!(stdout(&cache_command(&cache_dir, &project, &["verify"])).contains("0 cached source"));
+}
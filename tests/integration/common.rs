@@ -1,7 +1,34 @@
 //! Common utilities for integration tests
 
-use std::process::Command;
+use std::path::Path;
+use std::process::{Command, Output};
 
 pub fn lpm_command() -> Command {
     Command::new(env!("CARGO_BIN_EXE_lpm"))
 }
+
+/// `lpm` run in `dir` with `home` as its home directory, so that it sees
+/// neither the user's config nor `LPM_*` settings from the environment
+pub fn isolated_lpm_command(home: &Path, dir: &Path) -> Command {
+    let mut command = lpm_command();
+    command
+        .current_dir(dir)
+        .env("HOME", home)
+        .env_remove("XDG_CONFIG_HOME");
+    for (key, _) in std::env::vars_os() {
+        if key.to_string_lossy().starts_with("LPM_") {
+            command.env_remove(key);
+        }
+    }
+    command
+}
+
+/// Stdout of a command that must have succeeded
+pub fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
@@ -0,0 +1,91 @@
+//! Tests for `lpm config` command
+
+use super::common::{isolated_lpm_command, stdout};
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+use tempfile::TempDir;
+
+/// `lpm config <args>` in `dir`, with a home directory of its own
+fn config(home: &Path, dir: &Path, args: &[&str]) -> Output {
+    isolated_lpm_command(home, dir)
+        .arg("config")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_config_layers() {
+    let home = TempDir::new().unwrap();
+    let project = TempDir::new().unwrap();
+    fs::write(
+        project.path().join("package.yaml"),
+        "name: app\nversion: 0.1.0\n",
+    )
+    .unwrap();
+    let (home, dir) = (home.path(), project.path());
+
+    assert_eq!(stdout(&config(home, dir, &["get", "index_ttl"])), "3600\n");
+
+    stdout(&config(home, dir, &["set", "index_ttl", "60"]));
+    stdout(&config(
+        home,
+        dir,
+        &["set", "lua_binary_sources.5.4.8", "https://lua.example.com"],
+    ));
+    assert!(fs::read_to_string(home.join(".config/lpm/config.yaml"))
+        .unwrap()
+        .contains("5.4.8: https://lua.example.com"));
+    assert_eq!(
+        stdout(&config(home, dir, &["get", "lua_binary_sources.5.4.8"])),
+        "https://lua.example.com\n"
+    );
+
+    stdout(&config(
+        home,
+        dir,
+        &["set", "--project", "index_ttl", "120"],
+    ));
+    assert!(dir.join(".lpmrc.yaml").exists());
+    let list = stdout(&config(home, dir, &["list"]));
+    assert!(list.contains("index_ttl = 120  (project config"), "{list}");
+    assert!(list.contains("lua_binary_sources.5.4.8 = https://lua.example.com  (user config"));
+    assert!(
+        list.contains("verify_checksums = true  (default)"),
+        "{list}"
+    );
+
+    // The environment overrides both files
+    let output = isolated_lpm_command(home, dir)
+        .args(["config", "list"])
+        .env("LPM_INDEX_TTL", "5")
+        .output()
+        .unwrap();
+    assert!(stdout(&output).contains("index_ttl = 5  (environment LPM_INDEX_TTL)"));
+
+    stdout(&config(home, dir, &["unset", "--project", "index_ttl"]));
+    assert_eq!(stdout(&config(home, dir, &["get", "index_ttl"])), "60\n");
+}
+
+#[test]
+fn test_config_rejects_bad_values() {
+    let home = TempDir::new().unwrap();
+    let dir = TempDir::new().unwrap();
+
+    let output = config(home.path(), dir.path(), &["set", "index_ttl", "soon"]);
+    assert!(!output.status.success());
+
+    let output = config(home.path(), dir.path(), &["set", "colour", "blue"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown config key 'colour'"));
+
+    let output = config(
+        home.path(),
+        dir.path(),
+        &["set", "--project", "index_ttl", "1"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Not in an LPM project"));
+}
@@ -60,6 +60,36 @@ fn test_install_with_no_dev_and_dev_only() {
     assert!(stderr.contains("Cannot use both --no-dev and --dev-only") || stderr.contains("both"));
 }
 
+#[test]
+fn test_install_frozen_with_no_dev_and_dev_only_keeps_lua_modules() {
+    let temp = TempDir::new().unwrap();
+    let project_root = temp.path();
+
+    fs::write(
+        project_root.join("package.yaml"),
+        "name: test-project\nversion: 1.0.0\n",
+    )
+    .unwrap();
+    fs::write(
+        project_root.join("package.lock"),
+        "version: 1\ngenerated_at: 2024-01-01T00:00:00Z\npackages: {}\n",
+    )
+    .unwrap();
+    fs::create_dir_all(project_root.join("lua_modules")).unwrap();
+    fs::write(project_root.join("lua_modules/keep.lua"), "return {}").unwrap();
+
+    let output = lpm_command()
+        .args(["install", "--frozen", "--no-dev", "--dev-only"])
+        .current_dir(project_root)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Cannot use both --no-dev and --dev-only"));
+    assert!(project_root.join("lua_modules/keep.lua").exists());
+}
+
 #[test]
 fn test_install_from_path_nonexistent() {
     let temp = TempDir::new().unwrap();
@@ -4,8 +4,10 @@
 
 pub mod audit;
 pub mod build;
+pub mod cache;
 pub mod clean;
 pub mod common;
+pub mod config;
 pub mod error_recovery;
 pub mod init;
 pub mod install;
@@ -15,9 +17,11 @@ pub mod list;
 pub mod login;
 pub mod lua;
 pub mod outdated;
+pub mod owns;
 pub mod package;
 pub mod plugin;
 pub mod publish;
+pub mod registry;
 pub mod remove;
 pub mod run;
 pub mod security;
@@ -0,0 +1,77 @@
+//! Tests for `lpm owns` command
+
+use super::common::lpm_command;
+use std::fs;
+use tempfile::TempDir;
+
+/// A project with luasocket's files recorded the way an install records them
+fn project_with_luasocket(temp: &TempDir) {
+    let root = temp.path();
+    fs::write(
+        root.join("package.yaml"),
+        "name: test-project\nversion: 1.0.0\n",
+    )
+    .unwrap();
+    let lua_modules = root.join("lua_modules");
+    fs::create_dir_all(lua_modules.join("socket")).unwrap();
+    fs::write(lua_modules.join("socket.lua"), "return {}").unwrap();
+    fs::write(lua_modules.join("socket/http.lua"), "return {}").unwrap();
+
+    // sha256 of "return {}"
+    let checksum = "sha256:7df8f0cd9e1cd0b204a760783671c0ccca4a3258dc7c51f59e9e2295c6c25315";
+    let packages = lua_modules.join(".lpm/packages/luasocket");
+    fs::create_dir_all(&packages).unwrap();
+    fs::write(
+        packages.join("files.yaml"),
+        format!(
+            "version: 3.1.0-1\nfiles:\n  socket.lua: {}\n  socket/http.lua: {}\n",
+            checksum, checksum
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_owns_file_and_module() {
+    let temp = TempDir::new().unwrap();
+    project_with_luasocket(&temp);
+
+    for arg in ["lua_modules/socket.lua", "socket.lua", "socket.http"] {
+        let output = lpm_command()
+            .args(["owns", arg])
+            .current_dir(temp.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{arg}");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("is owned by luasocket 3.1.0-1"), "{stdout}");
+        assert!(!stdout.contains("modified"), "{stdout}");
+    }
+
+    fs::write(temp.path().join("lua_modules/socket.lua"), "return nil").unwrap();
+    let output = lpm_command()
+        .args(["owns", "socket.lua"])
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("(modified since it was installed)"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_owns_unknown_file() {
+    let temp = TempDir::new().unwrap();
+    project_with_luasocket(&temp);
+
+    let output = lpm_command()
+        .args(["owns", "ltn12.lua"])
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No installed package owns ltn12.lua"));
+}
@@ -0,0 +1,70 @@
+//! Tests for `lpm registry` command
+
+use super::common::lpm_command;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_registry_add_writes_manifests() {
+    let temp = TempDir::new().unwrap();
+    let rockspec = temp.path().join("acme-log.rockspec");
+    fs::write(
+        &rockspec,
+        r#"
+package = "acme-log"
+version = "1.0-1"
+source = { url = "http://127.0.0.1:8080/acme-log-1.0.tar.gz" }
+dependencies = { "lua >= 5.1" }
+build = { type = "builtin", modules = { ["acme.log"] = "src/log.lua" } }
+"#,
+    )
+    .unwrap();
+    let repo = temp.path().join("repo");
+
+    let output = lpm_command()
+        .args(["registry", "add"])
+        .arg(&repo)
+        .arg(&rockspec)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Added acme-log 1.0-1"));
+
+    assert!(repo.join("acme-log-1.0-1.rockspec").exists());
+    for manifest in ["manifest", "manifest-5.1", "manifest-5.4", "manifest.json"] {
+        let content = fs::read_to_string(repo.join(manifest)).unwrap();
+        assert!(content.contains("acme-log"), "{manifest}");
+    }
+
+    // Removing a file and rebuilding drops it from the manifests
+    fs::remove_file(repo.join("acme-log-1.0-1.rockspec")).unwrap();
+    let output = lpm_command()
+        .args(["registry", "build"])
+        .arg(&repo)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!fs::read_to_string(repo.join("manifest"))
+        .unwrap()
+        .contains("acme-log"));
+}
+
+#[test]
+fn test_registry_add_rejects_other_files() {
+    let temp = TempDir::new().unwrap();
+    let readme = temp.path().join("README.md");
+    fs::write(&readme, "# hi").unwrap();
+
+    let output = lpm_command()
+        .args(["registry", "add"])
+        .arg(temp.path().join("repo"))
+        .arg(&readme)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Not a rockspec or rock"));
+}